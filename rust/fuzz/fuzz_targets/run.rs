@@ -0,0 +1,8 @@
+//! Runs arbitrary programs with arbitrary input and semantics; see
+//! `befunge_93::fuzzing`. Start it with `cargo fuzz run run`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| befunge_93::fuzzing::run(data));
@@ -0,0 +1,48 @@
+/* C interface of the befunge-93 crate, built as a shared library with
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib`. */
+
+#ifndef BEFUNGE93_H
+#define BEFUNGE93_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BefInterpreter BefInterpreter;
+
+/* Create an interpreter. Free it with bef_free. */
+BefInterpreter *bef_new(void);
+void bef_free(BefInterpreter *bef);
+
+/* Load len bytes of program text. Returns 0, or -1 on error. */
+int bef_load(BefInterpreter *bef, const char *program, size_t len);
+
+/* Append len bytes to the input read by `&` and `~`. */
+void bef_push_input(BefInterpreter *bef, const char *input, size_t len);
+
+/* Whether the next step would read more input than has been pushed. */
+int bef_needs_input(const BefInterpreter *bef);
+
+/* Execute one step, or up to max_steps steps stopping early when input is
+ * needed. Return 1 while the program runs, 0 once it ended, -1 on error. */
+int bef_step(BefInterpreter *bef);
+int bef_run(BefInterpreter *bef, size_t max_steps);
+
+/* Copy up to cap bytes of output into buf, without a terminating NUL.
+ * Returns the full length of the output. */
+size_t bef_output(const BefInterpreter *bef, char *buf, size_t cap);
+
+/* Store the size of the part of the playfield in use, which grows as
+ * cells past it are written. */
+void bef_bounds(const BefInterpreter *bef, size_t *cols, size_t *rows);
+
+/* Message of the last error, or NULL. Owned by the interpreter. */
+const char *bef_last_error(const BefInterpreter *bef);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BEFUNGE93_H */
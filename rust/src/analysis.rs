@@ -0,0 +1,332 @@
+//! Static exploration of the states a program can be in, without running
+//! it. A state is the position of the PC, its direction and whether string
+//! mode is active; branches and `?` are assumed to go every possible way.
+
+use std::collections::BTreeSet;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::instructions::DRAFT_PLAYFIELD;
+use crate::semantics::Unknown;
+use crate::{Bounds, Command, Direction, Interpreter, Position, ProgramCounter};
+
+pub(crate) const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// States are indexed as on the largest playfield of any dialect, so that
+/// indices don't depend on the dialect.
+const INDEXED: Bounds = DRAFT_PLAYFIELD;
+
+pub(crate) const STATES: usize = INDEXED.rows * INDEXED.cols * 4 * 2;
+
+/// What static analysis found out about a program.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct Analysis {
+    /// Non-space cells the PC can never reach.
+    pub unreachable: Vec<Position>,
+    /// Reachable non-space cells from which `@` can never be reached.
+    pub never_ending: Vec<Position>,
+    /// Loops the PC can enter but never leave, each as the cells it passes.
+    pub cycles: Vec<Vec<Position>>,
+    /// Every loop the PC can go round, whether or not it can leave it, each
+    /// as the cells it passes.
+    pub loops: Vec<Vec<Position>>,
+    /// Reachable cells executed outside string mode that hold no
+    /// instruction.
+    pub unknown: Vec<Position>,
+    /// Whether a reachable `p` may change the program, in which case all of
+    /// the above may be wrong.
+    pub self_modifying: bool,
+}
+
+/// Where the PC is, where it's going and whether it's in string mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct State {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) dir: Direction,
+    pub(crate) stringmode: bool,
+}
+
+/// The states reachable from the start, indexed by `State::index`.
+pub(crate) struct Graph {
+    pub(crate) reached: Vec<bool>,
+    pub(crate) successors: Vec<Vec<usize>>,
+    pub(crate) predecessors: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// The reached states at the cell at `pos`.
+    pub(crate) fn states_at(&self, pos: Position) -> impl Iterator<Item = State> + '_ {
+        let first = State {
+            x: pos.x,
+            y: pos.y,
+            dir: DIRECTIONS[0],
+            stringmode: false,
+        }
+        .index();
+        (first..first + 8)
+            .filter(move |&idx| self.reached[idx])
+            .map(State::from_index)
+    }
+}
+
+impl State {
+    pub(crate) fn index(self) -> usize {
+        ((self.y * INDEXED.cols + self.x) * 4 + self.dir as usize) * 2 + self.stringmode as usize
+    }
+
+    pub(crate) fn from_index(idx: usize) -> Self {
+        let cell = idx / 8;
+        Self {
+            x: cell % INDEXED.cols,
+            y: cell / INDEXED.cols,
+            dir: DIRECTIONS[idx / 2 % 4],
+            stringmode: idx % 2 == 1,
+        }
+    }
+
+    pub(crate) fn pos(self) -> Position {
+        Position {
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    /// The state after leaving this cell moving `dir` on a playfield of
+    /// `size`, skipping `skip` more cells.
+    pub(crate) fn leave(self, dir: Direction, stringmode: bool, skip: usize, size: Bounds) -> Self {
+        let mut pc = ProgramCounter {
+            x: self.x,
+            y: self.y,
+        };
+        for _ in 0..=skip {
+            pc.advance(dir, size);
+        }
+        Self {
+            x: pc.x,
+            y: pc.y,
+            dir,
+            stringmode,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Explore every state the loaded program can reach from the start and
+    /// report unreachable code and places it can't terminate from.
+    pub fn analyze(&self) -> Analysis {
+        let graph = self.explore();
+        let mut analysis = Analysis::default();
+        let mut ends = vec![];
+        for state in (0..STATES)
+            .filter(|&idx| graph.reached[idx])
+            .map(State::from_index)
+        {
+            if !state.stringmode {
+                match self.playfield[state.y][state.x] {
+                    Command::End => ends.push(state.index()),
+                    Command::Put => analysis.self_modifying = true,
+                    Command::Char(_) => analysis.unknown.push(state.pos()),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut can_end = vec![false; STATES];
+        for &idx in &ends {
+            can_end[idx] = true;
+        }
+        while let Some(idx) = ends.pop() {
+            for &prev in &graph.predecessors[idx] {
+                if !can_end[prev] {
+                    can_end[prev] = true;
+                    ends.push(prev);
+                }
+            }
+        }
+
+        let size = self.get_size();
+        for y in 0..size.rows {
+            for x in 0..size.cols {
+                if self.playfield[y][x] == Command::Space {
+                    continue;
+                }
+                let pos = Position { x, y };
+                let mut states = graph.states_at(pos).peekable();
+                if states.peek().is_none() {
+                    analysis.unreachable.push(pos);
+                } else if !states.any(|state| can_end[state.index()]) {
+                    analysis.never_ending.push(pos);
+                }
+            }
+        }
+
+        analysis.unknown.sort_by_key(|pos| (pos.y, pos.x));
+        analysis.unknown.dedup();
+
+        let loops = loops(&graph.reached, &graph.successors);
+        analysis.cycles = loops
+            .iter()
+            .filter(|l| l.closed && l.states.iter().all(|&idx| !can_end[idx]))
+            .map(|l| cells(&l.states))
+            .collect();
+        analysis.loops = loops.iter().map(|l| cells(&l.states)).collect();
+        analysis
+    }
+
+    /// Find every state reachable from the start.
+    pub(crate) fn explore(&self) -> Graph {
+        let start = State {
+            x: 0,
+            y: 0,
+            dir: Direction::Right,
+            stringmode: false,
+        };
+        let mut graph = Graph {
+            reached: vec![false; STATES],
+            successors: vec![vec![]; STATES],
+            predecessors: vec![vec![]; STATES],
+        };
+
+        graph.reached[start.index()] = true;
+        let mut queue = vec![start];
+        while let Some(state) = queue.pop() {
+            for next in self.next_states(state) {
+                graph.successors[state.index()].push(next.index());
+                graph.predecessors[next.index()].push(state.index());
+                if !graph.reached[next.index()] {
+                    graph.reached[next.index()] = true;
+                    queue.push(next);
+                }
+            }
+        }
+        graph
+    }
+
+    /// The states the PC can be in after executing the cell of `state`.
+    fn next_states(&self, state: State) -> Vec<State> {
+        let cmd = self.playfield[state.y][state.x];
+        let dir = state.dir;
+        let size = self.get_size();
+        if state.stringmode {
+            return vec![state.leave(dir, cmd != Command::Str, 0, size)];
+        }
+        let go = |dir| state.leave(dir, false, 0, size);
+        match cmd {
+            Command::Right => vec![go(Direction::Right)],
+            Command::Left => vec![go(Direction::Left)],
+            Command::Up => vec![go(Direction::Up)],
+            Command::Down => vec![go(Direction::Down)],
+            Command::Rand => DIRECTIONS.iter().map(|&d| go(d)).collect(),
+            Command::IfH => vec![go(Direction::Right), go(Direction::Left)],
+            Command::IfV => vec![go(Direction::Down), go(Direction::Up)],
+            Command::Str => vec![state.leave(dir, true, 0, size)],
+            Command::Bri => vec![state.leave(dir, false, 1, size)],
+            Command::End => vec![],
+            Command::Char(_) => match self.semantics.unknown {
+                Unknown::Error => vec![],
+                Unknown::Nop => vec![go(dir)],
+                Unknown::Reflect => vec![go(dir.reverse())],
+            },
+            _ => vec![go(dir)],
+        }
+    }
+}
+
+/// The cells of `states`, row by row.
+fn cells(states: &[usize]) -> Vec<Position> {
+    let cells: BTreeSet<_> = states
+        .iter()
+        .map(|&idx| {
+            let pos = State::from_index(idx).pos();
+            (pos.y, pos.x)
+        })
+        .collect();
+    cells.into_iter().map(|(y, x)| Position { x, y }).collect()
+}
+
+/// A strongly connected component of the reached states containing at
+/// least one edge, i.e. a loop.
+struct Loop {
+    states: Vec<usize>,
+    /// Whether no edges leave it, so it can't be left.
+    closed: bool,
+}
+
+/// The loops among the reached states. Uses an iterative version of
+/// Tarjan's algorithm.
+fn loops(reached: &[bool], successors: &[Vec<usize>]) -> Vec<Loop> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; STATES];
+    let mut lowlink = vec![0; STATES];
+    let mut on_stack = vec![false; STATES];
+    let mut component = vec![UNVISITED; STATES];
+    let mut stack = vec![];
+    let mut components: Vec<Vec<usize>> = vec![];
+    let mut counter = 0;
+
+    for root in (0..STATES).filter(|&idx| reached[idx]) {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // Frames of the depth-first search: a state and its next edge.
+        let mut frames = vec![(root, 0)];
+        index[root] = counter;
+        lowlink[root] = counter;
+        counter += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(frame) = frames.last_mut() {
+            let v = frame.0;
+            if let Some(&w) = successors[v].get(frame.1) {
+                frame.1 += 1;
+                if index[w] == UNVISITED {
+                    index[w] = counter;
+                    lowlink[w] = counter;
+                    counter += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    frames.push((w, 0));
+                } else if on_stack[w] {
+                    lowlink[v] = lowlink[v].min(index[w]);
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                lowlink[parent] = lowlink[parent].min(lowlink[v]);
+            }
+            if lowlink[v] == index[v] {
+                let mut states = vec![];
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    component[w] = components.len();
+                    states.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(states);
+            }
+        }
+    }
+
+    components
+        .into_iter()
+        .enumerate()
+        .filter_map(|(id, states)| {
+            let edges = states.iter().flat_map(|&s| &successors[s]);
+            let inner = edges.clone().filter(|&&w| component[w] == id).count();
+            let closed = edges.count() == inner;
+            (inner > 0).then_some(Loop { states, closed })
+        })
+        .collect()
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+use crate::{Interpreter, Position};
+
+/// What the stack is expected to look like when the PC reaches a cell.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StackShape {
+    /// Exact number of values, if checked.
+    pub depth: Option<usize>,
+    /// Least number of values, if checked.
+    pub min_depth: Option<usize>,
+    /// Values expected on top of the stack, top first.
+    pub top: Vec<i64>,
+}
+
+impl StackShape {
+    /// A shape every stack has.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect exactly `depth` values.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Expect at least `depth` values.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = Some(depth);
+        self
+    }
+
+    /// Expect `values` on top of the stack, top first. Missing values count
+    /// as zeros, as when popping an empty stack.
+    pub fn top(mut self, values: &[i64]) -> Self {
+        self.top = values.to_vec();
+        self
+    }
+
+    /// Whether `stack`, bottom first, has this shape.
+    pub fn matches(&self, stack: &[i64]) -> bool {
+        let top = stack.iter().rev().copied().chain(std::iter::repeat(0));
+        self.depth.is_none_or(|d| stack.len() == d)
+            && self.min_depth.is_none_or(|d| stack.len() >= d)
+            && self.top.iter().zip(top).all(|(&want, got)| want == got)
+    }
+}
+
+impl fmt::Display for StackShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(depth) = self.depth {
+            parts.push(format!("depth {}", depth));
+        }
+        if let Some(depth) = self.min_depth {
+            parts.push(format!("depth at least {}", depth));
+        }
+        if !self.top.is_empty() {
+            let top: Vec<_> = self.top.iter().map(|v| v.to_string()).collect();
+            parts.push(format!("top [{}]", top.join(" ")));
+        }
+        if parts.is_empty() {
+            parts.push("any stack".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// A stack that did not have the expected shape at a checkpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The checkpoint.
+    pub pos: Position,
+    /// The shape declared for it.
+    pub expected: StackShape,
+    /// The stack found there, bottom first.
+    pub actual: Vec<i64>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let actual: Vec<_> = self.actual.iter().map(|v| v.to_string()).collect();
+        write!(
+            f,
+            "Expected {} at {}, found [{}]",
+            self.expected,
+            self.pos,
+            actual.join(" ")
+        )
+    }
+}
+
+/// Stack shapes declared at checkpoints, and the violations found so far.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Assertions {
+    shapes: HashMap<Position, StackShape>,
+    violations: Vec<Violation>,
+    fail: bool,
+}
+
+impl Assertions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+}
+
+impl Interpreter {
+    /// Check the stack has `shape` whenever the PC reaches `pos`, before
+    /// the cell is executed. Replaces any shape declared there before.
+    pub fn expect_stack(&mut self, pos: Position, shape: StackShape) {
+        self.assertions.shapes.insert(pos, shape);
+    }
+
+    /// Remove all stack shapes declared with `expect_stack`.
+    pub fn clear_stack_expectations(&mut self) {
+        self.assertions.shapes.clear();
+    }
+
+    /// Whether a violated stack shape stops the run with an error, rather
+    /// than only being recorded.
+    pub fn set_fail_on_violation(&mut self, fail: bool) {
+        self.assertions.fail = fail;
+    }
+
+    /// The stack shape violations of the current run, in order.
+    pub fn get_violations(&self) -> &[Violation] {
+        &self.assertions.violations
+    }
+
+    /// Check the stack against the shape declared at the PC, if any.
+    pub(crate) fn check_stack(&mut self) -> Result<()> {
+        let pos = self.get_position();
+        let shape = match self.assertions.shapes.get(&pos) {
+            Some(shape) => shape,
+            None => return Ok(()),
+        };
+        let stack = self.stack.values();
+        if shape.matches(&stack) {
+            return Ok(());
+        }
+
+        let violation = Violation {
+            pos,
+            expected: shape.clone(),
+            actual: stack,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%violation, "stack shape violated");
+        let message = violation.to_string();
+        self.assertions.violations.push(violation);
+        if self.assertions.fail {
+            bail!(message);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn clear_violations(&mut self) {
+        self.assertions.violations.clear();
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::input::InputQueue;
+use crate::replay::Session;
+use crate::{Command, Interpreter, NumberScan, StepResult};
+
+/// How many steps to run before yielding to other tasks.
+const STEPS_PER_YIELD: usize = 1024;
+
+/// Runs an interpreter with input from an `AsyncRead` and output to an
+/// `AsyncWrite`, so waiting on I/O does not block a thread.
+pub struct AsyncInterpreter<R, W> {
+    interpreter: Interpreter,
+    input: R,
+    output: W,
+    buffer: InputQueue,
+}
+
+impl<R, W> AsyncInterpreter<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    /// Wrap `interpreter`, replacing its input with `input`.
+    pub fn new(mut interpreter: Interpreter, input: R, output: W) -> Self {
+        let buffer = InputQueue::new();
+        interpreter.set_input(buffer.clone());
+        Self {
+            interpreter,
+            input,
+            output,
+            buffer,
+        }
+    }
+
+    /// Inspect the wrapped interpreter.
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    /// Unwrap into the interpreter, input and output.
+    pub fn into_inner(self) -> (Interpreter, R, W) {
+        (self.interpreter, self.input, self.output)
+    }
+
+    /// Run the program to completion.
+    pub async fn run(&mut self) -> Result<()> {
+        self.run_with(|_, _| true).await
+    }
+
+    /// Run the program, calling `f` after every step like
+    /// `Interpreter::run`.
+    pub async fn run_with(&mut self, mut f: impl FnMut(&Interpreter, usize) -> bool) -> Result<()> {
+        self.interpreter.reset();
+
+        let mut iter_n = 0;
+        let mut written = 0;
+
+        loop {
+            self.fill().await?;
+            let result = self.interpreter.step_in_context()?;
+
+            let output = self.interpreter.output_since(written);
+            if !output.text.is_empty() {
+                self.output
+                    .write_all(output.text.as_bytes())
+                    .await
+                    .context("Writing output")?;
+                self.output.flush().await.context("Flushing output")?;
+                written = output.next;
+            }
+
+            if result == StepResult::Stop {
+                break;
+            }
+
+            iter_n += 1;
+            if !f(&self.interpreter, iter_n) {
+                break;
+            }
+            if iter_n % STEPS_PER_YIELD == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read ahead the input the next step is going to consume.
+    async fn fill(&mut self) -> Result<()> {
+        let int = &self.interpreter;
+        if int.stringmode || matches!(int.session, Session::Replaying(..)) {
+            return Ok(());
+        }
+
+        let mut pending = self.buffer.bytes();
+        pending.splice(0..0, int.unread);
+        match int.get_current_command() {
+            Command::InC if pending.is_empty() => {
+                self.read_byte().await?;
+            }
+            Command::InI => {
+                let mut scan = NumberScan::default();
+                if !scan.is_complete(pending) {
+                    while let Some(b) = self.read_byte().await? {
+                        if !scan.feed(b) {
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Move a byte from the input to the buffer. Returns `None` at EOF.
+    async fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut buf = [0; 1];
+        let n = self.input.read(&mut buf).await.context("Reading a byte")?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.buffer.push(&buf);
+        Ok(Some(buf[0]))
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Cursor, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use ansi_term::Colour::{Black, Fixed};
+use anyhow::{anyhow, Context, Result};
+use crossterm::cursor::{self, MoveTo};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{execute, Command};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use structopt::StructOpt;
+
+use befunge_93::generator::{self, GeneratorOptions};
+use befunge_93::packs::Pack;
+use befunge_93::{Interpreter, Position};
+
+use crate::profile::heat;
+use crate::style::{self, theme};
+use crate::{new_interpreter, read_program_of, terminal, Opts};
+
+/// Generated programs that end within this many steps aren't shown.
+const MIN_STEPS: usize = 50;
+
+#[derive(StructOpt)]
+pub struct ArtOpts {
+    /// Programs to show in turn. Random ones are generated if none are given.
+    files: Vec<PathBuf>,
+    #[structopt(long, default_value = "3000")]
+    /// Steps each program is run for at most.
+    max_steps: usize,
+    #[structopt(long, default_value = "10")]
+    /// Steps between frames.
+    steps_per_frame: usize,
+    #[structopt(long, default_value = "40")]
+    /// Delay between frames (in milliseconds).
+    delay: u64,
+    #[structopt(long, default_value = "16")]
+    /// Number of cells the PC leaves a fading trail on.
+    trail: usize,
+    #[structopt(long)]
+    /// Stop after this many programs instead of looping forever.
+    count: Option<usize>,
+    #[structopt(long)]
+    /// Seed for generating programs.
+    seed: Option<u64>,
+}
+
+pub fn main(opts: &Opts, art_opts: &ArtOpts) -> Result<()> {
+    let mut rng = match art_opts.seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    };
+    let mut gen_opts = GeneratorOptions {
+        cols: 40,
+        rows: 12,
+        ..GeneratorOptions::default()
+    };
+    // Programs wait for no input.
+    gen_opts.weights.remove(&'&');
+    gen_opts.weights.remove(&'~');
+
+    let session = terminal::Session::start().context("Failed to set up the terminal")?;
+    execute!(io::stdout(), cursor::Hide, Clear(ClearType::All))?;
+    let mut shown = 0;
+    let result = loop {
+        if art_opts.count.is_some_and(|count| shown >= count) {
+            break Ok(());
+        }
+        let (name, source) = if art_opts.files.is_empty() {
+            let source = generator::generate(&gen_opts, &mut rng)?;
+            ("random program".to_string(), source.into_bytes())
+        } else {
+            let path = &art_opts.files[shown % art_opts.files.len()];
+            (path.display().to_string(), read_program_of(opts, path)?)
+        };
+
+        let mut interpreter = new_interpreter(opts);
+        interpreter
+            .load(&mut &source[..])
+            .with_context(|| anyhow!("Failed to load {}", name))?;
+        if art_opts.files.is_empty() && ends_early(opts, &source) {
+            continue;
+        }
+        interpreter.set_input(Cursor::new(vec![]));
+        if let Err(e) = show(&mut interpreter, &name, art_opts, &opts.packs) {
+            break Err(e);
+        }
+        shown += 1;
+    };
+    if session.is_none() {
+        execute!(io::stdout(), cursor::Show)?;
+    }
+    result
+}
+
+/// Whether the program `source` ends within `MIN_STEPS` steps.
+fn ends_early(opts: &Opts, source: &[u8]) -> bool {
+    let mut copy = new_interpreter(opts);
+    if copy.load(&mut &source[..]).is_err() {
+        return true;
+    }
+    copy.set_input(Cursor::new(vec![]));
+    let mut ended = true;
+    let _ = copy.run(|_, steps| {
+        ended = steps < MIN_STEPS;
+        ended
+    });
+    ended
+}
+
+/// Animate a run of `interpreter`, the program called `name`, which may use
+/// the instructions of `packs`.
+fn show(
+    interpreter: &mut Interpreter,
+    name: &str,
+    art_opts: &ArtOpts,
+    packs: &[&Pack],
+) -> Result<()> {
+    let mut trail = VecDeque::with_capacity(art_opts.trail + 1);
+    let mut write_error = None;
+    let result = interpreter.run(|int, steps| {
+        trail.push_front(int.get_position());
+        trail.truncate(art_opts.trail.max(1));
+        if steps % art_opts.steps_per_frame.max(1) != 0 {
+            return steps < art_opts.max_steps;
+        }
+        let frame = render(int, name, steps, &trail, packs);
+        if let Err(e) = io::stdout().write_all(frame.as_bytes()) {
+            write_error = Some(e);
+            return false;
+        }
+        let _ = io::stdout().flush();
+        thread::sleep(Duration::from_millis(art_opts.delay));
+        steps < art_opts.max_steps
+    });
+    if let Some(e) = write_error {
+        return Err(e).context("Failed to draw");
+    }
+
+    let ending = if result.is_err() { " (failed)" } else { "" };
+    let frame = render(
+        interpreter,
+        &format!("{}{}", name, ending),
+        0,
+        &trail,
+        packs,
+    );
+    print!("{}", frame);
+    io::stdout().flush()?;
+    thread::sleep(Duration::from_millis(art_opts.delay * 10));
+    Ok(())
+}
+
+/// A frame showing the playfield with cells coloured by how often they were
+/// executed and the PC's trail on top, followed by the output. The emoji of
+/// pack instructions on the trail light up next to the title.
+fn render(
+    int: &Interpreter,
+    name: &str,
+    steps: usize,
+    trail: &VecDeque<Position>,
+    packs: &[&Pack],
+) -> String {
+    let profile = int.get_profile();
+    let max = profile.iter().flatten().copied().max().unwrap_or(0);
+    // The code, and wherever the PC wandered off to.
+    let mut rows: Vec<String> = (0..int.get_size().rows).map(|y| int.get_row(y)).collect();
+    let height = trail.iter().map(|pos| pos.y + 1).max().unwrap_or(0);
+    while rows.len() > height && rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .chain(trail.iter().map(|pos| pos.x + 1))
+        .max()
+        .unwrap_or(0);
+
+    let mut frame = String::new();
+    let _ = MoveTo(0, 0).write_ansi(&mut frame);
+    let mut clear_line = String::new();
+    let _ = Clear(ClearType::UntilNewLine).write_ansi(&mut clear_line);
+    let mut clear_below = String::new();
+    let _ = Clear(ClearType::FromCursorDown).write_ansi(&mut clear_below);
+    let mut title = if steps > 0 {
+        format!("{} - step {}", name, steps)
+    } else {
+        name.to_string()
+    };
+    let mut lit = vec![];
+    for pos in trail {
+        let c = rows[pos.y].chars().nth(pos.x).unwrap_or(' ');
+        let emoji = packs
+            .iter()
+            .find_map(|pack| pack.lookup(c))
+            .map(|i| i.emoji);
+        if let Some(emoji) = emoji.filter(|emoji| !lit.contains(emoji)) {
+            lit.push(emoji);
+        }
+    }
+    if !lit.is_empty() {
+        title = format!("{} {}", title, lit.concat());
+    }
+    let _ = writeln!(frame, "{}{}\n", theme().strong.paint(title), clear_line);
+    let colours = style::colours();
+    for (y, row) in rows.iter().enumerate() {
+        let mut chars: Vec<char> = row.chars().collect();
+        chars.resize(width, ' ');
+        let mut line = String::new();
+        for (x, c) in chars.into_iter().enumerate() {
+            let pos = Position { x, y };
+            let cell = c.to_string();
+            let styled = match trail.iter().position(|&p| p == pos).filter(|_| colours) {
+                // From white at the PC down the greyscale ramp.
+                Some(age) => {
+                    let shade = 255 - (age * 23 / trail.len().max(1)) as u8;
+                    Black.on(Fixed(shade)).paint(cell)
+                }
+                None => match heat(profile[y][x], max).filter(|_| colours) {
+                    Some(colour) => Black.on(colour).paint(cell),
+                    None => theme().faint.paint(cell),
+                },
+            };
+            line += &styled.to_string();
+        }
+        let _ = writeln!(frame, "{}{}", line, clear_line);
+    }
+    let output = int.get_output();
+    let last = output.lines().last().unwrap_or("");
+    let last: String = last.chars().rev().take(width.max(40)).collect();
+    let last: String = last.chars().rev().collect();
+    let _ = write!(frame, "\n{:?}{}{}", last, clear_line, clear_below);
+    frame
+}
@@ -0,0 +1,167 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::corpus;
+use befunge_93::summary::{HaltReason, RunSummary};
+
+use crate::style::theme;
+use crate::{new_interpreter, read_all_input, read_program_of, Opts};
+
+/// Steps each program may take unless `--max-steps` says otherwise, so
+/// that a program that never ends doesn't stop the batch.
+const DEFAULT_MAX_STEPS: usize = 10_000_000;
+
+#[derive(StructOpt)]
+pub struct BatchOpts {
+    #[structopt(short, long = "file", number_of_values = 1, required = true)]
+    /// Program to run, or a directory of programs. May be given more than
+    /// once.
+    files: Vec<PathBuf>,
+    #[structopt(short, long)]
+    /// Also run programs in subdirectories of the directories given.
+    recursive: bool,
+    #[structopt(short, long)]
+    /// Print only the summary table, not the output of each program.
+    quiet: bool,
+}
+
+/// Run every program given, one after the other with the same input,
+/// printing their output and then a table of how each run went. Exits with
+/// a failure code if any of them didn't end normally.
+pub fn main(opts: &Opts, batch_opts: &BatchOpts) -> Result<()> {
+    let mut paths = vec![];
+    for path in &batch_opts.files {
+        if path.is_dir() {
+            paths.extend(
+                corpus::find_programs(path, batch_opts.recursive)
+                    .with_context(|| anyhow!("Failed to list '{}'", path.display()))?,
+            );
+        } else {
+            paths.push(path.clone());
+        }
+    }
+    // Every program reads the same input, which is read up front.
+    let input = read_all_input(opts)?;
+
+    let mut summaries = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let summary = run(opts, path, &input);
+        if !batch_opts.quiet {
+            println!("{}", theme().strong.paint(path.display().to_string()));
+            if !summary.output.is_empty() {
+                println!("{}", summary.output.trim_end_matches('\n'));
+            }
+            if let Some(error) = &summary.error {
+                println!("{} {}", theme().error.paint("Error:"), error);
+            }
+            println!();
+        }
+        summaries.push(summary);
+    }
+
+    print_table(&paths, &summaries);
+    if summaries.iter().any(|s| s.halt_reason != HaltReason::End) {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Run the program at `path` with `input`, never failing: errors loading
+/// it are reported in the summary too.
+fn run(opts: &Opts, path: &Path, input: &[u8]) -> RunSummary {
+    let started = Instant::now();
+    let mut interpreter = new_interpreter(opts);
+    let loaded = read_program_of(opts, path).and_then(|source| {
+        interpreter
+            .load(&mut &source[..])
+            .context("Failed to load program")
+    });
+    if let Err(e) = loaded {
+        return RunSummary {
+            halt_reason: HaltReason::Error,
+            output: String::new(),
+            stack: vec![],
+            steps: 0,
+            elapsed_ms: 0,
+            error: Some(format!("{:#}", e)),
+            stats: None,
+        };
+    }
+    interpreter.set_input(Cursor::new(input.to_vec()));
+
+    let max_steps = opts.max_steps.unwrap_or(DEFAULT_MAX_STEPS);
+    let mut limited = false;
+    let result = interpreter.run_with_deadline(opts.time_limit(), |_, steps| {
+        limited = steps >= max_steps;
+        !limited
+    });
+    let (halt_reason, error) = match result {
+        Ok(progress) if progress.timed_out => (
+            HaltReason::TimeLimit,
+            Some(format!("Stopped after {} ms", progress.elapsed.as_millis())),
+        ),
+        Ok(_) if limited => (
+            HaltReason::StepLimit,
+            Some(format!("Stopped after {} steps", max_steps)),
+        ),
+        Ok(_) => (HaltReason::End, None),
+        Err(e) => (HaltReason::Error, Some(format!("{:#}", e))),
+    };
+    RunSummary {
+        halt_reason,
+        output: interpreter.get_output().to_string(),
+        stack: interpreter.get_stack().values(),
+        steps: interpreter.get_steps(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        error,
+        stats: None,
+    }
+}
+
+fn print_table(paths: &[PathBuf], summaries: &[RunSummary]) {
+    let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0).max(7);
+    println!(
+        "{}",
+        theme().strong.paint(format!(
+            "{:<width$}  {:>10}  {:<10}  error",
+            "program",
+            "steps",
+            "halt",
+            width = width
+        ))
+    );
+    for (name, summary) in names.iter().zip(summaries) {
+        let halt = format!("{:<10}", summary.halt_reason.name());
+        let halt = match summary.halt_reason {
+            HaltReason::End => theme().ok.paint(halt),
+            HaltReason::Error => theme().error.paint(halt),
+            HaltReason::StepLimit | HaltReason::TimeLimit => theme().warning.paint(halt),
+        };
+        let line = format!(
+            "{:<width$}  {:>10}  {}  {}",
+            name,
+            summary.steps,
+            halt,
+            summary.error.as_deref().unwrap_or(""),
+            width = width
+        );
+        println!("{}", line.trim_end());
+    }
+
+    let ended = summaries
+        .iter()
+        .filter(|s| s.halt_reason == HaltReason::End)
+        .count();
+    println!(
+        "\n{} programs: {} ended, {} didn't",
+        summaries.len(),
+        ended,
+        summaries.len() - ended
+    );
+}
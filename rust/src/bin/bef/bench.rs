@@ -0,0 +1,87 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::StructOpt;
+
+use crate::style::theme;
+use crate::{load, read_all_input, Opts};
+
+#[derive(StructOpt)]
+pub struct BenchOpts {
+    #[structopt(short, long)]
+    /// Path to program file.
+    file: PathBuf,
+    #[structopt(short = "n", long, default_value = "10")]
+    /// Number of timed runs.
+    iterations: usize,
+    #[structopt(long, default_value = "1")]
+    /// Runs before the timed ones, to warm up caches.
+    warmup: usize,
+}
+
+pub fn main(opts: &Opts, bench_opts: &BenchOpts) -> Result<()> {
+    if bench_opts.iterations == 0 {
+        bail!("Expected at least one iteration");
+    }
+    let path = &bench_opts.file;
+    let mut interpreter = load(opts, path);
+    // Every run reads the same input, which is read up front.
+    let input = read_all_input(opts)?;
+
+    let mut times = Vec::with_capacity(bench_opts.iterations);
+    let mut steps = 0;
+    for iteration in 0..bench_opts.warmup + bench_opts.iterations {
+        interpreter.set_input(Cursor::new(input.clone()));
+        let mut limited = false;
+        let started = Instant::now();
+        let result = interpreter.run(|_, n| {
+            limited = opts.over_step_limit(n);
+            !limited
+        });
+        let elapsed = started.elapsed();
+        let result = opts.limit_steps(result, limited);
+        if let Err(e) = result.with_context(|| anyhow!("Run {} failed", iteration + 1)) {
+            opts.report(path, interpreter.diagnose(&e));
+        }
+        if iteration >= bench_opts.warmup {
+            times.push(elapsed);
+            steps = interpreter.get_steps();
+        }
+    }
+
+    let total: Duration = times.iter().sum();
+    let mean = total / times.len() as u32;
+    times.sort();
+    let median = times[times.len() / 2];
+    let variance = times
+        .iter()
+        .map(|t| (t.as_secs_f64() - mean.as_secs_f64()).powi(2))
+        .sum::<f64>()
+        / times.len() as f64;
+
+    println!(
+        "{} {} iterations of {} steps",
+        theme().strong.paint(path.display().to_string()),
+        times.len(),
+        steps
+    );
+    println!(
+        "  time    mean {}  median {}  min {}  max {}  stddev {}",
+        ms(mean.as_secs_f64()),
+        ms(median.as_secs_f64()),
+        ms(times[0].as_secs_f64()),
+        ms(times[times.len() - 1].as_secs_f64()),
+        ms(variance.sqrt())
+    );
+    println!(
+        "  speed   {:.0} steps/s",
+        (steps * times.len()) as f64 / total.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn ms(secs: f64) -> String {
+    format!("{:.3} ms", secs * 1000.0)
+}
@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process;
+
+use anyhow::{anyhow, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::bisect::{bisect, Outcome, Status};
+use befunge_93::replay::Recording;
+use befunge_93::runner::Snapshot;
+use befunge_93::schema::Versioned;
+use befunge_93::semantics::Semantics;
+use befunge_93::Interpreter;
+
+use crate::style::theme;
+
+#[derive(StructOpt)]
+pub struct BisectOpts {
+    /// Path to program file.
+    file: PathBuf,
+    #[structopt(long, default_value = "")]
+    /// Semantics of the left side, as comma-separated `key=value` overrides.
+    left: Semantics,
+    #[structopt(long, default_value = "")]
+    /// Semantics of the right side, as comma-separated `key=value` overrides.
+    right: Semantics,
+    #[structopt(long)]
+    /// Recording to replay on both sides; otherwise the input of a run of
+    /// the left side is recorded first.
+    replay: Option<PathBuf>,
+    #[structopt(long, default_value = "1000")]
+    /// Steps between checkpoints.
+    interval: usize,
+    #[structopt(long, default_value = "10000000")]
+    /// Give up after this many steps.
+    max_steps: usize,
+    #[structopt(long)]
+    /// Print the result as JSON.
+    json: bool,
+}
+
+fn interpreter(program: &[u8], semantics: Semantics) -> Result<Interpreter> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_semantics(semantics);
+    interpreter
+        .load(&mut &program[..])
+        .context("Failed to load program")?;
+    Ok(interpreter)
+}
+
+fn print_state(prefix: &str, state: &Snapshot) -> String {
+    let stack: Vec<_> = state.stack.iter().map(|v| v.to_string()).collect();
+    format!(
+        "{}at {} moving {}, stack [{}], output {:?}",
+        prefix,
+        state.position,
+        state.direction,
+        stack.join(" "),
+        state.output
+    )
+}
+
+fn print_outcome(name: &str, outcome: &Outcome) -> String {
+    let status = match &outcome.status {
+        Status::Running => "running".to_string(),
+        Status::Halted => "halted".to_string(),
+        Status::Failed(e) => format!("failed ({})", e),
+    };
+    print_state(&format!("{:<7} {} ", name, status), &outcome.state)
+}
+
+pub fn main(opts: &BisectOpts) -> Result<()> {
+    let program = fs::read(&opts.file)
+        .with_context(|| anyhow!("Failed to open '{}'", opts.file.display()))?;
+
+    let recording = match &opts.replay {
+        Some(path) => {
+            let file =
+                File::open(path).with_context(|| anyhow!("Failed to open '{}'", path.display()))?;
+            Recording::read(BufReader::new(file))
+                .with_context(|| anyhow!("Failed to read recording '{}'", path.display()))?
+        }
+        None => {
+            let mut recorder = interpreter(&program, opts.left)?;
+            recorder.record();
+            // Whatever the outcome, the recording covers the input used.
+            let _ = recorder.run(|_, steps| steps < opts.max_steps);
+            recorder.take_recording().expect("recording")
+        }
+    };
+
+    let mut left = interpreter(&program, opts.left)?;
+    let mut right = interpreter(&program, opts.right)?;
+    left.replay(recording.clone());
+    right.replay(recording);
+
+    let divergence = bisect(&mut left, &mut right, opts.interval, opts.max_steps);
+
+    if opts.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&Versioned::new(&divergence))?
+        );
+    } else {
+        match &divergence {
+            None => println!("No divergence"),
+            Some(d) => {
+                println!("Diverging at step {}:", d.step);
+                println!("{}", print_state("  before       ", &d.before));
+                println!("{}", theme().error.paint(print_outcome("< left", &d.left)));
+                println!("{}", theme().ok.paint(print_outcome("> right", &d.right)));
+            }
+        }
+    }
+
+    if divergence.is_some() {
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+//! Shared debugging sessions over TCP. A host runs the program and others
+//! attach to watch it; whoever holds the turn issues debugger commands.
+//!
+//! The protocol is experimental: clients send commands as lines of text
+//! and the host answers with JSON lines, each a `ServerMsg`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use ansi_term::Style;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use befunge_93::stepper::Stepper;
+use befunge_93::Position;
+
+use crate::style::theme;
+use crate::{load, Opts};
+
+/// Most steps a single `run` command takes.
+const MAX_RUN: usize = 1_000_000;
+
+#[derive(StructOpt)]
+pub enum CollabCmd {
+    /// Host a session debugging a program.
+    Host {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long, default_value = "127.0.0.1:7093")]
+        /// Address to listen on.
+        listen: String,
+    },
+    /// Attach to a session, reading commands from stdin.
+    Attach {
+        #[structopt(default_value = "127.0.0.1:7093")]
+        /// Address of the host.
+        addr: String,
+    },
+}
+
+/// What everyone attached to a session sees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    pub steps: usize,
+    pub position: Position,
+    pub direction: String,
+    /// Bottom first.
+    pub stack: Vec<i64>,
+    pub output: String,
+    pub playfield: Vec<String>,
+    pub breakpoints: Vec<Position>,
+    pub halted: bool,
+    pub error: Option<String>,
+    /// Client holding the turn, if any.
+    pub driver: Option<usize>,
+}
+
+/// A line sent by the host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServerMsg {
+    /// Sent once on attaching.
+    Welcome {
+        client: usize,
+    },
+    State(SessionState),
+    /// Something happened, e.g. a command failed or a client left.
+    Notice(String),
+}
+
+enum Event {
+    Joined(usize, TcpStream),
+    Line(usize, String),
+    Left(usize),
+}
+
+struct Session {
+    stepper: Stepper,
+    breakpoints: HashSet<Position>,
+    error: Option<String>,
+    driver: Option<usize>,
+    clients: HashMap<usize, TcpStream>,
+}
+
+pub fn main(opts: &Opts, cmd: &CollabCmd) -> Result<()> {
+    match cmd {
+        CollabCmd::Host { file, listen } => host(opts, file, listen),
+        CollabCmd::Attach { addr } => attach(addr),
+    }
+}
+
+fn host(opts: &Opts, path: &Path, addr: &str) -> Result<()> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| anyhow!("Failed to listen on {}", addr))?;
+    eprintln!(
+        "Hosting '{}' on {}; attach with `bef collab attach {}`",
+        path.display(),
+        listener.local_addr()?,
+        listener.local_addr()?
+    );
+    let mut session = Session {
+        stepper: Stepper::new(load(opts, path)),
+        breakpoints: HashSet::new(),
+        error: None,
+        driver: None,
+        clients: HashMap::new(),
+    };
+
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || accept(listener, sender));
+    for event in events {
+        match event {
+            Event::Joined(id, stream) => {
+                eprintln!("Client {} attached", id);
+                session.clients.insert(id, stream);
+                session.send(id, &ServerMsg::Welcome { client: id });
+                let state = ServerMsg::State(session.state());
+                session.send(id, &state);
+            }
+            Event::Line(id, line) => {
+                eprintln!("Client {}: {}", id, line);
+                match session.command(id, &line) {
+                    Ok(()) => session.broadcast(&ServerMsg::State(session.state())),
+                    Err(e) => session.send(id, &ServerMsg::Notice(e.to_string())),
+                }
+            }
+            Event::Left(id) => {
+                eprintln!("Client {} left", id);
+                session.clients.remove(&id);
+                if session.driver == Some(id) {
+                    session.driver = None;
+                    session.broadcast(&ServerMsg::Notice(format!(
+                        "Client {} left; the turn is free",
+                        id
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Accept clients, passing on what they send as events.
+fn accept(listener: TcpListener, events: Sender<Event>) {
+    for (id, stream) in listener.incoming().flatten().enumerate() {
+        let id = id + 1;
+        let writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_) => continue,
+        };
+        if events.send(Event::Joined(id, writer)).is_err() {
+            return;
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if events.send(Event::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::Left(id));
+        });
+    }
+}
+
+impl Session {
+    fn state(&self) -> SessionState {
+        let int = self.stepper.interpreter();
+        let mut playfield: Vec<_> = (0..int.get_size().rows).map(|y| int.get_row(y)).collect();
+        let pc = int.get_position();
+        while playfield.len() > pc.y + 1 && playfield.last().is_some_and(|r| r.is_empty()) {
+            playfield.pop();
+        }
+        SessionState {
+            steps: self.stepper.steps(),
+            position: pc,
+            direction: int.get_direction().to_string(),
+            stack: int.get_stack().values(),
+            output: int.get_output().to_string(),
+            playfield,
+            breakpoints: self.breakpoints.iter().copied().collect(),
+            halted: self.stepper.halted(),
+            error: self.error.clone(),
+            driver: self.driver,
+        }
+    }
+
+    fn send(&mut self, id: usize, msg: &ServerMsg) {
+        let line = serde_json::to_string(msg).expect("serializable message") + "\n";
+        if let Some(stream) = self.clients.get_mut(&id) {
+            // A client that can't be written to is dropped when its reader
+            // notices.
+            let _ = stream.write_all(line.as_bytes());
+        }
+    }
+
+    fn broadcast(&mut self, msg: &ServerMsg) {
+        let ids: Vec<_> = self.clients.keys().copied().collect();
+        for id in ids {
+            self.send(id, msg);
+        }
+    }
+
+    /// Carry out the command `line` sent by client `id`.
+    fn command(&mut self, id: usize, line: &str) -> Result<()> {
+        let (cmd, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match cmd {
+            "take" => {
+                match self.driver {
+                    Some(driver) if driver != id => {
+                        bail!("Client {} holds the turn", driver)
+                    }
+                    _ => self.driver = Some(id),
+                }
+                return Ok(());
+            }
+            "release" => {
+                if self.driver == Some(id) {
+                    self.driver = None;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        if self.driver != Some(id) {
+            bail!("Take the turn with `take` before issuing commands");
+        }
+
+        match cmd {
+            "step" | "s" => {
+                let n = if arg.is_empty() { 1 } else { arg.parse()? };
+                self.advance(n, false)
+            }
+            "run" | "r" => self.advance(MAX_RUN, true),
+            "break" | "b" => {
+                let (x, y) = arg.split_once(',').context("Expected break X,Y")?;
+                let pos = Position {
+                    x: x.trim().parse()?,
+                    y: y.trim().parse()?,
+                };
+                if !self.breakpoints.remove(&pos) {
+                    self.breakpoints.insert(pos);
+                }
+                Ok(())
+            }
+            "input" | "i" => {
+                self.stepper.push_input(format!("{}\n", arg).as_bytes());
+                Ok(())
+            }
+            _ => bail!(
+                "Unknown command '{}'; expected take, release, step [N], run, break X,Y or \
+                 input TEXT",
+                cmd
+            ),
+        }
+    }
+
+    /// Take up to `steps` steps, stopping at breakpoints if `breaking`.
+    fn advance(&mut self, steps: usize, breaking: bool) -> Result<()> {
+        if let Some(e) = &self.error {
+            bail!("The program failed: {}", e);
+        }
+        for taken in 0..steps {
+            if self.stepper.halted() {
+                break;
+            }
+            if self.stepper.needs_input() {
+                bail!("The program is waiting for input; send it with `input TEXT`");
+            }
+            if let Err(e) = self.stepper.step() {
+                self.error = Some(format!("{:#}", e));
+                break;
+            }
+            let pos = self.stepper.interpreter().get_position();
+            if breaking && taken > 0 && self.breakpoints.contains(&pos) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn attach(addr: &str) -> Result<()> {
+    let stream =
+        TcpStream::connect(addr).with_context(|| anyhow!("Failed to connect to {}", addr))?;
+    let reader = BufReader::new(stream.try_clone()?);
+    thread::spawn(move || {
+        let mut me = 0;
+        for line in reader.lines() {
+            let msg = match line.map_err(anyhow::Error::from).and_then(|line| {
+                serde_json::from_str::<ServerMsg>(&line).map_err(anyhow::Error::from)
+            }) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    eprintln!("Lost the session: {}", e);
+                    process::exit(1);
+                }
+            };
+            match msg {
+                ServerMsg::Welcome { client } => {
+                    me = client;
+                    println!(
+                        "Attached as client {}. Commands: take, release, step [N], run, \
+                         break X,Y, input TEXT",
+                        client
+                    );
+                }
+                ServerMsg::State(state) => print_state(&state, me),
+                ServerMsg::Notice(notice) => println!("{}", theme().warning.paint(notice)),
+            }
+        }
+        eprintln!("The host ended the session");
+        process::exit(0);
+    });
+
+    let mut stream = stream;
+    for line in io::stdin().lock().lines() {
+        writeln!(stream, "{}", line?).context("Failed to send the command")?;
+    }
+    Ok(())
+}
+
+fn print_state(state: &SessionState, me: usize) {
+    println!();
+    for (y, row) in state.playfield.iter().enumerate() {
+        let mut chars: Vec<char> = row.chars().collect();
+        if y == state.position.y {
+            chars.resize(chars.len().max(state.position.x + 1), ' ');
+        }
+        let line: String = chars
+            .iter()
+            .enumerate()
+            .map(|(x, c)| {
+                let pos = Position { x, y };
+                if pos == state.position {
+                    Style::new().reverse().paint(c.to_string()).to_string()
+                } else if state.breakpoints.contains(&pos) {
+                    Style::new().underline().paint(c.to_string()).to_string()
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect();
+        println!("  {}", line);
+    }
+    let stack: Vec<_> = state.stack.iter().map(|v| v.to_string()).collect();
+    let status = match (&state.error, state.halted) {
+        (Some(e), _) => format!("failed: {}", e),
+        (None, true) => "halted".to_string(),
+        (None, false) => "paused".to_string(),
+    };
+    let driver = match state.driver {
+        Some(driver) if driver == me => "you hold the turn".to_string(),
+        Some(driver) => format!("client {} holds the turn", driver),
+        None => "the turn is free".to_string(),
+    };
+    println!(
+        "{} step {} at {} moving {}, {}",
+        theme().label.paint(status),
+        state.steps,
+        state.position,
+        state.direction,
+        driver
+    );
+    println!("{} [{}]", theme().label.paint("Stack:"), stack.join(" "));
+    println!("{} {:?}", theme().label.paint("Output:"), state.output);
+}
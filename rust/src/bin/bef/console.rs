@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+
+use befunge_93::{Interpreter, Position};
+
+use crate::session_log::SessionLog;
+use crate::style::theme;
+use crate::terminal;
+
+const HELP: &str = "Commands:
+  s, n, or enter   step
+  c                continue until a breakpoint
+  b X Y            set or clear a breakpoint at (X, Y)
+  p                print the stack
+  X Y V            write V, a number or a character, to (X, Y)
+  q                quit";
+
+/// A command typed at the `--debug` prompt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DebugCommand {
+    Step,
+    Continue,
+    Break(Position),
+    Stack,
+    Poke(Position, u8),
+    Quit,
+    Help,
+}
+
+fn parse_position(x: &str, y: &str) -> Result<Position> {
+    let coord = |s: &str| -> Result<usize> {
+        s.parse()
+            .with_context(|| format!("Invalid coordinate '{}'", s))
+    };
+    Ok(Position {
+        x: coord(x)?,
+        y: coord(y)?,
+    })
+}
+
+impl FromStr for DebugCommand {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let words: Vec<_> = s.split_whitespace().collect();
+        Ok(match words.as_slice() {
+            [] | ["s"] | ["n"] => Self::Step,
+            ["c"] => Self::Continue,
+            ["p"] => Self::Stack,
+            ["q"] => Self::Quit,
+            ["h"] | ["help"] | ["?"] => Self::Help,
+            ["b", x, y] => Self::Break(parse_position(x, y)?),
+            [x, y, v] => {
+                let pos = parse_position(x, y)?;
+                let value = match (v.parse::<u8>(), v.chars().count()) {
+                    (Ok(value), _) => value,
+                    (Err(_), 1) if v.is_ascii() => v.as_bytes()[0],
+                    _ => bail!("Invalid value '{}', expected 0-255 or a character", v),
+                };
+                Self::Poke(pos, value)
+            }
+            _ => bail!("Unknown command '{}'; type h for help", s.trim()),
+        })
+    }
+}
+
+/// The `--debug` prompt, read from stdin after every step.
+#[derive(Default)]
+pub struct Console {
+    breakpoints: HashSet<Position>,
+    /// Whether to run on until a breakpoint.
+    continuing: bool,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take commands for `int`, which just took a step, until one resumes
+    /// it. Returns `false` if the program should stop.
+    pub fn after_step(&mut self, int: &mut Interpreter, log: Option<&SessionLog>) -> bool {
+        let pos = int.get_position();
+        if self.continuing {
+            if !self.breakpoints.contains(&pos) {
+                return true;
+            }
+            self.continuing = false;
+            println!("{} at {}", theme().error.paint("Breakpoint"), pos);
+        }
+
+        loop {
+            let line = match terminal::read_line("(bef) ") {
+                Ok(Some(line)) => line,
+                Ok(None) | Err(_) => return false,
+            };
+            if let Some(log) = log {
+                log.log("debugger", line.trim_end_matches('\n'));
+            }
+
+            let cmd = match line.parse() {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            match cmd {
+                DebugCommand::Step => return true,
+                DebugCommand::Continue => {
+                    self.continuing = true;
+                    return true;
+                }
+                DebugCommand::Quit => return false,
+                DebugCommand::Help => println!("{}", HELP),
+                DebugCommand::Stack => println!(
+                    "{} {}",
+                    theme().label.paint("Stack:"),
+                    int.get_stack().render(theme())
+                ),
+                DebugCommand::Break(pos) => {
+                    if !int.get_size().contains(pos) {
+                        println!("{} is outside the playfield", pos);
+                    } else if self.breakpoints.remove(&pos) {
+                        println!("Cleared the breakpoint at {}", pos);
+                    } else {
+                        self.breakpoints.insert(pos);
+                        println!("Set a breakpoint at {}", pos);
+                    }
+                }
+                DebugCommand::Poke(pos, value) => match int.set_cell(pos, value) {
+                    Ok(()) => println!("Wrote {:?} to {}", value as char, pos),
+                    Err(e) => println!("{}", e),
+                },
+            }
+        }
+    }
+}
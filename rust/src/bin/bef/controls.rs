@@ -0,0 +1,120 @@
+//! Keys pressed while a run draws frames: pausing, stepping, changing the
+//! delay, quitting and scrolling the viewport.
+
+use std::time::{Duration, Instant};
+
+use crossterm::event::KeyCode;
+
+use befunge_93::Interpreter;
+
+use crate::run::RunOpts;
+use crate::screen::Screen;
+use crate::terminal;
+
+/// Longest delay between steps `+` goes up to, in milliseconds.
+const MAX_DELAY: u64 = 5000;
+
+/// How often the screen is redrawn while paused, to follow resizes.
+const PAUSED_REDRAW: Duration = Duration::from_millis(100);
+
+/// What the keys pressed so far asked for.
+pub struct Controls {
+    paused: bool,
+    /// Run one step and pause again.
+    step: bool,
+    quit: bool,
+    /// Delay between steps, in milliseconds.
+    delay: u64,
+}
+
+impl Controls {
+    pub fn new(delay: Option<u16>) -> Self {
+        Self {
+            paused: false,
+            step: false,
+            quit: false,
+            delay: delay.map_or(0, u64::from),
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether `q` was pressed.
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    /// A line telling what the keys do, and the state they set.
+    pub fn status(&self) -> String {
+        format!(
+            "{}, delay {} ms; space pauses, s steps, +/- change the delay, q quits",
+            if self.paused { "Paused" } else { "Running" },
+            self.delay
+        )
+    }
+
+    fn press(&mut self, key: KeyCode, screen: &mut Screen) {
+        match key {
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.paused = !self.paused,
+            KeyCode::Char('s') | KeyCode::Char('n') => {
+                self.step = self.paused;
+                self.paused = true;
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.delay = (self.delay * 2).clamp(10, MAX_DELAY);
+            }
+            KeyCode::Char('-') => {
+                self.delay /= 2;
+                if self.delay < 10 {
+                    self.delay = 0;
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Left => screen.scroll(-1, 0),
+            KeyCode::Right => screen.scroll(1, 0),
+            KeyCode::Up => screen.scroll(0, -1),
+            KeyCode::Down => screen.scroll(0, 1),
+            KeyCode::PageUp => screen.scroll_page(false),
+            KeyCode::PageDown => screen.scroll_page(true),
+            KeyCode::Char('c') => screen.follow(),
+            _ => {}
+        }
+    }
+
+    /// Act on the keys pressed since the last call, without waiting.
+    pub fn poll(&mut self, screen: &mut Screen) {
+        while let Ok(Some(key)) = terminal::poll_key() {
+            self.press(key, screen);
+        }
+    }
+
+    /// Wait while paused, drawing `int` as keys are pressed, until the run
+    /// is resumed, a step is asked for or `q` is pressed.
+    pub fn wait_while_paused(&mut self, screen: &mut Screen, opts: &RunOpts, int: &Interpreter) {
+        while self.paused && !self.step && !self.quit {
+            if let Ok(Some(key)) = terminal::wait_key(PAUSED_REDRAW) {
+                self.press(key, screen);
+            }
+            screen.set_status(self.status());
+            screen.draw(opts, int);
+        }
+        self.step = false;
+    }
+
+    /// Wait for the delay between steps, acting on keys meanwhile. Stops
+    /// early if the run is paused or `q` is pressed.
+    pub fn sleep(&mut self, screen: &mut Screen) {
+        let until = Instant::now() + Duration::from_millis(self.delay);
+        loop {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() || self.paused || self.quit {
+                return;
+            }
+            if let Ok(Some(key)) = terminal::wait_key(left) {
+                self.press(key, screen);
+            }
+        }
+    }
+}
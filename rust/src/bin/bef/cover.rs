@@ -0,0 +1,64 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use structopt::StructOpt;
+
+use crate::{is_stdin, load, route_input, Opts};
+
+#[derive(StructOpt)]
+pub struct CoverOpts {
+    #[structopt(short, long)]
+    /// Path to program file.
+    file: PathBuf,
+    #[structopt(long)]
+    /// Read program input from a file, or `-` for stdin.
+    input: Option<PathBuf>,
+    #[structopt(long, default_value = "text", possible_values = &["text", "html"])]
+    /// Report format.
+    format: String,
+    #[structopt(short, long)]
+    /// Write the report here instead of to stdout.
+    output: Option<PathBuf>,
+}
+
+pub fn main(opts: &Opts, cover_opts: &CoverOpts) -> Result<()> {
+    let path = &cover_opts.file;
+    let mut interpreter = load(opts, path);
+    match &cover_opts.input {
+        Some(input) if is_stdin(input) => interpreter.set_input(io::stdin()),
+        Some(input) => {
+            let file = File::open(input)
+                .with_context(|| anyhow!("Failed to open '{}'", input.display()))?;
+            interpreter.set_input(BufReader::new(file));
+        }
+        None => route_input(opts, &mut interpreter, None)?,
+    }
+
+    let mut limited = false;
+    let result = interpreter.run(|_, steps| {
+        limited = opts.over_step_limit(steps);
+        !limited
+    });
+    let result = opts.limit_steps(result, limited);
+
+    let report = interpreter.coverage_report();
+    let document = if cover_opts.format == "html" {
+        report.to_html(&format!("Coverage of {}", path.display()))
+    } else {
+        report.to_text()
+    };
+    match &cover_opts.output {
+        Some(output) => fs::write(output, document)
+            .with_context(|| anyhow!("Failed to write '{}'", output.display()))?,
+        None => print!("{}", document),
+    }
+
+    // The coverage of a failed run is still worth seeing, so the error
+    // comes after it.
+    if let Err(e) = result.context("Failed to run the program") {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+    Ok(())
+}
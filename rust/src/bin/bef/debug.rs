@@ -0,0 +1,613 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use structopt::StructOpt;
+
+use befunge_93::stepper::Stepper;
+use befunge_93::{Bounds, Position};
+
+use crate::{is_stdin, new_interpreter, read_program, Opts};
+
+/// Steps taken between redraws while running.
+const STEPS_PER_FRAME: usize = 1000;
+
+#[derive(StructOpt)]
+pub struct DebugOpts {
+    /// Path to program file.
+    file: PathBuf,
+    #[structopt(long = "break", parse(try_from_str = parse_position))]
+    /// Pause before executing the cell at X,Y. May be repeated.
+    breakpoints: Vec<Position>,
+}
+
+/// Parse a cell written as `X,Y`.
+fn parse_position(s: &str) -> Result<Position> {
+    let (x, y) = s.split_once(',').context("Expected X,Y")?;
+    Ok(Position {
+        x: x.trim().parse()?,
+        y: y.trim().parse()?,
+    })
+}
+
+/// What keys typed in the debugger do.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// Keys are shortcuts.
+    Keys,
+    /// Keys are typed into the command bar.
+    Command,
+    /// Keys are typed into the command bar as program input.
+    Input,
+    /// Keys edit the playfield.
+    Edit,
+}
+
+struct Debugger<'a> {
+    opts: &'a Opts,
+    /// Where the program is saved.
+    path: PathBuf,
+    /// The program as edited, which is what restarting runs.
+    grid: Vec<Vec<char>>,
+    shebang: Option<String>,
+    comments: Vec<String>,
+    /// Where typing goes in the editor.
+    cursor: Position,
+    /// Whether the program was edited since it was last saved.
+    modified: bool,
+    /// Program input read from `--input`.
+    input: Option<Vec<u8>>,
+    stepper: Stepper,
+    breakpoints: HashSet<Position>,
+    running: bool,
+    quit: bool,
+    mode: Mode,
+    /// What has been typed into the command bar.
+    line: String,
+    message: String,
+    error: Option<String>,
+    /// Scroll offset of the playfield, as (rows, columns).
+    field_scroll: (u16, u16),
+    /// Lines of output scrolled back from the end.
+    output_scroll: usize,
+}
+
+/// Debug the program given in `debug_opts`, starting in the editor if
+/// `edit`.
+pub fn main(opts: &Opts, debug_opts: &DebugOpts, edit: bool) -> Result<()> {
+    let path = &debug_opts.file;
+    if is_stdin(path) {
+        bail!("Can't debug a program read from stdin, which the debugger needs");
+    }
+    let source = match read_program(path) {
+        Err(e)
+            if edit
+                && e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::NotFound) =>
+        {
+            vec![]
+        }
+        result => result?,
+    };
+    let mut interpreter = new_interpreter(opts);
+    interpreter
+        .load(&mut &source[..])
+        .context("Failed to load program")?;
+    let input = match &opts.input {
+        Some(path) => Some(read_program(path)?),
+        None => None,
+    };
+
+    let size = interpreter.get_size();
+    if let Some(pos) = debug_opts
+        .breakpoints
+        .iter()
+        .find(|&&pos| !size.contains(pos))
+    {
+        bail!("{} is outside the playfield", pos);
+    }
+    let grid = (0..size.rows)
+        .map(|y| {
+            let mut row: Vec<char> = interpreter.get_row(y).chars().collect();
+            row.resize(size.cols, ' ');
+            row
+        })
+        .collect();
+    let mut debugger = Debugger {
+        opts,
+        path: path.clone(),
+        grid,
+        shebang: interpreter.get_shebang().map(String::from),
+        comments: interpreter.get_comments().to_vec(),
+        cursor: Position { x: 0, y: 0 },
+        modified: false,
+        input,
+        stepper: Stepper::new(interpreter),
+        breakpoints: debug_opts.breakpoints.iter().copied().collect(),
+        running: false,
+        quit: false,
+        mode: if edit { Mode::Edit } else { Mode::Keys },
+        line: String::new(),
+        message: String::new(),
+        error: None,
+        field_scroll: (0, 0),
+        output_scroll: 0,
+    };
+    debugger.restart();
+    debugger.message = if edit {
+        "Type to edit; Tab to debug, F5 to run, Ctrl-S to save, Ctrl-Q to quit".to_string()
+    } else {
+        "Press s to step, r to run, e to edit, : for commands, q to quit".to_string()
+    };
+    let mut terminal = ratatui::init();
+    let result = debugger.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Debugger<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = if self.running {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(250)
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.key(key);
+                    }
+                }
+            }
+            if self.running && self.mode == Mode::Keys {
+                self.advance(STEPS_PER_FRAME, true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the program can't take another step.
+    fn finished(&self) -> bool {
+        self.stepper.halted() || self.error.is_some()
+    }
+
+    /// Take up to `steps` steps, stopping at breakpoints if `breaking`.
+    fn advance(&mut self, steps: usize, breaking: bool) {
+        for _ in 0..steps {
+            if self.finished() {
+                self.running = false;
+                return;
+            }
+            if self.input.is_none() && self.stepper.needs_input() {
+                self.mode = Mode::Input;
+                self.line.clear();
+                return;
+            }
+            let bounds = self.stepper.interpreter().get_bounds();
+            if let Err(e) = self.stepper.step() {
+                self.error = Some(format!("{:#}", e));
+                self.running = false;
+                return;
+            }
+            let grown = self.stepper.interpreter().get_bounds();
+            if grown != bounds {
+                self.message = format!("The playfield in use grew to {}", grown);
+            }
+            if self.stepper.halted() {
+                self.message = "The program ended; press q to quit".to_string();
+            }
+            let pos = self.stepper.interpreter().get_position();
+            if breaking && self.breakpoints.contains(&pos) {
+                self.running = false;
+                self.message = format!("Breakpoint at {}", pos);
+                return;
+            }
+        }
+    }
+
+    /// The edited program as source text.
+    fn source(&self) -> String {
+        let mut rows: Vec<String> = self
+            .grid
+            .iter()
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        if let Some(shebang) = &self.shebang {
+            rows.insert(0, shebang.clone());
+        }
+        if !self.comments.is_empty() {
+            rows.push(self.opts.comment_marker.clone());
+            rows.extend(self.comments.iter().cloned());
+        }
+        rows.iter().map(|row| format!("{}\n", row)).collect()
+    }
+
+    /// Load the edited program afresh, ready to run from the start.
+    fn restart(&mut self) {
+        let mut interpreter = new_interpreter(self.opts);
+        if let Err(e) = interpreter.load(&mut self.source().as_bytes()) {
+            self.message = format!("Failed to load the program: {:#}", e);
+        }
+        self.stepper = Stepper::new(interpreter);
+        if let Some(input) = &self.input {
+            self.stepper.push_input(input);
+        }
+        self.error = None;
+        self.running = false;
+        self.output_scroll = 0;
+    }
+
+    fn save(&mut self) {
+        match fs::write(&self.path, self.source()) {
+            Ok(()) => {
+                self.modified = false;
+                self.message = format!("Saved '{}'", self.path.display());
+            }
+            Err(e) => self.message = format!("Failed to save '{}': {}", self.path.display(), e),
+        }
+    }
+
+    /// Quit, unless there are unsaved edits and this isn't the second time
+    /// of asking.
+    fn request_quit(&mut self) {
+        if self.modified && !self.message.starts_with("Unsaved") {
+            self.message = "Unsaved changes; quit again to discard them".to_string();
+        } else {
+            self.quit = true;
+        }
+    }
+
+    /// Leave the editor, restarting the program with the edits.
+    fn stop_editing(&mut self) {
+        self.mode = Mode::Keys;
+        self.restart();
+        self.message =
+            "Press s to step, r to run, e to edit, : for commands, q to quit".to_string();
+    }
+
+    fn edit(&mut self, key: KeyEvent) {
+        let Position { x, y } = self.cursor;
+        let Bounds { cols, rows } = self.size();
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('s') if ctrl => self.save(),
+            KeyCode::Char('q') if ctrl => self.request_quit(),
+            KeyCode::Char(c) if !ctrl && (c == ' ' || c.is_ascii_graphic()) => {
+                self.grid[y][x] = c;
+                self.modified = true;
+                self.cursor.x = (x + 1) % cols;
+            }
+            KeyCode::Backspace => {
+                self.cursor.x = x.checked_sub(1).unwrap_or(cols - 1);
+                self.grid[y][self.cursor.x] = ' ';
+                self.modified = true;
+            }
+            KeyCode::Delete => {
+                self.grid[y][x] = ' ';
+                self.modified = true;
+            }
+            KeyCode::Enter => {
+                self.cursor = Position {
+                    x: 0,
+                    y: (y + 1) % rows,
+                }
+            }
+            KeyCode::Left => self.cursor.x = x.checked_sub(1).unwrap_or(cols - 1),
+            KeyCode::Right => self.cursor.x = (x + 1) % cols,
+            KeyCode::Up => self.cursor.y = y.checked_sub(1).unwrap_or(rows - 1),
+            KeyCode::Down => self.cursor.y = (y + 1) % rows,
+            KeyCode::Home => self.cursor.x = 0,
+            KeyCode::End => {
+                self.cursor.x = self.grid[y]
+                    .iter()
+                    .rposition(|&c| c != ' ')
+                    .map_or(0, |end| (end + 1).min(cols - 1))
+            }
+            KeyCode::Tab | KeyCode::Esc => self.stop_editing(),
+            KeyCode::F(5) => {
+                self.stop_editing();
+                self.resume();
+            }
+            _ => {}
+        }
+    }
+
+    /// Size of the playfield of the program.
+    fn size(&self) -> Bounds {
+        self.stepper.interpreter().get_size()
+    }
+
+    fn toggle_breakpoint(&mut self, pos: Position) {
+        if self.breakpoints.remove(&pos) {
+            self.message = format!("Removed the breakpoint at {}", pos);
+        } else {
+            self.breakpoints.insert(pos);
+            self.message = format!("Added a breakpoint at {}", pos);
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        match self.mode {
+            Mode::Keys => self.shortcut(key.code),
+            Mode::Edit => self.edit(key),
+            Mode::Command | Mode::Input => match key.code {
+                KeyCode::Enter => {
+                    let line = std::mem::take(&mut self.line);
+                    if self.mode == Mode::Input {
+                        self.stepper.push_input(format!("{}\n", line).as_bytes());
+                    }
+                    let mode = std::mem::replace(&mut self.mode, Mode::Keys);
+                    if mode == Mode::Command {
+                        self.command(&line);
+                    }
+                }
+                KeyCode::Esc => {
+                    self.mode = Mode::Keys;
+                    self.running = false;
+                    self.line.clear();
+                }
+                KeyCode::Backspace => {
+                    self.line.pop();
+                }
+                KeyCode::Char(c) => self.line.push(c),
+                _ => {}
+            },
+        }
+    }
+
+    fn shortcut(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char('q') => self.request_quit(),
+            KeyCode::Char('s') | KeyCode::Char(' ') => self.advance(1, false),
+            KeyCode::Char('r') | KeyCode::Char('c') => self.resume(),
+            KeyCode::Char('p') => self.running = false,
+            KeyCode::Char('e') => {
+                self.mode = Mode::Edit;
+                self.running = false;
+                self.message =
+                    "Type to edit; Tab to debug, F5 to run, Ctrl-S to save, Ctrl-Q to quit"
+                        .to_string();
+            }
+            KeyCode::Char('b') => self.toggle_breakpoint(self.stepper.interpreter().get_position()),
+            KeyCode::Char(':') => {
+                self.mode = Mode::Command;
+                self.line.clear();
+            }
+            KeyCode::Up => self.field_scroll.0 = self.field_scroll.0.saturating_sub(1),
+            KeyCode::Down => {
+                self.field_scroll.0 = (self.field_scroll.0 + 1).min(self.size().rows as u16 - 1)
+            }
+            KeyCode::Left => self.field_scroll.1 = self.field_scroll.1.saturating_sub(1),
+            KeyCode::Right => {
+                self.field_scroll.1 = (self.field_scroll.1 + 1).min(self.size().cols as u16 - 1)
+            }
+            KeyCode::PageUp => self.output_scroll += 5,
+            KeyCode::PageDown => self.output_scroll = self.output_scroll.saturating_sub(5),
+            _ => {}
+        }
+    }
+
+    /// Run until a breakpoint, leaving the one at the PC first.
+    fn resume(&mut self) {
+        if self.finished() {
+            return;
+        }
+        self.advance(1, false);
+        self.running = !self.finished();
+        self.message = "Running; press p to pause".to_string();
+    }
+
+    fn command(&mut self, line: &str) {
+        let mut words = line.split_whitespace();
+        let result = match (words.next(), words.next()) {
+            (None, _) => Ok(()),
+            (Some("s" | "step"), n) => n
+                .map_or(Ok(1), str::parse)
+                .map(|n| self.advance(n, true))
+                .map_err(|e| anyhow!("Invalid step count: {}", e)),
+            (Some("r" | "run" | "c" | "continue"), None) => {
+                self.resume();
+                Ok(())
+            }
+            (Some("p" | "pause"), None) => {
+                self.running = false;
+                Ok(())
+            }
+            (Some("b" | "break"), Some(pos)) => parse_position(pos).and_then(|pos| {
+                if !self.size().contains(pos) {
+                    bail!("{} is outside the playfield", pos);
+                }
+                self.toggle_breakpoint(pos);
+                Ok(())
+            }),
+            (Some("b" | "break"), None) => {
+                let mut sorted: Vec<_> = self.breakpoints.iter().collect();
+                sorted.sort_by_key(|pos| (pos.y, pos.x));
+                let list: Vec<_> = sorted.iter().map(|pos| pos.to_string()).collect();
+                self.message = format!("Breakpoints: {}", list.join(" "));
+                Ok(())
+            }
+            (Some("q" | "quit"), None) => {
+                self.request_quit();
+                Ok(())
+            }
+            (Some(cmd), _) => Err(anyhow!(
+                "Unknown command '{}'; expected step [N], run, pause, break [X,Y] or quit",
+                cmd
+            )),
+        };
+        if let Err(e) = result {
+            self.message = e.to_string();
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status, bar] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [field, side] = Layout::horizontal([
+            Constraint::Length(self.size().cols as u16 + 2),
+            Constraint::Min(20),
+        ])
+        .areas(main);
+        let [stack, output] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(side);
+
+        self.draw_playfield(frame, field);
+        self.draw_stack(frame, stack);
+        self.draw_output(frame, output);
+        frame.render_widget(Paragraph::new(self.status()), status);
+
+        let bar_text = match self.mode {
+            Mode::Keys | Mode::Edit => Line::from(self.message.as_str()),
+            Mode::Command => Line::from(format!(":{}", self.line)),
+            Mode::Input => Line::from(vec![
+                Span::styled("Program input> ", Style::new().fg(Color::Green)),
+                Span::raw(self.line.as_str()),
+            ]),
+        };
+        frame.render_widget(Paragraph::new(bar_text), bar);
+    }
+
+    fn draw_playfield(&self, frame: &mut Frame, area: Rect) {
+        let int = self.stepper.interpreter();
+        let editing = self.mode == Mode::Edit;
+        // The cursor stands in for the PC while editing.
+        let pc = if editing {
+            self.cursor
+        } else {
+            int.get_position()
+        };
+        let lines: Vec<Line> = (0..int.get_size().rows)
+            .map(|y| {
+                let row = if editing {
+                    self.grid[y].clone()
+                } else {
+                    let mut row: Vec<char> = int.get_row(y).chars().collect();
+                    row.resize(int.get_size().cols, ' ');
+                    row
+                };
+                let spans: Vec<Span> = row
+                    .into_iter()
+                    .enumerate()
+                    .map(|(x, c)| {
+                        let pos = Position { x, y };
+                        let style = if pos == pc {
+                            Style::new().fg(Color::Black).bg(Color::Yellow)
+                        } else if self.breakpoints.contains(&pos) {
+                            Style::new().fg(Color::White).bg(Color::Red)
+                        } else {
+                            Style::new()
+                        };
+                        Span::styled(c.to_string(), style)
+                    })
+                    .collect();
+                Line::from(spans)
+            })
+            .collect();
+        let title = match (editing, self.modified) {
+            (true, true) => " Playfield (editing, modified) ".to_string(),
+            (true, false) => " Playfield (editing) ".to_string(),
+            (false, true) => format!(" Playfield {} (modified) ", int.get_bounds()),
+            (false, false) => format!(" Playfield {} ", int.get_bounds()),
+        };
+        let block = Block::bordered().title(title);
+        frame.render_widget(
+            Paragraph::new(lines).block(block).scroll(self.field_scroll),
+            area,
+        );
+    }
+
+    fn draw_stack(&self, frame: &mut Frame, area: Rect) {
+        let stack = self.stepper.interpreter().get_stack();
+        let items: Vec<_> = stack
+            .values()
+            .into_iter()
+            .rev()
+            .map(|v| match u8::try_from(v) {
+                Ok(b) if b.is_ascii_graphic() => format!("{:>8}  {:?}", v, b as char),
+                _ => format!("{:>8}", v),
+            })
+            .collect();
+        let title = format!(" Stack ({}, top first) ", items.len());
+        frame.render_widget(List::new(items).block(Block::bordered().title(title)), area);
+    }
+
+    fn draw_output(&self, frame: &mut Frame, area: Rect) {
+        let output = self.stepper.interpreter().get_output();
+        let lines: Vec<_> = output.split('\n').collect();
+        let height = area.height.saturating_sub(2) as usize;
+        let end = lines.len().saturating_sub(self.output_scroll).max(1);
+        let start = end.saturating_sub(height);
+        let text: Vec<Line> = lines[start..end].iter().map(|&l| Line::from(l)).collect();
+        frame.render_widget(
+            Paragraph::new(text).block(Block::bordered().title(" Output ")),
+            area,
+        );
+    }
+
+    fn status(&self) -> Line<'_> {
+        let int = self.stepper.interpreter();
+        if self.mode == Mode::Edit {
+            let cell = self.grid[self.cursor.y][self.cursor.x];
+            return Line::from(vec![
+                Span::styled(
+                    " editing ",
+                    Style::new()
+                        .fg(Color::Black)
+                        .bg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!(
+                    " {}  at {}  '{}'",
+                    self.path.display(),
+                    self.cursor,
+                    cell
+                )),
+            ]);
+        }
+        let (state, colour) = if let Some(e) = &self.error {
+            (format!("failed: {}", e), Color::Red)
+        } else if self.stepper.halted() {
+            ("halted".to_string(), Color::Green)
+        } else if self.running {
+            ("running".to_string(), Color::Yellow)
+        } else {
+            ("paused".to_string(), Color::Blue)
+        };
+        Line::from(vec![
+            Span::styled(
+                format!(" {} ", state),
+                Style::new()
+                    .fg(Color::Black)
+                    .bg(colour)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                " step {}  at {} moving {}  '{}'",
+                self.stepper.steps(),
+                int.get_position(),
+                int.get_direction(),
+                int.get_current_command()
+            )),
+        ])
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Read};
+
+use crossterm::event::KeyCode;
+
+use crate::terminal;
+
+/// Program input read a key at a time as keys are pressed, rather than a
+/// line at a time, so that `~` can drive interactive programs such as
+/// games. Keys are not echoed. Arrow keys read as the escape sequences
+/// terminals send for them, and Ctrl-D ends the input.
+#[derive(Default)]
+pub struct Keys {
+    /// Bytes of the last key pressed that are yet to be read.
+    pending: Vec<u8>,
+}
+
+impl Keys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Read for Keys {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            match terminal::read_key()? {
+                Some(key) => self.pending = key_bytes(key),
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+/// The bytes a terminal sends for `key`, if any.
+fn key_bytes(key: KeyCode) -> Vec<u8> {
+    let bytes: &[u8] = match key {
+        KeyCode::Char(c) => return c.to_string().into_bytes(),
+        KeyCode::Enter => b"\n",
+        KeyCode::Tab => b"\t",
+        KeyCode::Backspace => b"\x7f",
+        KeyCode::Esc => b"\x1b",
+        KeyCode::Up => b"\x1b[A",
+        KeyCode::Down => b"\x1b[B",
+        KeyCode::Right => b"\x1b[C",
+        KeyCode::Left => b"\x1b[D",
+        _ => b"",
+    };
+    bytes.to_vec()
+}
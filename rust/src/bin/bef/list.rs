@@ -0,0 +1,107 @@
+//! `bef list`: the playfield of a program with coordinates, and what each
+//! of its cells does, for reading programs.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use befunge_93::instructions;
+use befunge_93::{Interpreter, Position};
+
+use crate::style::theme;
+use crate::{load, Opts};
+
+#[derive(StructOpt)]
+pub struct ListOpts {
+    #[structopt(short, long)]
+    /// Path to program file.
+    file: PathBuf,
+}
+
+pub fn main(opts: &Opts, list_opts: &ListOpts) -> Result<()> {
+    let interpreter = load(opts, &list_opts.file);
+    let rows = rows(&interpreter);
+    let cols = rows.iter().map(Vec::len).max().unwrap_or(0);
+
+    // Column numbers, tens above units.
+    let tens: String = (0..cols)
+        .map(|x| match x % 10 {
+            0 => char::from_digit((x / 10 % 10) as u32, 10).unwrap_or(' '),
+            _ => ' ',
+        })
+        .collect();
+    let units: String = (0..cols)
+        .map(|x| char::from_digit((x % 10) as u32, 10).unwrap_or(' '))
+        .collect();
+    println!("    {}", theme().faint.paint(tens.trim_end()));
+    println!("    {}", theme().faint.paint(units));
+    for (y, row) in rows.iter().enumerate() {
+        let cells: String = row
+            .iter()
+            .enumerate()
+            .map(|(x, &c)| {
+                let shown = if c.is_control() { '\u{b7}' } else { c };
+                let style = match note(Position { x, y }, c) {
+                    Some("entry") => theme().pc,
+                    Some(_) => theme().error,
+                    None => return shown.to_string(),
+                };
+                style.paint(shown.to_string()).to_string()
+            })
+            .collect();
+        println!("{}  {}", theme().faint.paint(format!("{:>2}", y)), cells);
+    }
+    println!();
+
+    for (y, row) in rows.iter().enumerate() {
+        // Cells between quotes on a row are most likely pushed in string
+        // mode rather than executed, whichever way the PC crosses them.
+        let mut quoted = false;
+        for (x, &c) in row.iter().enumerate() {
+            let pos = Position { x, y };
+            let note = note(pos, c);
+            if c == '"' {
+                quoted = !quoted;
+            }
+            if c == ' ' && note.is_none() {
+                continue;
+            }
+            let name = match instructions::lookup(interpreter.get_dialect(), c) {
+                _ if quoted && c != '"' => "string character",
+                Some(doc) => doc.name,
+                None => "not an instruction",
+            };
+            let line = format!(
+                "{:<10} {:<6} {:<20} {}",
+                pos.to_string(),
+                format!("{:?}", c),
+                name,
+                note.unwrap_or("")
+            );
+            println!("{}", line.trim_end());
+        }
+    }
+    Ok(())
+}
+
+/// The rows of the playfield in use, without trailing spaces.
+fn rows(int: &Interpreter) -> Vec<Vec<char>> {
+    let mut rows: Vec<Vec<char>> = (0..int.get_bounds().rows)
+        .map(|y| int.get_row(y).chars().collect())
+        .collect();
+    while rows.last().is_some_and(Vec::is_empty) {
+        rows.pop();
+    }
+    rows
+}
+
+/// What is special about the cell at `pos` holding `c`, if anything: the
+/// PC enters the program there, or leaves it.
+fn note(pos: Position, c: char) -> Option<&'static str> {
+    match c {
+        _ if pos == (Position { x: 0, y: 0 }) => Some("entry"),
+        '@' => Some("exit"),
+        _ => None,
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+
+use befunge_93::instructions::{self, Dialect};
+use befunge_93::semantics::Unknown;
+use befunge_93::{Interpreter, Position};
+
+use crate::{new_interpreter, Opts};
+
+// Values from the LSP specification.
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const SYMBOL_NAMESPACE: u8 = 3;
+const SYMBOL_EVENT: u8 = 24;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// A place in a document, as a line and a UTF-16 column.
+type Location = (usize, usize);
+
+/// A program as opened in the editor.
+struct Document {
+    text: String,
+    interpreter: Interpreter,
+    /// Where in the text each cell of the playfield was loaded from.
+    origins: Vec<Vec<Option<Location>>>,
+}
+
+impl Document {
+    fn new(opts: &Opts, text: String) -> Self {
+        let mut interpreter = new_interpreter(opts);
+        // Loading from memory only fails if the file has no code at all.
+        let _ = interpreter.load(&mut text.as_bytes());
+        let origins = origins(&text, interpreter.get_shebang().is_some(), opts);
+        Self {
+            text,
+            interpreter,
+            origins,
+        }
+    }
+
+    /// Where the cell at `pos` came from, or where it would have if the
+    /// lines were long enough, up to the end of the text.
+    fn location(&self, pos: Position) -> Location {
+        if let Some(location) = self.origins[pos.y][pos.x] {
+            return location;
+        }
+        let shebang = self.interpreter.get_shebang().is_some() as usize;
+        let line = pos.y + shebang;
+        match self.text.lines().count().checked_sub(1) {
+            Some(last) if line > last => {
+                let text = self.text.lines().nth(last).unwrap_or_default();
+                (last, utf16_col(text, text.len()))
+            }
+            _ => (line, pos.x),
+        }
+    }
+
+    fn range(&self, from: Position, to: Position) -> Value {
+        let (start, end) = (self.location(from), self.location(to));
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 + 1 },
+        })
+    }
+
+    /// The smallest range holding every one of `cells`.
+    fn bounding_range(&self, cells: &[Position]) -> Value {
+        let min_x = cells.iter().map(|pos| pos.x).min().unwrap_or(0);
+        let min_y = cells.iter().map(|pos| pos.y).min().unwrap_or(0);
+        let max_x = cells.iter().map(|pos| pos.x).max().unwrap_or(0);
+        let max_y = cells.iter().map(|pos| pos.y).max().unwrap_or(0);
+        self.range(
+            Position { x: min_x, y: min_y },
+            Position { x: max_x, y: max_y },
+        )
+    }
+
+    fn diagnostics(&self, opts: &Opts) -> Vec<Value> {
+        let mut diagnostics = vec![];
+        let diagnostic = |range: Value, severity: u8, message: String| {
+            json!({
+                "range": range,
+                "severity": severity,
+                "source": "bef",
+                "message": message,
+            })
+        };
+
+        let shebang = self.interpreter.get_shebang().is_some() as usize;
+        let marker = (!opts.no_comments).then_some(opts.comment_marker.as_str());
+        let code = self
+            .text
+            .lines()
+            .enumerate()
+            .skip(shebang)
+            .take_while(|(_, line)| Some(*line) != marker);
+        let size = self.interpreter.get_size();
+        for (row, (line, text)) in code.enumerate() {
+            let len = text.len();
+            if len > size.cols {
+                let range = json!({
+                    "start": { "line": line, "character": utf16_col(text, size.cols) },
+                    "end": { "line": line, "character": utf16_col(text, len) },
+                });
+                let message = format!(
+                    "This line is {} cells long, but the playfield is {} wide; the rest wraps \
+                     onto the next row",
+                    len, size.cols
+                );
+                diagnostics.push(diagnostic(range, SEVERITY_WARNING, message));
+            }
+            if row == size.rows {
+                let range = json!({
+                    "start": { "line": line, "character": 0 },
+                    "end": { "line": line, "character": utf16_col(text, len) },
+                });
+                let message = format!(
+                    "The playfield has {} rows; this and the following lines wrap round onto \
+                     the first ones",
+                    size.rows
+                );
+                diagnostics.push(diagnostic(range, SEVERITY_WARNING, message));
+            }
+        }
+
+        let analysis = self.interpreter.analyze();
+        let caveat = if analysis.self_modifying {
+            " (the program modifies itself, so it might after all)"
+        } else {
+            ""
+        };
+        for &pos in &analysis.unreachable {
+            if self.interpreter.get_row(pos.y).chars().nth(pos.x) == Some('@') {
+                let message = format!("This `@` can never be reached{}", caveat);
+                diagnostics.push(diagnostic(self.range(pos, pos), SEVERITY_WARNING, message));
+            }
+        }
+        let severity = match self.interpreter.get_semantics().unknown {
+            Unknown::Error => SEVERITY_ERROR,
+            Unknown::Nop | Unknown::Reflect => SEVERITY_WARNING,
+        };
+        for &pos in &analysis.unknown {
+            let c = self.interpreter.get_row(pos.y).chars().nth(pos.x);
+            let message = format!(
+                "{:?} is not an instruction, and is executed here",
+                c.unwrap_or(' ')
+            );
+            diagnostics.push(diagnostic(self.range(pos, pos), severity, message));
+        }
+        diagnostics
+    }
+
+    fn hover(&self, line: usize, character: usize) -> Value {
+        let c = self
+            .text
+            .lines()
+            .nth(line)
+            .and_then(|text| char_at_utf16(text, character));
+        match c.and_then(|c| instructions::lookup(Dialect::Befunge93, c)) {
+            Some(doc) => json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": format!(
+                        "**`{}` {}** ({})\n\n`{}`\n\n{}",
+                        doc.c,
+                        doc.name,
+                        doc.kind.name(),
+                        doc.stack,
+                        doc.description
+                    ),
+                },
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn symbols(&self) -> Vec<Value> {
+        let mut symbols = vec![];
+        for cells in self.regions() {
+            let first = cells[0];
+            symbols.push(json!({
+                "name": format!("region at {}", first),
+                "detail": cell_count(cells.len()),
+                "kind": SYMBOL_NAMESPACE,
+                "range": self.bounding_range(&cells),
+                "selectionRange": self.range(first, first),
+            }));
+        }
+        let analysis = self.interpreter.analyze();
+        for cells in &analysis.loops {
+            let first = cells[0];
+            let endless = analysis.cycles.contains(cells);
+            symbols.push(json!({
+                "name": format!("loop at {}", first),
+                "detail": format!(
+                    "{}{}",
+                    cell_count(cells.len()),
+                    if endless { ", never left" } else { "" }
+                ),
+                "kind": SYMBOL_EVENT,
+                "range": self.bounding_range(cells),
+                "selectionRange": self.range(first, first),
+            }));
+        }
+        symbols
+    }
+
+    /// Groups of adjacent cells that aren't spaces, row by row.
+    fn regions(&self) -> Vec<Vec<Position>> {
+        let size = self.interpreter.get_size();
+        let rows: Vec<Vec<char>> = (0..size.rows)
+            .map(|y| self.interpreter.get_row(y).chars().collect())
+            .collect();
+        let code = |pos: Position| rows[pos.y].get(pos.x).is_some_and(|&c| c != ' ');
+        let mut seen = vec![vec![false; size.cols]; size.rows];
+        let mut regions = vec![];
+        for y in 0..size.rows {
+            for x in 0..size.cols {
+                if seen[y][x] || !code(Position { x, y }) {
+                    continue;
+                }
+                seen[y][x] = true;
+                let mut cells = vec![];
+                let mut queue = vec![Position { x, y }];
+                while let Some(pos) = queue.pop() {
+                    cells.push(pos);
+                    let neighbours = [
+                        (pos.x.wrapping_sub(1), pos.y),
+                        (pos.x + 1, pos.y),
+                        (pos.x, pos.y.wrapping_sub(1)),
+                        (pos.x, pos.y + 1),
+                    ];
+                    for (x, y) in neighbours {
+                        if x < size.cols && y < size.rows && !seen[y][x] {
+                            let next = Position { x, y };
+                            if code(next) {
+                                seen[y][x] = true;
+                                queue.push(next);
+                            }
+                        }
+                    }
+                }
+                cells.sort_by_key(|pos| (pos.y, pos.x));
+                regions.push(cells);
+            }
+        }
+        regions
+    }
+}
+
+fn cell_count(n: usize) -> String {
+    format!("{} cell{}", n, if n == 1 { "" } else { "s" })
+}
+
+/// Where in `text` each cell of the playfield is loaded from, following
+/// the same wrapping as `Interpreter::load` on the playfield of the dialect.
+pub(crate) fn origins(text: &str, shebang: bool, opts: &Opts) -> Vec<Vec<Option<Location>>> {
+    let size = opts.dialect.playfield();
+    let mut origins = vec![vec![None; size.cols]; size.rows];
+    let marker = (!opts.no_comments).then_some(opts.comment_marker.as_str());
+    let (mut x, mut y) = (0, 0);
+    for (line, text) in text.split('\n').enumerate().skip(shebang as usize) {
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        if Some(text) == marker {
+            break;
+        }
+        let mut col = 0;
+        for c in text.chars() {
+            for _ in 0..c.len_utf8() {
+                origins[y][x] = Some((line, col));
+                x = (x + 1) % size.cols;
+                if x == 0 {
+                    y = (y + 1) % size.rows;
+                }
+            }
+            col += c.len_utf16();
+        }
+        x = 0;
+        y = (y + 1) % size.rows;
+    }
+    origins
+}
+
+/// The UTF-16 column of the byte at `idx` of `text`.
+fn utf16_col(text: &str, idx: usize) -> usize {
+    text.char_indices()
+        .take_while(|&(i, _)| i < idx)
+        .map(|(_, c)| c.len_utf16())
+        .sum()
+}
+
+/// The character at UTF-16 column `col` of `text`.
+fn char_at_utf16(text: &str, col: usize) -> Option<char> {
+    let mut at = 0;
+    for c in text.chars() {
+        if at == col {
+            return Some(c);
+        }
+        at += c.len_utf16();
+        if at > col {
+            return None;
+        }
+    }
+    None
+}
+
+/// Read a message, or `None` at the end of the input.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let len = len.context("Message without a Content-Length header")?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The language server, talking to an editor over stdin and stdout.
+struct Server<'a> {
+    opts: &'a Opts,
+    documents: HashMap<String, Document>,
+}
+
+impl Server<'_> {
+    /// Open or replace the document at `uri`, returning the diagnostics to
+    /// publish.
+    fn update(&mut self, uri: &str, text: String) -> Value {
+        let document = Document::new(self.opts, text);
+        let diagnostics = document.diagnostics(self.opts);
+        self.documents.insert(uri.to_string(), document);
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    /// Handle the notification `method`, returning a notification to send
+    /// back, if any.
+    fn notify(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str()?;
+                Some(self.update(uri, text.to_string()))
+            }
+            "textDocument/didChange" => {
+                // Documents are synced in full, so the last change is all
+                // of the text.
+                let changes = params["contentChanges"].as_array()?;
+                let text = changes.last()?["text"].as_str()?;
+                Some(self.update(uri, text.to_string()))
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                Some(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle the request `method`, returning its result, or `None` if the
+    /// method isn't supported.
+    fn request(&self, method: &str, params: &Value) -> Result<Option<Value>> {
+        let document = || {
+            let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+            self.documents
+                .get(uri)
+                .ok_or_else(|| anyhow!("Unknown document '{}'", uri))
+        };
+        Ok(Some(match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "bef", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/hover" => {
+                let pos = &params["position"];
+                let line = pos["line"].as_u64().unwrap_or_default() as usize;
+                let character = pos["character"].as_u64().unwrap_or_default() as usize;
+                document()?.hover(line, character)
+            }
+            "textDocument/documentSymbol" => Value::from(document()?.symbols()),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Serve the language server protocol over stdin and stdout until the
+/// editor exits.
+pub fn main(opts: &Opts) -> Result<()> {
+    let mut server = Server {
+        opts,
+        documents: HashMap::new(),
+    };
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut stdout = io::stdout();
+    while let Some(message) = read_message(&mut reader).context("Failed to read a message")? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let reply = match message.get("id") {
+            Some(id) => {
+                let (code, message) = match server.request(method, params) {
+                    Ok(Some(result)) => {
+                        let reply = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                        write_message(&mut stdout, &reply).context("Failed to write a message")?;
+                        continue;
+                    }
+                    Ok(None) => (METHOD_NOT_FOUND, format!("Unsupported method '{}'", method)),
+                    Err(e) => (INVALID_PARAMS, format!("{:#}", e)),
+                };
+                Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }))
+            }
+            None if method == "exit" => return Ok(()),
+            None => server.notify(method, params),
+        };
+        if let Some(reply) = reply {
+            write_message(&mut stdout, &reply).context("Failed to write a message")?;
+        }
+    }
+    Ok(())
+}
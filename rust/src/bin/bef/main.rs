@@ -0,0 +1,1367 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::codegen::Target;
+use befunge_93::conformance;
+use befunge_93::corpus::{self, CorpusStats, ProgramStats};
+use befunge_93::diagnostic::Diagnostic;
+use befunge_93::differential::{self, Reference};
+use befunge_93::format::{self, FormatOptions};
+use befunge_93::input::{InputTimeout, OnTimeout};
+use befunge_93::instructions::{self, Dialect};
+use befunge_93::interop::{self, SourceFormat};
+use befunge_93::judge::{self, JudgeLimits, Verdict};
+use befunge_93::output::{OutputLimit, OutputOverflow};
+use befunge_93::packs::{self, Pack};
+use befunge_93::schema::{self, Versioned};
+use befunge_93::semantics::{Cells, Chars, Choice, Semantics};
+use befunge_93::teach;
+use befunge_93::{Bounds, Interpreter, Overflow, Position, Progress, StackLimit};
+
+mod art;
+mod batch;
+mod bench;
+mod bisect;
+mod collab;
+mod console;
+mod controls;
+mod cover;
+#[cfg(feature = "tui")]
+mod debug;
+mod keys;
+mod list;
+mod lsp;
+mod new;
+mod pretty;
+mod profile;
+mod prompt;
+mod regression;
+mod render;
+mod repl;
+mod run;
+mod screen;
+mod serve;
+mod session_log;
+mod style;
+mod terminal;
+mod trace;
+mod watch;
+
+use keys::Keys;
+use session_log::{LoggedInput, SessionLog};
+use style::theme;
+
+#[derive(StructOpt)]
+#[structopt(name = "bef", author, about = "A simple Befunge-93 interpreter.")]
+struct Opts {
+    #[structopt(subcommand)]
+    cmd: Option<Cmd>,
+    #[structopt(long)]
+    /// Seed of the directions taken by `?`, making runs reproducible.
+    seed: Option<u64>,
+    #[structopt(long, value_name = "N")]
+    /// Stop running programs after N steps, failing with an error, so that
+    /// programs that never end can't hang scripts.
+    max_steps: Option<usize>,
+    #[structopt(long, value_name = "ms")]
+    /// Stop programs run by `bef run`, `bef pipe` and `bef batch` after this
+    /// long, failing with an error, so that slow programs can't hang
+    /// graders and servers.
+    timeout: Option<u64>,
+    #[structopt(long)]
+    /// Stop programs with an error when they come back to a state they
+    /// were in before, with no `?` or input read since, as they can then
+    /// never end.
+    detect_loops: bool,
+    #[structopt(long, value_name = "N")]
+    /// Hold at most N values on the stack, so that programs pushing forever
+    /// can't use up memory.
+    max_stack: Option<usize>,
+    #[structopt(
+        long,
+        value_name = "policy",
+        default_value = "error",
+        parse(try_from_str = Overflow::parse)
+    )]
+    /// What pushing onto a full stack does: error, drop-oldest or ignore.
+    stack_overflow: Overflow,
+    #[structopt(long, value_name = "bytes")]
+    /// Keep at most this much program output in memory, so that programs
+    /// printing forever can't use it up.
+    max_output: Option<usize>,
+    #[structopt(
+        long,
+        value_name = "policy",
+        default_value = "error",
+        parse(try_from_str = OutputOverflow::parse)
+    )]
+    /// What printing past --max-output does: error, drop-oldest, or stream
+    /// to stop keeping output, which is still written as it's printed.
+    output_overflow: OutputOverflow,
+    #[structopt(long, alias = "stdin-from")]
+    /// Read program input from a file instead of stdin, or `-` for stdin.
+    /// When the program is read from stdin too, it ends at a line holding
+    /// only `__INPUT__`, and the rest of stdin is its input.
+    input: Option<PathBuf>,
+    #[structopt(long, conflicts_with = "input")]
+    /// Read program input a key at a time as keys are pressed, without
+    /// waiting for enter or echoing them, so that interactive programs such
+    /// as games can be played. Ignored unless stdin is a terminal.
+    raw_input: bool,
+    #[structopt(long, value_name = "ms")]
+    /// Wait at most this long for each byte of program input, so that
+    /// programs waiting for input that never comes can't hang.
+    input_timeout: Option<u64>,
+    #[structopt(
+        long,
+        value_name = "policy",
+        default_value = "error",
+        parse(try_from_str = OnTimeout::parse)
+    )]
+    /// What waiting past --input-timeout does: error, or eof to act as at
+    /// the end of input.
+    on_input_timeout: OnTimeout,
+    #[structopt(long, default_value = "human", possible_values = &["human", "json"])]
+    /// How to print errors.
+    error_format: String,
+    #[structopt(long)]
+    /// Print without colours, as when the NO_COLOR environment variable is
+    /// set.
+    no_color: bool,
+    #[structopt(long, value_name = "file")]
+    /// Draw with the colours of a theme file, of `key = style` lines such as
+    /// `pc = bold black on yellow`. Keys: pc, border, stack, label, ok,
+    /// error, warning, accent, strong, faint and highlight.CATEGORY.
+    theme: Option<PathBuf>,
+    #[structopt(long, default_value = "")]
+    /// Semantic choices, as comma-separated `key=value` overrides.
+    semantics: Semantics,
+    #[structopt(long, default_value = "befunge-93")]
+    /// Run programs written for this dialect: befunge-93, or the
+    /// befunge-96 and befunge-97 drafts adding instructions to it and
+    /// growing the playfield from 80x25 to 160x50.
+    dialect: Dialect,
+    #[structopt(long, default_value = befunge_93::COMMENT_MARKER)]
+    /// Line after which the program file holds comments instead of code.
+    comment_marker: String,
+    #[structopt(long, conflicts_with = "comment-marker")]
+    /// Load the whole program file into the playfield.
+    no_comments: bool,
+    #[structopt(long, default_value = "bef", possible_values = SourceFormat::NAMES)]
+    /// Read program files in this format: bef, or funge98 for those of
+    /// Funge-98 interpreters and dos for CRLF files; see `bef convert`.
+    source_format: SourceFormat,
+    #[structopt(
+        long = "pack",
+        value_name = "name",
+        number_of_values = 1,
+        parse(try_from_str = packs::find)
+    )]
+    /// Add the novelty instructions of a pack; see `bef packs`. May be
+    /// given more than once.
+    packs: Vec<&'static Pack>,
+    // Without a subcommand, bef runs a program as `bef run` does.
+    #[structopt(flatten)]
+    run: run::RunOpts,
+}
+
+#[derive(StructOpt)]
+enum Cmd {
+    /// Run a program, showing its playfield, stack or trace at each step
+    /// if asked. This is what bef does without a subcommand.
+    Run(run::RunOpts),
+    /// Print the semantic choices of a configuration.
+    Semantics {
+        #[structopt(default_value = "")]
+        /// Configuration, as comma-separated `key=value` overrides.
+        semantics: Semantics,
+        #[structopt(long)]
+        /// Compare with another configuration.
+        diff: Option<Semantics>,
+    },
+    /// Aggregate metrics over a directory of programs.
+    CorpusStats {
+        /// Directory to scan recursively.
+        dir: PathBuf,
+        #[structopt(long, default_value = "json", possible_values = &["json", "csv"])]
+        /// Output format.
+        format: String,
+    },
+    /// List the programs in a directory with a thumbnail of each.
+    Ls {
+        /// Directory to list.
+        dir: PathBuf,
+        #[structopt(short, long)]
+        /// Also list programs in subdirectories.
+        recursive: bool,
+    },
+    /// Work with trace and recording files.
+    Trace(trace::TraceCmd),
+    /// Record, merge and show execution counts of programs.
+    Profile(profile::ProfileOpts),
+    /// Run a program and report which of its cells were executed.
+    Cover(cover::CoverOpts),
+    /// Run a program repeatedly, without showing anything, and report its
+    /// speed and how long runs took.
+    Bench(bench::BenchOpts),
+    /// Run many programs without showing anything, printing the output of
+    /// each and a table of how their runs went.
+    Batch(batch::BatchOpts),
+    /// Run a directory of programs within limits and compare what they
+    /// print with the `.expected` file next to each.
+    Test(regression::TestOpts),
+    /// Draw the playfield of a program as an SVG or PNG image.
+    Render(render::RenderOpts),
+    /// Print the playfield of a program with coordinates, marking where the
+    /// PC enters and the `@` cells, followed by the name of the instruction
+    /// in each cell.
+    List(list::ListOpts),
+    /// Write a starter program showing common idioms, with comments on how
+    /// it is laid out.
+    New(new::NewOpts),
+    /// Find the first step at which two configurations behave differently.
+    Bisect(bisect::BisectOpts),
+    /// Animate programs with their execution counts and the PC's trail, in
+    /// an endless loop. Random programs are shown unless some are given.
+    Art(art::ArtOpts),
+    /// Debug a program together with others over TCP (experimental).
+    Collab(collab::CollabCmd),
+    /// Serve an HTTP API running programs within limits, answering with
+    /// their output, final stack and steps, as `bef judge` does.
+    Serve(serve::ServeOpts),
+    #[cfg(feature = "tui")]
+    /// Debug a program full-screen, with panes for the playfield, stack and
+    /// output, breakpoints and a command bar.
+    Debug(debug::DebugOpts),
+    #[cfg(feature = "tui")]
+    /// Edit a program full-screen, switching between editing and debugging
+    /// it. The file is created when saved if it doesn't exist.
+    Edit(debug::DebugOpts),
+    /// Run a program printing only its output, as used by a
+    /// `#!/usr/bin/env -S bef pipe` line.
+    Pipe {
+        /// Path to program file, or `-` for stdin.
+        file: PathBuf,
+        #[structopt(flatten)]
+        output: run::OutputOpts,
+    },
+    /// Check that a program loads and fits the playfield, ends somewhere
+    /// and only executes instructions; optionally, analyze it statically.
+    Check {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long)]
+        /// Report unreachable code and places the program can't terminate
+        /// from.
+        analyze: bool,
+    },
+    /// Normalize the layout of program files.
+    Fmt {
+        /// Program files to format.
+        #[structopt(required = true)]
+        files: Vec<PathBuf>,
+        #[structopt(short, long)]
+        /// Rewrite the files instead of printing them.
+        in_place: bool,
+        #[structopt(long, conflicts_with = "in-place")]
+        /// Print the changes instead of the formatted files.
+        diff: bool,
+        #[structopt(long, conflicts_with_all = &["in-place", "diff"])]
+        /// Print nothing, but fail if a file isn't formatted.
+        check: bool,
+        #[structopt(long)]
+        /// Pad the field to its full size with spaces.
+        pad: bool,
+        #[structopt(long, default_value = "80x25", parse(try_from_str = parse_size))]
+        /// Size of the field, as COLSxROWS.
+        size: (usize, usize),
+        #[structopt(long, default_value = "8")]
+        /// Distance between tab stops.
+        tab_width: usize,
+    },
+    /// Convert a program file between the formats of bef and of other
+    /// Befunge tools, failing if it doesn't fit the playfield.
+    Convert {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long, possible_values = SourceFormat::NAMES)]
+        /// Format of the program file, that of `--source-format` if not
+        /// given.
+        from: Option<SourceFormat>,
+        #[structopt(long, possible_values = SourceFormat::NAMES)]
+        /// Format to write the program in.
+        to: SourceFormat,
+        #[structopt(short, long)]
+        /// Write the program here instead of to stdout.
+        output: Option<PathBuf>,
+    },
+    /// Rewrite a program to do less work without changing what it does.
+    Optimize {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(short, long)]
+        /// Write the program here instead of to stdout.
+        output: Option<PathBuf>,
+        #[structopt(short, long)]
+        /// List every changed cell on stderr.
+        verbose: bool,
+    },
+    /// Translate a program into a standalone Rust or C source file.
+    Compile {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long, default_value = "rust", possible_values = Target::NAMES)]
+        /// Language to translate to.
+        emit: Target,
+        #[structopt(short, long)]
+        /// Write the source here instead of to stdout.
+        output: Option<PathBuf>,
+        #[structopt(long)]
+        /// Also write a JSON source map mapping generated lines to cells.
+        source_map: Option<PathBuf>,
+    },
+    /// Run a program here and in a reference implementation with the same
+    /// input, and compare their output.
+    DiffRun {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long)]
+        /// Command running the reference; the program path replaces `{}`,
+        /// or is appended.
+        against: String,
+        #[structopt(long, default_value = "10")]
+        /// Seconds the reference may run.
+        timeout: u64,
+        #[structopt(long, default_value = "10000000")]
+        /// Steps this interpreter may run.
+        max_steps: usize,
+        #[structopt(long)]
+        /// Print the comparison as JSON.
+        json: bool,
+    },
+    /// Write a step-by-step walkthrough of a run, one page per step, as
+    /// teaching material.
+    Teach {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long, default_value = "1-40", parse(try_from_str = parse_steps))]
+        /// Steps to describe, as FIRST-LAST or a single step.
+        steps: RangeInclusive<usize>,
+        #[structopt(long, default_value = "text", possible_values = &["text", "html"])]
+        /// Output format.
+        format: String,
+        #[structopt(short, long)]
+        /// Write the document here instead of to stdout.
+        output: Option<PathBuf>,
+    },
+    /// Judge a submission: run it deterministically within tight limits
+    /// and print a JSON report with a verdict.
+    Judge {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(long)]
+        /// File holding the expected output.
+        expected: Option<PathBuf>,
+        #[structopt(long, default_value = "10000000")]
+        max_steps: usize,
+        #[structopt(long, default_value = "100000")]
+        /// Largest number of values on the stack.
+        max_stack: usize,
+        #[structopt(long, default_value = "65536")]
+        /// Largest output, in bytes.
+        max_output: usize,
+        #[structopt(long, default_value = "5000")]
+        /// Wall-clock time limit, in milliseconds.
+        time_limit: u64,
+        #[structopt(long, default_value = "0")]
+        /// Seed of the directions taken by `?`.
+        seed: u64,
+    },
+    /// Print the instructions of a dialect, with their stack effects.
+    Instructions {
+        #[structopt(long, default_value = "befunge-93")]
+        dialect: Dialect,
+        #[structopt(long, default_value = "text", possible_values = &["text", "markdown", "json"])]
+        format: String,
+    },
+    /// Type instructions and run them right away on a persistent stack.
+    Repl,
+    /// List the packs of novelty instructions `--pack` can add.
+    Packs,
+    /// Serve the language server protocol on stdin and stdout, giving
+    /// editors diagnostics, hover documentation and outlines of programs.
+    Lsp,
+    /// Run the built-in conformance programs against this interpreter.
+    Selftest,
+    /// Print the JSON schema of a document written by bef, or list them.
+    Schema {
+        #[structopt(possible_values = &schema::NAMES)]
+        /// Document to print the schema of.
+        name: Option<String>,
+    },
+}
+
+impl Opts {
+    /// Whether program input is read from stdin as asked with `--input -`.
+    fn input_from_stdin(&self) -> bool {
+        self.input.as_deref().is_some_and(is_stdin)
+    }
+
+    /// Whether a run that took `steps` steps must stop, per `--max-steps`.
+    fn over_step_limit(&self, steps: usize) -> bool {
+        self.max_steps.is_some_and(|max| steps >= max)
+    }
+
+    /// How long a run may take, per `--timeout`.
+    fn time_limit(&self) -> Duration {
+        self.timeout.map_or(Duration::MAX, Duration::from_millis)
+    }
+
+    /// The `result` of a run with `time_limit`, failing if it was stopped
+    /// at the limit.
+    fn limit_time(&self, result: Result<Progress>) -> Result<()> {
+        match (result, self.timeout) {
+            (Ok(progress), Some(ms)) if progress.timed_out => Err(anyhow!(
+                "Stopped after {} ms, the limit set with --timeout",
+                ms
+            )),
+            (result, _) => result.map(|_| ()),
+        }
+    }
+
+    /// The `result` of a run, failing if it was stopped by `--max-steps`.
+    fn limit_steps(&self, result: Result<()>, limited: bool) -> Result<()> {
+        match (result, self.max_steps) {
+            (Ok(()), Some(max)) if limited => Err(anyhow!(
+                "Stopped after {} steps, the limit set with --max-steps",
+                max
+            )),
+            (result, _) => result,
+        }
+    }
+
+    /// Print `diag` in the requested format and exit with a failure code.
+    fn report(&self, path: &Path, diag: Diagnostic) -> ! {
+        let file = if is_stdin(path) {
+            "<stdin>".to_string()
+        } else {
+            path.display().to_string()
+        };
+        let diag = diag.with_file(file);
+        if self.error_format == "json" {
+            eprintln!("{}", diag.to_json());
+        } else {
+            eprintln!("{}", diag.render(theme()));
+        }
+        process::exit(1);
+    }
+}
+
+/// Die of SIGPIPE like other tools when the program reading bef's output
+/// exits, rather than panicking on the next write.
+#[cfg(unix)]
+fn reset_sigpipe() {
+    // SAFETY: nothing else runs yet, and SIG_DFL is a valid handler.
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+fn main() -> Result<()> {
+    #[cfg(unix)]
+    reset_sigpipe();
+    let opts = Opts::from_args();
+    style::init(opts.theme.as_deref(), opts.no_color)?;
+
+    match &opts.cmd {
+        Some(Cmd::Semantics { semantics, diff }) => {
+            print_semantics(semantics, diff.as_ref());
+            Ok(())
+        }
+        Some(Cmd::CorpusStats { dir, format }) => {
+            let stats = CorpusStats::scan(dir)
+                .with_context(|| anyhow!("Failed to scan '{}'", dir.display()))?;
+            if format == "csv" {
+                print!("{}", stats.to_csv());
+            } else {
+                println!("{}", serde_json::to_string_pretty(&Versioned::new(&stats))?);
+            }
+            Ok(())
+        }
+        Some(Cmd::Ls { dir, recursive }) => list(dir, *recursive),
+        Some(Cmd::DiffRun {
+            file,
+            against,
+            timeout,
+            max_steps,
+            json,
+        }) => {
+            let reference = Reference::new(against, Duration::from_secs(*timeout))?;
+            diff_run(&opts, file, &reference, *max_steps, *json)
+        }
+        Some(Cmd::Teach {
+            file,
+            steps,
+            format,
+            output,
+        }) => teach(&opts, file, steps.clone(), format, output.as_deref()),
+        Some(Cmd::Judge {
+            file,
+            expected,
+            max_steps,
+            max_stack,
+            max_output,
+            time_limit,
+            seed,
+        }) => {
+            let limits = JudgeLimits {
+                max_steps: *max_steps,
+                max_stack: *max_stack,
+                max_output: *max_output,
+                time_limit: Duration::from_millis(*time_limit),
+                seed: *seed,
+            };
+            judge(&opts, file, expected.as_deref(), &limits)
+        }
+        Some(Cmd::Instructions { dialect, format }) => {
+            print_instructions(*dialect, format)?;
+            Ok(())
+        }
+        Some(Cmd::Repl) => repl::main(&opts),
+        Some(Cmd::Lsp) => lsp::main(&opts),
+        Some(Cmd::Packs) => {
+            for pack in packs::PACKS {
+                println!("{}: {}", theme().strong.paint(pack.name), pack.description);
+                for i in pack.instructions {
+                    println!("  {}  {} {:<10} {}", i.c, i.emoji, i.name, i.description);
+                }
+            }
+            Ok(())
+        }
+        Some(Cmd::Selftest) => selftest(),
+        Some(Cmd::Schema { name: None }) => {
+            println!("Schema version {}", schema::SCHEMA_VERSION);
+            for name in &schema::NAMES {
+                println!("  {}", name);
+            }
+            Ok(())
+        }
+        Some(Cmd::Schema { name: Some(name) }) => {
+            println!("{}", serde_json::to_string_pretty(&schema::schema(name)?)?);
+            Ok(())
+        }
+        Some(Cmd::Trace(cmd)) => trace::main(cmd),
+        Some(Cmd::Profile(cmd)) => profile::main(&opts, cmd),
+        Some(Cmd::Cover(cover_opts)) => cover::main(&opts, cover_opts),
+        Some(Cmd::Bench(bench_opts)) => bench::main(&opts, bench_opts),
+        Some(Cmd::Batch(batch_opts)) => batch::main(&opts, batch_opts),
+        Some(Cmd::Test(test_opts)) => regression::main(test_opts),
+        Some(Cmd::Render(render_opts)) => render::main(&opts, render_opts),
+        Some(Cmd::List(list_opts)) => list::main(&opts, list_opts),
+        Some(Cmd::New(new_opts)) => new::main(&opts, new_opts),
+        Some(Cmd::Bisect(opts)) => bisect::main(opts),
+        Some(Cmd::Art(art_opts)) => art::main(&opts, art_opts),
+        Some(Cmd::Collab(cmd)) => collab::main(&opts, cmd),
+        Some(Cmd::Serve(serve_opts)) => serve::main(serve_opts),
+        #[cfg(feature = "tui")]
+        Some(Cmd::Debug(debug_opts)) => debug::main(&opts, debug_opts, false),
+        #[cfg(feature = "tui")]
+        Some(Cmd::Edit(debug_opts)) => debug::main(&opts, debug_opts, true),
+        Some(Cmd::Run(run_opts)) => run::main(&opts, run_opts),
+        Some(Cmd::Pipe { file, output }) => run::pipe(&opts, file, output),
+        Some(Cmd::Check { file, analyze }) => check(&opts, file, *analyze),
+        Some(Cmd::Fmt {
+            files,
+            in_place,
+            diff,
+            check,
+            pad,
+            size: (cols, rows),
+            tab_width,
+        }) => {
+            let fmt_opts = FormatOptions {
+                cols: *cols,
+                rows: *rows,
+                tab_width: (*tab_width).max(1),
+                pad: *pad,
+                comment_marker: Some(opts.comment_marker.clone()).filter(|_| !opts.no_comments),
+            };
+            let mode = match (*in_place, *diff, *check) {
+                (true, _, _) => FmtMode::InPlace,
+                (_, true, _) => FmtMode::Diff,
+                (_, _, true) => FmtMode::Check,
+                _ => FmtMode::Print,
+            };
+            fmt(files, &fmt_opts, mode)
+        }
+        Some(Cmd::Convert {
+            file,
+            from,
+            to,
+            output,
+        }) => convert(
+            &opts,
+            file,
+            from.unwrap_or(opts.source_format),
+            *to,
+            output.as_deref(),
+        ),
+        Some(Cmd::Optimize {
+            file,
+            output,
+            verbose,
+        }) => optimize(&opts, file, output.as_deref(), *verbose),
+        Some(Cmd::Compile {
+            file,
+            emit,
+            output,
+            source_map,
+        }) => compile(&opts, file, *emit, output.as_deref(), source_map.as_deref()),
+        None => run::main(&opts, &opts.run),
+    }
+}
+
+fn print_instructions(dialect: Dialect, format: &str) -> Result<()> {
+    match format {
+        "markdown" => print!("{}", instructions::reference_table(dialect)),
+        "json" => println!(
+            "{}",
+            serde_json::to_string_pretty(&instructions::instructions(dialect))?
+        ),
+        _ => {
+            for doc in instructions::instructions(dialect) {
+                println!(
+                    "{}  {:<17} {:<11} {}",
+                    theme().strong.paint(doc.c.to_string()),
+                    doc.name,
+                    doc.stack,
+                    doc.description
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_semantics(semantics: &Semantics, other: Option<&Semantics>) {
+    let other = match other {
+        Some(other) => other,
+        None => {
+            for (key, value) in semantics.knobs() {
+                println!("{:<14} {}", key, value);
+            }
+            return;
+        }
+    };
+
+    println!("{:<14} {:<10} right", "knob", "left");
+    for ((key, left), (_, right)) in semantics.knobs().into_iter().zip(other.knobs()) {
+        let line = format!("{:<14} {:<10} {}", key, left, right);
+        if left == right {
+            println!("{}", line);
+        } else {
+            println!("{}", theme().warning.paint(line));
+        }
+    }
+}
+
+/// Print a thumbnail and a summary of every program in `dir`.
+fn list(dir: &Path, recursive: bool) -> Result<()> {
+    let paths = corpus::find_programs(dir, recursive)
+        .with_context(|| anyhow!("Failed to list '{}'", dir.display()))?;
+    for path in paths {
+        let source =
+            fs::read(&path).with_context(|| anyhow!("Failed to read '{}'", path.display()))?;
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let stats = ProgramStats::analyze(name, &source);
+        let notes = [
+            theme().strong.paint(&stats.path).to_string(),
+            format!(
+                "{}x{}, {} cells, {} bytes",
+                stats.cols, stats.rows, stats.cells, stats.bytes
+            ),
+            stats.features.join(", "),
+        ];
+
+        for (i, row) in corpus::thumbnail(&source, 20, 5).iter().enumerate() {
+            let note = notes.get(i).map_or("", String::as_str);
+            let line = format!("\u{2502}{}\u{2502} {}", row, note);
+            println!("{}", line.trim_end());
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// An interpreter configured by `opts`, with no program loaded.
+fn new_interpreter(opts: &Opts) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_semantics(opts.semantics);
+    interpreter.set_stack_limit(opts.max_stack.map(|depth| StackLimit {
+        depth,
+        overflow: opts.stack_overflow,
+    }));
+    interpreter.set_output_limit(opts.max_output.map(|bytes| OutputLimit {
+        bytes,
+        overflow: opts.output_overflow,
+    }));
+    interpreter.detect_loops(opts.detect_loops);
+    interpreter.set_input_timeout(opts.input_timeout.map(|ms| InputTimeout {
+        duration: Duration::from_millis(ms),
+        on_timeout: opts.on_input_timeout,
+    }));
+    interpreter.set_comment_marker(Some(opts.comment_marker.clone()).filter(|_| !opts.no_comments));
+    if opts.seed.is_some() {
+        interpreter.set_seed(opts.seed);
+    }
+    interpreter
+        .set_dialect(opts.dialect)
+        .expect("no custom instructions are defined yet");
+    for pack in &opts.packs {
+        if let Err(e) = pack.install(&mut interpreter) {
+            eprintln!("Failed to install pack '{}': {}", pack.name, e);
+            process::exit(1);
+        }
+    }
+    interpreter
+}
+
+/// Load the program at `path` as configured by `opts`, reporting failures.
+fn load(opts: &Opts, path: &Path) -> Interpreter {
+    load_with_source(opts, path).0
+}
+
+/// Load the program at `path` like `load`, also returning its source as it
+/// was read, which can't be read again from stdin.
+fn load_with_source(opts: &Opts, path: &Path) -> (Interpreter, Vec<u8>) {
+    let mut interpreter = new_interpreter(opts);
+
+    let result = read_program_of(opts, path).and_then(|source| {
+        interpreter
+            .load(&mut &source[..])
+            .context("Failed to load program")?;
+        Ok(source)
+    });
+    match result {
+        Ok(source) => (interpreter, source),
+        Err(e) => opts.report(path, Diagnostic::from_error(&e)),
+    }
+}
+
+/// Whether `path` stands for stdin.
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Read the program at `path`, or from stdin if it is `-`.
+fn read_program(path: &Path) -> Result<Vec<u8>> {
+    if is_stdin(path) {
+        let mut source = vec![];
+        io::stdin()
+            .read_to_end(&mut source)
+            .context("Failed to read the program from stdin")?;
+        Ok(source)
+    } else {
+        fs::read(path).with_context(|| anyhow!("Failed to read '{}'", path.display()))
+    }
+}
+
+/// The line ending a program read from stdin when the input follows it.
+const INPUT_MARKER: &str = "__INPUT__";
+
+/// Read the program at `path` in the format of `--source-format`, leaving
+/// the input on stdin if the program is read from it too.
+fn read_program_of(opts: &Opts, path: &Path) -> Result<Vec<u8>> {
+    let source = if is_stdin(path) && opts.input_from_stdin() {
+        read_program_before_input()?
+    } else {
+        read_program(path)?
+    };
+    Ok(interop::import(&source, opts.source_format))
+}
+
+/// Read the program from stdin up to the line marking the start of the
+/// input.
+fn read_program_before_input() -> Result<Vec<u8>> {
+    let mut source = vec![];
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    loop {
+        let mut line = vec![];
+        if stdin
+            .read_until(b'\n', &mut line)
+            .context("Failed to read the program from stdin")?
+            == 0
+        {
+            break;
+        }
+        let text = line.strip_suffix(b"\n").unwrap_or(&line);
+        if text.strip_suffix(b"\r").unwrap_or(text) == INPUT_MARKER.as_bytes() {
+            break;
+        }
+        source.extend_from_slice(&line);
+    }
+    Ok(source)
+}
+
+/// Route program input as configured by `opts`, keeping it apart from the
+/// debugger's use of stdin.
+fn route_input(opts: &Opts, interpreter: &mut Interpreter, log: Option<&SessionLog>) -> Result<()> {
+    let input: Box<dyn Read + Send> = if opts.raw_input && io::stdin().is_terminal() {
+        Box::new(Keys::new())
+    } else if opts.input_from_stdin() {
+        Box::new(io::stdin())
+    } else if let Some(path) = &opts.input {
+        let file =
+            File::open(path).with_context(|| anyhow!("Failed to open '{}'", path.display()))?;
+        Box::new(BufReader::new(file))
+    } else {
+        Box::new(io::stdin())
+    };
+    match log {
+        Some(log) => interpreter.set_input(LoggedInput::new(input, log.clone())),
+        None => interpreter.set_input(input),
+    }
+    Ok(())
+}
+
+/// Where the code in `source`, after `skip` lines and up to the comment
+/// `marker`, doesn't fit a playfield of `size`, as a line and column
+/// counted from 1 and a message. Lines are measured in cells as `chars`
+/// loads them.
+fn layout_errors(
+    source: &[u8],
+    skip: usize,
+    marker: Option<&str>,
+    chars: Chars,
+    size: Bounds,
+) -> Vec<(usize, usize, String)> {
+    let mut lines: Vec<&[u8]> = source
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+    if source.ends_with(b"\n") {
+        lines.pop();
+    }
+    let code = lines
+        .into_iter()
+        .enumerate()
+        .skip(skip)
+        .take_while(|(_, line)| Some(*line) != marker.map(str::as_bytes));
+
+    let mut errors = vec![];
+    for (row, (line, text)) in code.enumerate() {
+        let len = chars.decode(text).len();
+        if len > size.cols {
+            errors.push((
+                line + 1,
+                size.cols + 1,
+                format!(
+                    "line is {} cells long, but the playfield is {} wide",
+                    len, size.cols
+                ),
+            ));
+        }
+        if row == size.rows {
+            errors.push((
+                line + 1,
+                1,
+                format!(
+                    "the playfield has {} rows, this line is past them",
+                    size.rows
+                ),
+            ));
+        }
+    }
+    errors
+}
+
+/// Check the program at `path` loads and fits the playfield, holds an `@`
+/// and executes no characters that aren't instructions, printing the
+/// findings of static analysis too if `analyze`. Exits with a failure code
+/// if a check fails or the program may not terminate.
+fn check(opts: &Opts, path: &Path, analyze: bool) -> Result<()> {
+    let (interpreter, source) = load_with_source(opts, path);
+    let file = path.display();
+
+    let mut failed = false;
+    let mut fail = |line: usize, col: usize, message: String| {
+        println!(
+            "{}:{}:{}: {}",
+            file,
+            line,
+            col,
+            theme().error.paint(message)
+        );
+        failed = true;
+    };
+    let shebang = interpreter.get_shebang().is_some() as usize;
+    let marker = (!opts.no_comments).then_some(opts.comment_marker.as_str());
+    let chars = interpreter.get_semantics().chars;
+    let size = interpreter.get_size();
+    for (line, col, message) in layout_errors(&source, shebang, marker, chars, size) {
+        fail(line, col, message);
+    }
+    let text = String::from_utf8_lossy(&source);
+    let origins = lsp::origins(&text, shebang == 1, opts);
+    let analysis = interpreter.analyze();
+    for &pos in &analysis.unknown {
+        let c = interpreter.get_row(pos.y).chars().nth(pos.x).unwrap_or(' ');
+        if instructions::lookup(interpreter.get_dialect(), c).is_none() {
+            let (line, col) = origins[pos.y][pos.x].unwrap_or((pos.y + shebang, pos.x));
+            fail(
+                line + 1,
+                col + 1,
+                format!("{:?} is not an instruction, and is executed here", c),
+            );
+        }
+    }
+    if !(0..size.rows).any(|y| interpreter.get_row(y).contains('@')) {
+        fail(
+            1,
+            1,
+            "the program has no '@', so it can never end".to_string(),
+        );
+    }
+    if failed {
+        process::exit(1);
+    }
+    if !analyze {
+        return Ok(());
+    }
+
+    for (cells, what) in [
+        (&analysis.unreachable, "unreachable"),
+        (&analysis.never_ending, "can never reach '@'"),
+    ] {
+        for (pos, len) in row_runs(cells) {
+            let cols = if len == 1 {
+                (pos.x + 1).to_string()
+            } else {
+                format!("{}-{}", pos.x + 1, pos.x + len)
+            };
+            println!("{}:{}:{}: {}", file, pos.y + 1, cols, what);
+        }
+    }
+    for cycle in &analysis.cycles {
+        let pos = cycle[0];
+        println!(
+            "{}:{}:{}: {}",
+            file,
+            pos.y + 1,
+            pos.x + 1,
+            theme().error.paint(format!(
+                "loop through {} cells never terminates",
+                cycle.len()
+            ))
+        );
+    }
+    if analysis.self_modifying {
+        println!(
+            "{}",
+            theme()
+                .warning
+                .paint("note: the program uses 'p', so it may behave differently")
+        );
+    }
+
+    if !analysis.never_ending.is_empty() || !analysis.cycles.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Group `cells`, ordered by row and column, into runs of adjacent cells
+/// in a row, as their first cell and length.
+fn row_runs(cells: &[Position]) -> Vec<(Position, usize)> {
+    let mut runs: Vec<(Position, usize)> = vec![];
+    for &pos in cells {
+        match runs.last_mut() {
+            Some((start, len)) if start.y == pos.y && start.x + *len == pos.x => *len += 1,
+            _ => runs.push((pos, 1)),
+        }
+    }
+    runs
+}
+
+/// Parse a field size written as `COLSxROWS`.
+fn parse_size(s: &str) -> Result<(usize, usize)> {
+    let (cols, rows) = s.split_once('x').context("Expected COLSxROWS")?;
+    Ok((cols.parse()?, rows.parse()?))
+}
+
+fn parse_steps(s: &str) -> Result<RangeInclusive<usize>> {
+    let (first, last) = s.split_once('-').unwrap_or((s, s));
+    let (first, last) = (first.parse()?, last.parse()?);
+    if first == 0 || first > last {
+        bail!("Expected FIRST-LAST with 1 <= FIRST <= LAST");
+    }
+    Ok(first..=last)
+}
+
+/// What `bef fmt` does with formatted files.
+#[derive(Clone, Copy, PartialEq)]
+enum FmtMode {
+    Print,
+    InPlace,
+    Diff,
+    Check,
+}
+
+/// Format `files`. Exits with a failure code when checking and a file
+/// isn't formatted.
+fn fmt(files: &[PathBuf], fmt_opts: &FormatOptions, mode: FmtMode) -> Result<()> {
+    let mut unformatted = false;
+    for path in files {
+        let source = fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read '{}'", path.display()))?;
+        let formatted = format::format(&source, fmt_opts);
+        let changed = formatted != source;
+        match mode {
+            FmtMode::Print => print!("{}", formatted),
+            FmtMode::InPlace if changed => fs::write(path, &formatted)
+                .with_context(|| anyhow!("Failed to write '{}'", path.display()))?,
+            FmtMode::InPlace => {}
+            FmtMode::Diff if changed => {
+                println!("--- {}", path.display());
+                println!("+++ {}", path.display());
+                print!("{}", format::diff(&source, &formatted));
+            }
+            FmtMode::Diff => {}
+            FmtMode::Check if changed => {
+                eprintln!("{} is not formatted", path.display());
+                unformatted = true;
+            }
+            FmtMode::Check => {}
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Convert the program at `path` from format `from` to `to`, writing it to
+/// `output` or stdout.
+fn convert(
+    opts: &Opts,
+    path: &Path,
+    from: SourceFormat,
+    to: SourceFormat,
+    output: Option<&Path>,
+) -> Result<()> {
+    let source = interop::import(&read_program(path)?, from);
+    let mut interpreter = new_interpreter(opts);
+    interpreter
+        .load(&mut &source[..])
+        .with_context(|| anyhow!("Failed to load '{}'", path.display()))?;
+    let shebang = interpreter.get_shebang().is_some() as usize;
+    let chars = interpreter.get_semantics().chars;
+    let marker = interpreter.get_comment_marker();
+    let size = interpreter.get_size();
+    if let Some((line, _, message)) = layout_errors(&source, shebang, marker, chars, size).first() {
+        bail!(
+            "Failed to convert '{}': {} (line {})",
+            path.display(),
+            message,
+            line
+        );
+    }
+
+    let converted = interop::export(&interpreter, to)
+        .with_context(|| anyhow!("Failed to convert '{}' to {}", path.display(), to))?;
+    match output {
+        Some(out) => fs::write(out, converted)
+            .with_context(|| anyhow!("Failed to write '{}'", out.display()))?,
+        None => io::stdout().write_all(&converted)?,
+    }
+    Ok(())
+}
+
+/// Optimize the program at `path`, writing it to `output` or stdout.
+fn optimize(opts: &Opts, path: &Path, output: Option<&Path>, verbose: bool) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    let changes = interpreter
+        .optimize()
+        .with_context(|| anyhow!("Failed to optimize '{}'", path.display()))?;
+    if verbose {
+        for change in &changes {
+            eprintln!("{}", change);
+        }
+    }
+    eprintln!("{} cells changed", changes.len());
+
+    let source = interpreter.get_source();
+    match output {
+        Some(out) => fs::write(out, source)
+            .with_context(|| anyhow!("Failed to write '{}'", out.display()))?,
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+/// Translate the program at `path` into `target`, writing the source to
+/// `output` or stdout.
+fn compile(
+    opts: &Opts,
+    path: &Path,
+    target: Target,
+    output: Option<&Path>,
+    source_map: Option<&Path>,
+) -> Result<()> {
+    if opts.semantics.chars == Chars::Unicode {
+        bail!("Compiled programs work on bytes, so chars=unicode isn't supported");
+    }
+    if opts.semantics.cells != Cells::I64 {
+        bail!("Compiled programs work on 64-bit cells, so only cells=i64 is supported");
+    }
+    if opts.dialect.playfield() != Dialect::Befunge93.playfield() {
+        bail!(
+            "Compiled programs have the playfield of Befunge-93, so --dialect {} isn't supported",
+            opts.dialect
+        );
+    }
+    let interpreter = load(opts, path);
+    let mut generated = interpreter.translate(target);
+    generated.source_map.file = Some(path.display().to_string());
+
+    match output {
+        Some(out) => fs::write(out, &generated.source)
+            .with_context(|| anyhow!("Failed to write '{}'", out.display()))?,
+        None => print!("{}", generated.source),
+    }
+    if let Some(map) = source_map {
+        fs::write(map, generated.source_map.to_json())
+            .with_context(|| anyhow!("Failed to write '{}'", map.display()))?;
+    }
+    Ok(())
+}
+
+/// Compare the output of the program at `path` with that of `reference`,
+/// exiting with a failure code if they differ.
+fn diff_run(
+    opts: &Opts,
+    path: &Path,
+    reference: &Reference,
+    max_steps: usize,
+    json: bool,
+) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    let input = read_all_input(opts)?;
+
+    let comparison = differential::diff_run(&mut interpreter, max_steps, reference, path, &input)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&Versioned::new(&comparison))?
+        );
+    } else {
+        let sides = [
+            ("ours", &comparison.ours),
+            ("reference", &comparison.reference),
+        ];
+        match comparison.divergence {
+            None => println!(
+                "{} ({} characters)",
+                theme().ok.paint("Outputs match"),
+                comparison.ours.output.chars().count()
+            ),
+            Some(idx) => {
+                println!(
+                    "{} at character {}:",
+                    theme().error.paint("Outputs differ"),
+                    idx
+                );
+                for (name, result) in sides {
+                    let around: String = result
+                        .output
+                        .chars()
+                        .skip(idx.saturating_sub(20))
+                        .take(40)
+                        .collect();
+                    println!("  {:<9} {:?}", name, around);
+                }
+            }
+        }
+        for (name, result) in sides {
+            if let Some(error) = &result.error {
+                println!(
+                    "  {:<9} {} {}",
+                    name,
+                    theme().warning.paint("failed:"),
+                    error
+                );
+            }
+        }
+    }
+    if comparison.divergence.is_some() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn teach(
+    opts: &Opts,
+    path: &Path,
+    steps: RangeInclusive<usize>,
+    format: &str,
+    output: Option<&Path>,
+) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    route_input(opts, &mut interpreter, None)?;
+    let frames = interpreter
+        .teach(steps)
+        .context("Failed to run the program")?;
+    let document = if format == "html" {
+        teach::to_html(&frames, &path.display().to_string())
+    } else {
+        teach::to_text(&frames)
+    };
+    match output {
+        Some(output) => fs::write(output, document)
+            .with_context(|| anyhow!("Failed to write '{}'", output.display())),
+        None => {
+            print!("{}", document);
+            Ok(())
+        }
+    }
+}
+
+/// Judge the program at `path`, exiting with a failure code unless it
+/// finished and, if there is an expected output, printed it.
+fn judge(opts: &Opts, path: &Path, expected: Option<&Path>, limits: &JudgeLimits) -> Result<()> {
+    let program = read_program_of(opts, path)?;
+    let input = read_all_input(opts)?;
+    let expected = expected
+        .map(|path| {
+            fs::read_to_string(path).with_context(|| anyhow!("Failed to read '{}'", path.display()))
+        })
+        .transpose()?;
+
+    let report = judge::judge(&program, &input, expected.as_deref(), limits);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Versioned::new(&report))?
+    );
+    if !matches!(report.verdict, Verdict::Accepted | Verdict::Finished) {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// All of the program input, from `--input` or stdin. Unless `--input -`
+/// asks for it, a terminal on stdin gives none rather than waiting for it.
+fn read_all_input(opts: &Opts) -> Result<Vec<u8>> {
+    match &opts.input {
+        Some(path) if !is_stdin(path) => {
+            fs::read(path).with_context(|| anyhow!("Failed to read '{}'", path.display()))
+        }
+        None if io::stdin().is_terminal() => Ok(vec![]),
+        _ => {
+            let mut input = vec![];
+            io::stdin().read_to_end(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+fn selftest() -> Result<()> {
+    let results = conformance::run_suite();
+    let mut failed = 0;
+    for result in &results {
+        let case = result.case;
+        if result.passed() {
+            println!("{} {}", theme().ok.paint("ok    "), case.name);
+            continue;
+        }
+        failed += 1;
+        println!(
+            "{} {}: {}",
+            theme().error.paint("FAILED"),
+            case.name,
+            case.description
+        );
+        println!("       expected {:?}", case.expected);
+        println!("       got      {:?}", result.output);
+        if let Some(error) = &result.error {
+            println!("       error    {}", error);
+        }
+    }
+    println!("\n{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use befunge_93::{COMMENT_MARKER, PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+    const BEFUNGE_93: Bounds = Bounds {
+        cols: PLAYFIELD_COLS,
+        rows: PLAYFIELD_ROWS,
+    };
+
+    #[test]
+    fn measures_lines_as_loaded() {
+        // 80 cells as Unicode, but 81 bytes.
+        let source = format!("{}é\n@\n", ">".repeat(79));
+        assert!(layout_errors(source.as_bytes(), 0, None, Chars::Unicode, BEFUNGE_93).is_empty());
+        let errors = layout_errors(source.as_bytes(), 0, None, Chars::Bytes, BEFUNGE_93);
+        assert_eq!(
+            errors,
+            vec![(
+                1,
+                81,
+                "line is 81 cells long, but the playfield is 80 wide".to_string()
+            )]
+        );
+
+        let source = format!("{}é\n", ">".repeat(80));
+        assert_eq!(
+            layout_errors(source.as_bytes(), 0, None, Chars::Unicode, BEFUNGE_93).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn stops_at_the_comment_marker() {
+        let source = format!("@\r\n{}\r\n{}\n", COMMENT_MARKER, "x".repeat(100));
+        assert!(layout_errors(
+            source.as_bytes(),
+            0,
+            Some(COMMENT_MARKER),
+            Chars::Bytes,
+            BEFUNGE_93
+        )
+        .is_empty());
+        assert_eq!(
+            layout_errors(source.as_bytes(), 0, None, Chars::Bytes, BEFUNGE_93).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn measures_lines_on_the_playfield_of_the_dialect() {
+        let source = format!("{}\n", ">".repeat(100)).repeat(30);
+        let size = Dialect::Befunge96.playfield();
+        assert!(layout_errors(source.as_bytes(), 0, None, Chars::Bytes, size).is_empty());
+        assert_eq!(
+            layout_errors(source.as_bytes(), 0, None, Chars::Bytes, BEFUNGE_93).len(),
+            31
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! `bef new`: starter programs showing common idioms, with comments on
+//! how they are laid out, so that nobody has to begin from an empty
+//! playfield.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use structopt::StructOpt;
+
+use crate::Opts;
+
+/// A starter program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Template {
+    Hello,
+    Loop,
+    InputEcho,
+}
+
+impl Template {
+    /// Names of all templates, as accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["hello", "loop", "input-echo"];
+
+    /// The code of the program.
+    fn code(self) -> &'static str {
+        match self {
+            Self::Hello => "55+\"!dlroW ,olleH\">:#,_@\n",
+            Self::Loop => "1>:.1+:55+`#@_v\n ^            <\n",
+            Self::InputEcho => ">~:1+!#@_,v\n^         <\n",
+        }
+    }
+
+    /// What the program does and how, written after the comment marker.
+    fn comments(self) -> &'static str {
+        match self {
+            Self::Hello => {
+                "\
+Prints a line, using the string printer idiom.
+
+55+              push 10, a newline, which is printed last
+\"!dlroW ,olleH\"  push the text backwards, so that its first character
+                 ends up on top
+>:#,_            print characters until the 0 under the text, which an
+                 empty stack gives: `:` copies the top, `_` goes left to
+                 `,` unless it is 0, and `#` jumps over `,` on the way
+                 right and over `:` on the way left
+@                end
+
+Change the text between the quotes to print something else.
+"
+            }
+            Self::Loop => {
+                "\
+Prints the numbers from 1 to 10, using a counter loop.
+
+1            the counter starts at 1
+>:.          print a copy of the counter
+1+           count one more
+:55+`        compare a copy with 10: 1 if it is past it, 0 otherwise
+#@_          `_` goes right while it isn't, and left onto `@` when it
+             is; `#` jumps over `@` on the way in
+v, <, ^      the second row carries the PC back to the start of the
+             loop, at `>`
+
+Change the 55+ to count to something else, and put the body of the loop
+between `>` and the comparison.
+"
+            }
+            Self::InputEcho => {
+                "\
+Copies the input to the output, character by character, until it ends.
+
+>~           read a character; at the end of the input -1 is pushed
+:1+!         1 if a copy of it is -1, 0 otherwise
+#@_          `_` goes right while the input goes on, and left onto `@`
+             once it has ended; `#` jumps over `@` on the way in
+,            print the character
+v, <, ^      the second row carries the PC back to the start of the
+             loop, at `>`
+
+Put what to do with each character between `_` and `,`. Runs with
+--semantics eof=... other than -1 need another test for the end.
+"
+            }
+        }
+    }
+
+    /// The program file: the code, the comment marker and the comments.
+    pub fn render(self, marker: &str) -> String {
+        format!("{}{}\n{}", self.code(), marker, self.comments())
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hello" => Ok(Self::Hello),
+            "loop" => Ok(Self::Loop),
+            "input-echo" => Ok(Self::InputEcho),
+            _ => bail!(
+                "Unknown template '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hello => write!(f, "hello"),
+            Self::Loop => write!(f, "loop"),
+            Self::InputEcho => write!(f, "input-echo"),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+pub struct NewOpts {
+    /// Name of the program; `.bf` is added unless it has an extension.
+    name: PathBuf,
+    #[structopt(long, default_value = "hello", possible_values = Template::NAMES)]
+    /// Program to start from.
+    template: Template,
+}
+
+pub fn main(opts: &Opts, new_opts: &NewOpts) -> Result<()> {
+    let mut path = new_opts.name.clone();
+    if path.extension().is_none() {
+        path.set_extension("bf");
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| anyhow!("Failed to create '{}'", path.display()))?;
+    file.write_all(new_opts.template.render(&opts.comment_marker).as_bytes())
+        .with_context(|| anyhow!("Failed to write '{}'", path.display()))?;
+    eprintln!(
+        "Created {} from the {} template; run it with `bef run -f {}`",
+        path.display(),
+        new_opts.template,
+        path.display()
+    );
+    Ok(())
+}
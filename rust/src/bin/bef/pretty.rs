@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+
+use befunge_93::instructions::{self, Dialect};
+use befunge_93::trace::TraceEvent;
+use befunge_93::Command;
+
+use crate::style::theme;
+
+/// Kinds of instructions a trace can be filtered by.
+pub const KINDS: &[&str] = &[
+    "arith", "flow", "stack", "push", "string", "io", "mem", "other",
+];
+
+/// The kind of `cmd`, one of `KINDS` other than "string".
+pub fn kind(cmd: Command) -> &'static str {
+    instructions::lookup(Dialect::Befunge93, cmd.as_char()).map_or("other", |doc| doc.kind.name())
+}
+
+/// The instruction kinds `--trace` prints.
+#[derive(Debug, Clone)]
+pub struct TraceFilter(Vec<&'static str>);
+
+impl FromStr for TraceFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut kinds = vec![];
+        for name in s.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+            let kind = match KINDS.iter().find(|&&k| k == name) {
+                Some(kind) => *kind,
+                None => bail!(
+                    "Unknown instruction kind '{}', expected one of: {}",
+                    name,
+                    KINDS.join(", ")
+                ),
+            };
+            kinds.push(kind);
+        }
+        Ok(Self(kinds))
+    }
+}
+
+/// A rectangle of the playfield, written `X0,Y0,X1,Y1` with both corners
+/// included.
+#[derive(Debug, Clone, Copy)]
+pub struct Region([usize; 4]);
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<usize>, _>>()
+            .context("Expected X0,Y0,X1,Y1")?;
+        match coords[..] {
+            [x0, y0, x1, y1] if x0 <= x1 && y0 <= y1 => Ok(Self([x0, y0, x1, y1])),
+            [_, _, _, _] => bail!("The first corner must be above and left of the second"),
+            _ => bail!("Expected X0,Y0,X1,Y1"),
+        }
+    }
+}
+
+/// Prints trace events in aligned columns, collapsing runs of identical
+/// steps into a single count.
+pub struct TracePrinter {
+    kinds: Option<TraceFilter>,
+    region: Option<Region>,
+    last: Option<String>,
+    repeats: usize,
+}
+
+impl TracePrinter {
+    pub fn new(kinds: Option<TraceFilter>, region: Option<Region>) -> Self {
+        Self {
+            kinds,
+            region,
+            last: None,
+            repeats: 0,
+        }
+    }
+
+    pub fn print(&mut self, event: &TraceEvent) {
+        let kind_ok = self
+            .kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.0.contains(&kind(event.command)));
+        let region_ok = self.region.is_none_or(|Region([x0, y0, x1, y1])| {
+            (x0..=x1).contains(&event.pc.x) && (y0..=y1).contains(&event.pc.y)
+        });
+        if !kind_ok || !region_ok {
+            return;
+        }
+
+        let stack: Vec<_> = event.stack_after.iter().map(|v| v.to_string()).collect();
+        let mut line = format!(
+            "({:>2},{:>2}) {} {:<4} [{}]",
+            event.pc.x,
+            event.pc.y,
+            event.dir,
+            format!("{:?}", char::from(event.command)),
+            stack.join(" ")
+        );
+        if !event.output_delta.is_empty() {
+            line += &format!(" {:?}", event.output_delta);
+        }
+        if let Some(m) = &event.modification {
+            line += &format!(" {} {:?} -> {:?}", m.pos, m.old.as_char(), m.new.as_char());
+        }
+
+        if self.last.as_ref() == Some(&line) {
+            self.repeats += 1;
+            return;
+        }
+        self.flush();
+        println!("{:>8}  {}", event.step, line);
+        self.last = Some(line);
+    }
+
+    /// Print a note about the run between steps.
+    pub fn note(&mut self, text: &str) {
+        self.flush();
+        println!("{:>8}  {}", "", theme().faint.paint(text));
+    }
+
+    /// Print the count of steps collapsed since the last printed one.
+    pub fn flush(&mut self) {
+        if self.repeats > 0 {
+            println!(
+                "{:>8}  {}",
+                "",
+                theme().faint.paint(format!("\u{d7} {}", self.repeats))
+            );
+            self.repeats = 0;
+        }
+    }
+}
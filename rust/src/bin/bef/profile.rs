@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ansi_term::Colour::{self, Black, Blue, Cyan, Green, Red, Yellow};
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::profile::{heat_level, SavedProfile};
+
+use crate::pretty::{self, KINDS};
+use crate::style::{self, theme};
+use crate::{load, route_input, Opts};
+
+#[derive(StructOpt)]
+pub struct ProfileOpts {
+    #[structopt(subcommand)]
+    cmd: Option<ProfileCmd>,
+    #[structopt(short, long)]
+    /// Run a program and print a report of its hottest cells, its mix of
+    /// instructions and the time spent on each kind.
+    file: Option<PathBuf>,
+    #[structopt(long, default_value = "10")]
+    /// Number of most executed cells to report.
+    top: usize,
+}
+
+#[derive(StructOpt)]
+pub enum ProfileCmd {
+    /// Run a program and save how many times each cell was executed.
+    Record {
+        /// Path to program file.
+        file: PathBuf,
+        #[structopt(short, long)]
+        /// Where to write the profile.
+        output: PathBuf,
+    },
+    /// Add up profiles of the same program.
+    Merge {
+        #[structopt(required = true)]
+        profiles: Vec<PathBuf>,
+        #[structopt(short, long)]
+        /// Write the merged profile here instead of to stdout.
+        output: Option<PathBuf>,
+    },
+    /// Show profiles of the same program, added up, as a heat map.
+    Show {
+        #[structopt(required = true)]
+        profiles: Vec<PathBuf>,
+        #[structopt(long, default_value = "10")]
+        /// Number of most executed cells to list.
+        top: usize,
+    },
+}
+
+pub fn main(opts: &Opts, profile_opts: &ProfileOpts) -> Result<()> {
+    let cmd = match (&profile_opts.cmd, &profile_opts.file) {
+        (None, Some(file)) => return report(opts, file, profile_opts.top),
+        (Some(cmd), None) => cmd,
+        (Some(_), Some(_)) => bail!("Give either a file to report on or a subcommand, not both"),
+        (None, None) => bail!("Give a file to report on with -f, or a subcommand; see --help"),
+    };
+    match cmd {
+        ProfileCmd::Record { file, output } => record(opts, file, output),
+        ProfileCmd::Merge { profiles, output } => {
+            let profile = merge(profiles)?.to_json();
+            match output {
+                Some(path) => fs::write(path, profile + "\n")
+                    .with_context(|| anyhow!("Failed to write '{}'", path.display())),
+                None => {
+                    println!("{}", profile);
+                    Ok(())
+                }
+            }
+        }
+        ProfileCmd::Show { profiles, top } => {
+            show(&merge(profiles)?, *top);
+            Ok(())
+        }
+    }
+}
+
+fn record(opts: &Opts, path: &Path, output: &Path) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    route_input(opts, &mut interpreter, None)?;
+    let playfield = interpreter.save_profile().playfield;
+    let result = interpreter.run(|_, _| true);
+    print!("{}", interpreter.get_output());
+
+    let profile = SavedProfile {
+        playfield,
+        ..interpreter.save_profile()
+    };
+    fs::write(output, profile.to_json() + "\n")
+        .with_context(|| anyhow!("Failed to write '{}'", output.display()))?;
+    if let Err(e) = result.context("Failed to run the program") {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+    Ok(())
+}
+
+/// Steps taken and time spent on one kind of instruction.
+#[derive(Default, Clone, Copy)]
+struct KindStats {
+    steps: u64,
+    time: Duration,
+}
+
+fn report(opts: &Opts, path: &Path, top: usize) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    route_input(opts, &mut interpreter, None)?;
+
+    // A step is timed from the callback of the one before it, so the time
+    // includes a little of this bookkeeping, the same for every kind.
+    let mut kinds = vec![KindStats::default(); KINDS.len()];
+    let mut executing = kind_at(&interpreter);
+    let mut last = Instant::now();
+    let mut account = |kinds: &mut [KindStats], kind: &str| {
+        let now = Instant::now();
+        if let Some(idx) = KINDS.iter().position(|&k| k == kind) {
+            kinds[idx].steps += 1;
+            kinds[idx].time += now - last;
+        }
+        last = now;
+    };
+    let mut accounted = 0;
+    let mut limited = false;
+    let started = Instant::now();
+    let result = interpreter.run(|int, steps| {
+        account(&mut kinds, executing);
+        accounted = steps;
+        executing = kind_at(int);
+        limited = opts.over_step_limit(steps);
+        !limited
+    });
+    let result = opts.limit_steps(result, limited);
+    if interpreter.get_steps() > accounted {
+        account(&mut kinds, executing);
+    }
+    let elapsed = started.elapsed();
+
+    print!("{}", interpreter.get_output());
+    if !interpreter.get_output().is_empty() && !interpreter.get_output().ends_with('\n') {
+        println!();
+    }
+    let profile = interpreter.save_profile();
+    let steps = profile.steps();
+    println!(
+        "{} {} steps in {:.3} ms",
+        theme().strong.paint("Profile:"),
+        steps,
+        elapsed.as_secs_f64() * 1000.0
+    );
+
+    println!();
+    println!("{}", theme().strong.paint("Hottest cells"));
+    for (pos, count) in profile.hottest(top) {
+        let c = profile.playfield[pos.y].chars().nth(pos.x).unwrap_or(' ');
+        println!(
+            "  {:>10}  {:>5.1}%  {:<9} {:?}",
+            count,
+            percent(count, steps),
+            pos.to_string(),
+            c
+        );
+    }
+
+    let total: Duration = kinds.iter().map(|k| k.time).sum();
+    println!();
+    println!("{}", theme().strong.paint("Instruction mix"));
+    println!(
+        "  {:<6}  {:>10}  {:>6}  {:>10}  {:>6}",
+        "kind", "steps", "", "µs", ""
+    );
+    for (kind, stats) in KINDS.iter().zip(&kinds) {
+        if stats.steps == 0 {
+            continue;
+        }
+        println!(
+            "  {:<6}  {:>10}  {:>5.1}%  {:>10.1}  {:>5.1}%",
+            kind,
+            stats.steps,
+            percent(stats.steps, steps),
+            stats.time.as_secs_f64() * 1e6,
+            percent(stats.time.as_nanos() as u64, total.as_nanos() as u64)
+        );
+    }
+
+    if let Err(e) = result.context("Failed to run the program") {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+    Ok(())
+}
+
+/// The kind of instruction `int` executes next.
+fn kind_at(int: &befunge_93::Interpreter) -> &'static str {
+    let cmd = int.get_current_command();
+    if int.get_stringmode() && cmd.as_char() != '"' {
+        "string"
+    } else {
+        pretty::kind(cmd)
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+fn merge(paths: &[PathBuf]) -> Result<SavedProfile> {
+    let mut merged: Option<SavedProfile> = None;
+    for path in paths {
+        let profile = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| SavedProfile::from_json(&text))
+            .with_context(|| anyhow!("Failed to read profile '{}'", path.display()))?;
+        match &mut merged {
+            Some(merged) => merged
+                .merge(&profile)
+                .with_context(|| anyhow!("Failed to merge '{}'", path.display()))?,
+            None => merged = Some(profile),
+        }
+    }
+    Ok(merged.expect("at least one profile"))
+}
+
+/// The colour of a cell executed `count` times when the most executed one
+/// was executed `max` times, from blue to red.
+pub fn heat(count: u64, max: u64) -> Option<Colour> {
+    let heat = [Blue, Cyan, Green, Yellow, Red];
+    heat_level(count, max, heat.len()).map(|level| heat[level])
+}
+
+/// Print the program with the background of each cell coloured by how often
+/// it was executed, followed by the most executed cells.
+fn show(profile: &SavedProfile, top: usize) {
+    let counts = profile.counts();
+    let max = counts.iter().flatten().copied().max().unwrap_or(0);
+
+    for (y, line) in profile.playfield.iter().enumerate() {
+        let mut row = String::new();
+        for (x, c) in line.chars().enumerate() {
+            let cell = c.to_string();
+            match heat(counts[y][x], max).filter(|_| style::colours()) {
+                Some(colour) => row += &Black.on(colour).paint(cell).to_string(),
+                None => row += &theme().faint.paint(cell).to_string(),
+            }
+        }
+        println!("{}", row);
+    }
+
+    println!();
+    println!(
+        "{} runs, {} steps, {} cells executed",
+        profile.runs,
+        profile.steps(),
+        profile.cells.len()
+    );
+    for (pos, count) in profile.hottest(top) {
+        println!("  {:>10}  {}", count, pos);
+    }
+}
@@ -0,0 +1,35 @@
+use std::io::{self, BufRead, Read, Write};
+
+/// Program input read line by line from stdin after printing a prompt, so
+/// it can't be confused with the keys that step the debugger.
+pub struct Prompt {
+    prompt: &'static str,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl Prompt {
+    pub fn new(prompt: &'static str) -> Self {
+        Self {
+            prompt,
+            line: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for Prompt {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            eprint!("{}", self.prompt);
+            io::stderr().flush()?;
+            self.line.clear();
+            self.pos = 0;
+            io::stdin().lock().read_until(b'\n', &mut self.line)?;
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
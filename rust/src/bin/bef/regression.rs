@@ -0,0 +1,125 @@
+//! `bef test`: run a directory of example programs and compare what they
+//! print with their `.expected` files.
+
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::corpus;
+use befunge_93::judge::{JudgeLimits, Verdict};
+use befunge_93::regression::{self, Case};
+
+use crate::style::theme;
+
+#[derive(StructOpt)]
+pub struct TestOpts {
+    /// Directory of programs, each with a `.expected` file and optionally
+    /// a `.in` file next to it.
+    dir: PathBuf,
+    #[structopt(short, long)]
+    /// Also run programs in subdirectories.
+    recursive: bool,
+    #[structopt(long)]
+    /// Write what every program prints to its `.expected` file instead,
+    /// creating the missing ones.
+    bless: bool,
+    #[structopt(long, default_value = "10000000")]
+    /// Steps each program may take.
+    max_steps: usize,
+    #[structopt(long, default_value = "5000")]
+    /// Wall-clock time each program may take, in milliseconds.
+    time_limit: u64,
+}
+
+/// Run every case in the directory, printing whether each passed and how
+/// the output of those that didn't differs. Exits with a failure code if
+/// any failed.
+pub fn main(test_opts: &TestOpts) -> Result<()> {
+    let limits = JudgeLimits {
+        max_steps: test_opts.max_steps,
+        time_limit: Duration::from_millis(test_opts.time_limit),
+        ..JudgeLimits::default()
+    };
+    let dir = &test_opts.dir;
+    let cases: Vec<Case> = if test_opts.bless {
+        corpus::find_programs(dir, test_opts.recursive)?
+            .into_iter()
+            .map(Case::new)
+            .collect()
+    } else {
+        regression::discover(dir, test_opts.recursive)?
+    };
+    let name = |case: &Case| {
+        let path = case.program.strip_prefix(dir).unwrap_or(&case.program);
+        path.display().to_string()
+    };
+
+    let mut failed = vec![];
+    for case in &cases {
+        if test_opts.bless {
+            let report = regression::bless(case, &limits)?;
+            if report.verdict == Verdict::Finished {
+                println!("test {} ... blessed", name(case));
+            } else {
+                println!(
+                    "test {} ... {}: {}",
+                    name(case),
+                    theme().error.paint("not blessed"),
+                    report
+                        .error
+                        .as_deref()
+                        .unwrap_or(verdict_name(report.verdict))
+                );
+                failed.push(name(case));
+            }
+            continue;
+        }
+
+        let result = regression::run_case(case, &limits)
+            .with_context(|| anyhow!("Failed to run '{}'", case.program.display()))?;
+        if result.passed() {
+            println!("test {} ... ok", name(case));
+            continue;
+        }
+        println!("test {} ... {}", name(case), theme().error.paint("FAILED"));
+        match (&result.diff, &result.report.error) {
+            (Some(diff), _) => print!("{}", diff),
+            (None, Some(error)) => println!("{}", error),
+            (None, None) => println!("{}", verdict_name(result.report.verdict)),
+        }
+        failed.push(name(case));
+    }
+
+    println!();
+    if failed.is_empty() {
+        println!(
+            "{} programs {}",
+            cases.len(),
+            if test_opts.bless { "blessed" } else { "passed" }
+        );
+        return Ok(());
+    }
+    println!(
+        "{} of {} programs failed: {}",
+        failed.len(),
+        cases.len(),
+        failed.join(", ")
+    );
+    process::exit(1);
+}
+
+/// What stopped a run that didn't end with an error.
+fn verdict_name(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::StepLimit => "stopped at the step limit",
+        Verdict::StackLimit => "stopped at the stack limit",
+        Verdict::OutputLimit => "stopped at the output limit",
+        Verdict::TimeLimit => "stopped at the time limit",
+        Verdict::Accepted | Verdict::WrongAnswer | Verdict::Finished | Verdict::RuntimeError => {
+            "failed"
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+#[cfg(feature = "images")]
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::render::RenderOptions;
+
+use crate::style;
+use crate::{load, route_input, Opts};
+
+#[derive(StructOpt)]
+pub struct RenderOpts {
+    #[structopt(short, long)]
+    /// Path to program file.
+    file: PathBuf,
+    #[structopt(long, default_value = "svg", possible_values = &["svg", "png", "gif"])]
+    /// Image format; PNG and GIF need the `images` feature. A GIF is an
+    /// animation of the run, one frame per step, with the PC outlined.
+    format: String,
+    #[structopt(long)]
+    /// Outline the cell the PC is on.
+    pc: bool,
+    #[structopt(long)]
+    /// Draw every instruction in the same colour instead of colouring them
+    /// by category.
+    monochrome: bool,
+    #[structopt(long)]
+    /// Colour cells by how many times they were executed. The program is
+    /// run first, to the end or for `--steps`.
+    heatmap: bool,
+    #[structopt(long, value_name = "N")]
+    /// Run the program for N steps first, showing the playfield as it is
+    /// then.
+    steps: Option<usize>,
+    #[cfg(feature = "images")]
+    #[structopt(long, value_name = "MS", default_value = "100")]
+    /// Time each frame of a GIF is shown (in milliseconds).
+    delay: u64,
+    #[cfg(feature = "images")]
+    #[structopt(long, value_name = "N", default_value = "300")]
+    /// Most frames of a GIF; the animation stops there.
+    max_frames: usize,
+    #[structopt(short, long)]
+    /// Write the image here instead of to stdout.
+    output: Option<PathBuf>,
+}
+
+pub fn main(opts: &Opts, render_opts: &RenderOpts) -> Result<()> {
+    if render_opts.format == "gif" {
+        let image = gif(opts, render_opts)?;
+        return write(render_opts, &image);
+    }
+
+    let path = &render_opts.file;
+    let mut interpreter = load(opts, path);
+    if render_opts.heatmap || render_opts.steps.is_some() {
+        route_input(opts, &mut interpreter, None)?;
+        let mut limited = false;
+        let result = interpreter.run(|_, steps| {
+            limited = opts.over_step_limit(steps);
+            !limited && render_opts.steps.is_none_or(|max| steps < max)
+        });
+        if let Err(e) = opts
+            .limit_steps(result, limited)
+            .context("Failed to run the program")
+        {
+            opts.report(path, interpreter.diagnose(&e));
+        }
+    }
+
+    let options = RenderOptions {
+        pc: render_opts.pc,
+        heatmap: render_opts.heatmap,
+        highlight: (!render_opts.monochrome).then(style::palette),
+        ..Default::default()
+    };
+    let image = match render_opts.format.as_str() {
+        "png" => png(&interpreter, &options)?,
+        _ => interpreter.render_svg(&options).into_bytes(),
+    };
+    write(render_opts, &image)
+}
+
+fn write(render_opts: &RenderOpts, image: &[u8]) -> Result<()> {
+    match &render_opts.output {
+        Some(output) => fs::write(output, image)
+            .with_context(|| anyhow!("Failed to write '{}'", output.display())),
+        None if render_opts.format != "svg" && io::stdout().is_terminal() => {
+            bail!("Not writing a binary image to a terminal; give a file with -o")
+        }
+        None => io::stdout()
+            .write_all(image)
+            .context("Failed to write the image"),
+    }
+}
+
+#[cfg(feature = "images")]
+fn png(interpreter: &befunge_93::Interpreter, options: &RenderOptions) -> Result<Vec<u8>> {
+    interpreter.rasterize(options).to_png()
+}
+
+#[cfg(not(feature = "images"))]
+fn png(_: &befunge_93::Interpreter, _: &RenderOptions) -> Result<Vec<u8>> {
+    bail!("PNG images need bef to be built with the `images` feature")
+}
+
+/// An animation of a run, one frame per step.
+#[cfg(feature = "images")]
+fn gif(opts: &Opts, render_opts: &RenderOpts) -> Result<Vec<u8>> {
+    let path = &render_opts.file;
+    let mut interpreter = load(opts, path);
+    route_input(opts, &mut interpreter, None)?;
+    let options = RenderOptions {
+        pc: true,
+        heatmap: render_opts.heatmap,
+        highlight: (!render_opts.monochrome).then(style::palette),
+        ..Default::default()
+    };
+
+    let mut frames = vec![interpreter.rasterize(&options)];
+    let mut limited = false;
+    let result = interpreter.run(|int, steps| {
+        if frames.len() < render_opts.max_frames {
+            frames.push(int.rasterize(&options));
+        }
+        limited = opts.over_step_limit(steps);
+        let more = render_opts.steps.is_none_or(|max| steps < max);
+        !limited && more && frames.len() < render_opts.max_frames
+    });
+    if let Err(e) = opts
+        .limit_steps(result, limited)
+        .context("Failed to run the program")
+    {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+    befunge_93::render::encode_gif(&frames, Duration::from_millis(render_opts.delay))
+}
+
+#[cfg(not(feature = "images"))]
+fn gif(_: &Opts, _: &RenderOpts) -> Result<Vec<u8>> {
+    bail!("GIF images need bef to be built with the `images` feature")
+}
@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use befunge_93::instructions::{self, Dialect};
+use befunge_93::repl::Repl;
+
+use crate::prompt::Prompt;
+use crate::style::theme;
+use crate::{is_stdin, new_interpreter, Opts};
+
+/// Lines other than these commands are code, as `:` is an instruction.
+const HELP: &str = "Type instructions to run them on the same stack, e.g. `12+`.
+  :help [CHAR]   list the instructions, or describe one
+  :reset         empty the stack and the output
+  :quit          leave (or press Ctrl-D)";
+
+pub fn main(opts: &Opts) -> Result<()> {
+    let mut repl = Repl::new(new_interpreter(opts));
+    match &opts.input {
+        Some(path) if is_stdin(path) => {
+            bail!("Can't read program input from stdin, which the REPL reads lines from")
+        }
+        Some(path) => {
+            let file =
+                File::open(path).with_context(|| anyhow!("Failed to open '{}'", path.display()))?;
+            repl.interpreter_mut().set_input(BufReader::new(file));
+        }
+        None => repl
+            .interpreter_mut()
+            .set_input(Prompt::new("Program input> ")),
+    }
+
+    println!("{}", HELP);
+    let stdin = io::stdin();
+    loop {
+        eprint!("bef> ");
+        io::stderr().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            eprintln!();
+            return Ok(());
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        match line.trim() {
+            "" => continue,
+            ":quit" => return Ok(()),
+            ":reset" => repl.reset(),
+            ":help" => {
+                for doc in instructions::instructions(Dialect::Befunge93) {
+                    println!("  {}  {:<17} {}", doc.c, doc.name, doc.stack);
+                }
+                continue;
+            }
+            cmd if cmd.starts_with(":help ") => {
+                let mut chars = cmd[":help ".len()..].trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => match instructions::lookup(Dialect::Befunge93, c) {
+                        Some(doc) => println!(
+                            "{}  {} ({}): {}\n   stack: {}",
+                            theme().strong.paint(c.to_string()),
+                            doc.name,
+                            doc.kind.name(),
+                            doc.description,
+                            doc.stack
+                        ),
+                        None => println!("{:?} is not an instruction", c),
+                    },
+                    _ => println!("Expected a single character, e.g. `:help g`"),
+                }
+                continue;
+            }
+            _ => match repl.eval(line) {
+                Ok(printed) if !printed.is_empty() => {
+                    println!("{} {:?}", theme().label.paint("Output:"), printed)
+                }
+                Ok(_) => {}
+                Err(e) => println!("{} {:#}", theme().error.paint("Error:"), e),
+            },
+        }
+        println!(
+            "{} {}",
+            theme().label.paint("Stack:"),
+            repl.interpreter().get_stack().render(theme())
+        );
+    }
+}
@@ -0,0 +1,589 @@
+//! `bef run` and `bef pipe`: running a program, showing as much of its
+//! state as asked along the way.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::clap::{Error as ClapError, ErrorKind};
+use structopt::StructOpt;
+
+use befunge_93::literate;
+use befunge_93::render::RenderOptions;
+use befunge_93::replay::Recording;
+use befunge_93::schema::Versioned;
+use befunge_93::stats::RunStats;
+use befunge_93::summary::{HaltReason, RunSummary};
+use befunge_93::trace::TraceWriter;
+use befunge_93::{Interpreter, Progress};
+
+use crate::console::Console;
+use crate::controls::Controls;
+use crate::pretty::{Region, TraceFilter, TracePrinter};
+use crate::prompt::Prompt;
+use crate::screen::{self, Screen};
+use crate::session_log::{LoggedInput, SessionLog};
+use crate::style::theme;
+use crate::{is_stdin, load, route_input, terminal, watch, Opts};
+
+#[derive(StructOpt)]
+pub struct RunOpts {
+    #[structopt(short, long)]
+    /// Path to program file, or `-` to read the program from stdin, as
+    /// when none is given and stdin isn't a terminal.
+    file: Option<PathBuf>,
+    #[structopt(short, long, conflicts_with = "json")]
+    /// Print the playfield at each step. Ignored unless stdout is a
+    /// terminal.
+    pub playfield: bool,
+    #[structopt(short, long, conflicts_with = "json")]
+    /// Print the stack at each step. Ignored unless stdout is a terminal.
+    pub stack: bool,
+    #[structopt(short, long, conflicts_with = "json")]
+    /// Execute in trace mode, printing one aligned line per step.
+    trace: bool,
+    #[structopt(long, requires = "trace")]
+    /// Only trace instructions of these comma-separated kinds: arith, flow,
+    /// stack, push, string, io, mem, other.
+    trace_filter: Option<TraceFilter>,
+    #[structopt(long, requires = "trace")]
+    /// Only trace steps within the rectangle X0,Y0,X1,Y1.
+    trace_region: Option<Region>,
+    #[structopt(short, long)]
+    /// Delay between steps (in milliseconds). Whatever the delay, the state
+    /// is drawn at most about 30 times a second.
+    delay: Option<u16>,
+    #[structopt(long, conflicts_with = "json")]
+    /// Run in debug mode, taking commands at a prompt after each step; type
+    /// h for a list. Program input is then read after a prompt of its own,
+    /// unless given with `--input`. See `bef
+    /// debug` for a full-screen debugger.
+    debug: bool,
+    #[structopt(long)]
+    /// Log program output, program input and debugger commands to a file,
+    /// with timestamps.
+    log_session: Option<PathBuf>,
+    #[structopt(long, conflicts_with = "replay")]
+    /// Record input and random directions to a file.
+    record: Option<PathBuf>,
+    #[structopt(long)]
+    /// Replay input and random directions from a recording.
+    replay: Option<PathBuf>,
+    #[structopt(long)]
+    /// Write the state after each step to a file, as JSON lines.
+    trace_file: Option<PathBuf>,
+    #[structopt(long)]
+    /// Run the program again whenever it or the `--input` file changes.
+    watch: bool,
+    #[structopt(long)]
+    /// Count how many times each instruction executed, and print the counts
+    /// by kind and by instruction on stderr after the run, or add them to
+    /// the `--json` summary.
+    stats: bool,
+    #[structopt(
+        long,
+        conflicts_with_all = &["playfield", "stack", "trace", "debug", "delay", "json", "trace-file", "log-session"]
+    )]
+    /// Run blocks of instructions compiled ahead, which is faster. Runs
+    /// limited with `--max-steps` or `--timeout`, and those counting
+    /// `--stats`, still step.
+    compiled: bool,
+    #[cfg(feature = "jit")]
+    #[structopt(
+        long,
+        conflicts_with_all = &["playfield", "stack", "trace", "debug", "delay", "json", "trace-file", "log-session"]
+    )]
+    /// Like `--compiled`, additionally compiling blocks that run often to
+    /// native code.
+    jit: bool,
+    #[structopt(flatten)]
+    output: OutputOpts,
+}
+
+// Where the output of a run goes, shared by `bef run` and `bef pipe`. A
+// doc comment here would replace the about text of commands flattening it.
+#[derive(StructOpt)]
+pub struct OutputOpts {
+    #[structopt(short, long)]
+    /// Write program output to a file instead of printing it.
+    output: Option<PathBuf>,
+    #[structopt(long, requires = "output")]
+    /// Print program output even when writing it to a file.
+    tee: bool,
+    #[structopt(long)]
+    /// Print only a JSON summary of the run on stdout, with the program's
+    /// output, the final stack, the step count and why it ended; see `bef
+    /// schema run-summary`.
+    json: bool,
+}
+
+/// Print the `--json` summary of a run of `int` since `started`, ending
+/// with `result`, or at `limit` if one stopped it.
+fn print_summary(
+    int: &Interpreter,
+    result: &Result<()>,
+    limit: Option<HaltReason>,
+    started: Instant,
+) -> Result<()> {
+    let halt_reason = match (result, limit) {
+        (Ok(()), _) => HaltReason::End,
+        (Err(_), Some(limit)) => limit,
+        (Err(_), None) => HaltReason::Error,
+    };
+    let summary = RunSummary {
+        halt_reason,
+        output: int.get_output().to_string(),
+        stack: int.get_stack().values(),
+        steps: int.get_steps(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        stats: int.get_stats(),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Versioned::new(&summary))?
+    );
+    Ok(())
+}
+
+/// The limit that stopped a run ending with `result`, if any, given
+/// whether `--max-steps` was reached.
+fn halt_limit(result: &Result<Progress>, limited: bool) -> Option<HaltReason> {
+    match result {
+        Ok(progress) if progress.timed_out => Some(HaltReason::TimeLimit),
+        Ok(_) if limited => Some(HaltReason::StepLimit),
+        _ => None,
+    }
+}
+
+/// Print the `--stats` of a run on stderr, most frequent first.
+fn print_stats(stats: &RunStats) {
+    let share = |n: u64| 100.0 * n as f64 / stats.steps.max(1) as f64;
+    let mut kinds: Vec<_> = stats.kinds.iter().collect();
+    kinds.sort_by(|a, b| b.1.cmp(a.1));
+    let mut instructions: Vec<_> = stats.instructions.iter().collect();
+    instructions.sort_by(|a, b| b.1.cmp(a.1));
+
+    eprintln!("{} {} steps", theme().label.paint("Stats:"), stats.steps);
+    for (kind, &n) in kinds {
+        eprintln!("  {:<8} {:>10} {:>5.1}%", kind, n, share(n));
+    }
+    eprintln!("{}", theme().label.paint("By instruction:"));
+    for (c, &n) in instructions {
+        eprintln!("  {:<8} {:>10} {:>5.1}%", format!("{:?}", c), n, share(n));
+    }
+}
+
+/// Run the program at `path` printing nothing but its output, as it is
+/// produced.
+pub fn pipe(opts: &Opts, path: &Path, output_opts: &OutputOpts) -> Result<()> {
+    let mut interpreter = load(opts, path);
+    route_input(opts, &mut interpreter, None)?;
+    add_sinks(output_opts, &mut interpreter, !output_opts.json)?;
+
+    let started = Instant::now();
+    let mut limited = false;
+    let result = interpreter.run_with_deadline(opts.time_limit(), |_, steps| {
+        limited = opts.over_step_limit(steps);
+        !limited
+    });
+    let limit = halt_limit(&result, limited);
+    let result = opts.limit_steps(opts.limit_time(result), limited);
+    if output_opts.json {
+        print_summary(&interpreter, &result, limit, started)?;
+    }
+
+    if let Err(e) = result.context("Failed to run the program") {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+    Ok(())
+}
+
+/// Stdout, flushed after every write so that output shows as it's
+/// printed.
+struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut stdout = io::stdout();
+        let written = stdout.write(buf)?;
+        stdout.flush()?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// The `--output` file, named in errors writing it.
+struct OutputFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl OutputFile {
+    fn error(&self, e: io::Error) -> io::Error {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to write '{}': {}", self.path.display(), e),
+        )
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf).map_err(|e| self.error(e))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().map_err(|e| self.error(e))
+    }
+}
+
+/// Write the output of `int` as it's printed to the `--output` file, if
+/// any, and to stdout if `stdout` and the output isn't written to a file
+/// instead. Returns whether it's written to stdout.
+fn add_sinks(opts: &OutputOpts, int: &mut Interpreter, stdout: bool) -> Result<bool> {
+    let stdout = stdout && (opts.output.is_none() || opts.tee);
+    if stdout {
+        int.add_output_sink(Stdout);
+    }
+    if let Some(path) = &opts.output {
+        let file =
+            File::create(path).with_context(|| anyhow!("Failed to create '{}'", path.display()))?;
+        int.add_output_sink(OutputFile {
+            path: path.clone(),
+            writer: BufWriter::new(file),
+        });
+    }
+    Ok(stdout)
+}
+
+/// How to run the program without stepping, if asked with `--compiled`
+/// or `--jit` and no limit needs to see its steps.
+fn compiled_runner(opts: &Opts, run_opts: &RunOpts) -> Option<fn(&mut Interpreter) -> Result<()>> {
+    if opts.max_steps.is_some() || opts.timeout.is_some() {
+        return None;
+    }
+    #[cfg(feature = "jit")]
+    if run_opts.jit {
+        return Some(Interpreter::run_jit);
+    }
+    run_opts
+        .compiled
+        .then_some(Interpreter::run_compiled as fn(&mut Interpreter) -> Result<()>)
+}
+
+/// Time between frames drawn while running, about 30 per second.
+const FRAME_BUDGET: Duration = Duration::from_millis(33);
+
+/// Run a program as asked by `run_opts`.
+pub fn main(opts: &Opts, run_opts: &RunOpts) -> Result<()> {
+    let path = match &run_opts.file {
+        Some(path) => path.as_path(),
+        None if !io::stdin().is_terminal() => Path::new("-"),
+        None => ClapError::with_description(
+            "The following required arguments were not provided:\n    --file <file>",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+    if run_opts.watch {
+        return watch::main(opts, path);
+    }
+    if run_opts.debug && (is_stdin(path) || opts.input_from_stdin()) {
+        bail!(
+            "Can't debug with the program or its input read from stdin, which the debugger needs"
+        );
+    }
+
+    let mut interpreter = load(opts, path);
+    interpreter.collect_stats(run_opts.stats);
+    let session_log = match &run_opts.log_session {
+        Some(path) => Some(SessionLog::create(path)?),
+        None => None,
+    };
+    let log = session_log.as_ref();
+    if run_opts.debug && opts.input.is_none() && !opts.raw_input {
+        // The debugger reads its commands from stdin, so program input is
+        // read after a prompt of its own, unless it is read a key at a time.
+        let prompt = Prompt::new("Program input> ");
+        match log {
+            Some(log) => interpreter.set_input(LoggedInput::new(prompt, log.clone())),
+            None => interpreter.set_input(prompt),
+        }
+    } else {
+        route_input(opts, &mut interpreter, log)?;
+    }
+    let expected = literate::expected_output(interpreter.get_comments())
+        .context("Failed to read the program's comments")?;
+
+    if let Some(path) = &run_opts.replay {
+        let file =
+            File::open(path).with_context(|| anyhow!("Failed to open '{}'", path.display()))?;
+        let recording = Recording::read(BufReader::new(file))
+            .with_context(|| anyhow!("Failed to read recording '{}'", path.display()))?;
+        interpreter.replay(recording);
+    } else if run_opts.record.is_some() {
+        interpreter.record();
+    }
+
+    let mut trace_writer = match &run_opts.trace_file {
+        Some(path) => Some(TraceWriter::new(BufWriter::new(
+            File::create(path).with_context(|| anyhow!("Failed to create '{}'", path.display()))?,
+        ))),
+        None => None,
+    };
+    let mut trace_error = None;
+    let events = if run_opts.trace || trace_writer.is_some() {
+        let (sender, receiver) = mpsc::channel();
+        interpreter.on_trace(move |event| {
+            let _ = sender.send(event);
+        });
+        Some(receiver)
+    } else {
+        None
+    };
+    let resizes = if run_opts.trace {
+        let (sender, receiver) = mpsc::channel();
+        interpreter.on_resize(move |bounds| {
+            let _ = sender.send(bounds);
+        });
+        Some(receiver)
+    } else {
+        None
+    };
+    let mut printer = TracePrinter::new(run_opts.trace_filter.clone(), run_opts.trace_region);
+
+    // Piped into another program, only the program's own output is
+    // printed, so that it can be compared or hashed.
+    let tty = io::stdout().is_terminal();
+    if tty && !run_opts.output.json {
+        let options = RenderOptions {
+            pc: true,
+            theme: *theme(),
+            ..Default::default()
+        };
+        println!("Loaded:\n{}", interpreter.render_terminal(&options));
+        println!("Running program...");
+    }
+    // Without anything to show at each step, output is streamed as it's
+    // printed instead of redrawn.
+    let frames = tty && (run_opts.playfield || run_opts.stack);
+    let headless = !(frames || run_opts.trace || run_opts.debug);
+    let streamed = add_sinks(
+        &run_opts.output,
+        &mut interpreter,
+        headless && !run_opts.output.json,
+    )?;
+    if streamed && tty {
+        println!("{}", theme().label.paint("Output:"));
+    }
+    let mut logged_output = 0;
+    let mut console = Console::new();
+    let mut screen = Screen::new();
+    let session = if headless || run_opts.trace {
+        None
+    } else {
+        terminal::Session::start().context("Failed to set up the terminal")?
+    };
+    // The debugger reads lines instead.
+    let keys = session.is_some() && !run_opts.debug;
+    let mut controls = Controls::new(run_opts.delay);
+    if let (Some(session), true) = (&session, keys) {
+        session
+            .read_keys()
+            .context("Failed to set up the terminal")?;
+    }
+    let mut last_frame = Instant::now();
+    let mut frame_dropped = false;
+    let started = Instant::now();
+    let mut limited = false;
+    let on_step = |int: &mut Interpreter, steps: usize| {
+        limited = opts.over_step_limit(steps);
+        if let Some(log) = log {
+            let output = int.output_since(logged_output);
+            if !output.text.is_empty() {
+                log.log("output", &output.text);
+                logged_output = output.next;
+            }
+        }
+
+        for event in events.iter().flat_map(|events| events.try_iter()) {
+            if let Some(writer) = &mut trace_writer {
+                if let Err(e) = writer.write(&event) {
+                    trace_error = Some(e);
+                    return false;
+                }
+            }
+            if run_opts.trace {
+                printer.print(&event);
+            }
+        }
+        for bounds in resizes.iter().flat_map(|resizes| resizes.try_iter()) {
+            printer.note(&format!("the playfield in use grew to {}", bounds));
+        }
+        if limited {
+            frame_dropped = !run_opts.trace && !headless;
+            return false;
+        }
+
+        if run_opts.trace {
+            if run_opts.debug {
+                printer.flush();
+                return console.after_step(int, log);
+            }
+
+            return true;
+        }
+
+        if headless {
+            if let Some(delay) = run_opts.delay {
+                std::thread::sleep(Duration::from_millis(delay.into()));
+            }
+            return true;
+        }
+
+        // Steps are much faster than the terminal, so frames are only drawn
+        // once per frame budget, from the latest state, unless debugging or
+        // paused.
+        let paused = keys && controls.paused();
+        if run_opts.debug || paused || last_frame.elapsed() >= FRAME_BUDGET {
+            if run_opts.debug {
+                // The prompt was written under the last frame.
+                screen.invalidate();
+            }
+            if keys {
+                controls.poll(&mut screen);
+                screen.set_status(controls.status());
+            }
+            screen.draw(run_opts, int);
+            last_frame = Instant::now();
+            frame_dropped = false;
+        } else {
+            frame_dropped = true;
+        }
+
+        if run_opts.debug {
+            println!();
+            if !console.after_step(int, log) {
+                return false;
+            }
+        }
+
+        if keys {
+            controls.wait_while_paused(&mut screen, run_opts, int);
+            if !controls.quit() {
+                controls.sleep(&mut screen);
+            }
+            return !controls.quit();
+        }
+
+        if let Some(delay) = run_opts.delay {
+            std::thread::sleep(Duration::from_millis(delay.into()));
+        }
+
+        true
+    };
+    let result = match compiled_runner(opts, run_opts) {
+        Some(run) => run(&mut interpreter).map(|()| Progress {
+            steps: 0,
+            elapsed: started.elapsed(),
+            timed_out: false,
+        }),
+        None => interpreter.run_with_deadline(opts.time_limit(), on_step),
+    };
+
+    let limit = halt_limit(&result, limited);
+    let result = opts.limit_steps(opts.limit_time(result), limited);
+
+    if let Some(path) = &run_opts.record {
+        if let Some(recording) = interpreter.take_recording() {
+            let mut file = File::create(path)
+                .with_context(|| anyhow!("Failed to create '{}'", path.display()))?;
+            recording
+                .write(&mut file)
+                .with_context(|| anyhow!("Failed to write recording '{}'", path.display()))?;
+        }
+    }
+
+    if session.is_some() {
+        drop(session);
+        screen::print_state(run_opts, &interpreter);
+    } else if frame_dropped {
+        screen.draw(run_opts, &interpreter);
+    }
+
+    interpreter.stop_tracing();
+    for event in events.iter().flat_map(|events| events.try_iter()) {
+        if let Some(writer) = &mut trace_writer {
+            if trace_error.is_none() {
+                trace_error = writer.write(&event).err();
+            }
+        }
+        if run_opts.trace {
+            printer.print(&event);
+        }
+    }
+    printer.flush();
+
+    if let Some(writer) = trace_writer {
+        let path = run_opts.trace_file.as_ref().expect("tracing to a file");
+        let result = match trace_error {
+            Some(e) => Err(e),
+            None => writer.finish().map(|_| ()),
+        };
+        result.with_context(|| anyhow!("Failed to write trace '{}'", path.display()))?;
+    }
+
+    if let Some(log) = log {
+        let output = interpreter.output_since(logged_output);
+        if !output.text.is_empty() {
+            log.log("output", &output.text);
+        }
+        match &result {
+            Ok(()) => log.log("end", "ok"),
+            Err(e) => log.log("error", &format!("{:#}", e)),
+        }
+        let path = run_opts.log_session.as_ref().expect("logging the session");
+        log.finish()
+            .with_context(|| anyhow!("Failed to write session log '{}'", path.display()))?;
+    }
+
+    if controls.quit() {
+        return Ok(());
+    }
+    if run_opts.output.json {
+        print_summary(&interpreter, &result, limit, started)?;
+    } else if let Some(stats) = interpreter.get_stats() {
+        // The stats start on a line of their own after the output.
+        let output = interpreter.get_output();
+        if !output.is_empty() && !output.ends_with('\n') {
+            eprintln!();
+        }
+        print_stats(&stats);
+    }
+    if let Err(e) = result.context("Failed to run the program") {
+        opts.report(path, interpreter.diagnose(&e));
+    }
+
+    if let Some(expected) = expected {
+        if interpreter.get_output() != expected {
+            eprintln!(
+                "{}\n  expected: {:?}\n    actual: {:?}",
+                theme().error.paint("Output does not match the expectation"),
+                expected,
+                interpreter.get_output()
+            );
+            process::exit(1);
+        }
+        eprintln!("{}", theme().ok.paint("Output matches the expectation"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,332 @@
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::terminal::{self as term, Clear, ClearType};
+use crossterm::Command;
+
+use befunge_93::instructions::Dialect;
+use befunge_93::render::RenderOptions;
+use befunge_93::{Bounds, Interpreter, Position};
+
+use crate::run::RunOpts;
+use crate::style::theme;
+use crate::terminal;
+
+/// Terminal row of the first row of the playfield, counting from 0: after
+/// the title and the top of the box.
+const FIRST_ROW: usize = 2;
+
+/// Terminal rows kept for other things than playfield rows when the
+/// playfield doesn't fit: the title, the box, the line saying which part
+/// is shown, the stack and the start of the output.
+const RESERVED_ROWS: usize = 8;
+
+/// The part of the playfield drawn, all of it unless the terminal is too
+/// small.
+#[derive(Clone, Copy, PartialEq)]
+struct Viewport {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    /// Size of the whole playfield.
+    field: Bounds,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::new(Dialect::default().playfield())
+    }
+}
+
+impl Viewport {
+    /// A viewport showing all of a playfield of `field`.
+    fn new(field: Bounds) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: field.cols,
+            height: field.rows,
+            field,
+        }
+    }
+
+    fn clipped(&self) -> bool {
+        self.width < self.field.cols || self.height < self.field.rows
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        (self.x..self.x + self.width).contains(&pos.x)
+            && (self.y..self.y + self.height).contains(&pos.y)
+    }
+
+    /// Resize the viewport to fit a terminal of `cols` by `rows`, keeping
+    /// it within the playfield.
+    fn fit(&mut self, cols: usize, rows: usize) {
+        self.width = self.field.cols.min(cols.saturating_sub(2)).max(1);
+        self.height = self
+            .field
+            .rows
+            .min(rows.saturating_sub(RESERVED_ROWS))
+            .max(1);
+        self.x = self.x.min(self.field.cols - self.width);
+        self.y = self.y.min(self.field.rows - self.height);
+    }
+
+    /// Centre the viewport on `pos`, as far as the playfield allows.
+    fn centre(&mut self, pos: Position) {
+        self.x = pos
+            .x
+            .saturating_sub(self.width / 2)
+            .min(self.field.cols - self.width);
+        self.y = pos
+            .y
+            .saturating_sub(self.height / 2)
+            .min(self.field.rows - self.height);
+    }
+
+    /// Draw `cells` within the viewport in a box, with the part of each
+    /// side the viewport spans in bold when it doesn't show everything.
+    fn render(&self, cells: &[Vec<String>]) -> String {
+        let thumb = |from: usize, len: usize, total: usize, i: usize| {
+            let (start, end) = (from * len / total, ((from + len) * len).div_ceil(total));
+            self.clipped() && (start..end).contains(&i)
+        };
+        let border = theme().border;
+        let mut s = border
+            .paint(format!("\u{250C}{}\u{2510}", "\u{2500}".repeat(self.width)))
+            .to_string();
+        s.push('\n');
+        for (i, row) in cells[self.y..self.y + self.height].iter().enumerate() {
+            let side = if thumb(self.y, self.height, self.field.rows, i) {
+                "\u{2503}"
+            } else {
+                "\u{2502}"
+            };
+            s += &border.paint("\u{2502}").to_string();
+            s += &row[self.x..self.x + self.width].concat();
+            s += &border.paint(side).to_string();
+            s.push('\n');
+        }
+        let bottom: String = (0..self.width)
+            .map(|i| {
+                if thumb(self.x, self.width, self.field.cols, i) {
+                    '\u{2501}'
+                } else {
+                    '\u{2500}'
+                }
+            })
+            .collect();
+        s += &border
+            .paint(format!("\u{2514}{}\u{2518}", bottom))
+            .to_string();
+        if self.clipped() {
+            let status = format!(
+                "x {}-{}/{}, y {}-{}/{}; arrows scroll, c follows the PC",
+                self.x,
+                self.x + self.width - 1,
+                self.field.cols,
+                self.y,
+                self.y + self.height - 1,
+                self.field.rows
+            );
+            let status: String = status.chars().take(self.width + 2).collect();
+            let _ = write!(s, "\n{}", theme().faint.paint(status));
+        }
+        s
+    }
+}
+
+/// The frames of `--playfield` and `--stack`. After the first, which
+/// clears the terminal, only the cells that changed and what follows the
+/// playfield, if it changed, are drawn again, as redrawing everything
+/// flickers.
+///
+/// When the terminal is too small for the playfield, only a viewport is
+/// drawn, following the PC unless scrolled by hand.
+pub struct Screen {
+    /// The cells of the playfield as last drawn.
+    cells: Vec<Vec<String>>,
+    /// What was last drawn under the playfield, if anything was drawn.
+    tail: Option<String>,
+    viewport: Viewport,
+    /// The viewport as last drawn.
+    drawn: Viewport,
+    /// Whether the viewport is moved to keep the PC in it.
+    follow: bool,
+    /// A line drawn above the stack and output, if not empty.
+    status: String,
+}
+
+fn playfield_options() -> RenderOptions {
+    RenderOptions {
+        pc: true,
+        highlight: theme().highlight,
+        theme: *theme(),
+        ..Default::default()
+    }
+}
+
+/// What is drawn under the playfield: the stack, if asked, and the output.
+fn tail(opts: &RunOpts, int: &Interpreter) -> String {
+    let mut tail = String::new();
+    if opts.stack {
+        let _ = writeln!(
+            tail,
+            "{} {}",
+            theme().label.paint("Stack:"),
+            int.get_stack().render(theme())
+        );
+    }
+    let _ = write!(
+        tail,
+        "{}\n{}",
+        theme().label.paint("Output:"),
+        int.get_output()
+    );
+    tail
+}
+
+/// Append the escape sequence of `command` to `out`.
+fn queue(out: &mut String, command: impl Command) {
+    let _ = command.write_ansi(out);
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self {
+            cells: vec![],
+            tail: None,
+            viewport: Viewport::default(),
+            drawn: Viewport::default(),
+            follow: true,
+            status: String::new(),
+        }
+    }
+
+    /// Draw the next frame whole, as something else wrote to the terminal.
+    pub fn invalidate(&mut self) {
+        self.tail = None;
+    }
+
+    /// Draw `status` above the stack and output from the next frame on.
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    /// Move the viewport by `dx` columns and `dy` rows, no longer
+    /// following the PC.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let viewport = &mut self.viewport;
+        viewport.x = viewport
+            .x
+            .saturating_add_signed(dx)
+            .min(viewport.field.cols - viewport.width);
+        viewport.y = viewport
+            .y
+            .saturating_add_signed(dy)
+            .min(viewport.field.rows - viewport.height);
+        self.follow = false;
+    }
+
+    /// Move the viewport by a page up or, if `down`, down.
+    pub fn scroll_page(&mut self, down: bool) {
+        let page = self.viewport.height as isize;
+        self.scroll(0, if down { page } else { -page });
+    }
+
+    /// Keep the PC in the viewport again.
+    pub fn follow(&mut self) {
+        self.follow = true;
+    }
+
+    /// Draw the state of `int` as asked by `opts`.
+    pub fn draw(&mut self, opts: &RunOpts, int: &Interpreter) {
+        if self.viewport.field != int.get_size() {
+            self.viewport = Viewport::new(int.get_size());
+        }
+        if io::stdout().is_terminal() {
+            if let Ok((cols, rows)) = term::size() {
+                self.viewport.fit(cols.into(), rows.into());
+            }
+        }
+        let pc = int.get_position();
+        if self.follow && !self.viewport.contains(pc) {
+            self.viewport.centre(pc);
+        }
+        if self.viewport != self.drawn {
+            self.invalidate();
+        }
+
+        let full = self.tail.is_none();
+        let mut out = String::new();
+        if full {
+            queue(&mut out, Clear(ClearType::All));
+            queue(&mut out, MoveTo(0, 0));
+        }
+
+        let mut tail_row = 0;
+        if opts.playfield {
+            let cells = int.render_cells(&playfield_options());
+            let viewport = self.viewport;
+            if full {
+                let _ = writeln!(
+                    out,
+                    "{}\n{}",
+                    theme().label.paint("Playfield:"),
+                    viewport.render(&cells)
+                );
+            } else {
+                // The cursor is put back where the output ends after.
+                queue(&mut out, SavePosition);
+                for (y, (row, old)) in cells.iter().zip(&self.cells).enumerate() {
+                    for (x, (cell, old)) in row.iter().zip(old).enumerate() {
+                        let pos = Position { x, y };
+                        if cell != old && viewport.contains(pos) {
+                            let col = x - viewport.x + 1;
+                            let row = FIRST_ROW + y - viewport.y;
+                            queue(&mut out, MoveTo(col as u16, row as u16));
+                            out += cell;
+                        }
+                    }
+                }
+                queue(&mut out, RestorePosition);
+            }
+            self.cells = cells;
+            self.drawn = viewport;
+            tail_row = FIRST_ROW + viewport.height + 1 + viewport.clipped() as usize;
+        }
+
+        let mut tail = tail(opts, int);
+        if !self.status.is_empty() {
+            tail = format!("{}\n{}", theme().faint.paint(&self.status), tail);
+        }
+        if self.tail.as_ref() != Some(&tail) {
+            queue(&mut out, MoveTo(0, tail_row as u16));
+            queue(&mut out, Clear(ClearType::FromCursorDown));
+            out += &tail;
+            self.tail = Some(tail);
+        }
+
+        if terminal::reading_keys() {
+            out = out.replace('\n', "\r\n");
+        }
+        print!("{}", out);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Print the state of `int` as asked by `opts` once, as the alternate
+/// screen frames were drawn on is gone when the run ends.
+pub fn print_state(opts: &RunOpts, int: &Interpreter) {
+    if opts.playfield {
+        println!(
+            "{}\n{}",
+            theme().label.paint("Playfield:"),
+            int.render_terminal(&playfield_options())
+        );
+    }
+    print!("{}", tail(opts, int));
+    let _ = io::stdout().flush();
+}
@@ -0,0 +1,395 @@
+//! `bef serve`: a small HTTP API running programs as `bef judge` does, so
+//! that web playgrounds and graders can be backed by the interpreter.
+//!
+//! `POST /run` takes a JSON object with the `program`, and optionally its
+//! `input`, the `expected` output, a `seed` and limits lower than the
+//! server's: `max_steps`, `max_stack`, `max_output` and `time_limit` in
+//! milliseconds. It answers with a judge report, which carries the output,
+//! the final stack and the steps taken.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use structopt::StructOpt;
+
+use befunge_93::judge::{self, JudgeLimits};
+use befunge_93::schema::Versioned;
+
+#[derive(StructOpt)]
+pub struct ServeOpts {
+    #[structopt(long, default_value = "8080")]
+    /// Port to listen on.
+    port: u16,
+    #[structopt(long, default_value = "127.0.0.1")]
+    /// Address to listen on; 0.0.0.0 accepts connections from anywhere.
+    host: String,
+    #[structopt(long, default_value = "10000000")]
+    /// Most steps a request may run for.
+    max_steps: usize,
+    #[structopt(long, default_value = "100000")]
+    /// Most values a request may hold on the stack.
+    max_stack: usize,
+    #[structopt(long, default_value = "65536")]
+    /// Largest output of a request, in bytes.
+    max_output: usize,
+    #[structopt(long, default_value = "5000")]
+    /// Most wall-clock time a request may run for, in milliseconds.
+    time_limit: u64,
+    #[structopt(long, default_value = "1048576")]
+    /// Largest request body, in bytes.
+    max_body: usize,
+    #[structopt(long, default_value = "16")]
+    /// Most requests served at once; more are answered with 503.
+    max_connections: usize,
+}
+
+/// The body of `POST /run`. Limits above the server's are lowered to them.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunRequest {
+    program: String,
+    #[serde(default)]
+    input: String,
+    expected: Option<String>,
+    #[serde(default)]
+    seed: u64,
+    max_steps: Option<usize>,
+    max_stack: Option<usize>,
+    max_output: Option<usize>,
+    /// In milliseconds.
+    time_limit: Option<u64>,
+}
+
+/// An HTTP response: the status and a JSON body.
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn error(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }).to_string(),
+        }
+    }
+}
+
+/// Clients taking longer than this to send a whole request are dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients taking longer than this to take a response are dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients refused for being too many are given this long to take the
+/// refusal.
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest request line and headers, in bytes.
+const MAX_HEAD: usize = 8192;
+
+/// A connection being served, counted until it is dropped.
+struct Connection(Arc<AtomicUsize>);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A stream read until a deadline, so that clients sending a byte now and
+/// then can't hold a connection forever.
+struct Deadline {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the request took too long to send",
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+pub fn main(serve_opts: &ServeOpts) -> Result<()> {
+    let addr = format!("{}:{}", serve_opts.host, serve_opts.port);
+    let listener =
+        TcpListener::bind(&addr).with_context(|| anyhow!("Failed to listen on {}", addr))?;
+    eprintln!(
+        "Serving on http://{}; POST programs to /run",
+        listener.local_addr()?
+    );
+    let limits = JudgeLimits {
+        max_steps: serve_opts.max_steps,
+        max_stack: serve_opts.max_stack,
+        max_output: serve_opts.max_output,
+        time_limit: Duration::from_millis(serve_opts.time_limit),
+        seed: 0,
+    };
+    // Only this thread adds connections, so the count can't grow between
+    // checking and adding to it.
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming().flatten() {
+        if active.load(Ordering::SeqCst) >= serve_opts.max_connections {
+            // Refused apart, so that a client slow to take the refusal
+            // doesn't hold up accepting others.
+            thread::spawn(move || {
+                let busy = Response::error(503, "Too many requests at once; try again later");
+                let _ = stream.set_write_timeout(Some(REFUSAL_TIMEOUT));
+                let _ = write_response(stream, &busy);
+            });
+            continue;
+        }
+        active.fetch_add(1, Ordering::SeqCst);
+        let connection = Connection(active.clone());
+        let limits = limits.clone();
+        let max_body = serve_opts.max_body;
+        thread::spawn(move || {
+            let _connection = connection;
+            if let Err(e) = serve(stream, &limits, max_body) {
+                eprintln!("Failed to serve a request: {:#}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answer the one request sent on `stream`.
+fn serve(stream: TcpStream, limits: &JudgeLimits, max_body: usize) -> Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(Deadline {
+        stream: stream.try_clone()?,
+        deadline: Instant::now() + READ_TIMEOUT,
+    });
+    let response = match read_request(&mut reader, max_body) {
+        Ok((method, path, body)) => route(&method, &path, &body, limits),
+        Err(response) => response,
+    };
+    write_response(stream, &response)
+}
+
+/// Read the method, path and body of a request, or the response refusing
+/// it.
+fn read_request(
+    reader: &mut impl BufRead,
+    max_body: usize,
+) -> Result<(String, String, Vec<u8>), Response> {
+    let bad = |e: io::Error| {
+        let status = match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => 408,
+            _ => 400,
+        };
+        Response::error(status, format!("Failed to read the request: {}", e))
+    };
+    let mut head = reader.take(MAX_HEAD as u64);
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| {
+        line.clear();
+        head.read_line(line).map_err(bad)?;
+        if line.ends_with('\n') {
+            Ok(())
+        } else if head.limit() == 0 {
+            Err(Response::error(
+                431,
+                format!("The request line and headers exceed {} bytes", MAX_HEAD),
+            ))
+        } else {
+            Err(Response::error(400, "The request ended within its headers"))
+        }
+    };
+
+    read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(_version), None) => (method.to_string(), path.to_string()),
+        _ => return Err(Response::error(400, "Malformed request line")),
+    };
+
+    let mut length = 0;
+    loop {
+        read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| Response::error(400, "Invalid Content-Length"))?;
+            }
+        }
+    }
+    if length > max_body {
+        return Err(Response::error(
+            413,
+            format!(
+                "The body is {} bytes long, but at most {} are accepted",
+                length, max_body
+            ),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(bad)?;
+    Ok((method, path, body))
+}
+
+fn route(method: &str, path: &str, body: &[u8], limits: &JudgeLimits) -> Response {
+    match (method, path) {
+        // Preflight requests of browsers, answered by the headers every
+        // response carries.
+        ("OPTIONS", _) => Response {
+            status: 204,
+            body: String::new(),
+        },
+        ("POST", "/run") => match run(body, limits) {
+            Ok(body) => Response { status: 200, body },
+            Err(e) => Response::error(400, format!("{:#}", e)),
+        },
+        (_, "/run") => Response::error(405, "Use POST to run programs"),
+        _ => Response::error(404, format!("No such endpoint: {}", path)),
+    }
+}
+
+/// Judge the program of a `RunRequest`, returning the report as JSON.
+fn run(body: &[u8], limits: &JudgeLimits) -> Result<String> {
+    let request: RunRequest =
+        serde_json::from_slice(body).context("Failed to parse the request")?;
+    let lower = |asked: Option<usize>, limit: usize| asked.map_or(limit, |n| n.min(limit));
+    let limits = JudgeLimits {
+        max_steps: lower(request.max_steps, limits.max_steps),
+        max_stack: lower(request.max_stack, limits.max_stack),
+        max_output: lower(request.max_output, limits.max_output),
+        time_limit: request.time_limit.map_or(limits.time_limit, |ms| {
+            Duration::from_millis(ms).min(limits.time_limit)
+        }),
+        seed: request.seed,
+    };
+    let report = judge::judge(
+        request.program.as_bytes(),
+        request.input.as_bytes(),
+        request.expected.as_deref(),
+        &limits,
+    );
+    Ok(serde_json::to_string(&Versioned::new(&report))?)
+}
+
+fn write_response(mut stream: impl Write, response: &Response) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        response.status,
+        reason,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &str) -> Result<(String, String, Vec<u8>), Response> {
+        read_request(&mut request.as_bytes(), 64)
+    }
+
+    fn status(request: &str) -> u16 {
+        read(request).err().map_or(200, |response| response.status)
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let (method, path, body) = read("POST /run HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcdef")
+            .ok()
+            .unwrap();
+        assert_eq!((method.as_str(), path.as_str()), ("POST", "/run"));
+        assert_eq!(body, b"abc");
+    }
+
+    #[test]
+    fn refuses_malformed_requests() {
+        assert_eq!(status("GARBAGE\r\n\r\n"), 400);
+        assert_eq!(
+            status("POST /run HTTP/1.1\r\nContent-Length: x\r\n\r\n"),
+            400
+        );
+        assert_eq!(status("POST /run HTTP/1.1\r\nHost: localhost\r\n"), 400);
+    }
+
+    #[test]
+    fn refuses_oversized_requests() {
+        assert_eq!(
+            status("POST /run HTTP/1.1\r\nContent-Length: 65\r\n\r\n"),
+            413
+        );
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD));
+        assert_eq!(status(&long), 431);
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEAD / 8)
+        );
+        assert_eq!(status(&many), 431);
+    }
+
+    #[test]
+    fn times_out_slow_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"POST /run HTTP/1.1\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(Deadline {
+            stream,
+            deadline: Instant::now() + Duration::from_millis(100),
+        });
+        let refused = read_request(&mut reader, 64).err().unwrap();
+        assert_eq!(refused.status, 408);
+    }
+
+    #[test]
+    fn routes_requests() {
+        let limits = JudgeLimits::default();
+        assert_eq!(route("GET", "/", b"", &limits).status, 404);
+        assert_eq!(route("GET", "/run", b"", &limits).status, 405);
+        assert_eq!(route("POST", "/run", b"{", &limits).status, 400);
+        let response = route("POST", "/run", br#"{"program": "1.@"}"#, &limits);
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains(r#""output":"1 ""#));
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+
+/// A timestamped log of what happened in an interactive run. Clones write to
+/// the same file.
+#[derive(Clone)]
+pub struct SessionLog(Arc<Mutex<Inner>>);
+
+struct Inner {
+    writer: BufWriter<File>,
+    start: Instant,
+    error: Option<io::Error>,
+}
+
+impl SessionLog {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| anyhow!("Failed to create '{}'", path.display()))?;
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let log = Self(Arc::new(Mutex::new(Inner {
+            writer: BufWriter::new(file),
+            start: Instant::now(),
+            error: None,
+        })));
+        log.write_line(&format!(
+            "; befunge-93 session started at {} (unix time)",
+            started
+        ));
+        Ok(log)
+    }
+
+    /// Log an event of `kind`, e.g. `output` or `input`, with `text` quoted.
+    pub fn log(&self, kind: &str, text: &str) {
+        let elapsed = self.lock().start.elapsed();
+        self.write_line(&format!(
+            "[{:>4}.{:03}] {} {:?}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            kind,
+            text
+        ));
+    }
+
+    /// Flush the log, reporting any error that happened while writing it.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut inner = self.lock();
+        match inner.error.take() {
+            Some(e) => Err(e),
+            None => inner.writer.flush(),
+        }
+    }
+
+    fn write_line(&self, line: &str) {
+        let mut inner = self.lock();
+        if inner.error.is_none() {
+            if let Err(e) = writeln!(inner.writer, "{}", line) {
+                inner.error = Some(e);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.0.lock().expect("session log poisoned")
+    }
+}
+
+/// Program input that is logged as it is read.
+pub struct LoggedInput<R> {
+    inner: R,
+    log: SessionLog,
+}
+
+impl<R> LoggedInput<R> {
+    pub fn new(inner: R, log: SessionLog) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl<R: Read> Read for LoggedInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.log.log("input", &String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(n)
+    }
+}
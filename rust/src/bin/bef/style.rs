@@ -0,0 +1,57 @@
+//! The theme everything bef prints in the terminal is drawn with, set
+//! once from the options.
+
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+
+use befunge_93::render::Palette;
+use befunge_93::theme::Theme;
+
+/// The theme in use, and the one configured, which differ when colours
+/// are turned off.
+static THEMES: OnceLock<(Theme, Theme)> = OnceLock::new();
+
+/// Use the theme in the file at `path`, or the default one, unless colours
+/// are turned off with `no_color` or a `NO_COLOR` environment variable
+/// that isn't empty, or stdout isn't a terminal.
+pub fn init(path: Option<&Path>, no_color: bool) -> Result<()> {
+    let configured = match path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .with_context(|| anyhow!("Failed to read '{}'", path.display()))?;
+            Theme::parse(&text).with_context(|| anyhow!("Failed to load '{}'", path.display()))?
+        }
+        None => Theme::default(),
+    };
+    let no_color = no_color
+        || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+        || !io::stdout().is_terminal();
+    let theme = if no_color { Theme::plain() } else { configured };
+    let _ = THEMES.set((theme, configured));
+    Ok(())
+}
+
+fn themes() -> &'static (Theme, Theme) {
+    THEMES.get_or_init(|| (Theme::default(), Theme::default()))
+}
+
+/// The theme in use.
+pub fn theme() -> &'static Theme {
+    &themes().0
+}
+
+/// Whether colours are drawn at all.
+pub fn colours() -> bool {
+    *theme() != Theme::plain()
+}
+
+/// The highlight colours of the configured theme, for images, which are
+/// coloured whatever the terminal.
+pub fn palette() -> Palette {
+    themes().1.highlight.unwrap_or_default()
+}
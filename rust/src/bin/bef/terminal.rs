@@ -0,0 +1,216 @@
+//! The terminal while frames are drawn: the alternate screen, raw mode
+//! while reading keys, and putting everything back however bef exits.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, terminal};
+
+/// Whether a `Session` is active.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Whether keys are read as they are pressed, in raw mode.
+static KEYS: AtomicBool = AtomicBool::new(false);
+
+/// The alternate screen, from `Session::start` until the session is
+/// dropped, bef panics or it is interrupted, which all restore the
+/// terminal.
+pub struct Session(());
+
+impl Session {
+    /// Switch to the alternate screen, if stdout is a terminal.
+    pub fn start() -> io::Result<Option<Self>> {
+        if !io::stdout().is_terminal() {
+            return Ok(None);
+        }
+        static HOOKS: Once = Once::new();
+        HOOKS.call_once(install_hooks);
+
+        execute!(io::stdout(), terminal::EnterAlternateScreen)?;
+        ACTIVE.store(true, Ordering::SeqCst);
+        Ok(Some(Self(())))
+    }
+
+    /// Read keys as they are pressed with `poll_key` until the session
+    /// ends, rather than lines. Lines written then must end with `\r\n`.
+    pub fn read_keys(&self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        KEYS.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Whether keys are read as they are pressed, in raw mode.
+pub fn reading_keys() -> bool {
+    KEYS.load(Ordering::SeqCst)
+}
+
+/// The key pressed since the last call, if any, without waiting. Ctrl-C
+/// interrupts bef, as it would outside of raw mode.
+pub fn poll_key() -> io::Result<Option<KeyCode>> {
+    wait_key(Duration::ZERO)
+}
+
+/// The next key pressed within `timeout`, if any, as `poll_key`.
+pub fn wait_key(timeout: Duration) -> io::Result<Option<KeyCode>> {
+    let until = Instant::now() + timeout;
+    while event::poll(until.saturating_duration_since(Instant::now()))? {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        {
+            if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+                interrupt();
+            }
+            return Ok(Some(code));
+        }
+    }
+    Ok(None)
+}
+
+/// Exit as if interrupted by Ctrl-C, putting the terminal back first.
+fn interrupt() -> ! {
+    restore();
+    // As shells report being killed by SIGINT.
+    std::process::exit(130);
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Put the terminal back as it was, if a session changed it.
+fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        KEYS.store(false, Ordering::SeqCst);
+        reset();
+    }
+}
+
+/// Put the terminal back as a session leaves it, after another process
+/// drawing on it was stopped.
+pub fn reset() {
+    if io::stdout().is_terminal() {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show);
+    }
+}
+
+fn install_hooks() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+    }));
+
+    // Ctrl-C outside raw mode kills bef with SIGINT before any destructor
+    // runs, leaving the terminal on the alternate screen.
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        if let Ok(mut signals) = Signals::new([SIGINT, SIGTERM]) {
+            std::thread::spawn(move || {
+                if let Some(signal) = signals.forever().next() {
+                    restore();
+                    std::process::exit(128 + signal);
+                }
+            });
+        }
+    }
+}
+
+/// Read a line typed after `prompt`, in raw mode during a session so that
+/// Ctrl-C is a key rather than a signal. `None` at the end of input or
+/// when Ctrl-C or Ctrl-D is pressed.
+pub fn read_line(prompt: &str) -> io::Result<Option<String>> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    if !ACTIVE.load(Ordering::SeqCst) || !io::stdin().is_terminal() {
+        let mut line = String::new();
+        return match io::stdin().lock().read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        };
+    }
+
+    terminal::enable_raw_mode()?;
+    let line = read_raw_line();
+    if !reading_keys() {
+        terminal::disable_raw_mode()?;
+    }
+    eprintln!();
+    line
+}
+
+/// Wait for a key to be pressed, reading it in raw mode so that it needn't
+/// be followed by enter, and isn't echoed. `None` when Ctrl-D is pressed.
+/// Ctrl-C interrupts bef, as it would outside of raw mode.
+pub fn read_key() -> io::Result<Option<KeyCode>> {
+    terminal::enable_raw_mode()?;
+    let key = read_raw_key();
+    if !reading_keys() {
+        terminal::disable_raw_mode()?;
+    }
+    key
+}
+
+fn read_raw_key() -> io::Result<Option<KeyCode>> {
+    loop {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Char('c') if ctrl => {
+                let _ = terminal::disable_raw_mode();
+                interrupt();
+            }
+            KeyCode::Char('d') if ctrl => return Ok(None),
+            _ => return Ok(Some(code)),
+        }
+    }
+}
+
+fn read_raw_line() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Char('c') if ctrl => return Ok(None),
+            KeyCode::Char('d') if ctrl && line.is_empty() => return Ok(None),
+            KeyCode::Enter => return Ok(Some(line + "\n")),
+            KeyCode::Backspace if line.pop().is_some() => eprint!("\u{8} \u{8}"),
+            KeyCode::Char(c) if !ctrl => {
+                line.push(c);
+                eprint!("{}", c);
+            }
+            _ => continue,
+        }
+        io::stderr().flush()?;
+    }
+}
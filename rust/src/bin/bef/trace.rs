@@ -0,0 +1,124 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{anyhow, bail, Context, Result};
+use structopt::StructOpt;
+
+use befunge_93::query::Query;
+use befunge_93::replay::Recording;
+use befunge_93::trace::{first_divergence, read_trace, TraceEvent};
+
+use crate::style::theme;
+
+#[derive(StructOpt)]
+pub enum TraceCmd {
+    /// Find the first step at which two traces, or two recordings, diverge.
+    Diff {
+        left: PathBuf,
+        right: PathBuf,
+        #[structopt(short, long, default_value = "3")]
+        /// Number of steps to show around the divergence.
+        context: usize,
+    },
+    /// Print the steps of a trace matching a query, e.g.
+    /// `cmd == "p" && x > 40`.
+    Query {
+        trace: PathBuf,
+        /// Predicate over the fields step, x, y, depth, top, cmd, dir and
+        /// output.
+        query: Query,
+        #[structopt(short, long)]
+        /// Only print the number of matching steps.
+        count: bool,
+    },
+}
+
+enum Log {
+    Trace(Vec<TraceEvent>),
+    Recording(Recording),
+}
+
+impl Log {
+    fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read '{}'", path.display()))?;
+        let is_trace = text
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .is_some_and(|l| l.trim_start().starts_with('{'));
+
+        if is_trace {
+            Ok(Self::Trace(read_trace(text.as_bytes())?))
+        } else {
+            Ok(Self::Recording(Recording::read(text.as_bytes())?))
+        }
+    }
+}
+
+pub fn main(cmd: &TraceCmd) -> Result<()> {
+    match cmd {
+        TraceCmd::Diff {
+            left,
+            right,
+            context,
+        } => {
+            let same = match (Log::read(left)?, Log::read(right)?) {
+                (Log::Trace(l), Log::Trace(r)) => diff(&l, &r, *context),
+                (Log::Recording(l), Log::Recording(r)) => diff(l.events(), r.events(), *context),
+                _ => bail!("Cannot compare a trace with a recording"),
+            };
+            if !same {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        TraceCmd::Query {
+            trace,
+            query,
+            count,
+        } => {
+            let events = match Log::read(trace)? {
+                Log::Trace(events) => events,
+                Log::Recording(_) => bail!("Queries need a trace, not a recording"),
+            };
+            let matching = events.iter().filter(|e| query.matches(e));
+            if *count {
+                println!("{}", matching.count());
+            } else {
+                matching.for_each(|e| println!("{}", e));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print where `left` and `right` diverge, if they do. Returns whether they
+/// are the same.
+fn diff<T: PartialEq + Display>(left: &[T], right: &[T], context: usize) -> bool {
+    let idx = match first_divergence(left, right) {
+        Some(idx) => idx,
+        None => {
+            println!("No divergence in {} entries", left.len());
+            return true;
+        }
+    };
+
+    println!("Diverging at entry {}:", idx + 1);
+    for entry in &left[idx.saturating_sub(context)..idx] {
+        println!("  {}", entry);
+    }
+    for (sign, style, entries) in [("<", theme().error, left), (">", theme().ok, right)] {
+        let after = entries.iter().skip(idx).take(context + 1);
+        let mut shown = 0;
+        for entry in after {
+            println!("{}", style.paint(format!("{} {}", sign, entry)));
+            shown += 1;
+        }
+        if shown == 0 {
+            println!("{}", style.paint(format!("{} <end>", sign)));
+        }
+    }
+    false
+}
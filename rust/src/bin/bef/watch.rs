@@ -0,0 +1,122 @@
+//! `bef run --watch`: running a program again whenever it or its input
+//! file changes.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::style::theme;
+use crate::{is_stdin, terminal, Opts};
+
+/// Time between checks of the watched files.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What is known of a watched file, to tell when it changes. `None` while
+/// it doesn't exist, as editors may replace a file rather than write it.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn stamps_of(paths: &[PathBuf]) -> Vec<Stamp> {
+    paths.iter().map(|path| stamp(path)).collect()
+}
+
+/// The stamps of `paths` once they stop changing, starting from `stamps`,
+/// as editors may write a file in several goes.
+fn settled(paths: &[PathBuf], mut stamps: Vec<Stamp>) -> Vec<Stamp> {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let now = stamps_of(paths);
+        if now == stamps {
+            return stamps;
+        }
+        stamps = now;
+    }
+}
+
+/// Run the program at `path` in a new bef, with the same arguments but
+/// `--watch`, and again each time it or the `--input` file changes,
+/// stopping a run still going on.
+pub fn main(opts: &Opts, path: &Path) -> Result<()> {
+    if is_stdin(path) || opts.input_from_stdin() {
+        bail!("Can't watch a program or input read from stdin");
+    }
+    let mut paths: Vec<PathBuf> = vec![path.to_path_buf()];
+    paths.extend(opts.input.clone());
+    let exe = env::current_exe().context("Failed to find the bef executable")?;
+    let args: Vec<OsString> = env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--watch")
+        .collect();
+
+    let mut stamps = stamps_of(&paths);
+    loop {
+        eprintln!(
+            "{}",
+            theme()
+                .strong
+                .paint(format!("[watch] Running {}", path.display()))
+        );
+        let mut child = Command::new(&exe)
+            .args(&args)
+            .spawn()
+            .with_context(|| anyhow!("Failed to run '{}'", exe.display()))?;
+        let mut running = true;
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if running {
+                if let Some(status) = child.try_wait()? {
+                    running = false;
+                    report(status);
+                }
+            }
+            let now = stamps_of(&paths);
+            // Wait for a file being replaced to exist again.
+            if now != stamps && now.iter().all(Option::is_some) {
+                stamps = settled(&paths, now);
+                break;
+            }
+        }
+
+        if running {
+            stop(&mut child)?;
+            eprintln!(
+                "\n{}",
+                theme().error.paint("[watch] Changed; stopped the run")
+            );
+        }
+    }
+}
+
+/// Stop a run that is still going on, putting the terminal back as it was
+/// if it was drawing frames.
+fn stop(child: &mut Child) -> Result<()> {
+    // The run is killed, so it can't restore the terminal itself.
+    child.kill()?;
+    child.wait()?;
+    terminal::reset();
+    Ok(())
+}
+
+fn report(status: ExitStatus) {
+    let message = match status.code() {
+        Some(0) => theme().ok.paint("[watch] Finished; waiting for changes"),
+        Some(code) => theme().error.paint(format!(
+            "[watch] Failed with code {}; waiting for changes",
+            code
+        )),
+        None => theme().error.paint("[watch] Killed; waiting for changes"),
+    };
+    // Programs often don't end their output with a newline.
+    eprintln!("\n{}", message);
+}
@@ -0,0 +1,169 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::runner::Snapshot;
+use crate::{Checkpoint, Interpreter, StepResult};
+
+/// Whether a side of a bisection is still executing.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub enum Status {
+    Running,
+    Halted,
+    /// The step failed with the given error.
+    Failed(String),
+}
+
+/// One of the two interpreters being compared.
+struct Side<'a> {
+    int: &'a mut Interpreter,
+    status: Status,
+}
+
+impl Side<'_> {
+    fn step(&mut self) {
+        if self.status != Status::Running {
+            return;
+        }
+        self.status = match self.int.step_in_context() {
+            Ok(StepResult::Cont) => Status::Running,
+            Ok(StepResult::Stop) => Status::Halted,
+            Err(e) => Status::Failed(format!("{:#}", e)),
+        };
+    }
+}
+
+/// The state of one side right after the diverging step.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Outcome {
+    pub status: Status,
+    pub state: Snapshot,
+}
+
+/// The first step after which two interpreters disagree.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Divergence {
+    /// Number of the first diverging step, starting from one.
+    pub step: usize,
+    /// The common state before the diverging step.
+    pub before: Snapshot,
+    pub left: Outcome,
+    pub right: Outcome,
+}
+
+struct Bisector<'a> {
+    left: Side<'a>,
+    right: Side<'a>,
+}
+
+impl Bisector<'_> {
+    fn same(&self) -> bool {
+        self.left.status == self.right.status && self.left.int.same_state(self.right.int)
+    }
+
+    fn done(&self) -> bool {
+        self.left.status != Status::Running && self.right.status != Status::Running
+    }
+
+    fn checkpoint(&self) -> (Checkpoint, Checkpoint, Status, Status) {
+        (
+            self.left.int.checkpoint(),
+            self.right.int.checkpoint(),
+            self.left.status.clone(),
+            self.right.status.clone(),
+        )
+    }
+
+    fn restore(&mut self, checkpoint: &(Checkpoint, Checkpoint, Status, Status)) {
+        let (left, right, left_status, right_status) = checkpoint;
+        self.left.int.restore(left);
+        self.right.int.restore(right);
+        self.left.status = left_status.clone();
+        self.right.status = right_status.clone();
+    }
+
+    fn advance(&mut self, steps: usize) {
+        for _ in 0..steps {
+            if self.done() {
+                break;
+            }
+            self.left.step();
+            self.right.step();
+        }
+    }
+}
+
+/// Run `left` and `right` side by side from the start of their programs
+/// and find the first step after which their state differs.
+///
+/// Both sides are only compared every `interval` steps; once a difference
+/// shows up, the steps since the last matching checkpoint are bisected.
+/// Both interpreters should replay the same recording, since input cannot
+/// be rewound. Gives up after `max_steps` steps.
+pub fn bisect(
+    left: &mut Interpreter,
+    right: &mut Interpreter,
+    interval: usize,
+    max_steps: usize,
+) -> Option<Divergence> {
+    let interval = interval.max(1);
+    left.reset();
+    right.reset();
+
+    let mut b = Bisector {
+        left: Side {
+            int: left,
+            status: Status::Running,
+        },
+        right: Side {
+            int: right,
+            status: Status::Running,
+        },
+    };
+
+    let mut good = b.checkpoint();
+    let mut lo = 0;
+    let mut hi;
+    loop {
+        let n = interval.min(max_steps - lo);
+        b.advance(n);
+        hi = lo + n;
+        if !b.same() {
+            break;
+        }
+        if b.done() || hi >= max_steps {
+            return None;
+        }
+        good = b.checkpoint();
+        lo = hi;
+    }
+
+    // Invariant: the state after `lo` steps matches, after `hi` it does not.
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        b.restore(&good);
+        b.advance(mid - lo);
+        if b.same() {
+            good = b.checkpoint();
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    b.restore(&good);
+    let before = Snapshot::of(b.left.int, lo, false);
+    b.advance(1);
+
+    Some(Divergence {
+        step: hi,
+        before,
+        left: Outcome {
+            status: b.left.status.clone(),
+            state: Snapshot::of(b.left.int, hi, false),
+        },
+        right: Outcome {
+            status: b.right.status.clone(),
+            state: Snapshot::of(b.right.int, hi, false),
+        },
+    })
+}
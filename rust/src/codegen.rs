@@ -0,0 +1,141 @@
+//! Translation of programs into standalone source files in other languages,
+//! so they can be built into native binaries.
+//!
+//! Every non-blank cell becomes a piece of code run when the PC enters it.
+//! Cells written by `p`, and cells crossed in string mode, are executed by a
+//! small interpreter embedded in the generated program, so self-modifying
+//! programs still behave as under `Interpreter::run`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+use crate::instructions::Dialect;
+use crate::sourcemap::SourceMap;
+use crate::{Command, Interpreter, Position, PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+mod c;
+mod rust;
+
+/// A language programs can be translated to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Rust,
+    C,
+}
+
+impl Target {
+    /// Names of all targets, as accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["rust", "c"];
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rust" => Ok(Self::Rust),
+            "c" => Ok(Self::C),
+            _ => bail!(
+                "Unknown target '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rust => write!(f, "rust"),
+            Self::C => write!(f, "c"),
+        }
+    }
+}
+
+/// A translated program.
+#[derive(Debug, Clone)]
+pub struct Generated {
+    /// The generated source file.
+    pub source: String,
+    /// Maps lines of `source` back to playfield cells.
+    pub source_map: SourceMap,
+}
+
+/// Accumulates generated lines while recording the cells they come from.
+struct Writer {
+    source: String,
+    lines: usize,
+    source_map: SourceMap,
+}
+
+impl Writer {
+    fn new(target: Target) -> Self {
+        Self {
+            source: String::new(),
+            lines: 0,
+            source_map: SourceMap::new(target.to_string()),
+        }
+    }
+
+    /// Append one line of code.
+    fn line(&mut self, code: impl AsRef<str>) {
+        self.source += code.as_ref();
+        self.source.push('\n');
+        self.lines += 1;
+    }
+
+    /// Append one line of code generated for the cell at `pos`.
+    fn mapped(&mut self, pos: Position, code: impl AsRef<str>) {
+        self.source_map.add(self.lines + 1, pos, None);
+        self.line(code);
+    }
+
+    fn finish(self) -> Generated {
+        Generated {
+            source: self.source,
+            source_map: self.source_map,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Translate the loaded program into a standalone program in `target`,
+    /// following the current semantics. Generated programs have the
+    /// playfield of Befunge-93, so the interpreter must have it too.
+    pub fn translate(&self, target: Target) -> Generated {
+        assert_eq!(
+            self.get_size(),
+            Dialect::Befunge93.playfield(),
+            "only programs on the Befunge-93 playfield can be translated"
+        );
+        let mut w = Writer::new(target);
+        match target {
+            Target::Rust => rust::generate(self, &mut w),
+            Target::C => c::generate(self, &mut w),
+        }
+        w.finish()
+    }
+
+    /// The initial playfield as bytes, along with every non-blank cell.
+    fn cells(
+        &self,
+    ) -> (
+        [[u8; PLAYFIELD_COLS]; PLAYFIELD_ROWS],
+        Vec<(Position, Command)>,
+    ) {
+        let mut bytes = [[b' '; PLAYFIELD_COLS]; PLAYFIELD_ROWS];
+        let mut cells = vec![];
+        for (y, row) in self.playfield.iter().enumerate() {
+            for (x, &cmd) in row.iter().enumerate() {
+                bytes[y][x] = char::from(cmd) as u8;
+                if cmd != Command::Space {
+                    cells.push((Position { x, y }, cmd));
+                }
+            }
+        }
+        (bytes, cells)
+    }
+}
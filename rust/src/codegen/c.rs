@@ -0,0 +1,388 @@
+//! The C backend: a single C99 file in the spirit of the classic bef2c.
+
+use super::Writer;
+use crate::semantics::{DivZero, Eof, OutOfBounds, Unknown};
+use crate::{Command, Interpreter};
+
+/// A C character literal for `b`.
+fn char_literal(b: u8) -> String {
+    match b {
+        b'\'' | b'\\' => format!("'\\{}'", b as char),
+        b' '..=b'~' => format!("'{}'", b as char),
+        _ => b.to_string(),
+    }
+}
+
+/// A C string literal holding `bytes`. Octal escapes are used so that
+/// neither hex digits nor `?` can change their meaning.
+fn string_literal(bytes: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' | b'?' => s += &format!("\\{:03o}", b),
+            b' '..=b'~' => s.push(b as char),
+            _ => s += &format!("\\{:03o}", b),
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// Statements executing `cmd` outside string mode, or `None` if it does
+/// nothing.
+fn command(int: &Interpreter, cmd: Command) -> Option<String> {
+    let code = match cmd {
+        Command::Add => {
+            "{ int64_t b = pop(), a = pop(); push((int64_t)((uint64_t)a + (uint64_t)b)); }"
+        }
+        Command::Sub => {
+            "{ int64_t b = pop(), a = pop(); push((int64_t)((uint64_t)a - (uint64_t)b)); }"
+        }
+        Command::Mul => {
+            "{ int64_t b = pop(), a = pop(); push((int64_t)((uint64_t)a * (uint64_t)b)); }"
+        }
+        Command::Div => "divide(0);",
+        Command::Mod => "divide(1);",
+        Command::Not => "push(pop() == 0);",
+        Command::Gt => "{ int64_t b = pop(), a = pop(); push(a > b); }",
+        Command::Right => "dir = RIGHT;",
+        Command::Left => "dir = LEFT;",
+        Command::Up => "dir = UP;",
+        Command::Down => "dir = DOWN;",
+        Command::Rand => "dir = rand_dir();",
+        Command::IfH => "dir = pop() == 0 ? RIGHT : LEFT;",
+        Command::IfV => "dir = pop() == 0 ? DOWN : UP;",
+        Command::Str => "sm = 1;",
+        Command::Dup => "push(peek());",
+        Command::Swap => "{ int64_t b = pop(), a = pop(); push(b); push(a); }",
+        Command::Pop => "pop();",
+        Command::OutI => "printf(\"%\" PRId64 \" \", pop());",
+        Command::OutC => "put_char(pop());",
+        Command::Bri => "advance();",
+        Command::Get => "get();",
+        Command::Put => "put();",
+        Command::InI => "read_int();",
+        Command::InC => "read_char();",
+        Command::End => "return 0;",
+        Command::Space => return None,
+        Command::Num(n) => return Some(format!("push({});", n)),
+        Command::Char(c) => match int.semantics.unknown {
+            Unknown::Nop => return None,
+            _ => return Some(format!("unknown({});", char_literal(c as u8))),
+        },
+    };
+    Some(code.to_string())
+}
+
+const PRELUDE: &str = r#"
+#include <ctype.h>
+#include <errno.h>
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+#define COLS 80
+#define ROWS 25
+
+enum { RIGHT, LEFT, UP, DOWN };
+
+/* Cells written by `p`, which no longer run their generated code. */
+static unsigned char dirty[ROWS][COLS];
+static int x, y, dir = RIGHT, sm;
+static int64_t *stack;
+static size_t len, cap;
+static uint64_t rng;
+
+static void fail(const char *fmt, ...) {
+    va_list args;
+    fflush(stdout);
+    fprintf(stderr, "Error: Stepping at (%d, %d): ", x, y);
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+static int64_t pop(void) {
+    return len ? stack[--len] : 0;
+}
+
+static int64_t peek(void) {
+    return len ? stack[len - 1] : 0;
+}
+
+static void push(int64_t v) {
+    if (len == cap) {
+        cap = cap ? cap * 2 : 64;
+        stack = realloc(stack, cap * sizeof *stack);
+        if (!stack) {
+            fail("Out of memory");
+        }
+    }
+    stack[len++] = v;
+}
+
+static void advance(void) {
+    switch (dir) {
+    case RIGHT: x = (x + 1) % COLS; break;
+    case LEFT: x = (x + COLS - 1) % COLS; break;
+    case UP: y = (y + ROWS - 1) % ROWS; break;
+    default: y = (y + 1) % ROWS; break;
+    }
+}
+
+static int rand_dir(void) {
+    rng ^= rng << 13;
+    rng ^= rng >> 7;
+    rng ^= rng << 17;
+    return (int)(rng % 4);
+}
+
+/* Write `v` as a character, encoding bytes above 127 as UTF-8. */
+static void put_char(int64_t v) {
+    unsigned char b = (unsigned char)v;
+    if (b < 0x80) {
+        putchar(b);
+    } else {
+        putchar(0xC0 | (b >> 6));
+        putchar(0x80 | (b & 0x3F));
+    }
+}
+
+/* The next byte of input, or EOF at its end. */
+static int read_byte(void) {
+    int c;
+    fflush(stdout);
+    c = getchar();
+    if (c == EOF && ferror(stdin)) {
+        fail("Reading a byte");
+    }
+    return c;
+}
+
+static void read_char(void) {
+    int c = read_byte();
+    if (c == EOF) {
+        AT_EOF;
+        return;
+    }
+    push(c);
+}
+
+/* Read a number, skipping leading whitespace. The byte ending it is left
+   for the next `&` or `~`. */
+static void read_int(void) {
+    char buf[32];
+    size_t n = 0;
+    int c, ok = 1;
+    char *end;
+    int64_t v;
+    while ((c = read_byte()) != EOF && isspace(c)) {
+    }
+    if (c == EOF) {
+        AT_EOF;
+        return;
+    }
+    if (!isdigit(c) && c != '-') {
+        fail("Parsing '%c' into a number", c);
+    }
+    do {
+        if (n < sizeof buf - 1) {
+            buf[n++] = (char)c;
+        } else {
+            ok = 0;
+        }
+    } while ((c = read_byte()) != EOF && isdigit(c));
+    if (c != EOF) {
+        ungetc(c, stdin);
+    }
+    buf[n] = '\0';
+    errno = 0;
+    v = strtoll(buf, &end, 10);
+    if (!ok || *end != '\0' || errno == ERANGE) {
+        fail("Parsing '%s' into a number", buf);
+    }
+    push(v);
+}
+
+/* Pop the cell `cmd` uses into `cx` and `cy`, returning 0 if it does
+   nothing else. */
+static int cell(char cmd, int64_t *cx, int64_t *cy) {
+    int64_t py = pop(), px = pop();
+    if (px >= 0 && px < COLS && py >= 0 && py < ROWS) {
+        *cx = px;
+        *cy = py;
+        return 1;
+    }
+    OUT_OF_BOUNDS;
+}
+
+static void get(void) {
+    int64_t cx, cy;
+    if (!cell('g', &cx, &cy)) {
+        GET_OUTSIDE;
+        return;
+    }
+    push(field[cy][cx]);
+}
+
+static void put(void) {
+    int64_t cx, cy, v;
+    int inside = cell('p', &cx, &cy);
+    v = pop();
+    if (!inside) {
+        return;
+    }
+    if (v < 0 || v > 255) {
+        fail("Failed to convert %" PRId64 " into u8", v);
+    }
+    field[cy][cx] = (unsigned char)v;
+    dirty[cy][cx] = 1;
+}
+
+static void divide(int rem) {
+    int64_t b = pop(), a = pop();
+    if (b == 0) {
+        DIV_ZERO;
+    } else if (b == -1) {
+        push(rem ? 0 : (int64_t)(0 - (uint64_t)a));
+    } else {
+        push(rem ? a % b : a / b);
+    }
+}
+
+static void unknown(int c) {
+    (void)c;
+    UNKNOWN;
+}
+"#;
+
+/// Write the whole program for `int` to `w`.
+pub(super) fn generate(int: &Interpreter, w: &mut Writer) {
+    let (bytes, cells) = int.cells();
+
+    w.line("/* Translated from Befunge-93 by `bef compile --emit c`. */");
+    w.line(format!("/* Semantics: {} */", int.semantics));
+    w.line("");
+    w.line("static unsigned char field[25][80 + 1] = {");
+    for row in bytes.iter() {
+        w.line(format!("    {},", string_literal(row)));
+    }
+    w.line("};");
+
+    let at_eof = match int.semantics.eof {
+        Eof::Error => "fail(\"Reading a byte: end of input\")",
+        Eof::MinusOne => "push(-1)",
+        Eof::Zero => "push(0)",
+        Eof::Reflect => "dir ^= 1",
+    };
+    let div_zero = match int.semantics.div_zero {
+        DivZero::Error => "fail(\"Division by zero\")",
+        DivZero::Zero => "push(0)",
+    };
+    let unknown = match int.semantics.unknown {
+        Unknown::Error => "fail(\"Unknown command '%c'\", c)",
+        Unknown::Nop => "",
+        Unknown::Reflect => "dir ^= 1",
+    };
+    let out_of_bounds = match int.semantics.out_of_bounds {
+        OutOfBounds::Error => {
+            "if (px < 0 || px >= COLS) {
+        fail(\"Invalid x coordinate for %c command: %\" PRId64, cmd, px);
+    }
+    fail(\"Invalid y coordinate for %c command: %\" PRId64, cmd, py);
+    return 0"
+        }
+        OutOfBounds::Wrap => {
+            "*cx = (px % COLS + COLS) % COLS;
+    *cy = (py % ROWS + ROWS) % ROWS;
+    return 1"
+        }
+        OutOfBounds::Reflect => "dir ^= 1;\n    return 0",
+        OutOfBounds::Nop => "return 0",
+    };
+    let get_outside = match int.semantics.out_of_bounds {
+        OutOfBounds::Nop => "push(0)",
+        _ => "(void)0",
+    };
+    for line in PRELUDE
+        .replace("AT_EOF", at_eof)
+        .replace("DIV_ZERO", div_zero)
+        .replace("UNKNOWN", unknown)
+        .replace("OUT_OF_BOUNDS", out_of_bounds)
+        .replace("GET_OUTSIDE", get_outside)
+        .lines()
+    {
+        w.line(line);
+    }
+
+    w.line("");
+    w.line("/* Execute `c` as the current cell, returning 0 at `@`. */");
+    w.line("static int exec(int c) {");
+    w.line("    if (sm) {");
+    w.line("        if (c == '\"') {");
+    w.line("            sm = 0;");
+    w.line("        } else {");
+    w.line("            push(c);");
+    w.line("        }");
+    w.line("        advance();");
+    w.line("        return 1;");
+    w.line("    }");
+    w.line("    switch (c) {");
+    for b in 0..128u8 {
+        let cmd = Command::from(b as char);
+        if matches!(cmd, Command::Space | Command::Char(_)) {
+            continue;
+        }
+        if let Some(code) = command(int, cmd) {
+            w.line(format!("    case {}: {} break;", char_literal(b), code));
+        }
+    }
+    w.line("    case ' ': break;");
+    w.line("    default: unknown(c); break;");
+    w.line("    }");
+    w.line("    advance();");
+    w.line("    return 1;");
+    w.line("}");
+
+    w.line("");
+    w.line("/* Execute the current cell, returning 0 at `@`. */");
+    w.line("static int step(void) {");
+    w.line("    if (sm || dirty[y][x]) {");
+    w.line("        return exec(field[y][x]);");
+    w.line("    }");
+    w.line("    switch (y * COLS + x) {");
+    for (pos, cmd) in cells {
+        if let Some(code) = command(int, cmd) {
+            w.mapped(
+                pos,
+                format!(
+                    "    case {}: /* ({}, {}) {} */ {} break;",
+                    pos.y * 80 + pos.x,
+                    pos.x,
+                    pos.y,
+                    char_literal(char::from(cmd) as u8),
+                    code
+                ),
+            );
+        }
+    }
+    w.line("    default: break;");
+    w.line("    }");
+    w.line("    advance();");
+    w.line("    return 1;");
+    w.line("}");
+
+    w.line("");
+    w.line("int main(void) {");
+    w.line("    rng = (uint64_t)time(NULL) | 1;");
+    w.line("    while (step()) {");
+    w.line("    }");
+    w.line("    fflush(stdout);");
+    w.line("    return 0;");
+    w.line("}");
+}
@@ -0,0 +1,371 @@
+//! The Rust backend: a single file building with plain `rustc`.
+
+use super::Writer;
+use crate::semantics::{DivZero, Eof, OutOfBounds, Unknown};
+use crate::{Command, Interpreter};
+
+/// Code executing `cmd` outside string mode with the state in `s`, or
+/// `None` if it does nothing.
+fn command(int: &Interpreter, cmd: Command) -> Option<String> {
+    let code = match cmd {
+        Command::Add => "{ let b = s.pop(); let a = s.pop(); s.push(a.wrapping_add(b)); }",
+        Command::Sub => "{ let b = s.pop(); let a = s.pop(); s.push(a.wrapping_sub(b)); }",
+        Command::Mul => "{ let b = s.pop(); let a = s.pop(); s.push(a.wrapping_mul(b)); }",
+        Command::Div => "s.div(i64::wrapping_div)",
+        Command::Mod => "s.div(i64::wrapping_rem)",
+        Command::Not => "{ let a = s.pop(); s.push((a == 0) as i64); }",
+        Command::Gt => "{ let b = s.pop(); let a = s.pop(); s.push((a > b) as i64); }",
+        Command::Right => "s.dir = RIGHT",
+        Command::Left => "s.dir = LEFT",
+        Command::Up => "s.dir = UP",
+        Command::Down => "s.dir = DOWN",
+        Command::Rand => "s.dir = s.rand_dir()",
+        Command::IfH => "s.dir = if s.pop() == 0 { RIGHT } else { LEFT }",
+        Command::IfV => "s.dir = if s.pop() == 0 { DOWN } else { UP }",
+        Command::Str => "s.sm = true",
+        Command::Dup => "{ let a = s.peek(); s.push(a); }",
+        Command::Swap => "{ let b = s.pop(); let a = s.pop(); s.push(b); s.push(a); }",
+        Command::Pop => "{ s.pop(); }",
+        Command::OutI => "{ let a = s.pop(); s.write(&format!(\"{} \", a)); }",
+        Command::OutC => "{ let a = s.pop(); s.write(&(a as u8 as char).to_string()); }",
+        Command::Bri => "s.advance()",
+        Command::Get => "s.get()",
+        Command::Put => "s.put()",
+        Command::InI => "s.read_int()",
+        Command::InC => "s.read_char()",
+        Command::End => "return false",
+        Command::Space => return None,
+        Command::Num(n) => return Some(format!("s.push({})", n)),
+        Command::Char(c) => match int.semantics.unknown {
+            Unknown::Nop => return None,
+            _ if c.is_ascii() => return Some(format!("s.unknown(b{:?})", c)),
+            _ => return Some(format!("s.unknown({})", c as u8)),
+        },
+    };
+    Some(code.to_string())
+}
+
+/// A Rust byte string literal holding `bytes`.
+fn byte_string(bytes: &[u8]) -> String {
+    let mut s = String::from("b\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                s.push('\\');
+                s.push(b as char);
+            }
+            b' '..=b'~' => s.push(b as char),
+            _ => s += &format!("\\x{:02x}", b),
+        }
+    }
+    s.push('"');
+    s
+}
+
+const PRELUDE: &str = r#"
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const COLS: usize = 80;
+const ROWS: usize = 25;
+
+const RIGHT: u8 = 0;
+const LEFT: u8 = 1;
+const UP: u8 = 2;
+const DOWN: u8 = 3;
+
+struct State {
+    field: [[u8; COLS]; ROWS],
+    /// Cells written by `p`, which no longer run their generated code.
+    dirty: [[bool; COLS]; ROWS],
+    x: usize,
+    y: usize,
+    dir: u8,
+    sm: bool,
+    stack: Vec<i64>,
+    input: io::Bytes<io::Stdin>,
+    /// The byte that ended the last number read by `&`.
+    unread: Option<u8>,
+    out: io::BufWriter<io::Stdout>,
+    rng: u64,
+}
+
+impl State {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self {
+            field: PLAYFIELD,
+            dirty: [[false; COLS]; ROWS],
+            x: 0,
+            y: 0,
+            dir: RIGHT,
+            sm: false,
+            stack: Vec::new(),
+            input: io::stdin().bytes(),
+            unread: None,
+            out: io::BufWriter::new(io::stdout()),
+            rng: seed | 1,
+        }
+    }
+
+    fn fail(&mut self, msg: &str) -> ! {
+        let _ = self.out.flush();
+        eprintln!("Error: Stepping at ({}, {}): {}", self.x, self.y, msg);
+        process::exit(1);
+    }
+
+    fn pop(&mut self) -> i64 {
+        self.stack.pop().unwrap_or(0)
+    }
+
+    fn peek(&self) -> i64 {
+        self.stack.last().copied().unwrap_or(0)
+    }
+
+    fn push(&mut self, v: i64) {
+        self.stack.push(v);
+    }
+
+    fn advance(&mut self) {
+        match self.dir {
+            RIGHT => self.x = (self.x + 1) % COLS,
+            LEFT => self.x = (self.x + COLS - 1) % COLS,
+            UP => self.y = (self.y + ROWS - 1) % ROWS,
+            _ => self.y = (self.y + 1) % ROWS,
+        }
+    }
+
+    fn rand_dir(&mut self) -> u8 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % 4) as u8
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.out.write_all(text.as_bytes()).is_err() {
+            process::exit(1);
+        }
+    }
+
+    /// The next byte of input, or `None` at its end.
+    fn read_byte(&mut self) -> Option<u8> {
+        if let Some(b) = self.unread.take() {
+            return Some(b);
+        }
+        let _ = self.out.flush();
+        match self.input.next() {
+            Some(Ok(b)) => Some(b),
+            Some(Err(_)) => self.fail("Reading a byte"),
+            None => None,
+        }
+    }
+
+    fn read_char(&mut self) {
+        match self.read_byte() {
+            Some(b) => self.push(b as i64),
+            None => AT_EOF,
+        }
+    }
+
+    fn read_int(&mut self) {
+        let mut s = String::new();
+        loop {
+            match self.read_byte() {
+                Some(b) if s.is_empty() && b.is_ascii_whitespace() => {}
+                Some(b) if b.is_ascii_digit() || (s.is_empty() && b == b'-') => {
+                    s.push(b as char)
+                }
+                Some(b) if s.is_empty() => {
+                    self.fail(&format!("Parsing '{}' into a number", b as char))
+                }
+                Some(b) => {
+                    self.unread = Some(b);
+                    break;
+                }
+                None if s.is_empty() => return AT_EOF,
+                None => break,
+            }
+        }
+        match s.parse() {
+            Ok(v) => self.push(v),
+            Err(_) => self.fail(&format!("Parsing '{}' into a number", s)),
+        }
+    }
+
+    /// The cell `cmd` uses, or `None` if it does nothing else.
+    #[allow(unused_variables)]
+    fn cell(&mut self, cmd: char) -> Option<(usize, usize)> {
+        let y = self.pop();
+        let x = self.pop();
+        if (0..COLS as i64).contains(&x) && (0..ROWS as i64).contains(&y) {
+            return Some((x as usize, y as usize));
+        }
+        OUT_OF_BOUNDS
+    }
+
+    fn get(&mut self) {
+        match self.cell('g') {
+            Some((x, y)) => {
+                let v = self.field[y][x];
+                self.push(v as i64);
+            }
+            None => GET_OUTSIDE,
+        }
+    }
+
+    fn put(&mut self) {
+        let cell = self.cell('p');
+        let v = self.pop();
+        let (x, y) = match cell {
+            Some(cell) => cell,
+            None => return,
+        };
+        if !(0..=255).contains(&v) {
+            self.fail(&format!("Failed to convert {} into u8", v));
+        }
+        self.field[y][x] = v as u8;
+        self.dirty[y][x] = true;
+    }
+
+    fn div(&mut self, f: fn(i64, i64) -> i64) {
+        let b = self.pop();
+        let a = self.pop();
+        if b == 0 {
+            DIV_ZERO
+        } else {
+            self.push(f(a, b));
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn unknown(&mut self, c: u8) {
+        UNKNOWN
+    }
+"#;
+
+/// Write the whole program for `int` to `w`.
+pub(super) fn generate(int: &Interpreter, w: &mut Writer) {
+    let (bytes, cells) = int.cells();
+
+    w.line("// Translated from Befunge-93 by `bef compile --emit rust`.");
+    w.line(format!("// Semantics: {}", int.semantics));
+    w.line("");
+    w.line("const PLAYFIELD: [[u8; COLS]; ROWS] = [");
+    for row in bytes.iter() {
+        w.line(format!("    *{},", byte_string(row)));
+    }
+    w.line("];");
+
+    let at_eof = match int.semantics.eof {
+        Eof::Error => "self.fail(\"Reading a byte: end of input\")",
+        Eof::MinusOne => "self.push(-1)",
+        Eof::Zero => "self.push(0)",
+        Eof::Reflect => "self.dir ^= 1",
+    };
+    let div_zero = match int.semantics.div_zero {
+        DivZero::Error => "self.fail(\"Division by zero\")",
+        DivZero::Zero => "self.push(0)",
+    };
+    let unknown = match int.semantics.unknown {
+        Unknown::Error => "self.fail(&format!(\"Unknown command '{}'\", c as char))",
+        Unknown::Nop => "",
+        Unknown::Reflect => "self.dir ^= 1",
+    };
+    let out_of_bounds = match int.semantics.out_of_bounds {
+        OutOfBounds::Error => {
+            "if !(0..COLS as i64).contains(&x) {
+            self.fail(&format!(\"Invalid x coordinate for {} command: {}\", cmd, x));
+        }
+        self.fail(&format!(\"Invalid y coordinate for {} command: {}\", cmd, y))"
+        }
+        OutOfBounds::Wrap => {
+            "Some((x.rem_euclid(COLS as i64) as usize, y.rem_euclid(ROWS as i64) as usize))"
+        }
+        OutOfBounds::Reflect => "self.dir ^= 1;\n        None",
+        OutOfBounds::Nop => "None",
+    };
+    let get_outside = match int.semantics.out_of_bounds {
+        OutOfBounds::Nop => "self.push(0)",
+        _ => "{}",
+    };
+    for line in PRELUDE
+        .replace("AT_EOF", at_eof)
+        .replace("DIV_ZERO", div_zero)
+        .replace("UNKNOWN", unknown)
+        .replace("OUT_OF_BOUNDS", out_of_bounds)
+        .replace("GET_OUTSIDE", get_outside)
+        .lines()
+    {
+        w.line(line);
+    }
+
+    w.line("");
+    w.line("    /// Execute `c` as the current cell, returning false at `@`.");
+    w.line("    fn exec(&mut self, c: u8) -> bool {");
+    w.line("        if self.sm {");
+    w.line("            if c == b'\"' {");
+    w.line("                self.sm = false;");
+    w.line("            } else {");
+    w.line("                self.push(c as i64);");
+    w.line("            }");
+    w.line("            self.advance();");
+    w.line("            return true;");
+    w.line("        }");
+    w.line("        let s = self;");
+    w.line("        match c {");
+    for b in 0..128u8 {
+        let cmd = Command::from(b as char);
+        if matches!(cmd, Command::Space | Command::Char(_)) {
+            continue;
+        }
+        if let Some(code) = command(int, cmd) {
+            w.line(format!("            b{:?} => {},", b as char, code));
+        }
+    }
+    w.line("            b' ' => {}");
+    w.line("            c => s.unknown(c),");
+    w.line("        }");
+    w.line("        s.advance();");
+    w.line("        true");
+    w.line("    }");
+    w.line("}");
+
+    w.line("");
+    w.line("/// Execute the current cell, returning false at `@`.");
+    w.line("fn step(s: &mut State) -> bool {");
+    w.line("    if s.sm || s.dirty[s.y][s.x] {");
+    w.line("        let c = s.field[s.y][s.x];");
+    w.line("        return s.exec(c);");
+    w.line("    }");
+    w.line("    match (s.x, s.y) {");
+    for (pos, cmd) in cells {
+        if let Some(code) = command(int, cmd) {
+            w.mapped(
+                pos,
+                format!(
+                    "        ({}, {}) => {}, // {:?}",
+                    pos.x,
+                    pos.y,
+                    code,
+                    char::from(cmd)
+                ),
+            );
+        }
+    }
+    w.line("        _ => {}");
+    w.line("    }");
+    w.line("    s.advance();");
+    w.line("    true");
+    w.line("}");
+
+    w.line("");
+    w.line("fn main() {");
+    w.line("    let mut s = State::new();");
+    w.line("    while step(&mut s) {}");
+    w.line("    let _ = s.out.flush();");
+    w.line("}");
+}
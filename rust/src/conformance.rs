@@ -0,0 +1,179 @@
+//! Small programs pinning down the behaviour of the interpreter, run by
+//! `bef selftest`. They use the default semantics.
+
+use crate::Interpreter;
+
+/// Programs running longer than this count as failing.
+const MAX_STEPS: usize = 100_000;
+
+/// A conformance program and what it must print.
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub name: &'static str,
+    /// What the behaviour checked is.
+    pub description: &'static str,
+    pub source: &'static str,
+    /// What `&` and `~` read.
+    pub input: &'static str,
+    pub expected: &'static str,
+}
+
+/// Every conformance program.
+pub const CASES: &[Case] = &[
+    Case {
+        name: "wrap-horizontal",
+        description: "the PC leaving the left edge comes back at the right one",
+        source: "<@,\"A\"",
+        input: "",
+        expected: "A",
+    },
+    Case {
+        name: "wrap-vertical",
+        description: "the PC leaving the top edge comes back at the bottom one",
+        source: "^\n@\n,\n\"\nB\n\"",
+        input: "",
+        expected: "B",
+    },
+    Case {
+        name: "bridge",
+        description: "`#` skips the next cell",
+        source: "1#2.@",
+        input: "",
+        expected: "1 ",
+    },
+    Case {
+        name: "bridge-at-edge",
+        description: "`#` on the edge skips the cell across it",
+        source: "<                                                                          @.1@#",
+        input: "",
+        expected: "1 ",
+    },
+    Case {
+        name: "stringmode",
+        description: "string mode pushes every cell, spaces included",
+        source: "\"a b\",,,@",
+        input: "",
+        expected: "b a",
+    },
+    Case {
+        name: "stringmode-vertical",
+        description: "string mode works in any direction",
+        source: "v\n0\n|\n\"\nD\n\"\n,\n@",
+        input: "",
+        expected: "D",
+    },
+    Case {
+        name: "horizontal-if",
+        description: "`_` goes left on a non-zero value",
+        source: "1_@,\"L\"",
+        input: "",
+        expected: "L",
+    },
+    Case {
+        name: "get-blank",
+        description: "`g` reads cells outside the program as spaces",
+        source: "99g.@",
+        input: "",
+        expected: "32 ",
+    },
+    Case {
+        name: "put-get",
+        description: "`g` reads back what `p` wrote",
+        source: "\"a\"00p00g,@",
+        input: "",
+        expected: "a",
+    },
+    Case {
+        name: "self-modification",
+        description: "cells written by `p` are executed",
+        source: "\"@\"90p7.  8.@",
+        input: "",
+        expected: "7 ",
+    },
+    Case {
+        name: "division",
+        description: "`/` and `%` round towards zero",
+        source: "73/.73%.07-3/.07-3%.@",
+        input: "",
+        expected: "2 1 -2 -1 ",
+    },
+    Case {
+        name: "empty-stack",
+        description: "popping an empty stack gives zero",
+        source: ".:.@",
+        input: "",
+        expected: "0 0 ",
+    },
+    Case {
+        name: "logic",
+        description: "`!` and `` ` `` push 1 or 0",
+        source: "0!.1!.21`.12`.@",
+        input: "",
+        expected: "1 0 1 0 ",
+    },
+    Case {
+        name: "stack-ops",
+        description: "`:` duplicates, `\\` swaps and `$` discards",
+        source: "12\\..3:..45$.@",
+        input: "",
+        expected: "1 2 3 3 4 ",
+    },
+    Case {
+        name: "negative-output",
+        description: "`.` prints signed numbers followed by a space",
+        source: "05-.@",
+        input: "",
+        expected: "-5 ",
+    },
+    Case {
+        name: "input",
+        description: "`&` reads a number up to a space and `~` one byte",
+        source: "&.~,@",
+        input: "42 x",
+        expected: "42 x",
+    },
+];
+
+/// The result of running a `Case`.
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub case: &'static Case,
+    /// What the program printed.
+    pub output: String,
+    /// Why the program stopped early, if it did.
+    pub error: Option<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.output == self.case.expected
+    }
+}
+
+/// Run every case in `CASES`.
+pub fn run_suite() -> Vec<CaseResult> {
+    CASES.iter().map(run_case).collect()
+}
+
+/// Run `case` on a fresh interpreter.
+pub fn run_case(case: &'static Case) -> CaseResult {
+    let mut int = Interpreter::new();
+    int.set_input(case.input.as_bytes());
+    let mut halted = true;
+    let error = int
+        .load(&mut case.source.as_bytes())
+        .and_then(|_| {
+            int.run(|_, steps| {
+                halted = steps < MAX_STEPS;
+                halted
+            })
+        })
+        .err()
+        .map(|e| format!("{:#}", e))
+        .or_else(|| (!halted).then(|| format!("Didn't halt within {} steps", MAX_STEPS)));
+    CaseResult {
+        case,
+        output: int.get_output().to_string(),
+        error,
+    }
+}
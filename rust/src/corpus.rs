@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::instructions::{instructions, Dialect};
+use crate::{Command, PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+/// File extensions considered Befunge programs when scanning directories.
+pub const EXTENSIONS: &[&str] = &["bf", "b93", "befunge"];
+
+/// Static metrics of a single program.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ProgramStats {
+    /// Path of the program.
+    pub path: String,
+    /// Size of the file in bytes.
+    pub bytes: usize,
+    /// Number of lines.
+    pub rows: usize,
+    /// Length of the longest line.
+    pub cols: usize,
+    /// Number of non-space cells.
+    pub cells: usize,
+    /// How often each non-space character occurs.
+    pub instructions: BTreeMap<char, usize>,
+    /// Language features the program appears to use.
+    pub features: Vec<&'static str>,
+}
+
+impl ProgramStats {
+    /// Compute the metrics of `source`.
+    ///
+    /// Features are detected heuristically: string literals are only
+    /// recognized when they run left to right on a single line.
+    pub fn analyze(path: impl Into<String>, source: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(source);
+        let lines: Vec<&str> = text.lines().collect();
+        let mut instructions = BTreeMap::new();
+        let mut unknown = false;
+
+        for line in &lines {
+            let mut in_string = false;
+            for c in line.chars() {
+                if c == '"' {
+                    in_string = !in_string;
+                }
+                if c == ' ' {
+                    continue;
+                }
+                *instructions.entry(c).or_insert(0) += 1;
+                if !in_string && matches!(Command::from(c), Command::Char(_)) {
+                    unknown = true;
+                }
+            }
+        }
+
+        let cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let uses = |c| instructions.contains_key(&c);
+
+        let mut features = vec![];
+        if uses('&') || uses('~') {
+            features.push("input");
+        }
+        if uses('?') {
+            features.push("random");
+        }
+        if uses('"') {
+            features.push("stringmode");
+        }
+        if uses('g') {
+            features.push("get");
+        }
+        if uses('p') {
+            features.push("self-modifying");
+        }
+        if lines.len() > PLAYFIELD_ROWS || cols > PLAYFIELD_COLS {
+            features.push("oversized");
+        }
+        if unknown {
+            features.push("non-93-instructions");
+        }
+
+        Self {
+            path: path.into(),
+            bytes: source.len(),
+            rows: lines.len(),
+            cols,
+            cells: instructions.values().sum(),
+            instructions,
+            features,
+        }
+    }
+}
+
+/// Aggregate metrics over a collection of programs.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct CorpusStats {
+    /// Metrics of each program.
+    pub programs: Vec<ProgramStats>,
+    /// Total size in bytes.
+    pub bytes: usize,
+    /// Total number of non-space cells.
+    pub cells: usize,
+    /// How often each non-space character occurs across all programs.
+    pub instructions: BTreeMap<char, usize>,
+    /// How many programs use each feature.
+    pub features: BTreeMap<&'static str, usize>,
+}
+
+impl CorpusStats {
+    /// Add a program to the aggregate.
+    pub fn add(&mut self, program: ProgramStats) {
+        self.bytes += program.bytes;
+        self.cells += program.cells;
+        for (&c, &n) in &program.instructions {
+            *self.instructions.entry(c).or_insert(0) += n;
+        }
+        for &feature in &program.features {
+            *self.features.entry(feature).or_insert(0) += 1;
+        }
+        self.programs.push(program);
+    }
+
+    /// Compute the metrics of every program under `dir`, recursively.
+    pub fn scan(dir: &Path) -> Result<Self> {
+        let mut stats = Self::default();
+        for path in find_programs(dir, true)? {
+            let source =
+                fs::read(&path).with_context(|| anyhow!("Reading '{}'", path.display()))?;
+            stats.add(ProgramStats::analyze(path.display().to_string(), &source));
+        }
+        Ok(stats)
+    }
+
+    /// Render as CSV, one row per program and one column per Befunge-93
+    /// instruction.
+    pub fn to_csv(&self) -> String {
+        let columns: Vec<char> = instructions(Dialect::Befunge93)
+            .iter()
+            .map(|doc| doc.c)
+            .collect();
+
+        let mut header: Vec<String> = ["path", "bytes", "rows", "cols", "cells", "features"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        header.extend(columns.iter().map(|c| csv_field(&c.to_string())));
+
+        let mut out = header.join(",") + "\n";
+        for p in &self.programs {
+            let mut row = vec![
+                csv_field(&p.path),
+                p.bytes.to_string(),
+                p.rows.to_string(),
+                p.cols.to_string(),
+                p.cells.to_string(),
+                csv_field(&p.features.join(";")),
+            ];
+            row.extend(
+                columns
+                    .iter()
+                    .map(|c| p.instructions.get(c).copied().unwrap_or(0).to_string()),
+            );
+            out += &row.join(",");
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Shades of thumbnail characters, from no code to all code.
+const SHADES: &[char] = &[' ', '\u{2591}', '\u{2592}', '\u{2593}', '\u{2588}'];
+
+/// Render the playfield of `source` scaled down to `cols` by `rows`
+/// characters, each shaded by how many of the cells it covers are not
+/// spaces. Any code at all gets the lightest shade.
+pub fn thumbnail(source: &[u8], cols: usize, rows: usize) -> Vec<String> {
+    let text = String::from_utf8_lossy(source);
+    let mut filled = vec![vec![0; cols]; rows];
+    for (y, line) in text.lines().take(PLAYFIELD_ROWS).enumerate() {
+        for (x, c) in line.chars().take(PLAYFIELD_COLS).enumerate() {
+            if c != ' ' {
+                filled[y * rows / PLAYFIELD_ROWS][x * cols / PLAYFIELD_COLS] += 1;
+            }
+        }
+    }
+
+    let per_block = (PLAYFIELD_COLS / cols).max(1) * (PLAYFIELD_ROWS / rows).max(1);
+    filled
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&n| match n {
+                    0 => SHADES[0],
+                    n => SHADES[(1 + n * (SHADES.len() - 2) / per_block).min(SHADES.len() - 1)],
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Paths of the programs in `dir`, sorted, including those in
+/// subdirectories if `recursive`.
+pub fn find_programs(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    collect(dir, recursive, &mut paths)?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn collect(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| anyhow!("Reading '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                collect(&path, recursive, paths)?;
+            }
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| EXTENSIONS.contains(&ext))
+        {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
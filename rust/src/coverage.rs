@@ -0,0 +1,258 @@
+use std::fmt::Write;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::teach::escape_html;
+use crate::{Bounds, Command, Grid, Interpreter, Position};
+
+const EXECUTED: u8 = 0b01;
+const DATA: u8 = 0b10;
+
+/// Accumulates which cells were executed and which were accessed as data.
+#[derive(Clone)]
+pub(crate) struct Tracker(Grid<u8>);
+
+impl Tracker {
+    pub(crate) fn new(size: Bounds) -> Self {
+        Self(Grid::new(size, 0))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.fill(0);
+    }
+
+    pub(crate) fn resize(&mut self, size: Bounds) {
+        self.0.resize(size, 0);
+    }
+
+    pub(crate) fn executed(&mut self, pos: Position) {
+        self.0[pos.y][pos.x] |= EXECUTED;
+    }
+
+    pub(crate) fn data(&mut self, pos: Position) {
+        self.0[pos.y][pos.x] |= DATA;
+    }
+}
+
+/// A summary of which parts of the playfield were exercised.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Coverage {
+    /// Cells that were executed at least once.
+    pub visited: Vec<Position>,
+    /// Non-space cells that were never executed.
+    pub unvisited: Vec<Position>,
+    /// Cells that were read by `g` or written by `p`, but never executed.
+    pub data_only: Vec<Position>,
+    /// Percentage of non-space cells that were executed.
+    pub percent: f64,
+}
+
+impl Interpreter {
+    /// Summarize coverage accumulated since the last call to
+    /// `reset_coverage`, across any number of runs.
+    pub fn get_coverage(&self) -> Coverage {
+        let mut visited = vec![];
+        let mut unvisited = vec![];
+        let mut data_only = vec![];
+        let mut code_cells = 0;
+        let mut code_visited = 0;
+
+        for (y, row) in self.coverage.0.iter().enumerate() {
+            for (x, &flags) in row.iter().enumerate() {
+                let pos = Position { x, y };
+                let is_code = !matches!(self.playfield[y][x], Command::Space);
+                let executed = flags & EXECUTED != 0;
+
+                if is_code {
+                    code_cells += 1;
+                }
+                if executed {
+                    visited.push(pos);
+                    if is_code {
+                        code_visited += 1;
+                    }
+                } else {
+                    if is_code {
+                        unvisited.push(pos);
+                    }
+                    if flags & DATA != 0 {
+                        data_only.push(pos);
+                    }
+                }
+            }
+        }
+
+        let percent = if code_cells == 0 {
+            100.0
+        } else {
+            code_visited as f64 * 100.0 / code_cells as f64
+        };
+
+        Coverage {
+            visited,
+            unvisited,
+            data_only,
+            percent,
+        }
+    }
+
+    /// Forget coverage collected by previous runs.
+    pub fn reset_coverage(&mut self) {
+        self.coverage.clear();
+    }
+
+    /// The playfield annotated with the coverage collected so far, see
+    /// `to_text` and `to_html`.
+    pub fn coverage_report(&self) -> CoverageReport {
+        let mut rows: Vec<_> = (0..self.get_size().rows).map(|y| self.get_row(y)).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        let coverage = self.get_coverage();
+        let mut marks: Vec<Vec<Mark>> = rows
+            .iter()
+            .map(|row| vec![Mark::Blank; row.chars().count()])
+            .collect();
+        let mut mark = |pos: Position, m: Mark| {
+            if let Some(row) = marks.get_mut(pos.y) {
+                if pos.x >= row.len() {
+                    row.resize(pos.x + 1, Mark::Blank);
+                }
+                row[pos.x] = m;
+            }
+        };
+        let mut executed = 0;
+        for &pos in &coverage.visited {
+            if !matches!(self.playfield[pos.y][pos.x], Command::Space) {
+                mark(pos, Mark::Executed);
+                executed += 1;
+            }
+        }
+        for &pos in &coverage.unvisited {
+            mark(pos, Mark::NeverExecuted);
+        }
+        for &pos in &coverage.data_only {
+            mark(pos, Mark::Data);
+        }
+        CoverageReport {
+            rows,
+            marks,
+            executed,
+            code_cells: executed + coverage.unvisited.len(),
+            percent: coverage.percent,
+        }
+    }
+}
+
+/// How a cell is marked in a coverage report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mark {
+    /// A space, or outside the program.
+    Blank,
+    Executed,
+    NeverExecuted,
+    /// Never executed, but read by `g` or written by `p`.
+    Data,
+}
+
+impl Mark {
+    fn as_char(self) -> char {
+        match self {
+            Self::Blank => ' ',
+            Self::Executed => '+',
+            Self::NeverExecuted => '-',
+            Self::Data => 'd',
+        }
+    }
+
+    fn class(self) -> Option<&'static str> {
+        match self {
+            Self::Blank => None,
+            Self::Executed => Some("hit"),
+            Self::NeverExecuted => Some("miss"),
+            Self::Data => Some("data"),
+        }
+    }
+}
+
+/// The playfield with each cell marked as executed or not.
+#[derive(Debug, Clone)]
+pub struct CoverageReport {
+    /// The rows of the playfield up to the last non-empty one.
+    rows: Vec<String>,
+    marks: Vec<Vec<Mark>>,
+    /// Non-space cells executed.
+    pub executed: usize,
+    /// Non-space cells.
+    pub code_cells: usize,
+    pub percent: f64,
+}
+
+impl CoverageReport {
+    fn summary(&self) -> String {
+        format!(
+            "{} of {} cells executed ({:.1}%)",
+            self.executed, self.code_cells, self.percent
+        )
+    }
+
+    /// Each row followed by a line marking its cells `+` if executed, `-`
+    /// if never executed and `d` if only used as data, then the percentage.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (row, marks) in self.rows.iter().zip(&self.marks) {
+            let _ = writeln!(out, "{}", row);
+            let line: String = marks.iter().map(|m| m.as_char()).collect();
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "+ executed, - never executed, d only used as data");
+        let _ = writeln!(out, "{}", self.summary());
+        out
+    }
+
+    /// A standalone HTML document titled `title`, with cells coloured by
+    /// whether they were executed.
+    pub fn to_html(&self, title: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "<!DOCTYPE html>");
+        let _ = writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">");
+        let _ = writeln!(out, "<title>{}</title>", escape_html(title));
+        let _ = writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE);
+        let _ = writeln!(out, "<h1>{}</h1>", escape_html(title));
+        let _ = writeln!(out, "<p>{}</p>", self.summary());
+        let _ = writeln!(out, "<pre class=\"field\">");
+        for (row, marks) in self.rows.iter().zip(&self.marks) {
+            let chars: Vec<char> = row.chars().collect();
+            for (x, mark) in marks.iter().enumerate() {
+                let c = escape_html(&chars.get(x).unwrap_or(&' ').to_string());
+                match mark.class() {
+                    Some(class) => {
+                        let _ = write!(out, "<span class=\"{}\">{}</span>", class, c);
+                    }
+                    None => out += &c,
+                }
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "</pre>");
+        let _ = writeln!(
+            out,
+            "<p class=\"legend\"><span class=\"hit\">executed</span> \
+             <span class=\"miss\">never executed</span> \
+             <span class=\"data\">only used as data</span></p>"
+        );
+        let _ = writeln!(out, "</body>\n</html>");
+        out
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; max-width: 60em; margin: auto; }
+pre.field { background: #f6f6f6; padding: 0.5em; line-height: 1.2; }
+.hit { background: #b7e4b0; }
+.miss { background: #f4a6a6; }
+.data { background: #c6d8f5; }
+.legend span { padding: 2px 4px; }
+";
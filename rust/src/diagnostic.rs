@@ -0,0 +1,166 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::schema::Versioned;
+use crate::theme::Theme;
+use crate::Interpreter;
+
+/// How many stack values, counting from the top, a diagnostic shows.
+const STACK_TOP: usize = 5;
+
+/// Where in the playfield a diagnostic points at.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Location {
+    /// Zero-based column.
+    pub x: usize,
+    /// Zero-based row.
+    pub y: usize,
+    /// Direction the PC was moving.
+    pub direction: char,
+    /// The text of the playfield row.
+    pub source: String,
+}
+
+/// A renderable description of a load or runtime error.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Diagnostic {
+    /// The outermost error message.
+    pub message: String,
+    /// The underlying causes, outermost first.
+    pub causes: Vec<String>,
+    /// Name of the program file, if any.
+    pub file: Option<String>,
+    /// Offending cell, for runtime errors.
+    pub location: Option<Location>,
+    /// The top of the stack, top first.
+    pub stack: Vec<i64>,
+}
+
+impl Diagnostic {
+    /// Build a diagnostic carrying just the error chain.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let mut chain = error.chain().map(|e| e.to_string());
+        Self {
+            message: chain.next().unwrap_or_default(),
+            causes: chain.collect(),
+            file: None,
+            location: None,
+            stack: vec![],
+        }
+    }
+
+    /// Attach the name of the program file.
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Render as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Versioned::new(self)).expect("diagnostics are always serializable")
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &Theme::default())
+    }
+}
+
+impl Diagnostic {
+    /// Render for terminals, drawn with `theme`.
+    pub fn render(&self, theme: &Theme) -> String {
+        let mut s = String::new();
+        self.write(&mut s, theme)
+            .expect("writing to a string can't fail");
+        s
+    }
+
+    fn write(&self, f: &mut impl fmt::Write, theme: &Theme) -> fmt::Result {
+        writeln!(
+            f,
+            "{}{}",
+            theme.error.paint("error: "),
+            theme.strong.paint(&self.message)
+        )?;
+
+        let loc = match &self.location {
+            Some(loc) => loc,
+            None => {
+                for cause in &self.causes {
+                    writeln!(f, "  {} {}", theme.accent.paint("caused by:"), cause)?;
+                }
+                return Ok(());
+            }
+        };
+
+        let line_no = (loc.y + 1).to_string();
+        let gutter = " ".repeat(line_no.len());
+        let pipe = theme.accent.paint("|");
+
+        writeln!(
+            f,
+            "{}{} {}:{}:{} (moving {})",
+            gutter,
+            theme.accent.paint("-->"),
+            self.file.as_deref().unwrap_or("<playfield>"),
+            loc.y + 1,
+            loc.x + 1,
+            loc.direction
+        )?;
+        writeln!(f, "{} {}", gutter, pipe)?;
+        writeln!(
+            f,
+            "{} {} {}",
+            theme.accent.paint(&line_no),
+            pipe,
+            loc.source
+        )?;
+        write!(
+            f,
+            "{} {} {}{}",
+            gutter,
+            pipe,
+            " ".repeat(loc.x),
+            theme.error.paint("^")
+        )?;
+        match self.causes.last() {
+            Some(cause) => writeln!(f, " {}", theme.error.paint(cause))?,
+            None => writeln!(f)?,
+        }
+
+        let stack: Vec<_> = self.stack.iter().map(|v| v.to_string()).collect();
+        write!(
+            f,
+            "{} {} stack (top first): [{}]",
+            gutter,
+            theme.accent.paint("="),
+            stack.join(", ")
+        )
+    }
+}
+
+impl Interpreter {
+    /// Describe `error`, which occurred while running, with the current
+    /// state of the interpreter.
+    pub fn diagnose(&self, error: &anyhow::Error) -> Diagnostic {
+        let pos = self.get_position();
+        let mut diag = Diagnostic::from_error(error);
+        diag.location = Some(Location {
+            x: pos.x,
+            y: pos.y,
+            direction: self.get_direction().as_char(),
+            source: self.get_row(pos.y),
+        });
+        diag.stack = self
+            .stack
+            .values()
+            .into_iter()
+            .rev()
+            .take(STACK_TOP)
+            .collect();
+        diag
+    }
+}
@@ -0,0 +1,176 @@
+//! Differential testing: running a program through this interpreter and an
+//! external reference implementation with the same input, and comparing
+//! what they print.
+
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::trace::first_divergence;
+use crate::Interpreter;
+
+/// How a run of one implementation ended.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct RunResult {
+    /// What the program printed.
+    pub output: String,
+    /// Why the run failed, if it did.
+    pub error: Option<String>,
+}
+
+/// The outcome of a differential run.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Comparison {
+    pub ours: RunResult,
+    pub reference: RunResult,
+    /// Index of the first character at which the outputs differ, if they
+    /// do. Errors are reported but not compared.
+    pub divergence: Option<usize>,
+}
+
+/// An external Befunge-93 implementation.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /// The program and its arguments. The path of the program to run
+    /// replaces a `{}` argument, or is added at the end if there is none.
+    pub command: Vec<String>,
+    /// Runs taking longer are killed and count as failed.
+    pub timeout: Duration,
+}
+
+impl Reference {
+    /// A reference run by `command`, split at whitespace.
+    pub fn new(command: &str, timeout: Duration) -> Result<Self> {
+        let command: Vec<_> = command.split_whitespace().map(String::from).collect();
+        if command.is_empty() {
+            bail!("Empty reference command");
+        }
+        Ok(Self { command, timeout })
+    }
+
+    /// Run the program at `program`, feeding it `input`.
+    pub fn run(&self, program: &Path, input: &[u8]) -> Result<RunResult> {
+        let program = program.to_string_lossy();
+        let mut args: Vec<_> = self.command[1..]
+            .iter()
+            .map(|arg| {
+                if arg == "{}" {
+                    program.to_string()
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect();
+        if !self.command[1..].iter().any(|arg| arg == "{}") {
+            args.push(program.to_string());
+        }
+
+        let mut child = Command::new(&self.command[0])
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| anyhow!("Failed to start '{}'", self.command[0]))?;
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let input = input.to_vec();
+        // The reference may exit without reading everything.
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+        // Output is passed on in chunks, so that what was printed can be
+        // collected even if a killed reference left a process holding the
+        // pipe open.
+        let mut stdout = child.stdout.take().expect("piped stdout");
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n @ 1..) = stdout.read(&mut buf) {
+                if sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut stderr = child.stderr.take().expect("piped stderr");
+        let err_reader = thread::spawn(move || {
+            let mut buf = String::new();
+            let _ = stderr.read_to_string(&mut buf);
+            buf
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        let output: Vec<u8> = match status {
+            Some(_) => chunks.iter().flatten().collect(),
+            None => chunks.try_iter().flatten().collect(),
+        };
+        let error = match status {
+            None => Some(format!("Timed out after {:?}", self.timeout)),
+            Some(status) if !status.success() => {
+                Some(match err_reader.join().unwrap_or_default().trim() {
+                    "" => status.to_string(),
+                    message => format!("{}: {}", status, message),
+                })
+            }
+            Some(_) => None,
+        };
+        Ok(RunResult {
+            output: String::from_utf8_lossy(&output).into_owned(),
+            error,
+        })
+    }
+}
+
+/// Run `int`, loaded with the program at `program`, and `reference` on the
+/// program with the same `input`, and compare their output. `int` is given
+/// up to `max_steps` steps.
+pub fn diff_run(
+    int: &mut Interpreter,
+    max_steps: usize,
+    reference: &Reference,
+    program: &Path,
+    input: &[u8],
+) -> Result<Comparison> {
+    int.set_input(Cursor::new(input.to_vec()));
+    let mut halted = true;
+    let error = int
+        .run(|_, steps| {
+            halted = steps < max_steps;
+            halted
+        })
+        .err()
+        .map(|e| format!("{:#}", e))
+        .or_else(|| (!halted).then(|| format!("Didn't halt within {} steps", max_steps)));
+    let ours = RunResult {
+        output: int.get_output().to_string(),
+        error,
+    };
+    let reference = reference.run(program, input)?;
+
+    let left: Vec<char> = ours.output.chars().collect();
+    let right: Vec<char> = reference.output.chars().collect();
+    Ok(Comparison {
+        divergence: first_divergence(&left, &right),
+        ours,
+        reference,
+    })
+}
@@ -0,0 +1,464 @@
+//! The table mapping each cell byte to the code executing it, and the API
+//! for adding instructions to it.
+
+use std::cmp::Ordering;
+use std::convert::{TryFrom, TryInto};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::instructions::{self, Dialect};
+use crate::semantics::{Chars, OutOfBounds, Semantics, Unknown};
+use crate::stack::BinOp;
+use crate::{Command, Direction, Interpreter, NumberScan, Position, StackTy, StepResult};
+
+/// Executes the instruction in a cell, given the byte in it. Unless it
+/// returns `StepResult::Stop` the PC moves on afterwards.
+type Handler = fn(&mut Interpreter, u8) -> Result<StepResult>;
+
+/// Executes a cell holding a character that doesn't fit in a byte, which
+/// is never an instruction.
+type WideHandler = fn(&mut Interpreter, char) -> Result<StepResult>;
+
+/// A custom instruction, see `Interpreter::define_instruction`.
+pub type Instruction = fn(&mut Interpreter) -> Result<()>;
+
+/// Handlers for every byte, built from the semantics and the custom
+/// instructions so that stepping doesn't have to decide again each time.
+#[derive(Clone)]
+pub(crate) struct Dispatch {
+    handlers: [Handler; 256],
+    custom: [Option<Instruction>; 256],
+    /// The dialect whose instructions are added to those of Befunge-93.
+    pub(crate) dialect: Dialect,
+    /// What cells holding characters past a byte, only loaded or put with
+    /// `chars=unicode`, do.
+    pub(crate) wide: WideHandler,
+}
+
+impl Dispatch {
+    pub(crate) fn new(semantics: &Semantics) -> Self {
+        let mut dispatch = Self {
+            handlers: [cont; 256],
+            custom: [None; 256],
+            dialect: Dialect::Befunge93,
+            wide: |_, _| Ok(StepResult::Cont),
+        };
+        dispatch.rebuild(semantics);
+        dispatch
+    }
+
+    /// The handler for cells holding `byte`.
+    pub(crate) fn handler(&self, byte: u8) -> Handler {
+        self.handlers[byte as usize]
+    }
+
+    /// Whether any custom instruction is defined.
+    pub(crate) fn has_custom(&self) -> bool {
+        self.custom.iter().any(Option::is_some)
+    }
+
+    /// Fill in the table again, after the semantics or the custom
+    /// instructions changed.
+    pub(crate) fn rebuild(&mut self, semantics: &Semantics) {
+        let unknown: Handler = match semantics.unknown {
+            Unknown::Error => |_, b| bail!("Unknown command '{}'", b as char),
+            Unknown::Nop => cont,
+            Unknown::Reflect => |int, _| {
+                int.reverse();
+                Ok(StepResult::Cont)
+            },
+        };
+        self.wide = match semantics.unknown {
+            Unknown::Error => |_, c| bail!("Unknown command '{}'", c),
+            Unknown::Nop => |_, _| Ok(StepResult::Cont),
+            Unknown::Reflect => |int, _| {
+                int.reverse();
+                Ok(StepResult::Cont)
+            },
+        };
+        self.handlers = [unknown; 256];
+        for (b, custom) in self.custom.iter().enumerate() {
+            if custom.is_some() {
+                self.handlers[b] = |int, b| {
+                    let f = int.dispatch.custom[b as usize].expect("custom instruction");
+                    f(int).map(|_| StepResult::Cont)
+                };
+            }
+        }
+        for b in b'0'..=b'9' {
+            self.handlers[b as usize] = |int, b| {
+                int.stack.push((b - b'0').into());
+                Ok(StepResult::Cont)
+            };
+        }
+        for &(c, handler) in BUILTINS {
+            self.handlers[c as usize] = handler;
+        }
+        for &(c, handler) in dialect_builtins(self.dialect).iter().copied().flatten() {
+            self.handlers[c as usize] = handler;
+        }
+    }
+}
+
+/// The instructions `dialect` adds to Befunge-93, in the tables of the
+/// dialects it builds on.
+fn dialect_builtins(dialect: Dialect) -> &'static [&'static [(u8, Handler)]] {
+    match dialect {
+        Dialect::Befunge93 => &[],
+        Dialect::Befunge96 => &[BEFUNGE_96],
+        Dialect::Befunge97 => &[BEFUNGE_96, BEFUNGE_97],
+    }
+}
+
+fn cont(_: &mut Interpreter, _: u8) -> Result<StepResult> {
+    Ok(StepResult::Cont)
+}
+
+/// Sets the direction of the PC.
+macro_rules! go {
+    ($dir:expr) => {
+        |int, _| {
+            int.dir = $dir;
+            Ok(StepResult::Cont)
+        }
+    };
+}
+
+/// The Befunge-93 instructions other than digits.
+const BUILTINS: &[(u8, Handler)] = &[
+    (b' ', cont),
+    (b'+', |int, _| {
+        int.binop(BinOp::Add);
+        Ok(StepResult::Cont)
+    }),
+    (b'-', |int, _| {
+        int.binop(BinOp::Sub);
+        Ok(StepResult::Cont)
+    }),
+    (b'*', |int, _| {
+        int.binop(BinOp::Mul);
+        Ok(StepResult::Cont)
+    }),
+    (b'/', |int, _| {
+        int.divop(BinOp::Div)?;
+        Ok(StepResult::Cont)
+    }),
+    (b'%', |int, _| {
+        int.divop(BinOp::Mod)?;
+        Ok(StepResult::Cont)
+    }),
+    (b'!', |int, _| {
+        let x = int.stack.pop();
+        int.stack.push(if x == 0 { 1 } else { 0 });
+        Ok(StepResult::Cont)
+    }),
+    (b'`', |int, _| {
+        int.binop(BinOp::Gt);
+        Ok(StepResult::Cont)
+    }),
+    (b'>', go!(Direction::Right)),
+    (b'<', go!(Direction::Left)),
+    (b'^', go!(Direction::Up)),
+    (b'v', go!(Direction::Down)),
+    (b'?', |int, _| {
+        int.dir = int.rand_dir()?;
+        Ok(StepResult::Cont)
+    }),
+    (b'_', |int, _| {
+        let x = int.stack.pop();
+        int.dir = if x == 0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        };
+        Ok(StepResult::Cont)
+    }),
+    (b'|', |int, _| {
+        let x = int.stack.pop();
+        int.dir = if x == 0 {
+            Direction::Down
+        } else {
+            Direction::Up
+        };
+        Ok(StepResult::Cont)
+    }),
+    (b'"', |int, _| {
+        int.stringmode = !int.stringmode;
+        Ok(StepResult::Cont)
+    }),
+    (b':', |int, _| {
+        int.stack.dup();
+        Ok(StepResult::Cont)
+    }),
+    (b'\\', |int, _| {
+        int.stack.swap();
+        Ok(StepResult::Cont)
+    }),
+    (b'$', |int, _| {
+        int.stack.pop();
+        Ok(StepResult::Cont)
+    }),
+    (b'.', |int, _| {
+        let x = int.stack.pop_text();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(value = %x, "output");
+        int.write_output(&format!("{} ", x));
+        Ok(StepResult::Cont)
+    }),
+    (b',', |int, _| {
+        let x = int.stack.pop();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(value = x, "output");
+        let c = int.output_char(x);
+        int.write_output(&c.to_string());
+        Ok(StepResult::Cont)
+    }),
+    (b'&', |int, _| {
+        let mut scan = NumberScan::default();
+        loop {
+            match int.read_byte()? {
+                Some(b) if scan.feed(b) => {}
+                Some(b) if scan.text.is_empty() => {
+                    bail!("Parsing '{}' into a number", b as char)
+                }
+                Some(b) => {
+                    int.unread = Some(b);
+                    break;
+                }
+                None if scan.text.is_empty() => {
+                    int.at_eof()?;
+                    return Ok(StepResult::Cont);
+                }
+                None => break,
+            }
+        }
+        int.stack.push_parsed(&scan.text)?;
+        Ok(StepResult::Cont)
+    }),
+    (b'~', |int, _| {
+        match int.read_byte()? {
+            Some(b) => int.stack.push(b.into()),
+            None => int.at_eof()?,
+        }
+        Ok(StepResult::Cont)
+    }),
+    (b'#', |int, _| {
+        int.advance_pc();
+        Ok(StepResult::Cont)
+    }),
+    (b'g', |int, _| {
+        let Some(pos) = int.pop_cell('g')? else {
+            if int.semantics.out_of_bounds == OutOfBounds::Nop {
+                int.stack.push(0);
+            }
+            return Ok(StepResult::Cont);
+        };
+        let value = int.playfield[pos.y][pos.x].value();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(x = pos.x, y = pos.y, value, "get");
+        int.stack.push(value);
+        Ok(StepResult::Cont)
+    }),
+    (b'p', |int, _| {
+        let pos = int.pop_cell('p')?;
+        let val = int.stack.pop();
+        let Some(pos) = pos else {
+            return Ok(StepResult::Cont);
+        };
+        let c = match int.semantics.chars {
+            Chars::Bytes => {
+                let byte: u8 = val
+                    .try_into()
+                    .with_context(|| anyhow!("Failed to convert {} into u8", val))?;
+                byte as char
+            }
+            Chars::Unicode => u32::try_from(val)
+                .ok()
+                .and_then(char::from_u32)
+                .with_context(|| anyhow!("Failed to convert {} into a character", val))?,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(x = pos.x, y = pos.y, value = val, "put");
+        int.put_tracked(pos, c.into());
+        Ok(StepResult::Cont)
+    }),
+    (b'@', |_, _| Ok(StepResult::Stop)),
+];
+
+/// Pushes the value of a hexadecimal digit.
+fn hex_digit(int: &mut Interpreter, b: u8) -> Result<StepResult> {
+    int.stack.push((b - b'a' + 10).into());
+    Ok(StepResult::Cont)
+}
+
+/// The instructions Befunge-96 adds to Befunge-93.
+const BEFUNGE_96: &[(u8, Handler)] = &[
+    (b'a', hex_digit),
+    (b'b', hex_digit),
+    (b'c', hex_digit),
+    (b'd', hex_digit),
+    (b'e', hex_digit),
+    (b'f', hex_digit),
+    (b'\'', |int, _| {
+        int.advance_pc();
+        let value = int.playfield[int.pc.y][int.pc.x].value();
+        int.stack.push(value);
+        Ok(StepResult::Cont)
+    }),
+    (b'r', |int, _| {
+        int.reverse();
+        Ok(StepResult::Cont)
+    }),
+];
+
+/// The instructions Befunge-97 adds to Befunge-96.
+const BEFUNGE_97: &[(u8, Handler)] = &[
+    (b'[', |int, _| {
+        int.dir = int.dir.turn_left();
+        Ok(StepResult::Cont)
+    }),
+    (b']', |int, _| {
+        int.dir = int.dir.turn_right();
+        Ok(StepResult::Cont)
+    }),
+    (b'w', |int, _| {
+        let b = int.stack.pop();
+        let a = int.stack.pop();
+        int.dir = match a.cmp(&b) {
+            Ordering::Less => int.dir.turn_left(),
+            Ordering::Greater => int.dir.turn_right(),
+            Ordering::Equal => int.dir,
+        };
+        Ok(StepResult::Cont)
+    }),
+    (b'j', |int, _| {
+        let n = int.stack.pop();
+        // Going around the playfield once ends where it started, so
+        // jumping back is jumping forward by the rest of the way round.
+        let size = int.get_size();
+        let around = match int.dir {
+            Direction::Left | Direction::Right => size.cols,
+            Direction::Up | Direction::Down => size.rows,
+        };
+        for _ in 0..n.rem_euclid(around as StackTy) {
+            int.advance_pc();
+        }
+        Ok(StepResult::Cont)
+    }),
+    (b'n', |int, _| {
+        int.stack.reset();
+        Ok(StepResult::Cont)
+    }),
+    (b'z', cont),
+];
+
+impl Interpreter {
+    /// Pop the coordinates of a cell used as data by `cmd`. `None` if they
+    /// are outside the playfield and `cmd` should do nothing else.
+    fn pop_cell(&mut self, cmd: char) -> Result<Option<Position>> {
+        let y = self.stack.pop();
+        let x = self.stack.pop();
+
+        let size = self.get_size();
+        let (cols, rows) = (size.cols as StackTy, size.rows as StackTy);
+        let (x, y) = if (0..cols).contains(&x) && (0..rows).contains(&y) {
+            (x, y)
+        } else {
+            match self.semantics.out_of_bounds {
+                OutOfBounds::Error if !(0..cols).contains(&x) => {
+                    bail!("Invalid x coordinate for {} command: {}", cmd, x)
+                }
+                OutOfBounds::Error => bail!("Invalid y coordinate for {} command: {}", cmd, y),
+                OutOfBounds::Wrap => (x.rem_euclid(cols), y.rem_euclid(rows)),
+                OutOfBounds::Reflect => {
+                    self.reverse();
+                    return Ok(None);
+                }
+                OutOfBounds::Nop => return Ok(None),
+            }
+        };
+        let pos = Position {
+            x: x as usize,
+            y: y as usize,
+        };
+        self.coverage.data(pos);
+        Ok(Some(pos))
+    }
+
+    /// Run `f` whenever the PC reaches a cell holding `c`, which must be a
+    /// byte that isn't a Befunge-93 instruction. The PC moves on after it,
+    /// as with any other instruction.
+    ///
+    /// Programs using custom instructions are always stepped one cell at a
+    /// time, and are unknown to static analysis and code generation.
+    pub fn define_instruction(&mut self, c: char, f: Instruction) -> Result<()> {
+        let byte =
+            u8::try_from(c).map_err(|_| anyhow!("Instruction '{}' doesn't fit in a cell", c))?;
+        if BUILTINS.iter().any(|&(b, _)| b == byte) || byte.is_ascii_digit() {
+            bail!("'{}' is already a Befunge-93 instruction", c);
+        }
+        if instructions::lookup(self.dispatch.dialect, c).is_some() {
+            bail!("'{}' is already a {} instruction", c, self.dispatch.dialect);
+        }
+        self.dispatch.custom[byte as usize] = Some(f);
+        self.dispatch.rebuild(&self.semantics);
+        Ok(())
+    }
+
+    /// Run programs written for `dialect`, adding its instructions to
+    /// those of Befunge-93 and giving the playfield its size. Cells
+    /// outside a smaller field are dropped, so the dialect is best set
+    /// before loading a program. Fails if an instruction defined with
+    /// `define_instruction` is one of them.
+    pub fn set_dialect(&mut self, dialect: Dialect) -> Result<()> {
+        for &(b, _) in dialect_builtins(dialect).iter().copied().flatten() {
+            if self.dispatch.custom[b as usize].is_some() {
+                bail!("'{}' is already a custom instruction", b as char);
+            }
+        }
+        self.dispatch.dialect = dialect;
+        self.dispatch.rebuild(&self.semantics);
+
+        let size = dialect.playfield();
+        self.playfield.resize(size, Command::Space);
+        self.profile.resize(size, 0);
+        self.coverage.resize(size);
+        if !size.contains(self.get_position()) {
+            self.pc.reset();
+        }
+        self.update_bounds();
+        Ok(())
+    }
+
+    /// Get the dialect programs are run as.
+    pub fn get_dialect(&self) -> Dialect {
+        self.dispatch.dialect
+    }
+
+    /// Remove all instructions defined with `define_instruction`.
+    pub fn clear_instructions(&mut self) {
+        self.dispatch.custom = [None; 256];
+        self.dispatch.rebuild(&self.semantics);
+    }
+
+    /// Push `value` onto the stack, for custom instructions.
+    pub fn push(&mut self, value: StackTy) {
+        self.stack.push(value);
+    }
+
+    /// Pop the top of the stack, or zero if it's empty, for custom
+    /// instructions.
+    pub fn pop(&mut self) -> StackTy {
+        self.stack.pop()
+    }
+
+    /// Change the direction the PC is moving, for custom instructions.
+    pub fn set_direction(&mut self, dir: Direction) {
+        self.dir = dir;
+    }
+
+    /// Append `text` to the output, for custom instructions.
+    pub fn print(&mut self, text: &str) {
+        self.write_output(text);
+    }
+}
@@ -0,0 +1,183 @@
+//! C interface, declared in `include/befunge93.h`.
+//!
+//! The crate builds as a Rust library only; build the shared library to
+//! link C code against with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Functions returning `int` report errors as `-1`; the message is then
+//! available from `bef_last_error`.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use crate::stepper::Stepper;
+
+/// An interpreter owned by C code.
+pub struct BefInterpreter {
+    stepper: Stepper,
+    last_error: Option<CString>,
+}
+
+impl BefInterpreter {
+    fn status(&mut self, result: anyhow::Result<bool>) -> c_int {
+        match result {
+            Ok(running) => running as c_int,
+            Err(e) => {
+                let msg = format!("{:#}", e).replace('\0', " ");
+                self.last_error = Some(CString::new(msg).expect("NULs were replaced"));
+                -1
+            }
+        }
+    }
+}
+
+unsafe fn bytes<'a>(data: *const c_char, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data as *const u8, len)
+    }
+}
+
+/// Create an interpreter. Free it with `bef_free`.
+#[no_mangle]
+pub extern "C" fn bef_new() -> *mut BefInterpreter {
+    Box::into_raw(Box::new(BefInterpreter {
+        stepper: Stepper::default(),
+        last_error: None,
+    }))
+}
+
+/// Free an interpreter created by `bef_new`.
+///
+/// # Safety
+///
+/// `bef` must come from `bef_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bef_free(bef: *mut BefInterpreter) {
+    if !bef.is_null() {
+        drop(Box::from_raw(bef));
+    }
+}
+
+/// Load `len` bytes of program text, to be run from the start. Returns 0 on
+/// success.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter and `program` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bef_load(
+    bef: *mut BefInterpreter,
+    program: *const c_char,
+    len: usize,
+) -> c_int {
+    let bef = &mut *bef;
+    let result = bef.stepper.load(bytes(program, len)).map(|_| false);
+    bef.status(result)
+}
+
+/// Append `len` bytes to the input read by `&` and `~`.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter and `input` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bef_push_input(
+    bef: *mut BefInterpreter,
+    input: *const c_char,
+    len: usize,
+) {
+    (*bef).stepper.push_input(bytes(input, len));
+}
+
+/// Whether the next step would read more input than has been pushed.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bef_needs_input(bef: *const BefInterpreter) -> c_int {
+    (*bef).stepper.needs_input() as c_int
+}
+
+/// Execute one step. Returns 1 while the program runs and 0 once it ended.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bef_step(bef: *mut BefInterpreter) -> c_int {
+    let bef = &mut *bef;
+    let result = bef.stepper.step();
+    bef.status(result)
+}
+
+/// Execute up to `max_steps` steps, stopping early when input is needed.
+/// Returns 1 while the program runs and 0 once it ended.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bef_run(bef: *mut BefInterpreter, max_steps: usize) -> c_int {
+    let bef = &mut *bef;
+    let result = bef.stepper.run(max_steps);
+    bef.status(result)
+}
+
+/// Copy up to `cap` bytes of the output into `buf`, without a terminating
+/// NUL. Returns the full length of the output, so a return value greater
+/// than `cap` means it was truncated.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter and `buf` must point to `cap` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bef_output(
+    bef: *const BefInterpreter,
+    buf: *mut c_char,
+    cap: usize,
+) -> usize {
+    let output = (*bef).stepper.interpreter().get_output().as_bytes();
+    if !buf.is_null() {
+        ptr::copy_nonoverlapping(output.as_ptr(), buf as *mut u8, output.len().min(cap));
+    }
+    output.len()
+}
+
+/// Store the columns and rows of the part of the playfield in use, which
+/// grows as cells past it are written.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter, and `cols` and `rows` writable.
+#[no_mangle]
+pub unsafe extern "C" fn bef_bounds(
+    bef: *const BefInterpreter,
+    cols: *mut usize,
+    rows: *mut usize,
+) {
+    let bounds = (*bef).stepper.interpreter().get_bounds();
+    *cols = bounds.cols;
+    *rows = bounds.rows;
+}
+
+/// The message of the last error, or NULL. The string is owned by the
+/// interpreter and valid until the next call that fails.
+///
+/// # Safety
+///
+/// `bef` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bef_last_error(bef: *const BefInterpreter) -> *const c_char {
+    (*bef)
+        .last_error
+        .as_ref()
+        .map_or(ptr::null(), |msg| msg.as_ptr())
+}
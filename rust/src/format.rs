@@ -0,0 +1,139 @@
+//! Normalization of program files, as done by `bef fmt`.
+
+use crate::{COMMENT_MARKER, PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+/// How to format a program file.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Width of the field; longer lines are cut.
+    pub cols: usize,
+    /// Height of the field; further rows are dropped.
+    pub rows: usize,
+    /// Tabs are expanded to stops this many columns apart.
+    pub tab_width: usize,
+    /// Pad every row to the full width and add rows up to the full height.
+    pub pad: bool,
+    /// The line after which the file is comments, which are kept apart from
+    /// the field.
+    pub comment_marker: Option<String>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            cols: PLAYFIELD_COLS,
+            rows: PLAYFIELD_ROWS,
+            tab_width: 8,
+            pad: false,
+            comment_marker: Some(COMMENT_MARKER.to_string()),
+        }
+    }
+}
+
+/// Format `source`: line endings become `\n`, tabs are expanded, trailing
+/// whitespace is removed and the code is fitted to the field. A leading
+/// `#!` line and the comments after the marker are kept, with only their
+/// line endings and trailing whitespace normalized.
+pub fn format(source: &str, opts: &FormatOptions) -> String {
+    let source = source.replace("\r\n", "\n").replace('\r', "\n");
+    let mut lines = source.lines().peekable();
+    let mut out = vec![];
+
+    if let Some(shebang) = lines.next_if(|l| l.starts_with("#!")) {
+        out.push(shebang.trim_end().to_string());
+    }
+
+    let mut code = vec![];
+    let mut comments = None;
+    for line in lines.by_ref() {
+        if opts.comment_marker.as_deref() == Some(line.trim_end()) {
+            comments = Some(line.trim_end().to_string());
+            break;
+        }
+        code.push(format_row(line, opts));
+    }
+
+    code.truncate(opts.rows);
+    if opts.pad {
+        code.resize(opts.rows, String::new());
+        for row in &mut code {
+            let len = row.chars().count();
+            row.extend(std::iter::repeat_n(' ', opts.cols - len));
+        }
+    } else {
+        while code.last().is_some_and(|row| row.is_empty()) {
+            code.pop();
+        }
+    }
+    out.extend(code);
+
+    if let Some(marker) = comments {
+        out.push(marker);
+        out.extend(lines.map(|l| l.trim_end().to_string()));
+        while out.last().is_some_and(|l| l.is_empty()) {
+            out.pop();
+        }
+    }
+
+    out.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+fn format_row(line: &str, opts: &FormatOptions) -> String {
+    let mut row = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let stop = (len / opts.tab_width + 1) * opts.tab_width;
+            row.extend(std::iter::repeat_n(' ', stop - len));
+            len = stop;
+        } else {
+            row.push(c);
+            len += 1;
+        }
+    }
+    let row: String = row.chars().take(opts.cols).collect();
+    row.trim_end().to_string()
+}
+
+/// The lines that differ between `old` and `new`, as hunks of removed and
+/// added lines headed by the line numbers they start at.
+pub fn diff(old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // Length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        out += &format!("@@ -{} +{} @@\n", i + 1, j + 1);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                break;
+            }
+            if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                out += &format!("-{}\n", old[i]);
+                i += 1;
+            } else {
+                out += &format!("+{}\n", new[j]);
+                j += 1;
+            }
+        }
+    }
+    out
+}
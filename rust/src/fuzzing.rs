@@ -0,0 +1,82 @@
+//! The entry point of the fuzz targets in `fuzz/`: arbitrary bytes taken
+//! apart into semantics, a program and its input, and run within limits
+//! tight enough that fuzzers try many of them a second.
+//!
+//! Errors are how runs of nonsense programs usually end, and are fine;
+//! only panics, hangs and runaway memory are bugs.
+
+use std::io::Cursor;
+
+use crate::instructions::Dialect;
+use crate::output::{OutputLimit, OutputOverflow};
+use crate::semantics::{
+    Cells, Chars, Choice, DivZero, Eof, OutOfBounds, Semantics, Unknown, Wrapping,
+};
+use crate::{Interpreter, Overflow, StackLimit};
+
+/// Steps a fuzzed run may take.
+pub const MAX_STEPS: usize = 10_000;
+/// Values the stack of a fuzzed run may hold.
+pub const MAX_STACK: usize = 1024;
+/// Bytes of output a fuzzed run may keep.
+pub const MAX_OUTPUT: usize = 4096;
+
+/// Bytes at the start of the data choosing the configuration.
+const HEADER_LEN: usize = 4;
+
+/// Run `data` as a program: the first bytes choose the semantics, the
+/// dialect and what happens past the limits, the bytes after them up to
+/// the first NUL are the program and the rest is its input.
+pub fn run(data: &[u8]) {
+    let (header, rest) = data.split_at(data.len().min(HEADER_LEN));
+    let mut bits = header
+        .iter()
+        .fold(0u32, |bits, &b| (bits << 8) | u32::from(b));
+    let (program, input) = match rest.iter().position(|&b| b == 0) {
+        Some(end) => (&rest[..end], &rest[end + 1..]),
+        None => (rest, &[][..]),
+    };
+
+    let mut int = Interpreter::new();
+    int.set_semantics(Semantics {
+        eof: pick::<Eof>(&mut bits),
+        div_zero: pick::<DivZero>(&mut bits),
+        out_of_bounds: pick::<OutOfBounds>(&mut bits),
+        wrapping: pick::<Wrapping>(&mut bits),
+        unknown: pick::<Unknown>(&mut bits),
+        chars: pick::<Chars>(&mut bits),
+        // Squaring in a loop makes bigint cells millions of digits wide
+        // within a few dozen steps, which is slow but no bug.
+        cells: if bits % 2 == 0 {
+            Cells::I32
+        } else {
+            Cells::I64
+        },
+    });
+    bits /= 2;
+    int.set_stack_limit(Some(StackLimit {
+        depth: MAX_STACK,
+        overflow: pick::<Overflow>(&mut bits),
+    }));
+    int.set_output_limit(Some(OutputLimit {
+        bytes: MAX_OUTPUT,
+        overflow: pick::<OutputOverflow>(&mut bits),
+    }));
+    let dialect = Dialect::ALL[bits as usize % Dialect::ALL.len()];
+    int.set_dialect(dialect)
+        .expect("no custom instructions are defined");
+    int.set_seed(Some(0));
+    int.set_input(Cursor::new(input.to_vec()));
+
+    if int.load(&mut &program[..]).is_ok() {
+        let _ = int.run(|_, steps| steps < MAX_STEPS);
+    }
+}
+
+/// Take a choice of `C` out of `bits`.
+fn pick<C: Choice>(bits: &mut u32) -> C {
+    let n = C::CHOICES.len() as u32;
+    let choice = C::CHOICES[(*bits % n) as usize].1;
+    *bits /= n;
+    choice
+}
@@ -0,0 +1,87 @@
+//! Random playfields, to fuzz the interpreter with or to start
+//! genetic-programming experiments from.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::instructions::{instructions, Dialect};
+use crate::{PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+/// What random programs look like.
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    /// Width of the generated code.
+    pub cols: usize,
+    /// Height of the generated code.
+    pub rows: usize,
+    /// Relative frequency of each character in a cell. ASCII characters
+    /// that aren't instructions are allowed, to exercise unknown commands.
+    pub weights: BTreeMap<char, u32>,
+    /// Number of `@` placed at random cells after filling the field, so
+    /// that every program has a way to end.
+    pub ends: usize,
+}
+
+impl Default for GeneratorOptions {
+    /// Every Befunge-93 instruction equally likely, with blank cells eight
+    /// times as likely, on the full field with a single guaranteed `@`.
+    fn default() -> Self {
+        let mut weights: BTreeMap<char, u32> = instructions(Dialect::Befunge93)
+            .iter()
+            .map(|doc| (doc.c, 1))
+            .collect();
+        weights.insert(' ', 8);
+        Self {
+            cols: PLAYFIELD_COLS,
+            rows: PLAYFIELD_ROWS,
+            weights,
+            ends: 1,
+        }
+    }
+}
+
+/// Generate the source of a random program, rows separated by `\n`.
+pub fn generate(opts: &GeneratorOptions, rng: &mut impl Rng) -> Result<String> {
+    if opts.cols == 0 || opts.cols > PLAYFIELD_COLS || opts.rows == 0 || opts.rows > PLAYFIELD_ROWS
+    {
+        bail!(
+            "Invalid size {}x{}, must fit in {}x{}",
+            opts.cols,
+            opts.rows,
+            PLAYFIELD_COLS,
+            PLAYFIELD_ROWS
+        );
+    }
+    if let Some(&c) = opts
+        .weights
+        .keys()
+        .find(|&&c| !c.is_ascii() || c == '\n' || c == '\r')
+    {
+        bail!("Character {:?} can't be placed in a cell", c);
+    }
+    let chars: Vec<char> = opts.weights.keys().copied().collect();
+    let dist = match WeightedIndex::new(opts.weights.values()) {
+        Ok(dist) => dist,
+        Err(e) => bail!("Invalid instruction weights: {}", e),
+    };
+
+    let mut field: Vec<Vec<char>> = (0..opts.rows)
+        .map(|_| (0..opts.cols).map(|_| chars[dist.sample(rng)]).collect())
+        .collect();
+    for _ in 0..opts.ends {
+        let y = rng.gen_range(0, opts.rows);
+        let x = rng.gen_range(0, opts.cols);
+        field[y][x] = '@';
+    }
+
+    Ok(field
+        .iter()
+        .map(|row| {
+            let row: String = row.iter().collect();
+            format!("{}\n", row.trim_end())
+        })
+        .collect())
+}
@@ -0,0 +1,102 @@
+//! Storage for a value per cell of a playfield, whose size depends on the
+//! dialect.
+
+use std::ops::{Index, IndexMut};
+use std::slice::{ChunksExact, ChunksExactMut};
+
+use crate::Bounds;
+
+/// A value for each cell of a playfield, indexed as `[y][x]` like the
+/// arrays of a fixed-size field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    size: Bounds,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    /// A grid of `size` with `value` in every cell.
+    pub fn new(size: Bounds, value: T) -> Self {
+        assert!(size.cols > 0 && size.rows > 0, "empty grid");
+        Self {
+            size,
+            cells: vec![value; size.cols * size.rows],
+        }
+    }
+
+    /// Put `value` in every cell.
+    pub fn fill(&mut self, value: T) {
+        self.cells.fill(value);
+    }
+
+    /// Change the size of the grid to `size`, keeping the cells in both
+    /// and putting `value` in the new ones.
+    pub fn resize(&mut self, size: Bounds, value: T) {
+        if size == self.size {
+            return;
+        }
+        let mut resized = Self::new(size, value);
+        for (y, row) in self.iter().take(size.rows).enumerate() {
+            let cols = row.len().min(size.cols);
+            resized[y][..cols].clone_from_slice(&row[..cols]);
+        }
+        *self = resized;
+    }
+}
+
+impl<T> Grid<T> {
+    /// Width and height of the grid.
+    pub fn size(&self) -> Bounds {
+        self.size
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.size.rows
+    }
+
+    /// Whether the grid has no rows, which it never has.
+    pub fn is_empty(&self) -> bool {
+        self.size.rows == 0
+    }
+
+    /// The rows, from the top.
+    pub fn iter(&self) -> ChunksExact<'_, T> {
+        self.cells.chunks_exact(self.size.cols)
+    }
+
+    /// The rows, from the top, to change them.
+    pub fn iter_mut(&mut self) -> ChunksExactMut<'_, T> {
+        self.cells.chunks_exact_mut(self.size.cols)
+    }
+
+    /// The cells, row by row, as laid out in memory for native code.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.cells.as_mut_ptr()
+    }
+}
+
+impl<T> Index<usize> for Grid<T> {
+    type Output = [T];
+
+    fn index(&self, y: usize) -> &[T] {
+        assert!(y < self.size.rows, "row {} is outside the grid", y);
+        &self.cells[y * self.size.cols..(y + 1) * self.size.cols]
+    }
+}
+
+impl<T> IndexMut<usize> for Grid<T> {
+    fn index_mut(&mut self, y: usize) -> &mut [T] {
+        assert!(y < self.size.rows, "row {} is outside the grid", y);
+        &mut self.cells[y * self.size.cols..(y + 1) * self.size.cols]
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Grid<T> {
+    type Item = &'a [T];
+    type IntoIter = ChunksExact<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::semantics::Choice;
+use crate::Interpreter;
+
+/// What `&` and `~` do when no input comes within the timeout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnTimeout {
+    /// Act as at the end of input, as the `eof` semantic says.
+    Eof,
+    /// Abort the run.
+    Error,
+}
+
+impl Choice for OnTimeout {
+    const CHOICES: &'static [(&'static str, Self)] = &[("eof", Self::Eof), ("error", Self::Error)];
+}
+
+/// How long `&` and `~` wait for input, and what they do after that.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputTimeout {
+    pub duration: Duration,
+    pub on_timeout: OnTimeout,
+}
+
+/// Program input that is pushed in from elsewhere rather than read from a
+/// stream, e.g. by a GUI. Clones share the same queue.
+///
+/// Reading from an empty queue reports the end of input.
+#[derive(Clone, Default)]
+pub struct InputQueue(Arc<Mutex<VecDeque<u8>>>);
+
+impl InputQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `bytes` to the queue.
+    pub fn push(&self, bytes: &[u8]) {
+        self.lock().extend(bytes);
+    }
+
+    /// Number of bytes waiting to be read.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no bytes are waiting to be read.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Whether any waiting byte satisfies `f`.
+    pub fn any(&self, f: impl Fn(u8) -> bool) -> bool {
+        self.lock().iter().any(|&b| f(b))
+    }
+
+    /// A copy of the bytes waiting to be read.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.lock().iter().copied().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<u8>> {
+        self.0.lock().expect("input queue poisoned")
+    }
+}
+
+impl Read for InputQueue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut queue = self.lock();
+        let n = buf.len().min(queue.len());
+        for (dst, src) in buf.iter_mut().zip(queue.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+/// Read a byte from `reader`, or `None` at the end of input.
+pub(crate) fn read_one(reader: &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut buf = [0; 1];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buf[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reads input on a thread of its own, a byte at a time when asked, so that
+/// waiting for it can time out. A byte asked for that is late is returned
+/// by the next read.
+pub(crate) struct InputPump {
+    requests: Sender<()>,
+    bytes: Receiver<io::Result<Option<u8>>>,
+    /// Whether a byte was asked for and hasn't been received yet.
+    waiting: bool,
+}
+
+impl InputPump {
+    fn spawn(mut reader: Box<dyn Read + Send>) -> Self {
+        let (requests, asked) = mpsc::channel();
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for () in asked {
+                if sender.send(read_one(&mut reader)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests,
+            bytes,
+            waiting: false,
+        }
+    }
+
+    /// The next byte, waiting at most `timeout` if given. Fails with
+    /// `io::ErrorKind::TimedOut` if none came in time.
+    fn read(&mut self, timeout: Option<Duration>) -> io::Result<Option<u8>> {
+        if !self.waiting {
+            // The thread only stops once this pump is dropped.
+            let _ = self.requests.send(());
+            self.waiting = true;
+        }
+        let received = match timeout {
+            Some(timeout) => self.bytes.recv_timeout(timeout),
+            None => self.bytes.recv().map_err(RecvTimeoutError::from),
+        };
+        match received {
+            Ok(byte) => {
+                self.waiting = false;
+                byte
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Wait at most `timeout.duration` for each byte read by `&` and `~`,
+    /// doing what `timeout.on_timeout` says when none comes, or wait for as
+    /// long as it takes with `None`. Input is then read on a thread of its
+    /// own.
+    pub fn set_input_timeout(&mut self, timeout: Option<InputTimeout>) {
+        self.input_timeout = timeout;
+    }
+
+    /// Get the timeout of input reads, if any.
+    pub fn get_input_timeout(&self) -> Option<InputTimeout> {
+        self.input_timeout
+    }
+
+    /// The next byte of the input reader, or `None` at its end, waiting at
+    /// most as long as the input timeout says.
+    pub(crate) fn read_input(&mut self) -> Result<Option<u8>> {
+        let timeout = self.input_timeout;
+        if self.pump.is_none() && timeout.is_none() {
+            return read_one(&mut self.input).context("Reading a byte");
+        }
+        let input = &mut self.input;
+        let pump = self
+            .pump
+            .get_or_insert_with(|| InputPump::spawn(mem::replace(input, Box::new(io::empty()))));
+        match pump.read(timeout.map(|t| t.duration)) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                let timeout = timeout.expect("reads only time out with a timeout");
+                match timeout.on_timeout {
+                    OnTimeout::Eof => Ok(None),
+                    OnTimeout::Error => bail!(
+                        "Reading a byte: no input within {} ms",
+                        timeout.duration.as_millis()
+                    ),
+                }
+            }
+            byte => byte.context("Reading a byte"),
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Descriptions of the instructions of each dialect: their names, stack
+//! effects and what they do. Help texts, hovers and reference tables are
+//! all built from these.
+
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{Bounds, PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+/// A language the interpreter understands.
+///
+/// Befunge-96 and Befunge-97 were drafts on the way to Befunge-98, each
+/// adding instructions to those of Befunge-93 and allowing larger fields
+/// than its 80x25. Their programs run on a field of `DRAFT_PLAYFIELD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    #[default]
+    Befunge93,
+    Befunge96,
+    Befunge97,
+}
+
+/// The playfield of the drafts, twice as wide and high as that of
+/// Befunge-93.
+pub const DRAFT_PLAYFIELD: Bounds = Bounds {
+    cols: 2 * PLAYFIELD_COLS,
+    rows: 2 * PLAYFIELD_ROWS,
+};
+
+impl Dialect {
+    /// Every dialect, in the order they're listed.
+    pub const ALL: &'static [Dialect] =
+        &[Dialect::Befunge93, Dialect::Befunge96, Dialect::Befunge97];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Befunge93 => "befunge-93",
+            Self::Befunge96 => "befunge-96",
+            Self::Befunge97 => "befunge-97",
+        }
+    }
+
+    /// The size of the playfield of the dialect.
+    pub fn playfield(self) -> Bounds {
+        match self {
+            Self::Befunge93 => Bounds {
+                cols: PLAYFIELD_COLS,
+                rows: PLAYFIELD_ROWS,
+            },
+            Self::Befunge96 | Self::Befunge97 => DRAFT_PLAYFIELD,
+        }
+    }
+
+    /// The tables of instructions of the dialect, each adding to the ones
+    /// before.
+    fn tables(self) -> &'static [&'static [InstructionDoc]] {
+        match self {
+            Self::Befunge93 => &[BEFUNGE_93],
+            Self::Befunge96 => &[BEFUNGE_93, BEFUNGE_96],
+            Self::Befunge97 => &[BEFUNGE_93, BEFUNGE_96, BEFUNGE_97],
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Self::ALL.iter().find(|d| d.name() == s) {
+            Some(&dialect) => Ok(dialect),
+            None => {
+                let names: Vec<_> = Self::ALL.iter().map(|d| d.name()).collect();
+                bail!(
+                    "Unknown dialect '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// What an instruction is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Arith,
+    Flow,
+    Stack,
+    Push,
+    String,
+    Io,
+    Mem,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Arith => "arith",
+            Self::Flow => "flow",
+            Self::Stack => "stack",
+            Self::Push => "push",
+            Self::String => "string",
+            Self::Io => "io",
+            Self::Mem => "mem",
+        }
+    }
+}
+
+/// The description of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct InstructionDoc {
+    #[serde(rename = "char")]
+    pub c: char,
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Values popped and pushed, written `before -- after` with the top of
+    /// the stack last.
+    pub stack: &'static str,
+    pub description: &'static str,
+}
+
+const fn doc(
+    c: char,
+    name: &'static str,
+    kind: Kind,
+    stack: &'static str,
+    description: &'static str,
+) -> InstructionDoc {
+    InstructionDoc {
+        c,
+        name,
+        kind,
+        stack,
+        description,
+    }
+}
+
+const BEFUNGE_93: &[InstructionDoc] = &[
+    doc('0', "push 0", Kind::Push, "-- 0", "Push 0."),
+    doc('1', "push 1", Kind::Push, "-- 1", "Push 1."),
+    doc('2', "push 2", Kind::Push, "-- 2", "Push 2."),
+    doc('3', "push 3", Kind::Push, "-- 3", "Push 3."),
+    doc('4', "push 4", Kind::Push, "-- 4", "Push 4."),
+    doc('5', "push 5", Kind::Push, "-- 5", "Push 5."),
+    doc('6', "push 6", Kind::Push, "-- 6", "Push 6."),
+    doc('7', "push 7", Kind::Push, "-- 7", "Push 7."),
+    doc('8', "push 8", Kind::Push, "-- 8", "Push 8."),
+    doc('9', "push 9", Kind::Push, "-- 9", "Push 9."),
+    doc(
+        '+',
+        "add",
+        Kind::Arith,
+        "a b -- a+b",
+        "Pop b and a, push a + b.",
+    ),
+    doc(
+        '-',
+        "subtract",
+        Kind::Arith,
+        "a b -- a-b",
+        "Pop b and a, push a - b.",
+    ),
+    doc(
+        '*',
+        "multiply",
+        Kind::Arith,
+        "a b -- a*b",
+        "Pop b and a, push a * b.",
+    ),
+    doc(
+        '/',
+        "divide",
+        Kind::Arith,
+        "a b -- a/b",
+        "Pop b and a, push a / b rounded towards zero.",
+    ),
+    doc(
+        '%',
+        "modulo",
+        Kind::Arith,
+        "a b -- a%b",
+        "Pop b and a, push the remainder of a / b.",
+    ),
+    doc(
+        '!',
+        "not",
+        Kind::Arith,
+        "a -- !a",
+        "Pop a, push 1 if it is zero and 0 otherwise.",
+    ),
+    doc(
+        '`',
+        "greater than",
+        Kind::Arith,
+        "a b -- a>b",
+        "Pop b and a, push 1 if a > b and 0 otherwise.",
+    ),
+    doc('>', "right", Kind::Flow, "--", "Start moving right."),
+    doc('<', "left", Kind::Flow, "--", "Start moving left."),
+    doc('^', "up", Kind::Flow, "--", "Start moving up."),
+    doc('v', "down", Kind::Flow, "--", "Start moving down."),
+    doc(
+        '?',
+        "random",
+        Kind::Flow,
+        "--",
+        "Start moving in a random direction.",
+    ),
+    doc(
+        '_',
+        "horizontal if",
+        Kind::Flow,
+        "a --",
+        "Pop a, move right if it is zero and left otherwise.",
+    ),
+    doc(
+        '|',
+        "vertical if",
+        Kind::Flow,
+        "a --",
+        "Pop a, move down if it is zero and up otherwise.",
+    ),
+    doc(
+        '"',
+        "string mode",
+        Kind::String,
+        "--",
+        "Toggle string mode, in which cells push their character codes.",
+    ),
+    doc(
+        ':',
+        "duplicate",
+        Kind::Stack,
+        "a -- a a",
+        "Push a copy of the top.",
+    ),
+    doc(
+        '\\',
+        "swap",
+        Kind::Stack,
+        "a b -- b a",
+        "Swap the top two values.",
+    ),
+    doc('$', "pop", Kind::Stack, "a --", "Pop a and discard it."),
+    doc(
+        '.',
+        "output number",
+        Kind::Io,
+        "a --",
+        "Pop a, print it as a number followed by a space.",
+    ),
+    doc(
+        ',',
+        "output character",
+        Kind::Io,
+        "a --",
+        "Pop a, print the character with that code.",
+    ),
+    doc('#', "bridge", Kind::Flow, "--", "Jump over the next cell."),
+    doc(
+        'g',
+        "get",
+        Kind::Mem,
+        "x y -- v",
+        "Pop y and x, push the code of the character at (x, y).",
+    ),
+    doc(
+        'p',
+        "put",
+        Kind::Mem,
+        "v x y --",
+        "Pop y, x and v, write the character with code v to (x, y).",
+    ),
+    doc(
+        '&',
+        "input number",
+        Kind::Io,
+        "-- n",
+        "Read a number from the input and push it.",
+    ),
+    doc(
+        '~',
+        "input character",
+        Kind::Io,
+        "-- c",
+        "Read a character from the input and push its code.",
+    ),
+    doc('@', "end", Kind::Flow, "--", "End the program."),
+];
+
+/// The instructions Befunge-96 adds to Befunge-93.
+const BEFUNGE_96: &[InstructionDoc] = &[
+    doc('a', "push 10", Kind::Push, "-- 10", "Push 10."),
+    doc('b', "push 11", Kind::Push, "-- 11", "Push 11."),
+    doc('c', "push 12", Kind::Push, "-- 12", "Push 12."),
+    doc('d', "push 13", Kind::Push, "-- 13", "Push 13."),
+    doc('e', "push 14", Kind::Push, "-- 14", "Push 14."),
+    doc('f', "push 15", Kind::Push, "-- 15", "Push 15."),
+    doc(
+        '\'',
+        "fetch character",
+        Kind::String,
+        "-- c",
+        "Push the code of the character in the next cell, and jump over it.",
+    ),
+    doc('r', "reflect", Kind::Flow, "--", "Reverse the direction."),
+];
+
+/// The instructions Befunge-97 adds to Befunge-96.
+const BEFUNGE_97: &[InstructionDoc] = &[
+    doc('[', "turn left", Kind::Flow, "--", "Turn 90 degrees left."),
+    doc(
+        ']',
+        "turn right",
+        Kind::Flow,
+        "--",
+        "Turn 90 degrees right.",
+    ),
+    doc(
+        'w',
+        "compare",
+        Kind::Flow,
+        "a b --",
+        "Pop b and a, turn left if a < b and right if a > b.",
+    ),
+    doc(
+        'j',
+        "jump forward",
+        Kind::Flow,
+        "n --",
+        "Pop n, jump over the next n cells, or back if n is negative.",
+    ),
+    doc(
+        'n',
+        "clear stack",
+        Kind::Stack,
+        "... --",
+        "Pop every value.",
+    ),
+    doc('z', "no-op", Kind::Flow, "--", "Do nothing."),
+];
+
+/// Every instruction of `dialect`.
+pub fn instructions(dialect: Dialect) -> Vec<&'static InstructionDoc> {
+    dialect
+        .tables()
+        .iter()
+        .flat_map(|table| table.iter())
+        .collect()
+}
+
+/// The instruction of `dialect` written `c`, if there is one.
+pub fn lookup(dialect: Dialect, c: char) -> Option<&'static InstructionDoc> {
+    dialect
+        .tables()
+        .iter()
+        .flat_map(|table| table.iter())
+        .find(|doc| doc.c == c)
+}
+
+/// A Markdown table of the instructions of `dialect`.
+pub fn reference_table(dialect: Dialect) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "| Char | Name | Stack | Description |");
+    let _ = writeln!(out, "|------|------|-------|-------------|");
+    for doc in instructions(dialect) {
+        let c = match doc.c {
+            '|' => "`\\|`".to_string(),
+            '`' => "`` ` ``".to_string(),
+            c => format!("`{}`", c),
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | `{}` | {} |",
+            c, doc.name, doc.stack, doc.description
+        );
+    }
+    out
+}
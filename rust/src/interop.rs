@@ -0,0 +1,149 @@
+//! Program files in the formats of other Befunge tools, converted to and
+//! from the one `Interpreter::load` reads, as done by `bef convert`.
+//!
+//! Files of this crate have lines ending in `\n` (or `\r\n`), and may start
+//! with a `#!` line and end with comments after a marker line. Funge-98
+//! interpreters read Befunge-93 programs by the Funge-98 rules instead: a
+//! `\r` on its own ends a line too, form feeds are ignored, and there are
+//! no comments, so every line is code.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+use crate::Interpreter;
+
+/// Form feed, separating the planes of Trefunge files.
+const FORM_FEED: u8 = 0x0c;
+
+/// A format of program files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceFormat {
+    /// This crate's.
+    Bef,
+    /// Funge-98 source files.
+    Funge98,
+    /// This crate's with lines ending in `\r\n`, as Windows tools write
+    /// them.
+    Dos,
+}
+
+impl SourceFormat {
+    /// Names of all formats, as accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["bef", "funge98", "dos"];
+}
+
+impl FromStr for SourceFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bef" => Ok(Self::Bef),
+            "funge98" => Ok(Self::Funge98),
+            "dos" => Ok(Self::Dos),
+            _ => bail!(
+                "Unknown source format '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SourceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bef => write!(f, "bef"),
+            Self::Funge98 => write!(f, "funge98"),
+            Self::Dos => write!(f, "dos"),
+        }
+    }
+}
+
+/// Convert `source`, a program file in format `from`, into one that
+/// `Interpreter::load` reads into the same playfield, with lines ending
+/// in `\n`.
+pub fn import(source: &[u8], from: SourceFormat) -> Vec<u8> {
+    match from {
+        SourceFormat::Bef => source.to_vec(),
+        SourceFormat::Funge98 => {
+            let mut out = Vec::with_capacity(source.len());
+            let mut bytes = source.iter().copied().peekable();
+            while let Some(b) = bytes.next() {
+                match b {
+                    b'\r' => {
+                        bytes.next_if_eq(&b'\n');
+                        out.push(b'\n');
+                    }
+                    FORM_FEED => {}
+                    _ => out.push(b),
+                }
+            }
+            out
+        }
+        SourceFormat::Dos => {
+            let mut out = Vec::with_capacity(source.len());
+            for (i, &b) in source.iter().enumerate() {
+                if !(b == b'\r' && source.get(i + 1) == Some(&b'\n')) {
+                    out.push(b);
+                }
+            }
+            out
+        }
+    }
+}
+
+/// Write the program loaded in `int` as a file in format `to`, with the
+/// characters of its cells encoded as `load` decodes them. A Funge-98
+/// file holds only the playfield, as its readers would load the `#!` line
+/// and the comments as code too, and can't hold cells with a `\r` or a
+/// form feed, which they read as a line break or skip.
+pub fn export(int: &Interpreter, to: SourceFormat) -> Result<Vec<u8>> {
+    let chars = int.get_semantics().chars;
+    let mut rows: Vec<String> = (0..int.get_size().rows).map(|y| int.get_row(y)).collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+
+    let mut lines: Vec<Vec<u8>> = rows.iter().map(|row| chars.encode(row)).collect();
+    if to == SourceFormat::Funge98 {
+        for (y, row) in rows.iter().enumerate() {
+            if let Some((x, c)) = row
+                .chars()
+                .enumerate()
+                .find(|&(_, c)| c == '\r' || c == '\x0c')
+            {
+                bail!(
+                    "Cell ({}, {}) holds {:?}, which Funge-98 files can't hold",
+                    x,
+                    y,
+                    c
+                );
+            }
+        }
+    } else {
+        if let Some(shebang) = int.get_shebang() {
+            lines.insert(0, shebang.as_bytes().to_vec());
+        }
+        if let (Some(marker), false) = (int.get_comment_marker(), int.get_comments().is_empty()) {
+            lines.push(marker.as_bytes().to_vec());
+            lines.extend(
+                int.get_comments()
+                    .iter()
+                    .map(|line| line.as_bytes().to_vec()),
+            );
+        }
+    }
+
+    let newline: &[u8] = if to == SourceFormat::Dos {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+    Ok(lines
+        .iter()
+        .flat_map(|line| line.iter().chain(newline))
+        .copied()
+        .collect())
+}
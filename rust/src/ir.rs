@@ -0,0 +1,284 @@
+//! Lowering of straight-line runs of the playfield into linear blocks of
+//! operations, so tight loops don't decode every cell on every pass.
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::semantics::Unknown;
+use crate::stack::BinOp;
+use crate::{Command, Direction, Grid, Interpreter, Position, ProgramCounter, StepResult};
+
+/// Most operations in a block, so loops without exits still compile.
+const MAX_BLOCK_LEN: usize = 1024;
+
+/// An operation of a compiled block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Push(i64),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Not,
+    Gt,
+    Dup,
+    Swap,
+    Pop,
+    OutI,
+    OutC,
+    /// A cell that only moves the PC.
+    Nop,
+}
+
+/// An operation together with the cell it was lowered from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instr {
+    pub pos: Position,
+    /// Direction of the PC when executing the cell.
+    pub dir: Direction,
+    pub op: Op,
+}
+
+/// A straight-line run of the playfield, entered outside string mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub instrs: Vec<Instr>,
+    /// Where the PC is after the block.
+    pub exit: Position,
+    /// Direction of the PC after the block.
+    pub exit_dir: Direction,
+    /// Whether the block ends in string mode.
+    pub exit_stringmode: bool,
+}
+
+/// Index of the block entered at `pc` moving `dir` in the block cache of a
+/// playfield `cols` wide.
+pub(crate) fn cache_index(pc: &ProgramCounter, dir: Direction, cols: usize) -> usize {
+    (pc.y * cols + pc.x) * 4 + dir as usize
+}
+
+impl Interpreter {
+    /// Compile the block entered at `start` moving `dir` outside string mode.
+    /// It ends before the first command that needs the interpreter: branches,
+    /// input, `?`, `g`, `p` and `@`. An empty block means the command at
+    /// `start` is one of those.
+    pub fn compile_block(&self, start: Position, mut dir: Direction) -> Block {
+        let mut pc = ProgramCounter {
+            x: start.x,
+            y: start.y,
+        };
+        let mut stringmode = false;
+        let mut instrs = vec![];
+
+        while instrs.len() < MAX_BLOCK_LEN {
+            let pos = Position { x: pc.x, y: pc.y };
+            let cmd = self.playfield[pc.y][pc.x];
+            let cell_dir = dir;
+            let op = if stringmode {
+                match cmd {
+                    Command::Str => {
+                        stringmode = false;
+                        Op::Nop
+                    }
+                    _ => Op::Push(cmd.value()),
+                }
+            } else {
+                match cmd {
+                    Command::Add => Op::Add,
+                    Command::Sub => Op::Sub,
+                    Command::Mul => Op::Mul,
+                    Command::Div => Op::Div,
+                    Command::Mod => Op::Mod,
+                    Command::Not => Op::Not,
+                    Command::Gt => Op::Gt,
+                    Command::Dup => Op::Dup,
+                    Command::Swap => Op::Swap,
+                    Command::Pop => Op::Pop,
+                    Command::OutI => Op::OutI,
+                    Command::OutC => Op::OutC,
+                    Command::Num(n) => Op::Push(n.into()),
+                    Command::Space => Op::Nop,
+                    Command::Right => {
+                        dir = Direction::Right;
+                        Op::Nop
+                    }
+                    Command::Left => {
+                        dir = Direction::Left;
+                        Op::Nop
+                    }
+                    Command::Up => {
+                        dir = Direction::Up;
+                        Op::Nop
+                    }
+                    Command::Down => {
+                        dir = Direction::Down;
+                        Op::Nop
+                    }
+                    Command::Str => {
+                        stringmode = true;
+                        Op::Nop
+                    }
+                    Command::Bri => {
+                        pc.advance(dir, self.get_size());
+                        Op::Nop
+                    }
+                    Command::Char(_) => match self.semantics.unknown {
+                        Unknown::Nop => Op::Nop,
+                        Unknown::Reflect => {
+                            dir = dir.reverse();
+                            Op::Nop
+                        }
+                        Unknown::Error => break,
+                    },
+                    _ => break,
+                }
+            };
+            instrs.push(Instr {
+                pos,
+                dir: cell_dir,
+                op,
+            });
+            pc.advance(dir, self.get_size());
+        }
+
+        Block {
+            instrs,
+            exit: Position { x: pc.x, y: pc.y },
+            exit_dir: dir,
+            exit_stringmode: stringmode,
+        }
+    }
+
+    /// Run the program from the start like `run`, but executing compiled
+    /// blocks where possible. Blocks are recompiled when `p` writes into
+    /// them. Falls back to plain stepping while trace events are
+    /// emitted or stack shapes are checked.
+    pub fn run_compiled(&mut self) -> Result<()> {
+        if self.needs_stepping() {
+            return self.run(|_, _| true);
+        }
+        let result = self.run_blocks();
+        let flushed = self.flush_output_sinks();
+        result.and(flushed)
+    }
+
+    fn run_blocks(&mut self) -> Result<()> {
+        self.reset();
+
+        let size = self.get_size();
+        let mut cache: Vec<Option<Rc<Block>>> = vec![None; size.rows * size.cols * 4];
+        let mut compiled = Grid::new(size, false);
+        loop {
+            if !self.stringmode {
+                let slot = &mut cache[cache_index(&self.pc, self.dir, size.cols)];
+                let block = match slot {
+                    Some(block) => block.clone(),
+                    None => {
+                        let start = self.get_position();
+                        let block = Rc::new(self.compile_block(start, self.dir));
+                        for instr in &block.instrs {
+                            compiled[instr.pos.y][instr.pos.x] = true;
+                        }
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(x = start.x, y = start.y, dir = %self.dir, len = block.instrs.len(), "compiled block");
+                        *slot = Some(block.clone());
+                        block
+                    }
+                };
+                if !block.instrs.is_empty() {
+                    self.execute(&block)?;
+                    continue;
+                }
+            }
+
+            let (result, written) = self.step_watching_writes()?;
+            if result == StepResult::Stop {
+                return Ok(());
+            }
+            if written.is_some_and(|pos| compiled[pos.y][pos.x]) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(?written, "invalidated compiled blocks");
+                cache.iter_mut().for_each(|slot| *slot = None);
+                compiled.fill(false);
+            }
+        }
+    }
+
+    /// Execute one step with the interpreter, returning which cell it wrote
+    /// with `p`, if any.
+    pub(crate) fn step_watching_writes(&mut self) -> Result<(StepResult, Option<Position>)> {
+        let result = self.step_in_context()?;
+        Ok((result, self.last_put.map(|(pos, _)| pos)))
+    }
+
+    /// Stop at `instr` because executing it failed with `e`.
+    pub(crate) fn fail_at(&mut self, instr: &Instr, e: Error) -> Error {
+        self.pc = ProgramCounter {
+            x: instr.pos.x,
+            y: instr.pos.y,
+        };
+        self.dir = instr.dir;
+        e.context(anyhow!("Stepping at {}", instr.pos))
+    }
+
+    /// Execute the operations of `block` one by one.
+    pub(crate) fn execute(&mut self, block: &Block) -> Result<()> {
+        for instr in &block.instrs {
+            self.profile[instr.pos.y][instr.pos.x] += 1;
+            self.coverage.executed(instr.pos);
+
+            match instr.op {
+                Op::Push(v) => self.stack.push(v),
+                Op::Add => self.binop(BinOp::Add),
+                Op::Sub => self.binop(BinOp::Sub),
+                Op::Mul => self.binop(BinOp::Mul),
+                Op::Div | Op::Mod => {
+                    let result = if instr.op == Op::Div {
+                        self.divop(BinOp::Div)
+                    } else {
+                        self.divop(BinOp::Mod)
+                    };
+                    if let Err(e) = result {
+                        return Err(self.fail_at(instr, e));
+                    }
+                }
+                Op::Not => {
+                    let x = self.stack.pop();
+                    self.stack.push(if x == 0 { 1 } else { 0 });
+                }
+                Op::Gt => self.binop(BinOp::Gt),
+                Op::Dup => self.stack.dup(),
+                Op::Swap => self.stack.swap(),
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::OutI => {
+                    let x = self.stack.pop_text();
+                    self.write_output(&format!("{} ", x));
+                }
+                Op::OutC => {
+                    let x = self.stack.pop();
+                    let c = self.output_char(x);
+                    self.write_output(&c.to_string());
+                }
+                Op::Nop => {}
+            }
+            if matches!(instr.op, Op::OutI | Op::OutC) {
+                if let Err(e) = self.check_output().and_then(|_| self.check_sinks()) {
+                    return Err(self.fail_at(instr, e));
+                }
+            }
+            self.steps += 1;
+        }
+
+        self.pc = ProgramCounter {
+            x: block.exit.x,
+            y: block.exit.y,
+        };
+        self.dir = block.exit_dir;
+        self.stringmode = block.exit_stringmode;
+        Ok(())
+    }
+}
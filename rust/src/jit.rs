@@ -0,0 +1,446 @@
+//! Native code generation for hot blocks with Cranelift.
+//!
+//! Blocks from the `ir` module are first executed operation by operation.
+//! Once a block ran often enough it is compiled to a native function working
+//! directly on the stack buffer. A `p` into a compiled cell drops all blocks,
+//! native or not, and execution falls back to the interpreter until they are
+//! hot again.
+
+use anyhow::{anyhow, Result};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlagsData, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Module};
+
+use crate::ir::{cache_index, Block, Op};
+use crate::output::OutputOverflow;
+use crate::semantics::DivZero;
+use crate::{Grid, Interpreter, StepResult};
+
+/// How often a block runs before it is compiled to native code.
+const HOT_THRESHOLD: u32 = 16;
+
+/// How often the block starting at a cell is compiled to native code in a
+/// run. Code isn't freed before the run ends, so blocks that `p` keeps
+/// invalidating run their operations instead once they reach this.
+const MAX_COMPILES: u32 = 4;
+
+/// A compiled block: takes the stack buffer, a pointer to the stack length,
+/// the profile and a buffer for output. Returns 0 when the block completed,
+/// or one more than the index of the operation that divided by zero.
+type NativeFn = unsafe extern "C" fn(*mut i64, *mut u64, *mut u64, *mut String) -> u64;
+
+/// Called by native code for `.` (`kind` 0) and `,` (`kind` 1).
+extern "C" fn bef_jit_output(buf: *mut String, kind: u64, value: i64) {
+    // SAFETY: native code passes on the buffer `run_jit` handed to it.
+    let buf = unsafe { &mut *buf };
+    if kind == 0 {
+        buf.push_str(&format!("{} ", value));
+    } else {
+        buf.push(value as u8 as char);
+    }
+}
+
+enum Code {
+    /// Not run often enough yet.
+    Cold(u32),
+    Native(NativeFn),
+    /// Compilation failed, keep executing operations.
+    Uncompilable,
+}
+
+struct Entry {
+    block: Block,
+    code: Code,
+    /// Whether all cells of the block were marked as covered.
+    covered: bool,
+}
+
+struct Jit {
+    module: Option<JITModule>,
+    ctx: Context,
+    fctx: FunctionBuilderContext,
+    output: FuncId,
+}
+
+impl Jit {
+    fn new() -> Result<Self> {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false")?;
+        flags.set("is_pic", "false")?;
+        flags.set("opt_level", "speed")?;
+        let isa = cranelift_native::builder()
+            .map_err(|msg| anyhow!("Host machine is not supported: {}", msg))?
+            .finish(settings::Flags::new(flags))?;
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("bef_jit_output", bef_jit_output as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let ptr = module.target_config().pointer_type();
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(ptr));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params.push(AbiParam::new(types::I64));
+        let output =
+            module.declare_function("bef_jit_output", cranelift_module::Linkage::Import, &sig)?;
+
+        Ok(Self {
+            ctx: module.make_context(),
+            module: Some(module),
+            fctx: FunctionBuilderContext::new(),
+            output,
+        })
+    }
+
+    fn compile(&mut self, block: &Block, div_zero: DivZero, cols: usize) -> Result<NativeFn> {
+        let module = self.module.as_mut().expect("module is only taken on drop");
+        let ptr = module.target_config().pointer_type();
+        module.clear_context(&mut self.ctx);
+        for _ in 0..4 {
+            self.ctx.func.signature.params.push(AbiParam::new(ptr));
+        }
+        self.ctx
+            .func
+            .signature
+            .returns
+            .push(AbiParam::new(types::I64));
+
+        let mut b = FunctionBuilder::new(&mut self.ctx.func, &mut self.fctx);
+        let output = module.declare_func_in_func(self.output, b.func);
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        let params = b.block_params(entry).to_vec();
+        let (stack, len_ptr, profile, out) = (params[0], params[1], params[2], params[3]);
+        let flags = MemFlagsData::trusted();
+
+        let len = b.declare_var(types::I64);
+        let initial = b.ins().load(types::I64, flags, len_ptr, 0);
+        b.def_var(len, initial);
+
+        for (idx, instr) in block.instrs.iter().enumerate() {
+            let offset = ((instr.pos.y * cols + instr.pos.x) * 8) as i32;
+            let count = b.ins().load(types::I64, flags, profile, offset);
+            let count = b.ins().iadd_imm_s(count, 1);
+            b.ins().store(flags, count, profile, offset);
+
+            match instr.op {
+                Op::Push(v) => {
+                    let v = b.ins().iconst(types::I64, v);
+                    push(&mut b, stack, len, v);
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Gt => {
+                    let y = pop(&mut b, stack, len);
+                    let x = pop(&mut b, stack, len);
+                    let v = match instr.op {
+                        Op::Add => b.ins().iadd(x, y),
+                        Op::Sub => b.ins().isub(x, y),
+                        Op::Mul => b.ins().imul(x, y),
+                        _ => {
+                            let gt = b.ins().icmp(IntCC::SignedGreaterThan, x, y);
+                            b.ins().uextend(types::I64, gt)
+                        }
+                    };
+                    push(&mut b, stack, len, v);
+                }
+                Op::Div | Op::Mod => {
+                    let y = pop(&mut b, stack, len);
+                    let x = pop(&mut b, stack, len);
+                    let is_zero = b.ins().icmp_imm_s(IntCC::Equal, y, 0);
+                    if div_zero == DivZero::Error {
+                        let fail = b.create_block();
+                        let cont = b.create_block();
+                        b.ins().brif(is_zero, fail, &[], cont, &[]);
+                        b.switch_to_block(fail);
+                        b.seal_block(fail);
+                        let n = b.use_var(len);
+                        b.ins().store(flags, n, len_ptr, 0);
+                        let code = b.ins().iconst(types::I64, idx as i64 + 1);
+                        b.ins().return_(&[code]);
+                        b.switch_to_block(cont);
+                        b.seal_block(cont);
+                    }
+                    // Avoid trapping on zero and on the overflow of MIN / -1,
+                    // whose results are selected separately.
+                    let is_minus_one = b.ins().icmp_imm_s(IntCC::Equal, y, -1);
+                    let special = b.ins().bor(is_zero, is_minus_one);
+                    let one = b.ins().iconst(types::I64, 1);
+                    let divisor = b.ins().select(special, one, y);
+                    let zero = b.ins().iconst(types::I64, 0);
+                    let v = if instr.op == Op::Div {
+                        let q = b.ins().sdiv(x, divisor);
+                        let neg = b.ins().ineg(x);
+                        b.ins().select(is_minus_one, neg, q)
+                    } else {
+                        let r = b.ins().srem(x, divisor);
+                        b.ins().select(is_minus_one, zero, r)
+                    };
+                    let v = b.ins().select(is_zero, zero, v);
+                    push(&mut b, stack, len, v);
+                }
+                Op::Not => {
+                    let x = pop(&mut b, stack, len);
+                    let is_zero = b.ins().icmp_imm_s(IntCC::Equal, x, 0);
+                    let v = b.ins().uextend(types::I64, is_zero);
+                    push(&mut b, stack, len, v);
+                }
+                Op::Dup => {
+                    let v = peek(&mut b, stack, len);
+                    push(&mut b, stack, len, v);
+                }
+                Op::Swap => {
+                    let x = pop(&mut b, stack, len);
+                    let y = pop(&mut b, stack, len);
+                    push(&mut b, stack, len, x);
+                    push(&mut b, stack, len, y);
+                }
+                Op::Pop => {
+                    pop(&mut b, stack, len);
+                }
+                Op::OutI | Op::OutC => {
+                    let x = pop(&mut b, stack, len);
+                    let kind = b
+                        .ins()
+                        .iconst(types::I64, if instr.op == Op::OutI { 0 } else { 1 });
+                    b.ins().call(output, &[out, kind, x]);
+                }
+                Op::Nop => {}
+            }
+        }
+
+        let n = b.use_var(len);
+        b.ins().store(flags, n, len_ptr, 0);
+        let done = b.ins().iconst(types::I64, 0);
+        b.ins().return_(&[done]);
+        b.seal_all_blocks();
+        b.finalize(module.target_config());
+
+        let id = module.declare_anonymous_function(&self.ctx.func.signature)?;
+        module.define_function(id, &mut self.ctx)?;
+        module.clear_context(&mut self.ctx);
+        module.finalize_definitions()?;
+        let code = module.get_finalized_function(id);
+        // SAFETY: the function was built with the signature of `NativeFn`.
+        Ok(unsafe { std::mem::transmute::<*const u8, NativeFn>(code) })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: native functions don't outlive the run that owns `self`.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Pop into a value, producing 0 when the stack is empty like `Stack::pop`.
+fn pop(b: &mut FunctionBuilder, stack: Value, len: Variable) -> Value {
+    let n = b.use_var(len);
+    let nonempty = b.ins().icmp_imm_s(IntCC::NotEqual, n, 0);
+    let dec = b.ins().uextend(types::I64, nonempty);
+    let n = b.ins().isub(n, dec);
+    b.def_var(len, n);
+    // With an empty stack this reads the first slot, which is reserved.
+    let offset = b.ins().ishl_imm_u(n, 3);
+    let addr = b.ins().iadd(stack, offset);
+    let v = b.ins().load(types::I64, MemFlagsData::trusted(), addr, 0);
+    let zero = b.ins().iconst(types::I64, 0);
+    b.ins().select(nonempty, v, zero)
+}
+
+/// Read the value on top, producing 0 when the stack is empty like
+/// `Stack::dup`.
+fn peek(b: &mut FunctionBuilder, stack: Value, len: Variable) -> Value {
+    let n = b.use_var(len);
+    let nonempty = b.ins().icmp_imm_s(IntCC::NotEqual, n, 0);
+    let dec = b.ins().uextend(types::I64, nonempty);
+    let top = b.ins().isub(n, dec);
+    // With an empty stack this reads the first slot, which is reserved.
+    let offset = b.ins().ishl_imm_u(top, 3);
+    let addr = b.ins().iadd(stack, offset);
+    let v = b.ins().load(types::I64, MemFlagsData::trusted(), addr, 0);
+    let zero = b.ins().iconst(types::I64, 0);
+    b.ins().select(nonempty, v, zero)
+}
+
+fn push(b: &mut FunctionBuilder, stack: Value, len: Variable, v: Value) {
+    let n = b.use_var(len);
+    let offset = b.ins().ishl_imm_u(n, 3);
+    let addr = b.ins().iadd(stack, offset);
+    b.ins().store(MemFlagsData::trusted(), v, addr, 0);
+    let n = b.ins().iadd_imm_s(n, 1);
+    b.def_var(len, n);
+}
+
+/// Most values `block` can add to the stack.
+fn max_growth(block: &Block) -> usize {
+    block
+        .instrs
+        .iter()
+        .map(|instr| match instr.op {
+            Op::Swap => 2,
+            Op::Pop | Op::OutI | Op::OutC | Op::Nop => 0,
+            _ => 1,
+        })
+        .sum()
+}
+
+impl Interpreter {
+    /// Run the program from the start like `run_compiled`, additionally
+    /// compiling blocks that run often to native code. Falls back to plain
+    /// stepping while trace events are emitted or stack shapes are
+    /// checked.
+    pub fn run_jit(&mut self) -> Result<()> {
+        if self.needs_stepping() {
+            return self.run(|_, _| true);
+        }
+        let result = self.run_native();
+        let flushed = self.flush_output_sinks();
+        result.and(flushed)
+    }
+
+    fn run_native(&mut self) -> Result<()> {
+        let mut jit = Jit::new()?;
+        self.reset();
+
+        let size = self.get_size();
+        let mut cache: Vec<Option<Entry>> = Vec::new();
+        cache.resize_with(size.rows * size.cols * 4, || None);
+        let mut compiled = Grid::new(size, false);
+        let mut compiles = vec![0; cache.len()];
+        let mut output = String::new();
+        loop {
+            if !self.stringmode {
+                let index = cache_index(&self.pc, self.dir, size.cols);
+                let slot = &mut cache[index];
+                if slot.is_none() {
+                    let block = self.compile_block(self.get_position(), self.dir);
+                    for instr in &block.instrs {
+                        compiled[instr.pos.y][instr.pos.x] = true;
+                    }
+                    *slot = Some(Entry {
+                        block,
+                        code: Code::Cold(0),
+                        covered: false,
+                    });
+                }
+                let entry = slot.as_mut().expect("just filled");
+                if !entry.block.instrs.is_empty() {
+                    if let Code::Cold(hits) = entry.code {
+                        entry.code = if hits < HOT_THRESHOLD {
+                            Code::Cold(hits + 1)
+                        } else if compiles[index] == MAX_COMPILES {
+                            Code::Uncompilable
+                        } else {
+                            compiles[index] += 1;
+                            match jit.compile(&entry.block, self.semantics.div_zero, size.cols) {
+                                Ok(f) => Code::Native(f),
+                                Err(_) => Code::Uncompilable,
+                            }
+                        };
+                    }
+                    match entry.code {
+                        Code::Native(f) if !self.checks_output(&entry.block) => {
+                            self.execute_native(entry, f, &mut output)?
+                        }
+                        _ => self.execute(&entry.block)?,
+                    }
+                    continue;
+                }
+            }
+
+            let (result, written) = self.step_watching_writes()?;
+            if result == StepResult::Stop {
+                return Ok(());
+            }
+            if written.is_some_and(|pos| compiled[pos.y][pos.x]) {
+                cache.iter_mut().for_each(|slot| *slot = None);
+                compiled.fill(false);
+            }
+        }
+    }
+
+    /// Whether `block` prints while output past the limit is an error,
+    /// which must stop the run at the very instruction printing it, so the
+    /// block can't run natively.
+    fn checks_output(&self, block: &Block) -> bool {
+        self.output_limit
+            .is_some_and(|limit| limit.overflow == OutputOverflow::Error)
+            && block
+                .instrs
+                .iter()
+                .any(|instr| matches!(instr.op, Op::OutI | Op::OutC))
+    }
+
+    fn execute_native(
+        &mut self,
+        entry: &mut Entry,
+        f: NativeFn,
+        output: &mut String,
+    ) -> Result<()> {
+        let block = &entry.block;
+        let stack = self
+            .stack
+            .fixed_mut()
+            .expect("only fixed-width stacks are compiled");
+        stack.reserve(max_growth(block) + 1);
+        let mut len = stack.len() as u64;
+        // SAFETY: the buffer has room for everything the block can push and
+        // for reading the first slot, and the profile is a plain array, row by row.
+        let status = unsafe {
+            let status = f(
+                stack.as_mut_ptr(),
+                &mut len,
+                self.profile.as_mut_ptr(),
+                output,
+            );
+            stack.set_len(len as usize);
+            status
+        };
+        if !output.is_empty() {
+            self.write_output(output);
+            output.clear();
+            if let Err(e) = self.check_sinks() {
+                // The output of the whole block is written at once, as if by
+                // its last instruction printing.
+                let (last, instr) = block
+                    .instrs
+                    .iter()
+                    .enumerate()
+                    .rfind(|(_, instr)| matches!(instr.op, Op::OutI | Op::OutC))
+                    .expect("only blocks that print have output");
+                for instr in &block.instrs[..=last] {
+                    self.coverage.executed(instr.pos);
+                }
+                self.steps += last;
+                return Err(self.fail_at(instr, e));
+            }
+        }
+
+        if status == 0 {
+            if !entry.covered {
+                for instr in &block.instrs {
+                    self.coverage.executed(instr.pos);
+                }
+                entry.covered = true;
+            }
+            self.pc.x = block.exit.x;
+            self.pc.y = block.exit.y;
+            self.dir = block.exit_dir;
+            self.stringmode = block.exit_stringmode;
+            self.steps += block.instrs.len();
+            Ok(())
+        } else {
+            let failed = status as usize - 1;
+            for instr in &block.instrs[..=failed] {
+                self.coverage.executed(instr.pos);
+            }
+            self.steps += failed;
+            Err(self.fail_at(&block.instrs[failed], anyhow!("Division by zero")))
+        }
+    }
+}
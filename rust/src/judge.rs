@@ -0,0 +1,141 @@
+//! Running untrusted submissions the way an online judge would: on a fresh
+//! interpreter with no custom instructions, a fixed seed, the default
+//! semantics and tight limits, ending in a verdict.
+
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::Interpreter;
+
+/// How much a submission may use.
+#[derive(Debug, Clone)]
+pub struct JudgeLimits {
+    pub max_steps: usize,
+    /// Largest number of values on the stack.
+    pub max_stack: usize,
+    /// Largest output, in bytes.
+    pub max_output: usize,
+    /// Wall-clock time for the whole run.
+    pub time_limit: Duration,
+    /// Seed of the directions taken by `?`.
+    pub seed: u64,
+}
+
+impl Default for JudgeLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: 10_000_000,
+            max_stack: 100_000,
+            max_output: 64 * 1024,
+            time_limit: Duration::from_secs(5),
+            seed: 0,
+        }
+    }
+}
+
+/// How a submission did.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    /// The program ended and printed the expected output.
+    Accepted,
+    /// The program ended but printed something else.
+    WrongAnswer,
+    /// The program ended and there was no expected output to compare with.
+    Finished,
+    RuntimeError,
+    StepLimit,
+    StackLimit,
+    OutputLimit,
+    TimeLimit,
+}
+
+/// The outcome of judging a submission.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct JudgeReport {
+    pub verdict: Verdict,
+    /// What the program printed, cut at the output limit.
+    pub output: String,
+    /// Steps taken, including the one ending the program.
+    pub steps: usize,
+    /// Most values the stack held at once.
+    pub peak_stack: usize,
+    /// The stack when the run ended, bottom first.
+    pub stack: Vec<i64>,
+    pub elapsed_ms: u64,
+    /// The runtime error, if there was one.
+    pub error: Option<String>,
+}
+
+/// Run `program` with `input` within `limits`, comparing its output with
+/// `expected` if given. Trailing whitespace at the end of lines and of the
+/// output is ignored when comparing, as `.` always prints a space.
+pub fn judge(
+    program: &[u8],
+    input: &[u8],
+    expected: Option<&str>,
+    limits: &JudgeLimits,
+) -> JudgeReport {
+    let started = Instant::now();
+    let mut int = Interpreter::new();
+    int.set_seed(Some(limits.seed));
+    int.set_input(Cursor::new(input.to_vec()));
+
+    let mut peak_stack = 0;
+    let mut limit = None;
+    let result = int.load(&mut &program[..]).and_then(|_| {
+        int.run(|int, n| {
+            peak_stack = peak_stack.max(int.stack.len());
+            limit = if n >= limits.max_steps {
+                Some(Verdict::StepLimit)
+            } else if int.stack.len() > limits.max_stack {
+                Some(Verdict::StackLimit)
+            } else if int.get_output().len() > limits.max_output {
+                Some(Verdict::OutputLimit)
+            } else if n % 1024 == 0 && started.elapsed() > limits.time_limit {
+                Some(Verdict::TimeLimit)
+            } else {
+                None
+            };
+            limit.is_none()
+        })
+    });
+
+    let mut output = int.get_output().to_string();
+    if output.len() > limits.max_output {
+        let mut end = limits.max_output;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+    }
+    let (verdict, error) = match (result, limit) {
+        (Err(e), _) => (Verdict::RuntimeError, Some(format!("{:#}", e))),
+        (Ok(()), Some(limit)) => (limit, None),
+        (Ok(()), None) => {
+            let verdict = match expected {
+                None => Verdict::Finished,
+                Some(expected) if normalize(expected) == normalize(&output) => Verdict::Accepted,
+                Some(_) => Verdict::WrongAnswer,
+            };
+            (verdict, None)
+        }
+    };
+    JudgeReport {
+        verdict,
+        output,
+        steps: int.get_steps(),
+        peak_stack,
+        stack: int.stack.values(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+fn normalize(output: &str) -> String {
+    let lines: Vec<_> = output.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_end().to_string()
+}
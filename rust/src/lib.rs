@@ -1,12 +1,81 @@
-use std::convert::{Into, TryInto};
+use std::convert::TryFrom;
+use std::fmt;
 use std::io::{self, Read};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use ansi_term::Colour::{Green, Red, White, Yellow};
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+pub mod analysis;
+pub mod assertions;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod bisect;
+pub mod codegen;
+pub mod conformance;
+pub mod corpus;
+pub mod coverage;
+pub mod diagnostic;
+pub mod differential;
+pub mod dispatch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod fuzzing;
+pub mod generator;
+mod grid;
+pub mod input;
+pub mod instructions;
+pub mod interop;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod judge;
+pub mod literate;
+pub mod lockstep;
+pub mod loops;
+pub mod modifications;
+pub mod optimize;
+pub mod output;
+pub mod packs;
+pub mod profile;
+pub mod query;
+pub mod regression;
+pub mod render;
+pub mod repl;
+pub mod replay;
+pub mod runner;
+pub mod schema;
+pub mod semantics;
+pub mod sourcemap;
+mod stack;
+pub mod stats;
+pub mod stepper;
+pub mod summary;
+pub mod teach;
+pub mod testing;
+pub mod theme;
+pub mod trace;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+
+use assertions::Assertions;
+use dispatch::Dispatch;
+pub use grid::Grid;
+use input::{InputPump, InputTimeout};
+use instructions::Dialect;
+use output::{OutputLimit, OutputRing};
+use replay::{Event, Recording, Session};
+use semantics::{Cells, Chars, DivZero, Eof, Semantics};
+use stack::BinOp;
+pub use stack::{Overflow, Stack, StackLimit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "char", into = "char")]
 pub enum Command {
     Add,
     Sub,
@@ -40,7 +109,8 @@ pub enum Command {
 }
 
 impl Command {
-    fn as_char(&self) -> char {
+    /// The character the command is written as.
+    pub fn as_char(&self) -> char {
         match self {
             Self::Add => '+',
             Self::Sub => '-',
@@ -73,6 +143,12 @@ impl Command {
             Self::Char(c) => *c,
         }
     }
+
+    /// The value of the cell holding the command, as pushed by `g` and in
+    /// string mode.
+    pub fn value(&self) -> StackTy {
+        u32::from(self.as_char()).into()
+    }
 }
 
 impl From<char> for Command {
@@ -111,19 +187,42 @@ impl From<char> for Command {
     }
 }
 
-impl Into<char> for Command {
-    fn into(self) -> char {
-        self.as_char()
+impl From<Command> for char {
+    fn from(cmd: Command) -> Self {
+        cmd.as_char()
     }
 }
 
-impl ToString for Command {
-    fn to_string(&self) -> String {
-        self.as_char().to_string()
+impl JsonSchema for Command {
+    fn schema_name() -> String {
+        "Command".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        char::json_schema(gen)
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// A cell of the playfield.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 struct ProgramCounter {
     x: usize,
     y: usize,
@@ -139,74 +238,169 @@ impl ProgramCounter {
         self.y = 0;
     }
 
-    fn right(&mut self) {
-        self.x = (self.x + 1) % PLAYFIELD_COLS;
-    }
-
-    fn left(&mut self) {
-        if self.x == 0 {
-            self.x = PLAYFIELD_COLS - 1;
-        } else {
-            self.x -= 1;
+    /// Move to the next cell in `dir` on a playfield of `size`, wrapping
+    /// around its edges.
+    fn advance(&mut self, dir: Direction, size: Bounds) {
+        match dir {
+            Direction::Right => self.x = (self.x + 1) % size.cols,
+            Direction::Left => self.x = self.x.checked_sub(1).unwrap_or(size.cols - 1),
+            Direction::Down => self.y = (self.y + 1) % size.rows,
+            Direction::Up => self.y = self.y.checked_sub(1).unwrap_or(size.rows - 1),
         }
     }
+}
+
+/// The part of the playfield in use: the smallest rectangle at the origin
+/// holding every cell that isn't a space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Bounds {
+    pub cols: usize,
+    pub rows: usize,
+}
 
-    fn down(&mut self) {
-        self.y = (self.y + 1) % PLAYFIELD_ROWS;
+impl Bounds {
+    /// Whether `pos` is within the bounds.
+    pub fn contains(self, pos: Position) -> bool {
+        pos.x < self.cols && pos.y < self.rows
     }
 
-    fn up(&mut self) {
-        if self.y == 0 {
-            self.y = PLAYFIELD_ROWS - 1;
-        } else {
-            self.y -= 1;
+    /// The bounds grown to hold `pos`.
+    fn including(self, pos: Position) -> Self {
+        Self {
+            cols: self.cols.max(pos.x + 1),
+            rows: self.rows.max(pos.y + 1),
         }
     }
 }
 
-const PLAYFIELD_ROWS: usize = 25;
-const PLAYFIELD_COLS: usize = 80;
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.cols, self.rows)
+    }
+}
+
+/// Steps between checks of the clock by `Interpreter::run_with_deadline`.
+const DEADLINE_CHECK_STEPS: usize = 256;
+
+/// Height of the playfield of Befunge-93. Other dialects may have larger
+/// ones, see `Interpreter::get_size`.
+pub const PLAYFIELD_ROWS: usize = 25;
+/// Width of the playfield of Befunge-93.
+pub const PLAYFIELD_COLS: usize = 80;
+
+/// Execution counts of each playfield cell, indexed as `[y][x]`.
+pub type Profile = Grid<u64>;
 
 type StackTy = i64;
 
-#[derive(Clone)]
-pub struct Stack(Vec<StackTy>);
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
 
-impl Stack {
-    fn reset(&mut self) {
-        self.0.clear();
+impl Direction {
+    fn reverse(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
     }
 
-    fn pop(&mut self) -> StackTy {
-        self.0.pop().unwrap_or(0)
+    /// The direction 90 degrees to the left.
+    fn turn_left(self) -> Self {
+        match self {
+            Self::Up => Self::Left,
+            Self::Left => Self::Down,
+            Self::Down => Self::Right,
+            Self::Right => Self::Up,
+        }
+    }
+
+    /// The direction 90 degrees to the right.
+    fn turn_right(self) -> Self {
+        self.turn_left().reverse()
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Self::Up => '^',
+            Self::Down => 'v',
+            Self::Left => '<',
+            Self::Right => '>',
+        }
     }
+}
 
-    fn push(&mut self, val: StackTy) {
-        self.0.push(val);
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_char())
     }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
 
-    fn peek(&self) -> StackTy {
-        *self.0.last().unwrap_or(&0)
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "^" => Ok(Self::Up),
+            "v" => Ok(Self::Down),
+            "<" => Ok(Self::Left),
+            ">" => Ok(Self::Right),
+            _ => bail!("Invalid direction '{}'", s),
+        }
     }
 }
 
-impl ToString for Stack {
-    fn to_string(&self) -> String {
-        let mut s = String::new();
-        for item in &self.0 {
-            let s0 = Green.on(White).paint(item.to_string() + " ");
-            s += &s0;
+/// The default line separating the program from comments that are not
+/// loaded into the playfield.
+pub const COMMENT_MARKER: &str = "__END__";
+
+/// Find the line consisting of just `marker` in `source`. Returns the
+/// length of the code before it, which is all of `source` if there is none.
+fn split_comments(source: &[u8], marker: &str) -> usize {
+    let mut start = 0;
+    for line in source.split(|&b| b == b'\n') {
+        let text = line.strip_suffix(b"\r").unwrap_or(line);
+        if text == marker.as_bytes() {
+            return start;
         }
-        s
+        start += line.len() + 1;
     }
+    source.len()
 }
 
-#[derive(Copy, Clone)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+/// The number `&` reads, taken in a byte at a time. Leading whitespace
+/// is skipped, and the number ends at the first byte that can't be part of
+/// it, which is left for the next `&` or `~`.
+#[derive(Default)]
+struct NumberScan {
+    text: String,
+}
+
+impl NumberScan {
+    /// Take in `byte`. Returns `false` if it ends the number instead.
+    fn feed(&mut self, byte: u8) -> bool {
+        let first = self.text.is_empty();
+        if first && byte.is_ascii_whitespace() {
+            return true;
+        }
+        if byte.is_ascii_digit() || (first && byte == b'-') {
+            self.text.push(byte as char);
+            return true;
+        }
+        false
+    }
+
+    /// Whether `bytes` hold the whole number, up to and including the
+    /// byte ending it.
+    fn is_complete(&mut self, bytes: impl IntoIterator<Item = u8>) -> bool {
+        bytes.into_iter().any(|b| !self.feed(b))
+    }
 }
 
 #[derive(PartialEq)]
@@ -216,8 +410,9 @@ enum StepResult {
 }
 
 pub struct Interpreter {
-    /// The playfield to work on. Acts as code and data storage.
-    playfield: [[Command; PLAYFIELD_COLS]; PLAYFIELD_ROWS],
+    /// The playfield to work on, of the size of the dialect. Acts as code
+    /// and data storage.
+    playfield: Grid<Command>,
     /// The program counter.
     pc: ProgramCounter,
     /// The direction the PC is moving.
@@ -228,8 +423,81 @@ pub struct Interpreter {
     stringmode: bool,
     /// The PRNG used for `?`.
     rng: SmallRng,
+    /// What the PRNG is seeded with at the start of each run, if fixed.
+    seed: Option<u64>,
     /// The current output.
     output: String,
+    /// Bounded buffer holding the output instead of `output`, if set.
+    ring: Option<OutputRing>,
+    /// How much output is kept in memory, if limited.
+    output_limit: Option<OutputLimit>,
+    /// Whether output was printed past the limit with
+    /// `OutputOverflow::Error`.
+    output_overflowed: bool,
+    /// Where output is written as well, as it is printed.
+    sinks: Vec<Box<dyn io::Write + Send>>,
+    /// The first error writing to `sinks` since the last step.
+    sink_error: Option<io::Error>,
+    /// Where `&` and `~` read from.
+    input: Box<dyn Read + Send>,
+    /// How long reading a byte of input may take, if limited.
+    input_timeout: Option<InputTimeout>,
+    /// Reads `input` on a thread of its own, once reads have a timeout.
+    pump: Option<InputPump>,
+    /// The byte that ended the last number read by `&`, to be read again.
+    unread: Option<u8>,
+    /// Whether input and randomness are recorded or replayed.
+    session: Session,
+    /// How many times each cell was executed in the current run.
+    profile: Profile,
+    /// Steps completed in the current run.
+    steps: usize,
+    /// Which cells were executed or used as data, across runs.
+    coverage: coverage::Tracker,
+    /// How to behave where implementations disagree.
+    semantics: Semantics,
+    /// The line after which the program text is comments, if any.
+    comment_marker: Option<String>,
+    /// The lines following the comment marker of the loaded program.
+    comments: Vec<String>,
+    /// The `#!` line the loaded program started with, if any.
+    shebang: Option<String>,
+    /// Emits trace events, if tracing.
+    tracer: Option<trace::Tracer>,
+    /// The part of the playfield in use.
+    bounds: Bounds,
+    /// Told about changes of `bounds`, if set.
+    on_resize: Option<Box<dyn FnMut(Bounds) + Send>>,
+    /// The cells written by `p` in the current run, if tracking them.
+    modifications: Option<modifications::Log>,
+    /// Steps of the current run by the character executed, if counting
+    /// them.
+    stats: Option<stats::Counter>,
+    /// Spots runs that can never end, if looking for them.
+    loops: Option<loops::Detector>,
+    /// The cell written by `p` in the current step, and what it held.
+    last_put: Option<(Position, Command)>,
+    /// Stack shapes to check at checkpoints.
+    assertions: Assertions,
+    /// The code executing each cell byte.
+    dispatch: Dispatch,
+}
+
+/// A copy of the execution state of an interpreter, to return to later.
+///
+/// The input reader is not part of it, so only runs replaying a recording
+/// can be resumed faithfully.
+#[derive(Clone)]
+pub(crate) struct Checkpoint {
+    playfield: Grid<Command>,
+    pc: ProgramCounter,
+    dir: Direction,
+    stack: Stack,
+    stringmode: bool,
+    rng: SmallRng,
+    output: String,
+    ring: Option<OutputRing>,
+    session: Session,
 }
 
 impl Default for Interpreter {
@@ -242,48 +510,325 @@ impl Interpreter {
     /// Create a new empty interpreter.
     pub fn new() -> Self {
         Self {
-            playfield: [[Command::Space; PLAYFIELD_COLS]; PLAYFIELD_ROWS],
+            playfield: Grid::new(Dialect::default().playfield(), Command::Space),
             pc: ProgramCounter::init(),
             dir: Direction::Right,
-            stack: Stack(vec![]),
+            stack: Stack::default(),
             stringmode: false,
             rng: SmallRng::from_entropy(),
+            seed: None,
             output: String::new(),
+            ring: None,
+            output_limit: None,
+            output_overflowed: false,
+            sinks: vec![],
+            sink_error: None,
+            input: Box::new(io::stdin()),
+            input_timeout: None,
+            pump: None,
+            unread: None,
+            session: Session::Live,
+            profile: Grid::new(Dialect::default().playfield(), 0),
+            steps: 0,
+            coverage: coverage::Tracker::new(Dialect::default().playfield()),
+            semantics: Semantics::default(),
+            comment_marker: Some(COMMENT_MARKER.to_string()),
+            comments: vec![],
+            shebang: None,
+            tracer: None,
+            bounds: Bounds::default(),
+            on_resize: None,
+            modifications: None,
+            stats: None,
+            loops: None,
+            last_put: None,
+            assertions: Assertions::default(),
+            dispatch: Dispatch::new(&Semantics::default()),
         }
     }
 
-    /// Load playfield from reader.
+    /// Change how the interpreter behaves where implementations disagree.
+    pub fn set_semantics(&mut self, semantics: Semantics) {
+        self.semantics = semantics;
+        self.dispatch.rebuild(&self.semantics);
+        self.stack.set_cells(semantics.cells);
+    }
+
+    /// Hold at most `limit.depth` values on the stack, doing what
+    /// `limit.overflow` says with more, so that runaway programs can't use
+    /// up memory. `None` lets the stack grow without bounds.
+    pub fn set_stack_limit(&mut self, limit: Option<StackLimit>) {
+        self.stack.set_limit(limit);
+    }
+
+    /// Get the limit of the stack, if any.
+    pub fn get_stack_limit(&self) -> Option<StackLimit> {
+        self.stack.get_limit()
+    }
+
+    /// Get the semantic choices of the interpreter.
+    pub fn get_semantics(&self) -> &Semantics {
+        &self.semantics
+    }
+
+    /// Seed the PRNG used for `?` at the start of every run, making runs
+    /// reproducible. `None` goes back to unpredictable directions.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+        self.rng = match seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+    }
+
+    /// Set the line after which loaded programs are comments, never placed
+    /// on the playfield. `None` loads everything.
+    pub fn set_comment_marker(&mut self, marker: Option<String>) {
+        self.comment_marker = marker;
+    }
+
+    /// Get the line after which loaded programs are comments.
+    pub fn get_comment_marker(&self) -> Option<&str> {
+        self.comment_marker.as_deref()
+    }
+
+    /// Read program input from `reader` instead of stdin.
+    pub fn set_input(&mut self, reader: impl Read + Send + 'static) {
+        self.input = Box::new(reader);
+        self.pump = None;
+        self.unread = None;
+    }
+
+    /// Record every input byte and random direction of subsequent runs.
+    pub fn record(&mut self) {
+        self.session = Session::Recording(Recording::new());
+    }
+
+    /// Take the recording of the last run, if recording.
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        match std::mem::replace(&mut self.session, Session::Live) {
+            Session::Recording(rec) => Some(rec),
+            session => {
+                self.session = session;
+                None
+            }
+        }
+    }
+
+    /// Take input bytes and random directions of subsequent runs from
+    /// `recording` instead of the input and the PRNG.
+    pub fn replay(&mut self, recording: Recording) {
+        self.session = Session::Replaying(recording, 0);
+    }
+
+    /// Pass a `TraceEvent` to `sink` after every successful step.
+    pub fn on_trace(&mut self, sink: impl FnMut(trace::TraceEvent) + Send + 'static) {
+        self.tracer = Some(trace::Tracer::new(sink));
+    }
+
+    /// Pass the new bounds to `sink` whenever the part of the playfield in
+    /// use changes: when a program is loaded, or a cell past the bounds is
+    /// written.
+    pub fn on_resize(&mut self, sink: impl FnMut(Bounds) + Send + 'static) {
+        self.on_resize = Some(Box::new(sink));
+    }
+
+    /// Stop emitting trace events.
+    pub fn stop_tracing(&mut self) {
+        self.tracer = None;
+    }
+
+    /// Load playfield from reader. A leading `#!` line is skipped so
+    /// programs can be run as scripts, and lines may end in `\r\n`. Files
+    /// in the formats of other tools are converted with `interop::import`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn load(&mut self, reader: &mut impl io::Read) -> Result<()> {
         let mut buf = vec![];
         reader.read_to_end(&mut buf)?;
 
+        let mut source = &buf[..];
+        self.shebang = None;
+        if source.starts_with(b"#!") {
+            let end = source.iter().position(|&b| b == b'\n');
+            let line = &source[..end.unwrap_or(source.len())];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            self.shebang = Some(String::from_utf8_lossy(line).into_owned());
+            source = end.map_or(&[], |end| &source[end + 1..]);
+        }
+
+        let code_len = match &self.comment_marker {
+            Some(marker) => split_comments(source, marker),
+            None => source.len(),
+        };
+        self.comments = String::from_utf8_lossy(&source[code_len..])
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect();
+
+        let code = self.semantics.chars.decode(&source[..code_len]);
+        let size = self.get_size();
         let (mut x, mut y) = (0, 0);
-        for item in buf {
-            if item == b'\n' {
+        for (i, &item) in code.iter().enumerate() {
+            if item == '\r' && code.get(i + 1) == Some(&'\n') {
+                continue;
+            }
+            if item == '\n' {
                 x = 0;
-                y = (y + 1) % PLAYFIELD_ROWS;
+                y = (y + 1) % size.rows;
                 continue;
             }
 
-            self.playfield[y][x] = Command::from(item as char);
+            self.playfield[y][x] = Command::from(item);
 
-            x = (x + 1) % PLAYFIELD_COLS;
+            x = (x + 1) % size.cols;
             if x == 0 {
-                y = (y + 1) % PLAYFIELD_ROWS;
+                y = (y + 1) % size.rows;
             }
         }
+        self.update_bounds();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(bytes = code_len, comments = self.comments.len(), "loaded");
 
         Ok(())
     }
 
+    /// Empty the playfield, forgetting the loaded program along with its
+    /// comments and `#!` line.
+    pub fn clear_playfield(&mut self) {
+        self.playfield.fill(Command::Space);
+        self.comments.clear();
+        self.shebang = None;
+        self.update_bounds();
+    }
+
+    /// Replace the loaded program with the one in `reader`, keeping the
+    /// configuration: semantics, seed, input, instructions and sinks. The
+    /// interpreter is reset and its coverage forgotten, as it was of the
+    /// old program.
+    pub fn reload(&mut self, reader: &mut impl io::Read) -> Result<()> {
+        self.clear_playfield();
+        self.reset_coverage();
+        self.reset();
+        self.load(reader)
+    }
+
+    /// Get the lines after the comment marker of the loaded program.
+    pub fn get_comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Get the `#!` line the loaded program started with, which is not part
+    /// of the playfield.
+    pub fn get_shebang(&self) -> Option<&str> {
+        self.shebang.as_deref()
+    }
+
+    /// Get the part of the playfield in use.
+    pub fn get_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Get the size of the whole playfield, which is that of the dialect.
+    pub fn get_size(&self) -> Bounds {
+        self.playfield.size()
+    }
+
+    /// Compute the bounds afresh, after the whole playfield changed.
+    fn update_bounds(&mut self) {
+        let mut bounds = Bounds::default();
+        for (y, row) in self.playfield.iter().enumerate() {
+            for (x, &cmd) in row.iter().enumerate() {
+                if cmd != Command::Space {
+                    bounds = bounds.including(Position { x, y });
+                }
+            }
+        }
+        self.resize(bounds);
+    }
+
+    fn resize(&mut self, bounds: Bounds) {
+        if bounds != self.bounds {
+            self.bounds = bounds;
+            if let Some(sink) = &mut self.on_resize {
+                sink(bounds);
+            }
+        }
+    }
+
+    /// Write `cmd` to the cell at `pos`, growing the bounds if needed.
+    pub(crate) fn put(&mut self, pos: Position, cmd: Command) {
+        self.playfield[pos.y][pos.x] = cmd;
+        if cmd != Command::Space {
+            self.resize(self.bounds.including(pos));
+        }
+    }
+
     /// Get a copy of the current stack.
     pub fn get_stack(&self) -> Stack {
         self.stack.clone()
     }
 
-    /// Inspect the current output.
+    /// Inspect the current output, or as much of it as is kept with an
+    /// output limit, see `set_output_limit`.
     pub fn get_output(&self) -> &str {
-        &self.output
+        match &self.ring {
+            Some(ring) => ring.as_str(),
+            None => &self.output,
+        }
+    }
+
+    /// Get how many times each cell was executed in the current run.
+    pub fn get_profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Get how many steps the current run completed, the one ending the
+    /// program included. A step that failed isn't counted.
+    pub fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get the current position of the PC.
+    pub fn get_position(&self) -> Position {
+        Position {
+            x: self.pc.x,
+            y: self.pc.y,
+        }
+    }
+
+    /// Get the direction the PC is moving.
+    pub fn get_direction(&self) -> Direction {
+        self.dir
+    }
+
+    /// Get a row of the playfield as text, without trailing spaces.
+    pub fn get_row(&self, y: usize) -> String {
+        let row: String = self.playfield[y].iter().map(Command::as_char).collect();
+        row.trim_end().to_string()
+    }
+
+    /// Get the loaded program as source text: the `#!` line, the rows of the
+    /// playfield up to the last non-empty one and the comments, if any.
+    pub fn get_source(&self) -> String {
+        let mut rows: Vec<String> = (0..self.get_size().rows).map(|y| self.get_row(y)).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        if let Some(shebang) = &self.shebang {
+            rows.insert(0, shebang.clone());
+        }
+        if let (Some(marker), false) = (&self.comment_marker, self.comments.is_empty()) {
+            rows.push(marker.clone());
+            rows.extend(self.comments.iter().cloned());
+        }
+        rows.iter().map(|row| format!("{}\n", row)).collect()
+    }
+
+    /// Get whether the PC is in string mode, pushing cells instead of
+    /// executing them.
+    pub fn get_stringmode(&self) -> bool {
+        self.stringmode
     }
 
     /// Get the current command.
@@ -291,209 +836,338 @@ impl Interpreter {
         self.playfield[self.pc.y][self.pc.x]
     }
 
-    fn binop<F: Fn(StackTy, StackTy) -> StackTy>(&mut self, f: F) {
-        let y = self.stack.pop();
-        let x = self.stack.pop();
-        self.stack.push(f(x, y));
+    /// Write the character with code `value` to the cell at `pos`, as `p`
+    /// does.
+    pub fn set_cell(&mut self, pos: Position, value: u8) -> Result<()> {
+        if !self.get_size().contains(pos) {
+            bail!("{} is outside the playfield", pos);
+        }
+        self.put(pos, (value as char).into());
+        Ok(())
+    }
+
+    /// The character `,` prints for `value`: the value modulo 256 as a
+    /// byte, or the code point it is with `chars=unicode`, if any.
+    pub(crate) fn output_char(&self, value: StackTy) -> char {
+        match self.semantics.chars {
+            Chars::Bytes => value as u8 as char,
+            Chars::Unicode => u32::try_from(value)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    /// The next byte of input, or `None` at its end.
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        // Already logged when it was first read.
+        if let Some(b) = self.unread.take() {
+            return Ok(Some(b));
+        }
+        let byte = match self.session.next_replayed() {
+            Some(Ok(Event::Input(b))) => Some(b),
+            Some(Ok(Event::Eof)) => None,
+            Some(Ok(event)) => bail!("Expected an input event, replayed '{}'", event),
+            Some(Err(e)) => return Err(e),
+            None => {
+                // Prompts must show before waiting for an answer.
+                self.flush_output_sinks()?;
+                self.read_input()?
+            }
+        };
+        self.session.log(byte.map_or(Event::Eof, Event::Input));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?byte, "input");
+        Ok(byte)
+    }
+
+    /// Do what `eof` says `&` and `~` do at the end of input.
+    fn at_eof(&mut self) -> Result<()> {
+        match self.semantics.eof {
+            Eof::Error => bail!("Reading a byte: end of input"),
+            Eof::MinusOne => self.stack.push(-1),
+            Eof::Zero => self.stack.push(0),
+            Eof::Reflect => self.reverse(),
+        }
+        Ok(())
+    }
+
+    fn rand_dir(&mut self) -> Result<Direction> {
+        let dir = match self.session.next_replayed() {
+            Some(Ok(Event::Rand(dir))) => dir,
+            Some(Ok(event)) => bail!("Expected a rand event, replayed '{}'", event),
+            Some(Err(e)) => return Err(e),
+            None => [
+                Direction::Up,
+                Direction::Down,
+                Direction::Left,
+                Direction::Right,
+            ][self.rng.gen_range(0, 4)],
+        };
+        self.session.log(Event::Rand(dir));
+        Ok(dir)
+    }
+
+    fn binop(&mut self, op: BinOp) {
+        self.stack.binop(op);
+    }
+
+    fn divop(&mut self, op: BinOp) -> Result<()> {
+        if self.stack.peek() == 0 {
+            self.stack.pop();
+            self.stack.pop();
+            match self.semantics.div_zero {
+                DivZero::Error => bail!("Division by zero"),
+                DivZero::Zero => self.stack.push(0),
+            }
+        } else {
+            self.stack.binop(op);
+        }
+        Ok(())
+    }
+
+    fn reverse(&mut self) {
+        self.dir = self.dir.reverse();
     }
 
     fn step(&mut self) -> Result<StepResult> {
         let cmd = self.playfield[self.pc.y][self.pc.x];
+        #[cfg(feature = "tracing")]
+        tracing::trace!(x = self.pc.x, y = self.pc.y, opcode = %cmd, stringmode = self.stringmode, "step");
+        self.profile[self.pc.y][self.pc.x] += 1;
+        self.coverage.executed(self.get_position());
+        if let Some(counter) = &mut self.stats {
+            counter.count(cmd, self.stringmode);
+        }
 
         if self.stringmode {
             if let Command::Str = cmd {
                 self.stringmode = false;
             } else {
-                self.stack.push((cmd.as_char() as u8).into());
+                self.stack.push(cmd.value());
+                self.check_overflow()?;
             }
 
             self.advance_pc();
             return Ok(StepResult::Cont);
         }
 
-        match cmd {
-            Command::Add => self.binop(|x, y| x + y),
-            Command::Sub => self.binop(|x, y| x - y),
-            Command::Mul => self.binop(|x, y| x * y),
-            Command::Div => self.binop(|x, y| x / y),
-            Command::Mod => self.binop(|x, y| x % y),
-            Command::Not => {
-                let x = self.stack.pop();
-                self.stack.push(if x == 0 { 1 } else { 0 });
-            }
-            Command::Gt => self.binop(|x, y| if x > y { 1 } else { 0 }),
-            Command::Right => self.dir = Direction::Right,
-            Command::Left => self.dir = Direction::Left,
-            Command::Up => self.dir = Direction::Up,
-            Command::Down => self.dir = Direction::Down,
-            Command::Rand => {
-                self.dir = [
-                    Direction::Up,
-                    Direction::Down,
-                    Direction::Left,
-                    Direction::Right,
-                ][self.rng.gen_range(0, 4)];
-            }
-            Command::IfH => {
-                let x = self.stack.pop();
-                self.dir = if x == 0 {
-                    Direction::Right
-                } else {
-                    Direction::Left
-                };
-            }
-            Command::IfV => {
-                let x = self.stack.pop();
-                self.dir = if x == 0 {
-                    Direction::Down
-                } else {
-                    Direction::Up
-                };
-            }
-            Command::Str => self.stringmode = !self.stringmode,
-            Command::Dup => self.stack.push(self.stack.peek()),
-            Command::Swap => {
-                let x = self.stack.pop();
-                let y = self.stack.pop();
-                self.stack.push(x);
-                self.stack.push(y);
-            }
-            Command::Pop => {
-                self.stack.pop();
-            }
-            Command::OutI => {
-                let x = self.stack.pop();
-                self.output += &format!("{} ", x);
-            }
-            Command::OutC => {
-                let x = self.stack.pop();
-                self.output += &format!("{}", x as u8 as char);
-            }
-            Command::InI => {
-                let mut stdin = io::stdin();
-                let mut buf = [0; 1];
-                let mut s = String::new();
-                loop {
-                    stdin.read_exact(&mut buf).context("Reading a byte")?;
-                    if buf[0] == b' ' {
-                        break;
-                    }
-                    s.push(buf[0] as char);
-                }
-                self.stack.push(
-                    s.parse()
-                        .with_context(|| anyhow!("Parsing '{}' into a number", s))?,
-                );
-            }
-            Command::InC => {
-                let mut stdin = io::stdin();
-                let mut buf = [0; 1];
-                stdin.read_exact(&mut buf).context("Reading a byte")?;
-                self.stack.push(buf[0].into());
-            }
-            Command::Bri => self.advance_pc(),
-            Command::Space => {}
-            Command::Num(n) => self.stack.push(n as StackTy),
-            Command::Char(c) => self.stack.push(c.to_digit(10).unwrap().into()),
-            Command::Get => {
-                let y = self.stack.pop() as usize;
-                let x = self.stack.pop() as usize;
-
-                if x >= PLAYFIELD_COLS {
-                    bail!("Invalid x coordinate for g command: {}", x);
-                } else if y >= PLAYFIELD_ROWS {
-                    bail!("Invalid y coordinate for g command: {}", y);
-                }
-
-                let cmd: char = self.playfield[y][x].into();
-                self.stack.push((cmd as u8).into());
-            }
-            Command::Put => {
-                let y = self.stack.pop() as usize;
-                let x = self.stack.pop() as usize;
-
-                if x >= PLAYFIELD_COLS {
-                    bail!("Invalid x coordinate for p command: {}", x);
-                } else if y >= PLAYFIELD_ROWS {
-                    bail!("Invalid y coordinate for p command: {}", y);
-                }
-
-                let val = self.stack.pop();
-                let val: u8 = val
-                    .try_into()
-                    .with_context(|| anyhow!("Failed to convert {} into u8", val))?;
-                self.playfield[y][x] = Command::from(val as char);
-            }
-            Command::End => return Ok(StepResult::Stop),
+        let result = match u8::try_from(cmd.as_char()) {
+            Ok(byte) => self.dispatch.handler(byte)(self, byte)?,
+            Err(_) => (self.dispatch.wide)(self, cmd.as_char())?,
         };
+        self.check_overflow()?;
+        self.check_output()?;
+        if result == StepResult::Stop {
+            return Ok(StepResult::Stop);
+        }
 
         self.advance_pc();
         Ok(StepResult::Cont)
     }
 
     fn advance_pc(&mut self) {
-        match self.dir {
-            Direction::Right => self.pc.right(),
-            Direction::Left => self.pc.left(),
-            Direction::Up => self.pc.up(),
-            Direction::Down => self.pc.down(),
+        self.pc.advance(self.dir, self.get_size());
+    }
+
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            playfield: self.playfield.clone(),
+            pc: self.pc.clone(),
+            dir: self.dir,
+            stack: self.stack.clone(),
+            stringmode: self.stringmode,
+            rng: self.rng.clone(),
+            output: self.output.clone(),
+            ring: self.ring.clone(),
+            session: self.session.clone(),
         }
     }
 
-    pub fn run(&mut self, f: impl Fn(&Self, usize) -> bool) -> Result<()> {
+    pub(crate) fn restore(&mut self, checkpoint: &Checkpoint) {
+        let checkpoint = checkpoint.clone();
+        self.playfield = checkpoint.playfield;
+        self.update_bounds();
+        self.pc = checkpoint.pc;
+        self.dir = checkpoint.dir;
+        self.stack = checkpoint.stack;
+        self.stringmode = checkpoint.stringmode;
+        self.rng = checkpoint.rng;
+        self.output = checkpoint.output;
+        self.ring = checkpoint.ring;
+        self.session = checkpoint.session;
+    }
+
+    /// Whether the execution state of `self` and `other` is the same.
+    pub(crate) fn same_state(&self, other: &Self) -> bool {
+        self.pc == other.pc
+            && self.dir == other.dir
+            && self.stringmode == other.stringmode
+            && self.stack == other.stack
+            && self.output == other.output
+            && self.get_output_seq() == other.get_output_seq()
+            && self.playfield == other.playfield
+    }
+
+    /// Prepare for a new run from the top-left corner, as `run` does: the
+    /// PC, stack, output, profile, step count and replayed input go back to
+    /// how they were at the start, and a byte left unread by `&` is
+    /// dropped. The playfield keeps any cells written by `p`.
+    pub fn reset(&mut self) {
         self.pc.reset();
+        self.dir = Direction::Right;
+        self.stringmode = false;
         self.stack.reset();
-        self.output.clear();
+        self.clear_output();
+        self.session.rewind();
+        self.unread = None;
+        if let Some(seed) = self.seed {
+            self.rng = SmallRng::seed_from_u64(seed);
+        }
+        self.profile.fill(0);
+        self.steps = 0;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.restart();
+        }
+        if let Some(log) = &mut self.modifications {
+            log.clear();
+        }
+        if let Some(counter) = &mut self.stats {
+            counter.clear();
+        }
+        if let Some(detector) = &mut self.loops {
+            detector.clear();
+        }
+        self.clear_violations();
+    }
 
-        let mut iter_n = 0;
+    /// Whether every step must go through `step_in_context`, so faster
+    /// ways of running can't be used.
+    pub(crate) fn needs_stepping(&self) -> bool {
+        self.tracer.is_some()
+            || self.modifications.is_some()
+            || self.stats.is_some()
+            || self.loops.is_some()
+            || !self.assertions.is_empty()
+            || self.dispatch.has_custom()
+            || self.dispatch.dialect != Dialect::Befunge93
+            || self.semantics.chars == Chars::Unicode
+            || self.semantics.cells != Cells::I64
+            || self.stack.get_limit().is_some()
+    }
 
-        while self
-            .step()
-            .with_context(|| anyhow!("Stepping at {:?}", self.pc))?
-            != StepResult::Stop
-        {
-            iter_n += 1;
-            if !f(self, iter_n) {
-                break;
-            }
+    /// Fail if the last step pushed past the limit of the stack, with
+    /// `Overflow::Error`.
+    fn check_overflow(&mut self) -> Result<()> {
+        if self.stack.take_overflow() {
+            let depth = self.stack.get_limit().map_or(0, |limit| limit.depth);
+            bail!("Stack overflow: more than {} values", depth);
         }
-
         Ok(())
     }
-}
 
-impl ToString for Interpreter {
-    fn to_string(&self) -> String {
-        let mid_line = String::from("\u{2500}").repeat(PLAYFIELD_COLS);
-
-        // Build top line
-        let mut line = String::from("\u{250C}");
-        line += &mid_line;
-        line.push('\u{2510}');
+    fn step_in_context(&mut self) -> Result<StepResult> {
+        self.last_put = None;
+        let cmd = self.get_current_command();
+        let before = self
+            .tracer
+            .as_ref()
+            .map(|t| (t.before(self), self.get_output_seq()));
+        let result = self
+            .check_stack()
+            .and_then(|_| self.step())
+            .and_then(|result| self.check_sinks().map(|_| result))
+            .with_context(|| anyhow!("Stepping at {}", self.get_position()))?;
+        self.steps += 1;
+        if let Some((event, output_seq)) = before {
+            let mut tracer = self.tracer.take().expect("tracing");
+            tracer.after(self, event, output_seq);
+            self.tracer = Some(tracer);
+        }
+        if let Some(mut log) = self.modifications.take() {
+            log.step(self);
+            self.modifications = Some(log);
+        }
+        if result == StepResult::Cont {
+            if let Some(mut detector) = self.loops.take() {
+                let looping = detector.step(self, cmd);
+                self.loops = Some(detector);
+                looping?;
+            }
+        }
+        Ok(result)
+    }
 
-        let mut s = Yellow.paint(&line).to_string();
-        s.push('\n');
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn run(&mut self, mut f: impl FnMut(&Self, usize) -> bool) -> Result<()> {
+        self.run_mut(|int, steps| f(int, steps))
+    }
 
-        for (row_idx, row) in self.playfield.iter().enumerate() {
-            s += &Yellow.paint("\u{2502}").to_string();
+    /// Like `run`, but `f` may change the interpreter between steps, as a
+    /// debugger poking cells does.
+    pub fn run_mut(&mut self, mut f: impl FnMut(&mut Self, usize) -> bool) -> Result<()> {
+        self.reset();
 
-            for (col_idx, cmd) in row.iter().enumerate() {
-                // Highlight current PC
-                if row_idx == self.pc.y && col_idx == self.pc.x {
-                    s += &Red.on(White).bold().paint(cmd.to_string()).to_string();
-                } else {
-                    s.push(cmd.as_char());
-                }
+        let mut iter_n = 0;
+        let result = loop {
+            match self.step_in_context() {
+                Ok(StepResult::Stop) => break Ok(()),
+                Ok(StepResult::Cont) => {}
+                Err(e) => break Err(e),
+            }
+            iter_n += 1;
+            if !f(self, iter_n) {
+                break Ok(());
             }
+        };
+        let flushed = self.flush_output_sinks();
+        result.and(flushed)
+    }
 
-            s += &Yellow.paint("\u{2502}\n").to_string();
-        }
+    /// Like `run_mut`, but stop cleanly once the run has taken longer than
+    /// `limit`, so that servers can bound how long programs run whatever
+    /// their number of steps. A read of input can't be stopped, so only
+    /// an input timeout bounds how long one waits.
+    pub fn run_with_deadline(
+        &mut self,
+        limit: Duration,
+        mut f: impl FnMut(&mut Self, usize) -> bool,
+    ) -> Result<Progress> {
+        let started = Instant::now();
+        let mut taken = 0;
+        let mut timed_out = false;
+        self.run_mut(|int, steps| {
+            taken = steps;
+            // Checking the clock at every step would slow down short steps.
+            timed_out = steps % DEADLINE_CHECK_STEPS == 0 && started.elapsed() > limit;
+            !timed_out && f(int, steps)
+        })?;
+        Ok(Progress {
+            steps: taken,
+            elapsed: started.elapsed(),
+            timed_out,
+        })
+    }
+}
 
-        // Build bottom line
-        let mut line = String::from("\u{2514}");
-        line += &mid_line;
-        line.push('\u{2518}');
+/// How far a run with a deadline got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Steps taken before the run ended or was stopped, not counting the
+    /// one ending the program.
+    pub steps: usize,
+    /// Time the run took.
+    pub elapsed: Duration,
+    /// Whether the run was stopped at the deadline.
+    pub timed_out: bool,
+}
 
-        s + &Yellow.paint(&line).to_string()
+impl fmt::Display for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = render::RenderOptions {
+            pc: true,
+            ..Default::default()
+        };
+        f.write_str(&self.render_terminal(&options))
     }
 }
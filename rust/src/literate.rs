@@ -0,0 +1,49 @@
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Prefix of comment lines declaring expected output.
+pub const EXPECT_PREFIX: &str = "expect:";
+
+/// Collect the output a program declares in its comments, if any.
+///
+/// Every comment line starting with `expect:` contributes the rest of the
+/// line, after one optional space, to the expected output. Backslash
+/// escapes `\n`, `\t`, `\r`, `\\` and `\xNN` are understood, so newlines
+/// and trailing spaces can be written explicitly.
+pub fn expected_output(comments: &[String]) -> Result<Option<String>> {
+    let mut expected = None;
+    for (idx, line) in comments.iter().enumerate() {
+        if let Some(rest) = line.strip_prefix(EXPECT_PREFIX) {
+            let rest = rest.strip_prefix(' ').unwrap_or(rest);
+            let text = unescape(rest)
+                .with_context(|| anyhow!("Parsing expected output on comment line {}", idx + 1))?;
+            expected.get_or_insert_with(String::new).push_str(&text);
+        }
+    }
+    Ok(expected)
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .with_context(|| anyhow!("Invalid escape '\\x{}'", hex))?;
+                out.push(byte as char);
+            }
+            Some(c) => bail!("Invalid escape '\\{}'", c),
+            None => bail!("Dangling backslash"),
+        }
+    }
+    Ok(out)
+}
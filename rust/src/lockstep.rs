@@ -0,0 +1,98 @@
+//! Running two interpreters on the same program one step at a time each,
+//! to find where two configurations or implementations part ways.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::bisect::{Outcome, Status};
+use crate::runner::Snapshot;
+use crate::{Interpreter, StepResult};
+
+/// What differs between the two sides after a step, checked in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aspect {
+    /// One side halted or failed and the other didn't, or they failed
+    /// differently.
+    Status,
+    /// The position or direction of the PC.
+    Pc,
+    Stack,
+    /// The output printed by the step.
+    Output,
+}
+
+/// The first step after which two interpreters stepped in lockstep
+/// disagree.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Mismatch {
+    /// Number of the step, starting from one.
+    pub step: usize,
+    pub aspect: Aspect,
+    pub left: Outcome,
+    pub right: Outcome,
+}
+
+/// Run `left` and `right` from the start of their programs, one step each
+/// at a time, and report the first step after which their PC, stack or
+/// output differ. `None` if they agree until both end, or for `max_steps`
+/// steps.
+///
+/// Unlike `bisect::bisect`, nothing is rewound, so each side may read
+/// input of its own, as long as both get the same.
+pub fn lockstep(
+    left: &mut Interpreter,
+    right: &mut Interpreter,
+    max_steps: usize,
+) -> Option<Mismatch> {
+    left.reset();
+    right.reset();
+
+    for step in 1..=max_steps {
+        let (left_status, left_output) = step_once(left);
+        let (right_status, right_output) = step_once(right);
+
+        let aspect = if left_status != right_status {
+            Some(Aspect::Status)
+        } else if left.get_position() != right.get_position()
+            || left.get_direction() != right.get_direction()
+        {
+            Some(Aspect::Pc)
+        } else if !left.stack.same_values(&right.stack) {
+            Some(Aspect::Stack)
+        } else if left_output != right_output {
+            Some(Aspect::Output)
+        } else {
+            None
+        };
+        if let Some(aspect) = aspect {
+            return Some(Mismatch {
+                step,
+                aspect,
+                left: Outcome {
+                    status: left_status,
+                    state: Snapshot::of(left, step, false),
+                },
+                right: Outcome {
+                    status: right_status,
+                    state: Snapshot::of(right, step, false),
+                },
+            });
+        }
+        if left_status != Status::Running {
+            return None;
+        }
+    }
+    None
+}
+
+/// Execute a step of `int`, returning how it went and what it printed.
+fn step_once(int: &mut Interpreter) -> (Status, String) {
+    let seq = int.get_output_seq();
+    let status = match int.step_in_context() {
+        Ok(StepResult::Cont) => Status::Running,
+        Ok(StepResult::Stop) => Status::Halted,
+        Err(e) => Status::Failed(format!("{:#}", e)),
+    };
+    (status, int.output_since(seq).text)
+}
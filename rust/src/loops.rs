@@ -0,0 +1,103 @@
+//! Spotting runs that can never end because the interpreter came back to a
+//! state it was in before, with nothing left to chance or input in
+//! between.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{bail, Result};
+
+use crate::{Command, Direction, Interpreter, Position, Stack};
+
+/// The state of an interpreter between two steps.
+#[derive(Clone, PartialEq)]
+struct State {
+    pc: Position,
+    dir: Direction,
+    stringmode: bool,
+    stack: Stack,
+    /// A hash of the playfield, which is too large to keep a copy of.
+    playfield: u64,
+}
+
+/// Compares the state after each step with one saved at a growing
+/// distance behind it, finding any cycle within about twice its length
+/// past the point the run entered it, as in Brent's algorithm.
+#[derive(Clone, Default)]
+pub(crate) struct Detector {
+    saved: Option<State>,
+    /// Step of the run the state was saved at.
+    saved_at: usize,
+    /// Steps taken in the current run.
+    steps: usize,
+    /// Steps before saving the state again.
+    window: usize,
+}
+
+impl Detector {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Look at the state of `int` after a step that executed `cmd`,
+    /// failing if the run can never end.
+    pub(crate) fn step(&mut self, int: &Interpreter, cmd: Command) -> Result<()> {
+        self.steps += 1;
+        // What `?`, `&` and `~` do next may differ even from the same state.
+        if !int.stringmode && matches!(cmd, Command::Rand | Command::InI | Command::InC) {
+            self.saved = None;
+        }
+
+        match &self.saved {
+            Some(saved) if matches(saved, int) => bail!(
+                "Non-terminating loop detected: the state at step {} repeats every {} steps",
+                self.saved_at,
+                self.steps - self.saved_at
+            ),
+            Some(_) if self.steps - self.saved_at < self.window => {}
+            _ => {
+                self.window = (self.window * 2).max(1);
+                self.saved_at = self.steps;
+                self.saved = Some(State {
+                    pc: int.get_position(),
+                    dir: int.dir,
+                    stringmode: int.stringmode,
+                    stack: int.stack.clone(),
+                    playfield: playfield_hash(int),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `int` is in the `saved` state, comparing the cheapest parts
+/// first.
+fn matches(saved: &State, int: &Interpreter) -> bool {
+    saved.pc == int.get_position()
+        && saved.dir == int.dir
+        && saved.stringmode == int.stringmode
+        && saved.stack.len() == int.stack.len()
+        && saved.stack == int.stack
+        && saved.playfield == playfield_hash(int)
+}
+
+fn playfield_hash(int: &Interpreter) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for row in &int.playfield {
+        for cmd in row {
+            cmd.as_char().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+impl Interpreter {
+    /// Fail runs that come back to an earlier state, of the PC, its
+    /// direction, string mode, the stack and the playfield, with no `?` or
+    /// input read since, as they can never end. Off with `false`. Runs are
+    /// then stepped one cell at a time.
+    pub fn detect_loops(&mut self, detect: bool) {
+        self.loops = detect.then(Detector::default);
+    }
+}
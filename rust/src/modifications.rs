@@ -0,0 +1,70 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Command, Interpreter, Position};
+
+/// A cell written by `p`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Modification {
+    /// Number of the step writing the cell, starting from one.
+    pub step: usize,
+    /// The cell written.
+    pub pos: Position,
+    /// The command in the cell before the write.
+    pub old: Command,
+    /// The command written.
+    pub new: Command,
+}
+
+/// The cells written by `p` in the current run.
+#[derive(Clone, Default)]
+pub(crate) struct Log {
+    /// Steps taken in the current run.
+    steps: usize,
+    writes: Vec<Modification>,
+}
+
+impl Log {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Count a step, noting the cell it wrote, if any, in `int`.
+    pub(crate) fn step(&mut self, int: &Interpreter) {
+        self.steps += 1;
+        self.writes.extend(int.last_modification(self.steps));
+    }
+}
+
+impl Interpreter {
+    /// Log every cell written by `p` in subsequent runs, or stop logging
+    /// with `false`. Runs are then stepped one cell at a time.
+    pub fn track_modifications(&mut self, track: bool) {
+        self.modifications = track.then(Log::default);
+    }
+
+    /// The cells written by `p` in the current run, oldest first. Empty
+    /// unless tracking them with `track_modifications`.
+    pub fn get_modifications(&self) -> &[Modification] {
+        self.modifications
+            .as_ref()
+            .map_or(&[], |log| log.writes.as_slice())
+    }
+
+    /// Write `cmd` to the cell at `pos` for `p`, noting the write for the
+    /// modification log and trace events.
+    pub(crate) fn put_tracked(&mut self, pos: Position, cmd: Command) {
+        self.last_put = Some((pos, self.playfield[pos.y][pos.x]));
+        self.put(pos, cmd);
+    }
+
+    /// The write of the last step, if it was made by `p`, numbered `step`.
+    pub(crate) fn last_modification(&self, step: usize) -> Option<Modification> {
+        self.last_put.map(|(pos, old)| Modification {
+            step,
+            pos,
+            old,
+            new: self.playfield[pos.y][pos.x],
+        })
+    }
+}
@@ -0,0 +1,142 @@
+//! Rewrites of the playfield that keep the behaviour of a program while
+//! leaving less for the PC to do, based on the states found by static
+//! analysis.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::analysis::{Graph, State, STATES};
+use crate::{Command, Interpreter, Position};
+
+/// A cell changed by the optimizer.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Change {
+    pub pos: Position,
+    /// The command that was there.
+    pub from: char,
+    /// The command that is there now.
+    pub to: char,
+    /// Which rewrite made the change.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} -> {:?} ({})",
+            self.pos, self.from, self.to, self.reason
+        )
+    }
+}
+
+impl Interpreter {
+    /// Rewrite the playfield of the loaded program without changing what
+    /// it does, returning the changed cells:
+    ///
+    /// - cells the PC can never reach are cleared,
+    /// - `#` only ever jumping over a space becomes a space,
+    /// - a digit, a digit and `+`, `-` or `*` run straight through are
+    ///   folded into a single digit when the result is one.
+    ///
+    /// Programs that can execute `g` or `p` are refused, as any cell may be
+    /// data to them.
+    pub fn optimize(&mut self) -> Result<Vec<Change>> {
+        let graph = self.explore();
+        let touches_playfield = (0..STATES)
+            .filter(|&idx| graph.reached[idx])
+            .map(State::from_index)
+            .any(|s| {
+                !s.stringmode && matches!(self.playfield[s.y][s.x], Command::Get | Command::Put)
+            });
+        if touches_playfield {
+            bail!("Programs using 'g' or 'p' can't be optimized safely");
+        }
+
+        let mut changes = vec![];
+        let size = self.get_size();
+        for y in 0..size.rows {
+            for x in 0..size.cols {
+                let pos = Position { x, y };
+                let cmd = self.playfield[y][x];
+                if cmd == Command::Space {
+                    continue;
+                }
+                let mut states = graph.states_at(pos).peekable();
+                if states.peek().is_none() {
+                    changes.push(self.rewrite(pos, Command::Space, "unreachable"));
+                } else if cmd == Command::Bri
+                    && states.all(|s| {
+                        let skipped = s.leave(s.dir, false, 0, size);
+                        !s.stringmode && self.playfield[skipped.y][skipped.x] == Command::Space
+                    })
+                {
+                    changes.push(self.rewrite(pos, Command::Space, "jump over a space"));
+                } else if let Some(folded) = self.fold(&graph, pos) {
+                    changes.extend(folded);
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Fold the constant expression starting at `pos`, if it is one.
+    fn fold(&mut self, graph: &Graph, pos: Position) -> Option<Vec<Change>> {
+        let first = only_state(graph, pos)?;
+        let size = self.get_size();
+        let second = first.leave(first.dir, false, 0, size);
+        let third = second.leave(first.dir, false, 0, size);
+        for (prev, next) in [(first, second), (second, third)] {
+            let pos = next.pos();
+            if only_state(graph, pos) != Some(next)
+                || graph.predecessors[next.index()] != [prev.index()]
+            {
+                return None;
+            }
+        }
+
+        let cmds = [first, second, third].map(|s| self.playfield[s.y][s.x]);
+        let value = match cmds {
+            [Command::Num(a), Command::Num(b), op] => match op {
+                Command::Add => a.checked_add(b)?,
+                Command::Sub => a.checked_sub(b)?,
+                Command::Mul => a.checked_mul(b)?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if value > 9 {
+            return None;
+        }
+
+        Some(vec![
+            self.rewrite(first.pos(), Command::Num(value), "constant folding"),
+            self.rewrite(second.pos(), Command::Space, "constant folding"),
+            self.rewrite(third.pos(), Command::Space, "constant folding"),
+        ])
+    }
+
+    fn rewrite(&mut self, pos: Position, to: Command, reason: &'static str) -> Change {
+        let from = self.playfield[pos.y][pos.x];
+        self.playfield[pos.y][pos.x] = to;
+        Change {
+            pos,
+            from: from.as_char(),
+            to: to.as_char(),
+            reason,
+        }
+    }
+}
+
+/// The state the PC is always in at `pos`, if the cell is only ever run
+/// one way outside string mode.
+fn only_state(graph: &Graph, pos: Position) -> Option<State> {
+    let mut states = graph.states_at(pos);
+    match (states.next(), states.next()) {
+        (Some(state), None) if !state.stringmode => Some(state),
+        _ => None,
+    }
+}
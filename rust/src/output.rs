@@ -0,0 +1,293 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+
+use crate::semantics::Choice;
+use crate::Interpreter;
+
+/// What printing past the limit of the output kept in memory does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputOverflow {
+    /// Drop the oldest output, keeping the most recent.
+    DropOldest,
+    /// Abort the run.
+    Error,
+    /// Stop keeping output, which only goes to the sinks from then on.
+    Stream,
+}
+
+impl Choice for OutputOverflow {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("drop-oldest", Self::DropOldest),
+        ("error", Self::Error),
+        ("stream", Self::Stream),
+    ];
+}
+
+/// How many bytes of output are kept in memory, and what printing more
+/// does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputLimit {
+    pub bytes: usize,
+    pub overflow: OutputOverflow,
+}
+
+/// Output produced since a sequence number, as returned by
+/// `Interpreter::output_since`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChunk {
+    /// Sequence number of the first byte of `text`.
+    pub seq: u64,
+    /// The output still available from the requested sequence number on.
+    pub text: String,
+    /// Sequence number to ask for next time.
+    pub next: u64,
+    /// Bytes between the requested sequence number and `seq` that were
+    /// already dropped from the buffer.
+    pub dropped: u64,
+}
+
+/// A bounded buffer keeping only the most recent output. Every byte ever
+/// written has a sequence number, so consumers can poll for what is new.
+#[derive(Debug, Clone)]
+pub struct OutputRing {
+    /// The output kept, from byte `start` on; bytes before it were dropped
+    /// and are removed once they outgrow the rest.
+    buf: String,
+    start: usize,
+    capacity: usize,
+    /// Sequence number of the next byte written.
+    end: u64,
+}
+
+impl OutputRing {
+    /// Keep at most `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buf: String::with_capacity(capacity),
+            start: 0,
+            capacity,
+            end: 0,
+        }
+    }
+
+    /// Keep nothing, only counting bytes from `end` on.
+    fn counting(end: u64) -> Self {
+        Self {
+            end,
+            ..Self::new(0)
+        }
+    }
+
+    /// Append `text`, dropping the oldest output beyond the capacity. Only
+    /// whole characters are dropped.
+    pub fn push(&mut self, text: &str) {
+        self.end += text.len() as u64;
+        self.buf.push_str(text);
+        if self.buf.len() - self.start > self.capacity {
+            let mut start = self.buf.len() - self.capacity;
+            while !self.buf.is_char_boundary(start) {
+                start += 1;
+            }
+            self.start = start;
+            if self.start >= self.buf.len() - self.start {
+                self.buf.drain(..self.start);
+                self.start = 0;
+            }
+        }
+    }
+
+    /// The output held.
+    pub fn as_str(&self) -> &str {
+        &self.buf[self.start..]
+    }
+
+    /// Sequence number of the oldest byte still held.
+    pub fn start(&self) -> u64 {
+        self.end - self.as_str().len() as u64
+    }
+
+    /// Sequence number of the next byte written.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// The output held from sequence number `seq` on.
+    pub fn since(&self, seq: u64) -> OutputChunk {
+        let first = seq.clamp(self.start(), self.end);
+        let skip = (first - self.start()) as usize;
+        OutputChunk {
+            seq: first,
+            text: String::from_utf8_lossy(&self.as_str().as_bytes()[skip..]).into_owned(),
+            next: self.end,
+            dropped: first.saturating_sub(seq),
+        }
+    }
+}
+
+/// An output sink keeping everything written to it in memory, to be read
+/// while the interpreter still owns a clone of it.
+#[derive(Debug, Clone, Default)]
+pub struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn text(&self) -> String {
+        let bytes = self.0.lock().expect("capture lock");
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().expect("capture lock").extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Interpreter {
+    /// Also write output to `sink` as it is printed, along with every sink
+    /// added before, e.g. the terminal, a file and a `Capture`. Output is
+    /// still kept for `get_output` and `output_since`. Sinks are flushed
+    /// before input is read and when a run ends, and a sink failing to
+    /// write fails the run.
+    pub fn add_output_sink(&mut self, sink: impl Write + Send + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Flush and remove every sink added with `add_output_sink`.
+    pub fn clear_output_sinks(&mut self) -> Result<()> {
+        let flushed = self.flush_output_sinks();
+        self.sinks.clear();
+        flushed
+    }
+
+    /// Flush every sink added with `add_output_sink`.
+    pub fn flush_output_sinks(&mut self) -> Result<()> {
+        for sink in &mut self.sinks {
+            sink.flush().context("Failed to write the output")?;
+        }
+        Ok(())
+    }
+
+    /// Fail if writing to a sink failed since the last call.
+    pub(crate) fn check_sinks(&mut self) -> Result<()> {
+        match self.sink_error.take() {
+            Some(e) => Err(e).context("Failed to write the output"),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep only the last `capacity` bytes of output, or go back to
+    /// retaining all of it with `None`.
+    pub fn set_output_ring(&mut self, capacity: Option<usize>) {
+        self.set_output_limit(capacity.map(|bytes| OutputLimit {
+            bytes,
+            overflow: OutputOverflow::DropOldest,
+        }));
+    }
+
+    /// Keep at most `limit.bytes` of output in memory, doing what
+    /// `limit.overflow` says with more, or all of it with `None`.
+    pub fn set_output_limit(&mut self, limit: Option<OutputLimit>) {
+        self.output_limit = limit;
+        self.ring = match limit {
+            Some(OutputLimit {
+                bytes,
+                overflow: OutputOverflow::DropOldest,
+            }) => Some(OutputRing::new(bytes)),
+            _ => None,
+        };
+    }
+
+    /// Get the limit of the output kept in memory, if any.
+    pub fn get_output_limit(&self) -> Option<OutputLimit> {
+        self.output_limit
+    }
+
+    /// How many bytes were output in the current run, including those no
+    /// longer kept.
+    pub fn get_bytes_written(&self) -> u64 {
+        self.get_output_seq()
+    }
+
+    /// Sequence number of the next byte of output, i.e. how many bytes were
+    /// output in the current run.
+    pub fn get_output_seq(&self) -> u64 {
+        match &self.ring {
+            Some(ring) => ring.end(),
+            None => self.output.len() as u64,
+        }
+    }
+
+    /// The output from sequence number `seq` on, as far as it is still held.
+    pub fn output_since(&self, seq: u64) -> OutputChunk {
+        match &self.ring {
+            Some(ring) => ring.since(seq),
+            None => {
+                let end = self.output.len() as u64;
+                let first = seq.min(end);
+                OutputChunk {
+                    seq: first,
+                    text: String::from_utf8_lossy(&self.output.as_bytes()[first as usize..])
+                        .into_owned(),
+                    next: end,
+                    dropped: 0,
+                }
+            }
+        }
+    }
+
+    pub(crate) fn write_output(&mut self, text: &str) {
+        if let Some(limit) = self.output_limit {
+            if self.ring.is_none() && self.output.len() + text.len() > limit.bytes {
+                match limit.overflow {
+                    OutputOverflow::Error => {
+                        self.output_overflowed = true;
+                        return;
+                    }
+                    OutputOverflow::Stream => {
+                        self.ring = Some(OutputRing::counting(self.output.len() as u64));
+                        self.output = String::new();
+                    }
+                    OutputOverflow::DropOldest => unreachable!("dropping output uses a ring"),
+                }
+            }
+        }
+        match &mut self.ring {
+            Some(ring) => ring.push(text),
+            None => self.output += text,
+        }
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write_all(text.as_bytes()) {
+                self.sink_error.get_or_insert(e);
+            }
+        }
+    }
+
+    /// Fail if output was printed past the limit with
+    /// `OutputOverflow::Error` since the last call.
+    pub(crate) fn check_output(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.output_overflowed) {
+            let bytes = self.output_limit.map_or(0, |limit| limit.bytes);
+            bail!("Output overflow: more than {} bytes", bytes);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn clear_output(&mut self) {
+        self.output.clear();
+        self.output_overflowed = false;
+        // Output is kept again after streaming.
+        self.set_output_limit(self.output_limit);
+    }
+}
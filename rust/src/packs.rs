@@ -0,0 +1,129 @@
+//! Opt-in packs of novelty instructions, added to an interpreter with
+//! `Interpreter::define_instruction`. Nothing changes for programs unless
+//! a pack is installed.
+
+use anyhow::{bail, Result};
+
+use crate::dispatch::Instruction;
+use crate::Interpreter;
+
+/// An instruction of a pack.
+#[derive(Debug, Clone, Copy)]
+pub struct PackInstruction {
+    pub c: char,
+    pub name: &'static str,
+    /// What visualizers may show when the instruction runs.
+    pub emoji: &'static str,
+    pub description: &'static str,
+    pub run: Instruction,
+}
+
+/// A set of instructions installed together.
+#[derive(Debug, Clone, Copy)]
+pub struct Pack {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub instructions: &'static [PackInstruction],
+}
+
+impl Pack {
+    /// Define every instruction of the pack on `int`.
+    pub fn install(&self, int: &mut Interpreter) -> Result<()> {
+        for instruction in self.instructions {
+            int.define_instruction(instruction.c, instruction.run)?;
+        }
+        Ok(())
+    }
+
+    /// The instruction of the pack written `c`, if there is one.
+    pub fn lookup(&self, c: char) -> Option<&'static PackInstruction> {
+        self.instructions.iter().find(|i| i.c == c)
+    }
+}
+
+/// The Konami code, as pushed by `"^^vv<><>ba"`.
+const KONAMI_CODE: &[u8] = b"^^vv<><>ba";
+
+fn konami(int: &mut Interpreter) -> Result<()> {
+    let mut code: Vec<_> = (0..KONAMI_CODE.len()).map(|_| int.pop()).collect();
+    code.reverse();
+    if code
+        .iter()
+        .zip(KONAMI_CODE)
+        .all(|(&v, &b)| v == i64::from(b))
+    {
+        int.print("\u{1f3ae} 30 lives!\n");
+        int.push(30);
+    } else {
+        int.push(0);
+    }
+    Ok(())
+}
+
+/// Every pack.
+pub const PACKS: &[Pack] = &[
+    Pack {
+        name: "party",
+        description: "Celebrate in the output",
+        instructions: &[
+            PackInstruction {
+                c: 'h',
+                name: "heart",
+                emoji: "\u{2764}\u{fe0f}",
+                description: "Print a heart.",
+                run: |int| {
+                    int.print("\u{2764}\u{fe0f}");
+                    Ok(())
+                },
+            },
+            PackInstruction {
+                c: 'c',
+                name: "confetti",
+                emoji: "\u{1f389}",
+                description: "Print confetti.",
+                run: |int| {
+                    int.print("\u{1f389}");
+                    Ok(())
+                },
+            },
+            PackInstruction {
+                c: 's',
+                name: "sparkles",
+                emoji: "\u{2728}",
+                description: "Print as many sparkles as the popped value, up to 10.",
+                run: |int| {
+                    let n = int.pop().clamp(0, 10) as usize;
+                    int.print(&"\u{2728}".repeat(n));
+                    Ok(())
+                },
+            },
+        ],
+    },
+    Pack {
+        name: "konami",
+        description: "A hidden reward for those who know the code",
+        instructions: &[PackInstruction {
+            c: 'K',
+            name: "konami",
+            emoji: "\u{1f3ae}",
+            description: "Pop ten values; if, deepest first, they spell `^^vv<><>ba`, print a \
+                          reward and push 30, otherwise push 0.",
+            run: konami,
+        }],
+    },
+];
+
+/// The pack called `name`.
+pub fn find(name: &str) -> Result<&'static Pack> {
+    match PACKS.iter().find(|pack| pack.name == name) {
+        Some(pack) => Ok(pack),
+        None => {
+            let names: Vec<_> = PACKS.iter().map(|pack| pack.name).collect();
+            bail!(
+                "Unknown pack '{}', expected one of: {}",
+                name,
+                names.join(", ")
+            )
+        }
+    }
+}
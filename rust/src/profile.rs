@@ -0,0 +1,149 @@
+//! Execution counts saved to files, so that the profiles of many runs, such
+//! as those of a whole test suite, can be added up and viewed as one.
+
+use std::cmp::Reverse;
+
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{Dialect, DRAFT_PLAYFIELD};
+use crate::schema::Versioned;
+use crate::{Bounds, Interpreter, Position, Profile};
+
+/// How many times a cell was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CellCount {
+    pub x: usize,
+    pub y: usize,
+    pub count: u64,
+}
+
+/// The largest playfield a saved profile can be of, that of any dialect.
+const LARGEST: Bounds = DRAFT_PLAYFIELD;
+
+/// The execution counts of one or more runs of a program.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SavedProfile {
+    /// The rows of the playfield up to the last non-empty one, so that
+    /// profiles of different programs are never added up.
+    pub playfield: Vec<String>,
+    /// Number of runs counted.
+    pub runs: u64,
+    /// Cells executed at least once, row by row.
+    pub cells: Vec<CellCount>,
+}
+
+impl SavedProfile {
+    /// Add the counts of `other`, which must be of the same program.
+    pub fn merge(&mut self, other: &Self) -> Result<()> {
+        if self.playfield != other.playfield {
+            bail!("Profiles are of different programs");
+        }
+        let mut counts = self.counts();
+        for cell in &other.cells {
+            counts[cell.y][cell.x] += cell.count;
+        }
+        self.cells = cells(&counts);
+        self.runs += other.runs;
+        Ok(())
+    }
+
+    /// The counts as a grid, indexed as `[y][x]`, covering the saved
+    /// playfield and at least the Befunge-93 one.
+    pub fn counts(&self) -> Profile {
+        let mut size = Dialect::Befunge93.playfield();
+        for (y, row) in self.playfield.iter().enumerate() {
+            size.cols = size.cols.max(row.chars().count());
+            size.rows = size.rows.max(y + 1);
+        }
+        for cell in &self.cells {
+            size.cols = size.cols.max(cell.x + 1);
+            size.rows = size.rows.max(cell.y + 1);
+        }
+        let mut counts = Profile::new(size, 0);
+        for cell in &self.cells {
+            counts[cell.y][cell.x] = cell.count;
+        }
+        counts
+    }
+
+    /// Number of steps over all runs.
+    pub fn steps(&self) -> u64 {
+        self.cells.iter().map(|c| c.count).sum()
+    }
+
+    /// The `n` most executed cells, most executed first.
+    pub fn hottest(&self, n: usize) -> Vec<(Position, u64)> {
+        let mut cells: Vec<_> = self
+            .cells
+            .iter()
+            .map(|c| (Position { x: c.x, y: c.y }, c.count))
+            .collect();
+        cells.sort_by_key(|&(_, count)| Reverse(count));
+        cells.truncate(n);
+        cells
+    }
+
+    /// Serialize as JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Versioned::new(self)).expect("profiles are always serializable")
+    }
+
+    /// Parse a JSON profile.
+    pub fn from_json(s: &str) -> Result<Self> {
+        let profile = serde_json::from_str::<Versioned<Self>>(s)
+            .context("Parsing profile")?
+            .into_data()?;
+        if let Some(cell) = profile
+            .cells
+            .iter()
+            .find(|c| c.x >= LARGEST.cols || c.y >= LARGEST.rows)
+        {
+            bail!("Cell ({}, {}) is outside the playfield", cell.x, cell.y);
+        }
+        Ok(profile)
+    }
+}
+
+/// Which of `levels` levels of heat a cell executed `count` times is at,
+/// when the most executed one was executed `max` times, on a logarithmic
+/// scale from 0 up. Cells never executed are at none.
+pub fn heat_level(count: u64, max: u64, levels: usize) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    let scale = ((max as f64).ln() + 1.0) / levels as f64;
+    let level = (((count as f64).ln() + 1.0) / scale) as usize;
+    Some(level.min(levels - 1))
+}
+
+fn cells(counts: &Profile) -> Vec<CellCount> {
+    let mut cells = vec![];
+    for (y, row) in counts.iter().enumerate() {
+        for (x, &count) in row.iter().enumerate() {
+            if count > 0 {
+                cells.push(CellCount { x, y, count });
+            }
+        }
+    }
+    cells
+}
+
+impl Interpreter {
+    /// Save the execution counts of the current run, see `get_profile`.
+    /// The playfield saved is the one as it is now, which is not the loaded
+    /// program if it modified itself.
+    pub fn save_profile(&self) -> SavedProfile {
+        let rows = self.get_size().rows;
+        let mut playfield: Vec<_> = (0..rows).map(|y| self.get_row(y)).collect();
+        while playfield.last().is_some_and(|row| row.is_empty()) {
+            playfield.pop();
+        }
+        SavedProfile {
+            playfield,
+            runs: 1,
+            cells: cells(&self.profile),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Predicates over trace events, e.g. `cmd == "p" && x > 40`.
+//!
+//! A query compares step fields with literals using `==`, `!=`, `<`, `<=`,
+//! `>` and `>=`, and combines comparisons with `&&`, `||`, `!` and
+//! parentheses. Numeric fields are `step`, `x`, `y`, `depth` (stack size
+//! after the step) and `top` (top of the stack after the step); text fields,
+//! compared with `==` and `!=` only, are `cmd`, `dir` and `output`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::trace::TraceEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Step,
+    X,
+    Y,
+    Depth,
+    Top,
+    Cmd,
+    Dir,
+    Output,
+}
+
+impl Field {
+    const NAMES: &'static [(&'static str, Self)] = &[
+        ("step", Self::Step),
+        ("x", Self::X),
+        ("y", Self::Y),
+        ("depth", Self::Depth),
+        ("top", Self::Top),
+        ("cmd", Self::Cmd),
+        ("dir", Self::Dir),
+        ("output", Self::Output),
+    ];
+
+    fn is_text(self) -> bool {
+        matches!(self, Self::Cmd | Self::Dir | Self::Output)
+    }
+
+    fn value(self, event: &TraceEvent) -> Value {
+        match self {
+            Self::Step => Value::Int(event.step as i64),
+            Self::X => Value::Int(event.pc.x as i64),
+            Self::Y => Value::Int(event.pc.y as i64),
+            Self::Depth => Value::Int(event.stack_after.len() as i64),
+            Self::Top => Value::Int(event.stack_after.last().copied().unwrap_or(0)),
+            Self::Cmd => Value::Text(event.command.as_char().to_string()),
+            Self::Dir => Value::Text(event.dir.to_string()),
+            Self::Output => Value::Text(event.output_delta.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Cmp(Field, Op, Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, event: &TraceEvent) -> bool {
+        match self {
+            Self::Cmp(field, op, rhs) => {
+                let lhs = field.value(event);
+                let ord = match (&lhs, rhs) {
+                    (Value::Int(l), Value::Int(r)) => l.cmp(r),
+                    (Value::Text(l), Value::Text(r)) => l.cmp(r),
+                    _ => unreachable!("comparisons are type checked when parsed"),
+                };
+                match op {
+                    Op::Eq => ord.is_eq(),
+                    Op::Ne => ord.is_ne(),
+                    Op::Lt => ord.is_lt(),
+                    Op::Le => ord.is_le(),
+                    Op::Gt => ord.is_gt(),
+                    Op::Ge => ord.is_ge(),
+                }
+            }
+            Self::Not(e) => !e.eval(event),
+            Self::And(l, r) => l.eval(event) && r.eval(event),
+            Self::Or(l, r) => l.eval(event) || r.eval(event),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "'{}'", name),
+            Self::Int(n) => write!(f, "'{}'", n),
+            Self::Text(s) => write!(f, "{:?}", s),
+            Self::Op(op) => {
+                let op = match op {
+                    Op::Eq => "==",
+                    Op::Ne => "!=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                };
+                write!(f, "'{}'", op)
+            }
+            Self::And => write!(f, "'&&'"),
+            Self::Or => write!(f, "'||'"),
+            Self::Not => write!(f, "'!'"),
+            Self::Open => write!(f, "'('"),
+            Self::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some(c) => text.push(c),
+                            None => bail!("Unterminated string"),
+                        },
+                        Some(c) => text.push(c),
+                        None => bail!("Unterminated string"),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut num = c.to_string();
+                while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    num.push(d);
+                }
+                Token::Int(
+                    num.parse()
+                        .with_context(|| anyhow!("Invalid number '{}'", num))?,
+                )
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(d) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    ident.push(d);
+                }
+                Token::Ident(ident)
+            }
+            c => bail!("Unexpected character '{}'", c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => bail!("Expected ')'"),
+                }
+            }
+            Some(Token::Ident(name)) => self.comparison(&name),
+            Some(token) => bail!("Expected a field name, got {}", token),
+            None => bail!("Expected a field name, got the end of the query"),
+        }
+    }
+
+    fn comparison(&mut self, name: &str) -> Result<Expr> {
+        let field = Field::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, f)| *f)
+            .ok_or_else(|| {
+                let names: Vec<_> = Field::NAMES.iter().map(|(n, _)| *n).collect();
+                anyhow!(
+                    "Unknown field '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => bail!("Expected a comparison after '{}'", name),
+        };
+        let value = match self.next() {
+            Some(Token::Int(n)) if !field.is_text() => Value::Int(n),
+            Some(Token::Text(s)) if field.is_text() => Value::Text(s),
+            Some(Token::Int(_)) => bail!("'{}' is text and must be compared with a string", name),
+            Some(Token::Text(_)) => bail!("'{}' is a number and must be compared with one", name),
+            _ => bail!("Expected a value to compare '{}' with", name),
+        };
+        if field.is_text() && !matches!(op, Op::Eq | Op::Ne) {
+            bail!("'{}' can only be compared with == and !=", name);
+        }
+        Ok(Expr::Cmp(field, op, value))
+    }
+}
+
+/// A parsed predicate over trace events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query(Expr);
+
+impl Query {
+    /// Whether `event` satisfies the query.
+    pub fn matches(&self, event: &TraceEvent) -> bool {
+        self.0.eval(event)
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {} after the end of the query", token);
+        }
+        Ok(Self(expr))
+    }
+}
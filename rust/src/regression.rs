@@ -0,0 +1,101 @@
+//! Regression suites of example programs: each program sits next to a
+//! `.expected` file holding what it should print and, if it reads any, a
+//! `.in` file holding its input. Programs are run as judge submissions,
+//! within limits, and their output compared with the expected one.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::corpus;
+use crate::format;
+use crate::judge::{self, JudgeLimits, JudgeReport, Verdict};
+
+/// A program of a suite and the files next to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub program: PathBuf,
+    /// The input, which may not exist.
+    pub input: PathBuf,
+    /// The expected output, which may not exist.
+    pub expected: PathBuf,
+}
+
+impl Case {
+    /// The case of the program at `program`, whether or not the files next
+    /// to it exist.
+    pub fn new(program: PathBuf) -> Self {
+        Self {
+            input: program.with_extension("in"),
+            expected: program.with_extension("expected"),
+            program,
+        }
+    }
+
+    fn read_input(&self) -> Result<Vec<u8>> {
+        if !self.input.exists() {
+            return Ok(vec![]);
+        }
+        fs::read(&self.input).with_context(|| anyhow!("Failed to read '{}'", self.input.display()))
+    }
+}
+
+/// The result of running a case.
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub case: Case,
+    pub report: JudgeReport,
+    /// How the output differs from the expected one, as hunks of expected
+    /// lines removed and printed lines added, when it does.
+    pub diff: Option<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.report.verdict == Verdict::Accepted
+    }
+}
+
+/// The cases of the programs in `dir` that have an expected output, sorted,
+/// including those in subdirectories if `recursive`.
+pub fn discover(dir: &Path, recursive: bool) -> Result<Vec<Case>> {
+    Ok(corpus::find_programs(dir, recursive)?
+        .into_iter()
+        .map(Case::new)
+        .filter(|case| case.expected.exists())
+        .collect())
+}
+
+/// Run the program of `case` with its input within `limits`, comparing
+/// its output with the expected one. Fails only if the files can't be read.
+pub fn run_case(case: &Case, limits: &JudgeLimits) -> Result<CaseResult> {
+    let program = fs::read(&case.program)
+        .with_context(|| anyhow!("Failed to read '{}'", case.program.display()))?;
+    let expected = fs::read_to_string(&case.expected)
+        .with_context(|| anyhow!("Failed to read '{}'", case.expected.display()))?;
+    let report = judge::judge(&program, &case.read_input()?, Some(&expected), limits);
+    let diff = match report.verdict {
+        Verdict::WrongAnswer => Some(format::diff(&expected, &report.output)),
+        _ => None,
+    };
+    Ok(CaseResult {
+        case: case.clone(),
+        report,
+        diff,
+    })
+}
+
+/// Run the program of `case` and record what it prints as its expected
+/// output. Returns the report, whose verdict is `Finished` unless the run
+/// failed or hit a limit, in which case nothing is written.
+pub fn bless(case: &Case, limits: &JudgeLimits) -> Result<JudgeReport> {
+    let program = fs::read(&case.program)
+        .with_context(|| anyhow!("Failed to read '{}'", case.program.display()))?;
+    let report = judge::judge(&program, &case.read_input()?, None, limits);
+    if report.verdict == Verdict::Finished {
+        fs::write(&case.expected, &report.output)
+            .with_context(|| anyhow!("Failed to write '{}'", case.expected.display()))?;
+    }
+    Ok(report)
+}
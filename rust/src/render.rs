@@ -0,0 +1,574 @@
+//! Pictures of the playfield: in the terminal, and for documentation and
+//! blog posts as SVG, and with the `images` feature, PNG and animated GIF.
+
+use std::fmt::Write;
+
+use ansi_term::Colour;
+use ansi_term::Style;
+
+use crate::profile::heat_level;
+use crate::teach::escape_html;
+use crate::theme::Theme;
+use crate::{Interpreter, Position};
+
+/// What to show on top of the cells.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Outline the cell the PC is on.
+    pub pc: bool,
+    /// Colour cells by how many times they were executed in the last run.
+    pub heatmap: bool,
+    /// Colour instructions by category, or draw them all alike if `None`.
+    pub highlight: Option<Palette>,
+    /// Styles of the PC and the border in terminals.
+    pub theme: Theme,
+}
+
+/// An sRGB colour.
+pub type Rgb = (u8, u8, u8);
+
+/// What an instruction is for, as far as colouring it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// `>`, `<`, `^`, `v`, `?`, `_`, `|` and `#`.
+    Arrow,
+    /// `+`, `-`, `*`, `/`, `%`, `!` and `` ` ``.
+    Arith,
+    /// `.`, `,`, `&` and `~`.
+    Io,
+    /// `:`, `\`, `$`, `g` and `p`.
+    Stack,
+    Digit,
+    /// A `"` and what lies between it and the next on its row.
+    String,
+    /// `@`, spaces and anything else.
+    Other,
+}
+
+/// The colour of each category of instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub arrow: Rgb,
+    pub arith: Rgb,
+    pub io: Rgb,
+    pub stack: Rgb,
+    pub digit: Rgb,
+    pub string: Rgb,
+    pub other: Rgb,
+}
+
+impl Default for Palette {
+    /// Colours readable on both dark terminals and the light background of
+    /// images.
+    fn default() -> Self {
+        Self {
+            arrow: (0x1e, 0x88, 0xe5),
+            arith: (0xef, 0x6c, 0x00),
+            io: (0x43, 0xa0, 0x47),
+            stack: (0xab, 0x47, 0xbc),
+            digit: (0x00, 0x97, 0xa7),
+            string: (0xd3, 0x2f, 0x2f),
+            other: (0x75, 0x75, 0x75),
+        }
+    }
+}
+
+impl Palette {
+    pub fn colour(&self, category: Category) -> Rgb {
+        match category {
+            Category::Arrow => self.arrow,
+            Category::Arith => self.arith,
+            Category::Io => self.io,
+            Category::Stack => self.stack,
+            Category::Digit => self.digit,
+            Category::String => self.string,
+            Category::Other => self.other,
+        }
+    }
+}
+
+/// The categories of the characters of a row. Strings are found reading
+/// left to right, as most are written.
+pub fn categorize(row: &str) -> Vec<Category> {
+    let mut in_string = false;
+    row.chars()
+        .map(|c| {
+            if c == '"' {
+                in_string = !in_string;
+                return Category::String;
+            }
+            if in_string {
+                return Category::String;
+            }
+            match c {
+                '>' | '<' | '^' | 'v' | '?' | '_' | '|' | '#' => Category::Arrow,
+                '+' | '-' | '*' | '/' | '%' | '!' | '`' => Category::Arith,
+                '.' | ',' | '&' | '~' => Category::Io,
+                ':' | '\\' | '$' | 'g' | 'p' => Category::Stack,
+                '0'..='9' => Category::Digit,
+                _ => Category::Other,
+            }
+        })
+        .collect()
+}
+
+/// Width of a cell, in pixels.
+pub const CELL_WIDTH: u32 = 14;
+/// Height of a cell, in pixels.
+pub const CELL_HEIGHT: u32 = 24;
+/// Space around the grid, in pixels.
+const MARGIN: u32 = 8;
+
+const BACKGROUND: Rgb = (0xfd, 0xf6, 0xe3);
+const GRID: Rgb = (0xe4, 0xdc, 0xc4);
+const TEXT: Rgb = (0x33, 0x33, 0x33);
+const PC_FILL: Rgb = (0xf5, 0xc5, 0x42);
+const PC_OUTLINE: Rgb = (0xd8, 0x1b, 0x60);
+/// Cool to hot, as the heat map of `bef profile show`.
+const HEAT: [Rgb; 5] = [
+    (0xbb, 0xde, 0xfb),
+    (0xb2, 0xeb, 0xf2),
+    (0xc8, 0xe6, 0xc9),
+    (0xff, 0xf1, 0x76),
+    (0xff, 0xab, 0x91),
+];
+
+/// How a cell is painted.
+#[derive(Debug, Clone, Copy)]
+struct Paint {
+    c: char,
+    fill: Option<Rgb>,
+    /// The colour of the character.
+    ink: Rgb,
+    pc: bool,
+}
+
+/// The colour of a cell executed `count` times when the most executed one
+/// was executed `max` times.
+fn heat(count: u64, max: u64) -> Option<Rgb> {
+    heat_level(count, max, HEAT.len()).map(|level| HEAT[level])
+}
+
+/// `c` if it can be drawn, otherwise a stand-in.
+fn printable(c: char) -> char {
+    if c == ' ' || c.is_ascii_graphic() {
+        c
+    } else {
+        '?'
+    }
+}
+
+fn hex((r, g, b): Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+impl Interpreter {
+    /// The cells in use, row by row, and how to paint them.
+    fn paints(&self, options: &RenderOptions) -> Vec<Vec<Paint>> {
+        let bounds = self.get_bounds();
+        let pc = self.get_position();
+        let profile = self.get_profile();
+        let max = profile.iter().flatten().copied().max().unwrap_or(0);
+        let cols = bounds
+            .cols
+            .max(1)
+            .max(if options.pc { pc.x + 1 } else { 0 });
+        let rows = bounds
+            .rows
+            .max(1)
+            .max(if options.pc { pc.y + 1 } else { 0 });
+
+        (0..rows)
+            .map(|y| {
+                let text = self.get_row(y);
+                let row: Vec<char> = text.chars().collect();
+                let categories = categorize(&text);
+                (0..cols)
+                    .map(|x| {
+                        let is_pc = options.pc && Position { x, y } == pc;
+                        let fill = match (options.heatmap, is_pc) {
+                            (true, _) => heat(profile[y][x], max),
+                            (false, true) => Some(PC_FILL),
+                            (false, false) => None,
+                        };
+                        let ink = match (options.highlight, categories.get(x)) {
+                            (Some(palette), Some(&category)) => palette.colour(category),
+                            _ => TEXT,
+                        };
+                        Paint {
+                            c: printable(row.get(x).copied().unwrap_or(' ')),
+                            fill,
+                            ink,
+                            pc: is_pc,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The playfield in use as a standalone SVG image.
+    pub fn render_svg(&self, options: &RenderOptions) -> String {
+        let paints = self.paints(options);
+        let (width, height) = size(&paints);
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\">",
+            w = width,
+            h = height
+        );
+        let _ = writeln!(
+            out,
+            "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+            hex(BACKGROUND)
+        );
+        let _ = writeln!(
+            out,
+            "<g font-family=\"ui-monospace, Menlo, Consolas, monospace\" font-size=\"18\" \
+             text-anchor=\"middle\" fill=\"{}\">",
+            hex(TEXT)
+        );
+        for (y, row) in paints.iter().enumerate() {
+            for (x, paint) in row.iter().enumerate() {
+                let left = MARGIN + x as u32 * CELL_WIDTH;
+                let top = MARGIN + y as u32 * CELL_HEIGHT;
+                let _ = writeln!(
+                    out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" \
+                     stroke=\"{}\"/>",
+                    left,
+                    top,
+                    CELL_WIDTH,
+                    CELL_HEIGHT,
+                    paint.fill.map_or("none".into(), hex),
+                    hex(GRID)
+                );
+                if paint.c != ' ' {
+                    let ink = if paint.ink == TEXT {
+                        String::new()
+                    } else {
+                        format!(" fill=\"{}\"", hex(paint.ink))
+                    };
+                    let _ = writeln!(
+                        out,
+                        "<text x=\"{}\" y=\"{}\"{}>{}</text>",
+                        left + CELL_WIDTH / 2,
+                        top + CELL_HEIGHT - 7,
+                        ink,
+                        escape_html(&paint.c.to_string())
+                    );
+                }
+            }
+        }
+        let _ = writeln!(out, "</g>");
+        // Drawn last, so that the grid lines of the cells around it don't
+        // cover it.
+        for (pos, _) in pc_cells(&paints) {
+            let _ = writeln!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"{}\" stroke-width=\"2\"/>",
+                MARGIN + pos.x as u32 * CELL_WIDTH,
+                MARGIN + pos.y as u32 * CELL_HEIGHT,
+                CELL_WIDTH,
+                CELL_HEIGHT,
+                hex(PC_OUTLINE)
+            );
+        }
+        let _ = writeln!(out, "</svg>");
+        out
+    }
+}
+
+impl Interpreter {
+    /// Each cell of the whole playfield, row by row, as the text drawing it
+    /// in a terminal, with the PC in red on white if `options.pc`.
+    pub fn render_cells(&self, options: &RenderOptions) -> Vec<Vec<String>> {
+        let profile = self.get_profile();
+        let max = profile.iter().flatten().copied().max().unwrap_or(0);
+        let pc = self.get_position();
+
+        let mut rows = Vec::with_capacity(profile.len());
+        for (y, counts) in profile.iter().enumerate() {
+            let row: String = self.playfield[y].iter().map(|cmd| cmd.as_char()).collect();
+            let categories = categorize(&row);
+            let mut cells = Vec::with_capacity(counts.len());
+            for (x, c) in row.chars().enumerate() {
+                let cell = c.to_string();
+                if options.pc && pc == (Position { x, y }) {
+                    cells.push(options.theme.pc.paint(cell).to_string());
+                    continue;
+                }
+                let mut style = Style::new();
+                if let (Some(palette), false) = (options.highlight, c == ' ') {
+                    let (r, g, b) = palette.colour(categories[x]);
+                    style = style.fg(Colour::RGB(r, g, b));
+                }
+                if options.heatmap {
+                    if let Some((r, g, b)) = heat(counts[x], max) {
+                        style = style.on(Colour::RGB(r, g, b));
+                    }
+                }
+                if style == Style::new() {
+                    cells.push(cell);
+                } else {
+                    cells.push(style.paint(cell).to_string());
+                }
+            }
+            rows.push(cells);
+        }
+        rows
+    }
+
+    /// The whole playfield in a box, for terminals, drawn as by
+    /// `render_cells`.
+    pub fn render_terminal(&self, options: &RenderOptions) -> String {
+        let border = options.theme.border;
+        let mid_line = "\u{2500}".repeat(self.get_size().cols);
+        let mut s = border
+            .paint(format!("\u{250C}{}\u{2510}", mid_line))
+            .to_string();
+        s.push('\n');
+        for cells in self.render_cells(options) {
+            s += &border.paint("\u{2502}").to_string();
+            s += &cells.concat();
+            s += &border.paint("\u{2502}").to_string();
+            s.push('\n');
+        }
+        s += &border
+            .paint(format!("\u{2514}{}\u{2518}", mid_line))
+            .to_string();
+        s
+    }
+}
+
+/// The cells of `paints` the PC is on.
+fn pc_cells(paints: &[Vec<Paint>]) -> impl Iterator<Item = (Position, &Paint)> {
+    paints.iter().enumerate().flat_map(|(y, row)| {
+        row.iter()
+            .enumerate()
+            .filter(|(_, paint)| paint.pc)
+            .map(move |(x, paint)| (Position { x, y }, paint))
+    })
+}
+
+/// The size of a picture of `paints`, in pixels.
+fn size(paints: &[Vec<Paint>]) -> (u32, u32) {
+    let cols = paints.first().map_or(0, Vec::len) as u32;
+    let rows = paints.len() as u32;
+    (
+        2 * MARGIN + cols * CELL_WIDTH,
+        2 * MARGIN + rows * CELL_HEIGHT,
+    )
+}
+
+#[cfg(feature = "images")]
+pub use raster::{encode_gif, Raster};
+
+#[cfg(feature = "images")]
+mod raster {
+    use std::collections::HashMap;
+    use std::convert::{Infallible, TryFrom};
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+    use embedded_graphics::mono_font::ascii::FONT_10X20;
+    use embedded_graphics::mono_font::MonoTextStyle;
+    use embedded_graphics::pixelcolor::Rgb888;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::text::{Baseline, Text};
+
+    use super::*;
+
+    /// An image as rows of RGB pixels.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Raster {
+        pub width: u32,
+        pub height: u32,
+        /// Three bytes per pixel, row by row.
+        pub pixels: Vec<u8>,
+    }
+
+    impl Raster {
+        fn new(width: u32, height: u32, colour: Rgb) -> Self {
+            let pixel = [colour.0, colour.1, colour.2];
+            Self {
+                width,
+                height,
+                pixels: pixel.repeat((width * height) as usize),
+            }
+        }
+
+        /// The image grown to `width` by `height`, with new pixels in the
+        /// background colour.
+        fn padded(&self, width: u32, height: u32) -> Self {
+            let mut padded = Self::new(width.max(self.width), height.max(self.height), BACKGROUND);
+            let row = 3 * self.width as usize;
+            for y in 0..self.height as usize {
+                let start = 3 * y * padded.width as usize;
+                padded.pixels[start..start + row]
+                    .copy_from_slice(&self.pixels[y * row..(y + 1) * row]);
+            }
+            padded
+        }
+
+        /// Each pixel as the index of its colour in `palette`.
+        fn indexed(&self, palette: &HashMap<Rgb, u8>) -> Vec<u8> {
+            self.pixels
+                .chunks(3)
+                .map(|p| palette[&(p[0], p[1], p[2])])
+                .collect()
+        }
+
+        fn set(&mut self, x: u32, y: u32, (r, g, b): Rgb) {
+            if x < self.width && y < self.height {
+                let idx = 3 * (y * self.width + x) as usize;
+                self.pixels[idx..idx + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+
+        fn fill(&mut self, left: u32, top: u32, width: u32, height: u32, colour: Rgb) {
+            for y in top..top + height {
+                for x in left..left + width {
+                    self.set(x, y, colour);
+                }
+            }
+        }
+
+        /// Draw the outline of a rectangle, `thickness` pixels wide inside it.
+        fn outline(
+            &mut self,
+            left: u32,
+            top: u32,
+            width: u32,
+            height: u32,
+            thickness: u32,
+            colour: Rgb,
+        ) {
+            self.fill(left, top, width, thickness, colour);
+            self.fill(left, top + height - thickness, width, thickness, colour);
+            self.fill(left, top, thickness, height, colour);
+            self.fill(left + width - thickness, top, thickness, height, colour);
+        }
+
+        /// Encode as PNG.
+        pub fn to_png(&self) -> Result<Vec<u8>> {
+            let mut out = vec![];
+            let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().context("Encoding PNG")?;
+            writer
+                .write_image_data(&self.pixels)
+                .context("Encoding PNG")?;
+            writer.finish().context("Encoding PNG")?;
+            Ok(out)
+        }
+    }
+
+    /// Encode `frames`, drawn by `rasterize`, as an animated GIF looping
+    /// forever, showing each for `delay`. Frames smaller than the largest
+    /// are padded, as the playfield in use may grow during a run.
+    pub fn encode_gif(frames: &[Raster], delay: Duration) -> Result<Vec<u8>> {
+        let width = frames.iter().map(|f| f.width).max().unwrap_or(1);
+        let height = frames.iter().map(|f| f.height).max().unwrap_or(1);
+        let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+            bail!("{}x{} is too large for a GIF image", width, height);
+        };
+        // Pictures are drawn with a handful of colours, which make up the
+        // palette as they are.
+        let mut palette = HashMap::new();
+        let mut colours = vec![];
+        for pixel in frames.iter().flat_map(|f| f.pixels.chunks(3)) {
+            let colour = (pixel[0], pixel[1], pixel[2]);
+            if !palette.contains_key(&colour) {
+                if palette.len() == 256 {
+                    bail!("Frames use more than the 256 colours a GIF image can");
+                }
+                palette.insert(colour, palette.len() as u8);
+                colours.extend([colour.0, colour.1, colour.2]);
+            }
+        }
+        if !palette.contains_key(&BACKGROUND) && palette.len() < 256 {
+            palette.insert(BACKGROUND, palette.len() as u8);
+            colours.extend([BACKGROUND.0, BACKGROUND.1, BACKGROUND.2]);
+        }
+        // GIF delays are in hundredths of a second.
+        let delay = (delay.as_millis() / 10).min(u16::MAX.into()) as u16;
+
+        let mut out = vec![];
+        {
+            let mut encoder =
+                gif::Encoder::new(&mut out, w, h, &colours).context("Encoding GIF")?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .context("Encoding GIF")?;
+            for frame in frames {
+                let indexed = frame.padded(width, height).indexed(&palette);
+                let mut frame = gif::Frame::from_indexed_pixels(w, h, indexed, None);
+                frame.delay = delay;
+                encoder.write_frame(&frame).context("Encoding GIF")?;
+            }
+        }
+        Ok(out)
+    }
+
+    impl OriginDimensions for Raster {
+        fn size(&self) -> Size {
+            Size::new(self.width, self.height)
+        }
+    }
+
+    impl DrawTarget for Raster {
+        type Color = Rgb888;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            for Pixel(point, colour) in pixels {
+                if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) {
+                    self.set(x, y, (colour.r(), colour.g(), colour.b()));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Interpreter {
+        /// The playfield in use as a raster image, as `render_svg` draws it.
+        pub fn rasterize(&self, options: &RenderOptions) -> Raster {
+            let paints = self.paints(options);
+            let (width, height) = size(&paints);
+            let mut raster = Raster::new(width, height, BACKGROUND);
+
+            for (y, row) in paints.iter().enumerate() {
+                for (x, paint) in row.iter().enumerate() {
+                    let left = MARGIN + x as u32 * CELL_WIDTH;
+                    let top = MARGIN + y as u32 * CELL_HEIGHT;
+                    if let Some(fill) = paint.fill {
+                        raster.fill(left, top, CELL_WIDTH, CELL_HEIGHT, fill);
+                    }
+                    raster.outline(left, top, CELL_WIDTH + 1, CELL_HEIGHT + 1, 1, GRID);
+                    if paint.c != ' ' {
+                        let (r, g, b) = paint.ink;
+                        let style = MonoTextStyle::new(&FONT_10X20, Rgb888::new(r, g, b));
+                        let origin = Point::new(left as i32 + 2, top as i32 + 2);
+                        let _ =
+                            Text::with_baseline(&paint.c.to_string(), origin, style, Baseline::Top)
+                                .draw(&mut raster);
+                    }
+                }
+            }
+            for (pos, _) in pc_cells(&paints) {
+                let left = MARGIN + pos.x as u32 * CELL_WIDTH;
+                let top = MARGIN + pos.y as u32 * CELL_HEIGHT;
+                raster.outline(left, top, CELL_WIDTH + 1, CELL_HEIGHT + 1, 2, PC_OUTLINE);
+            }
+            raster
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Running short lines of instructions one after another on the same stack,
+//! output and playfield, as `bef repl` does.
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{Command, Direction, Interpreter, Position, StepResult, PLAYFIELD_ROWS};
+
+/// The row lines are placed on, out of the way of `g` and `p` near the
+/// origin: the last one of Befunge-93, which every dialect has.
+pub const REPL_ROW: usize = PLAYFIELD_ROWS - 1;
+
+/// An interpreter that runs lines of instructions as they're entered.
+pub struct Repl {
+    interpreter: Interpreter,
+    max_steps: usize,
+}
+
+impl Repl {
+    /// Run lines on `interpreter`, starting from an empty stack and output
+    /// but keeping its playfield.
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        interpreter.reset();
+        Self {
+            interpreter,
+            max_steps: 100_000,
+        }
+    }
+
+    /// Give up on lines that take more than `max_steps` steps, as they
+    /// likely loop forever.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Empty the stack and the output.
+    pub fn reset(&mut self) {
+        self.interpreter.stack.reset();
+        self.interpreter.clear_output();
+    }
+
+    /// Run `line`: place it on `REPL_ROW` followed by `@` and run it from
+    /// its first cell, moving right, until it ends. Returns what it printed.
+    /// A line that fails leaves the stack as it was when it failed.
+    pub fn eval(&mut self, line: &str) -> Result<String> {
+        let cells: Vec<char> = line.chars().collect();
+        let cols = self.interpreter.get_size().cols;
+        if cells.len() >= cols {
+            bail!("Lines can be at most {} cells long", cols - 1);
+        }
+        if cells.iter().any(|&c| c == '\n' || c == '\r') {
+            bail!("Lines can't contain line breaks");
+        }
+
+        let int = &mut self.interpreter;
+        for x in 0..cols {
+            let cmd = match cells.get(x) {
+                Some(&c) => Command::from(c),
+                None if x == cells.len() => Command::End,
+                None => Command::Space,
+            };
+            int.put(Position { x, y: REPL_ROW }, cmd);
+        }
+        int.pc.x = 0;
+        int.pc.y = REPL_ROW;
+        int.dir = Direction::Right;
+        int.stringmode = false;
+
+        let printed = int.get_output_seq();
+        for _ in 0..self.max_steps {
+            if int.step_in_context()? == StepResult::Stop {
+                return Ok(int.output_since(printed).text);
+            }
+        }
+        Err(anyhow!("Gave up after {} steps", self.max_steps))
+            .context("The line doesn't seem to end")
+    }
+}
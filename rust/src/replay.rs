@@ -0,0 +1,156 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::schema::{check_version, SCHEMA_VERSION};
+use crate::Direction;
+
+/// First line of a recording, followed by its schema version.
+const HEADER: &str = "; befunge-93 recording, schema_version ";
+
+/// A single nondeterministic event observed while running a program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// A byte consumed by `&` or `~`.
+    Input(u8),
+    /// The end of input, reached by `&` or `~`.
+    Eof,
+    /// A direction chosen by `?`.
+    Rand(Direction),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Input(b) => write!(f, "in {}", b),
+            Self::Eof => write!(f, "in eof"),
+            Self::Rand(dir) => write!(f, "rand {}", dir),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+        let (kind, val) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(val), None) => (kind, val),
+            _ => bail!("Malformed event '{}'", s),
+        };
+
+        match kind {
+            "in" if val == "eof" => Ok(Self::Eof),
+            "in" => {
+                Ok(Self::Input(val.parse().with_context(|| {
+                    anyhow!("Parsing input byte '{}'", val)
+                })?))
+            }
+            "rand" => Ok(Self::Rand(val.parse()?)),
+            _ => bail!("Unknown event kind '{}'", kind),
+        }
+    }
+}
+
+/// An ordered log of the events of a run, which can be fed back into an
+/// interpreter to reproduce the run exactly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    events: Vec<Event>,
+}
+
+impl Recording {
+    /// Create an empty recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recorded events, in order.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub(crate) fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Read a recording, one event per line. Empty lines and lines starting
+    /// with `;` are ignored, except for the header's schema version.
+    pub fn read(reader: impl BufRead) -> Result<Self> {
+        let mut events = vec![];
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line.context("Reading recording")?;
+            let line = line.trim();
+            if let Some(version) = line.strip_prefix(HEADER) {
+                let version = version.trim().parse().with_context(|| {
+                    anyhow!("Parsing schema version on recording line {}", idx + 1)
+                })?;
+                check_version(version)?;
+                continue;
+            }
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            events.push(
+                line.parse()
+                    .with_context(|| anyhow!("Parsing recording line {}", idx + 1))?,
+            );
+        }
+        Ok(Self { events })
+    }
+
+    /// Write the recording, one event per line.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{}{}", HEADER, SCHEMA_VERSION)?;
+        for event in &self.events {
+            writeln!(writer, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the interpreter's nondeterminism comes from.
+#[derive(Clone)]
+pub(crate) enum Session {
+    /// Read input and draw random directions as usual.
+    Live,
+    /// Like `Live`, but log every event.
+    Recording(Recording),
+    /// Take every event from a recording instead.
+    Replaying(Recording, usize),
+}
+
+impl Session {
+    /// Prepare for a new run.
+    pub(crate) fn rewind(&mut self) {
+        match self {
+            Self::Live => {}
+            Self::Recording(rec) => rec.events.clear(),
+            Self::Replaying(_, pos) => *pos = 0,
+        }
+    }
+
+    /// Next event to replay, if replaying.
+    pub(crate) fn next_replayed(&mut self) -> Option<Result<Event>> {
+        match self {
+            Self::Replaying(rec, pos) => {
+                let event = rec
+                    .events
+                    .get(*pos)
+                    .copied()
+                    .ok_or_else(|| anyhow!("Recording exhausted after {} events", pos));
+                *pos += 1;
+                Some(event)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn log(&mut self, event: Event) {
+        if let Self::Recording(rec) = self {
+            rec.push(event);
+        }
+    }
+}
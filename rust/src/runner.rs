@@ -0,0 +1,187 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{Direction, Interpreter, Position};
+
+/// A command sent to a running interpreter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    /// Stop stepping until resumed.
+    Pause,
+    /// Continue stepping after a pause.
+    Resume,
+    /// Execute a single step while paused.
+    Step,
+    /// End the run.
+    Stop,
+    /// Send back a snapshot of the state.
+    Snapshot,
+}
+
+/// A copy of the observable state of an interpreter.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Snapshot {
+    /// Steps executed so far.
+    pub steps: usize,
+    /// Whether execution is paused.
+    pub paused: bool,
+    /// Position of the PC.
+    pub position: Position,
+    /// Direction the PC is moving.
+    pub direction: char,
+    /// The stack, bottom first.
+    pub stack: Vec<i64>,
+    /// The output so far.
+    pub output: String,
+}
+
+impl Snapshot {
+    pub(crate) fn of(int: &Interpreter, steps: usize, paused: bool) -> Self {
+        Self {
+            steps,
+            paused,
+            position: int.get_position(),
+            direction: Direction::as_char(int.get_direction()),
+            stack: int.stack.values(),
+            output: int.get_output().to_string(),
+        }
+    }
+}
+
+struct Worker {
+    control: Receiver<Control>,
+    snapshots: Sender<Snapshot>,
+    paused: bool,
+}
+
+impl Worker {
+    /// Handle pending commands, waiting for one while paused. Returns
+    /// whether execution should go on.
+    fn poll(&mut self, int: &Interpreter, steps: usize) -> bool {
+        loop {
+            let msg = if self.paused {
+                match self.control.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => return false,
+                }
+            } else {
+                match self.control.try_recv() {
+                    Ok(msg) => msg,
+                    // A detached runner keeps going until the program ends.
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return true,
+                }
+            };
+
+            match msg {
+                Control::Pause => self.paused = true,
+                Control::Resume => self.paused = false,
+                Control::Step if self.paused => return true,
+                Control::Step => {}
+                Control::Stop => return false,
+                Control::Snapshot => {
+                    // Nobody waiting for the snapshot is not an error.
+                    let _ = self.snapshots.send(Snapshot::of(int, steps, self.paused));
+                }
+            }
+        }
+    }
+}
+
+/// Runs an interpreter on a worker thread, driven through a channel.
+///
+/// Commands take effect between steps, so a step blocked on input delays
+/// them until the input arrives.
+pub struct Runner {
+    control: Sender<Control>,
+    snapshots: Receiver<Snapshot>,
+    handle: JoinHandle<(Interpreter, Result<()>)>,
+}
+
+impl Runner {
+    /// Start running `interpreter` on a new thread, optionally paused
+    /// before the first step.
+    pub fn spawn(mut interpreter: Interpreter, paused: bool) -> Self {
+        let (control_tx, control_rx) = mpsc::channel();
+        let (snapshots_tx, snapshots_rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut worker = Worker {
+                control: control_rx,
+                snapshots: snapshots_tx,
+                paused,
+            };
+
+            if !worker.poll(&interpreter, 0) {
+                return (interpreter, Ok(()));
+            }
+            let result = interpreter.run(|int, steps| worker.poll(int, steps));
+            (interpreter, result)
+        });
+
+        Self {
+            control: control_tx,
+            snapshots: snapshots_rx,
+            handle,
+        }
+    }
+
+    fn send(&self, msg: Control) {
+        // Once the worker is done, commands are meaningless.
+        let _ = self.control.send(msg);
+    }
+
+    /// Pause after the current step.
+    pub fn pause(&self) {
+        self.send(Control::Pause);
+    }
+
+    /// Resume a paused run.
+    pub fn resume(&self) {
+        self.send(Control::Resume);
+    }
+
+    /// Execute one step of a paused run.
+    pub fn step(&self) {
+        self.send(Control::Step);
+    }
+
+    /// End the run after the current step.
+    pub fn stop(&self) {
+        self.send(Control::Stop);
+    }
+
+    /// Ask for a snapshot, to be collected with `poll_snapshot`.
+    pub fn request_snapshot(&self) {
+        self.send(Control::Snapshot);
+    }
+
+    /// Collect a requested snapshot, if it has arrived.
+    pub fn poll_snapshot(&self) -> Option<Snapshot> {
+        self.snapshots.try_recv().ok()
+    }
+
+    /// Request a snapshot and wait for it. Returns `None` if the run ended.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        self.request_snapshot();
+        self.snapshots.recv().ok()
+    }
+
+    /// Whether the run has ended.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait for the run to end, returning the interpreter and the outcome.
+    /// A paused run ends where it is, as no command can resume it anymore.
+    pub fn join(self) -> (Interpreter, Result<()>) {
+        let Self {
+            control, handle, ..
+        } = self;
+        drop(control);
+        handle.join().expect("interpreter thread panicked")
+    }
+}
@@ -0,0 +1,100 @@
+//! Versions and JSON schemas of the documents the crate writes: traces,
+//! diagnostics, source maps, corpus statistics, bisection reports, profiles,
+//! judge reports and run summaries.
+//! Every document carries a `schema_version`, which is bumped whenever a
+//! change could break its readers. Recordings are plain text and carry the
+//! version in their header comment instead.
+
+use anyhow::{bail, Result};
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use crate::bisect::Divergence;
+use crate::corpus::CorpusStats;
+use crate::diagnostic::Diagnostic;
+use crate::judge::JudgeReport;
+use crate::profile::SavedProfile;
+use crate::sourcemap::SourceMap;
+use crate::summary::RunSummary;
+use crate::trace::TraceEvent;
+
+/// Version of the documents written by this build.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A document along with the version of its schema, which is written as a
+/// `schema_version` field next to the document's own.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Versioned<T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T> Versioned<T> {
+    /// Wrap `data` with the current version.
+    pub fn new(data: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    /// Unwrap the document, failing if it was written by a newer version.
+    pub fn into_data(self) -> Result<T> {
+        check_version(self.schema_version)?;
+        Ok(self.data)
+    }
+}
+
+/// Fail if documents of `version` can't be read by this build.
+pub fn check_version(version: u32) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Unsupported schema version {} (this build reads up to {})",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// The first line of a trace, before its events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TraceHeader {
+    pub schema_version: u32,
+}
+
+/// Names of the documents `schema` knows about.
+pub const NAMES: [&str; 9] = [
+    "trace-header",
+    "trace-event",
+    "diagnostic",
+    "source-map",
+    "corpus-stats",
+    "bisect",
+    "profile",
+    "judge",
+    "run-summary",
+];
+
+/// The JSON schema of the document called `name`, one of `NAMES`.
+pub fn schema(name: &str) -> Result<RootSchema> {
+    Ok(match name {
+        "trace-header" => schema_for!(TraceHeader),
+        "trace-event" => schema_for!(TraceEvent),
+        "diagnostic" => schema_for!(Versioned<Diagnostic>),
+        "source-map" => schema_for!(Versioned<SourceMap>),
+        "corpus-stats" => schema_for!(Versioned<CorpusStats>),
+        "bisect" => schema_for!(Versioned<Option<Divergence>>),
+        "profile" => schema_for!(Versioned<SavedProfile>),
+        "judge" => schema_for!(Versioned<JudgeReport>),
+        "run-summary" => schema_for!(Versioned<RunSummary>),
+        _ => bail!(
+            "Unknown schema '{}' (expected one of: {})",
+            name,
+            NAMES.join(", ")
+        ),
+    })
+}
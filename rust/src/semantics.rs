@@ -0,0 +1,302 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+
+/// A semantic knob whose values are a fixed set of named choices.
+pub trait Choice: Copy + PartialEq + 'static {
+    /// Every choice along with its name.
+    const CHOICES: &'static [(&'static str, Self)];
+
+    /// The name of this choice.
+    fn name(self) -> &'static str {
+        Self::CHOICES
+            .iter()
+            .find(|(_, c)| *c == self)
+            .map(|(name, _)| *name)
+            .expect("every choice has a name")
+    }
+
+    /// Find the choice called `name`.
+    fn parse(name: &str) -> Result<Self> {
+        Self::CHOICES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::CHOICES.iter().map(|(n, _)| *n).collect();
+                anyhow!("Expected one of {}, got '{}'", names.join(", "), name)
+            })
+    }
+}
+
+/// What `&` and `~` do at the end of input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eof {
+    /// Abort the run.
+    Error,
+    /// Push -1, which most interpreters do.
+    MinusOne,
+    /// Push zero.
+    Zero,
+    /// Reverse the direction of the PC, without pushing a value.
+    Reflect,
+}
+
+impl Choice for Eof {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("error", Self::Error),
+        ("-1", Self::MinusOne),
+        ("0", Self::Zero),
+        ("reflect", Self::Reflect),
+    ];
+}
+
+/// What `/` and `%` do when dividing by zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DivZero {
+    /// Abort the run.
+    Error,
+    /// Push zero.
+    Zero,
+}
+
+impl Choice for DivZero {
+    const CHOICES: &'static [(&'static str, Self)] =
+        &[("error", Self::Error), ("zero", Self::Zero)];
+}
+
+/// What `g` and `p` do with coordinates outside the playfield, negative
+/// ones included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutOfBounds {
+    /// Abort the run.
+    Error,
+    /// Use the coordinates modulo the size of the playfield.
+    Wrap,
+    /// Reverse the direction of the PC, without pushing or writing a value.
+    Reflect,
+    /// Push zero for `g`, and drop the value for `p`.
+    Nop,
+}
+
+impl Choice for OutOfBounds {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("error", Self::Error),
+        ("wrap", Self::Wrap),
+        ("reflect", Self::Reflect),
+        ("nop", Self::Nop),
+    ];
+}
+
+/// What the PC does when it moves off an edge of the playfield.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wrapping {
+    /// Continue from the opposite edge.
+    Torus,
+}
+
+impl Choice for Wrapping {
+    const CHOICES: &'static [(&'static str, Self)] = &[("torus", Self::Torus)];
+}
+
+/// What executing a character that is not an instruction does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unknown {
+    /// Abort the run.
+    Error,
+    /// Do nothing, as if it was a space.
+    Nop,
+    /// Reverse the direction of the PC.
+    Reflect,
+}
+
+impl Choice for Unknown {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("error", Self::Error),
+        ("nop", Self::Nop),
+        ("reflect", Self::Reflect),
+    ];
+}
+
+/// What characters cells hold, and what `,` prints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chars {
+    /// Bytes: programs are read a byte at a time and `,` prints the value
+    /// modulo 256 as a Latin-1 character.
+    Bytes,
+    /// Unicode scalar values: programs are read as UTF-8, string mode and
+    /// `g` push whole code points and `,` prints them.
+    Unicode,
+}
+
+impl Choice for Chars {
+    const CHOICES: &'static [(&'static str, Self)] =
+        &[("bytes", Self::Bytes), ("unicode", Self::Unicode)];
+}
+
+impl Chars {
+    /// The characters of program text `source`, one per cell.
+    pub fn decode(self, source: &[u8]) -> Vec<char> {
+        match self {
+            Self::Bytes => source.iter().map(|&b| b as char).collect(),
+            Self::Unicode => String::from_utf8_lossy(source).chars().collect(),
+        }
+    }
+
+    /// Program text whose cells hold the characters of `text`, undoing
+    /// `decode`. As bytes, characters past U+00FF, which only `p` can put
+    /// in cells, are written as UTF-8.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Self::Bytes => {
+                let mut out = Vec::with_capacity(text.len());
+                for c in text.chars() {
+                    match u8::try_from(c) {
+                        Ok(b) => out.push(b),
+                        Err(_) => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                out
+            }
+            Self::Unicode => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// How wide values on the stack are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cells {
+    /// 32 bits, wrapping around as many classic interpreters do.
+    I32,
+    /// 64 bits, wrapping around.
+    I64,
+    /// As wide as they need to be.
+    Big,
+}
+
+impl Choice for Cells {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("i32", Self::I32),
+        ("i64", Self::I64),
+        ("bigint", Self::Big),
+    ];
+}
+
+/// The behaviour of the interpreter where implementations disagree.
+///
+/// Parsed from comma-separated `key=value` overrides of the defaults, e.g.
+/// `div-zero=zero,unknown=reflect`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Semantics {
+    pub eof: Eof,
+    pub div_zero: DivZero,
+    pub out_of_bounds: OutOfBounds,
+    pub wrapping: Wrapping,
+    pub unknown: Unknown,
+    pub chars: Chars,
+    pub cells: Cells,
+}
+
+impl Default for Semantics {
+    fn default() -> Self {
+        Self {
+            eof: Eof::MinusOne,
+            div_zero: DivZero::Error,
+            out_of_bounds: OutOfBounds::Error,
+            wrapping: Wrapping::Torus,
+            unknown: Unknown::Error,
+            chars: Chars::Bytes,
+            cells: Cells::I64,
+        }
+    }
+}
+
+impl Semantics {
+    /// Names of the knobs, in display order.
+    pub const KEYS: &'static [&'static str] = &[
+        "eof",
+        "div-zero",
+        "out-of-bounds",
+        "wrapping",
+        "unknown",
+        "chars",
+        "cells",
+    ];
+
+    /// Get the name of the current choice for knob `key`.
+    pub fn get(&self, key: &str) -> Result<&'static str> {
+        Ok(match key {
+            "eof" => self.eof.name(),
+            "div-zero" => self.div_zero.name(),
+            "out-of-bounds" => self.out_of_bounds.name(),
+            "wrapping" => self.wrapping.name(),
+            "unknown" => self.unknown.name(),
+            "chars" => self.chars.name(),
+            "cells" => self.cells.name(),
+            _ => bail!("Unknown semantic knob '{}'", key),
+        })
+    }
+
+    /// Set knob `key` to the choice called `value`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let ctx = |e: Error| anyhow!("Setting '{}': {}", key, e);
+        match key {
+            "eof" => self.eof = Eof::parse(value).map_err(ctx)?,
+            "div-zero" => self.div_zero = DivZero::parse(value).map_err(ctx)?,
+            "out-of-bounds" => self.out_of_bounds = OutOfBounds::parse(value).map_err(ctx)?,
+            "wrapping" => self.wrapping = Wrapping::parse(value).map_err(ctx)?,
+            "unknown" => self.unknown = Unknown::parse(value).map_err(ctx)?,
+            "chars" => self.chars = Chars::parse(value).map_err(ctx)?,
+            "cells" => self.cells = Cells::parse(value).map_err(ctx)?,
+            _ => bail!("Unknown semantic knob '{}'", key),
+        }
+        Ok(())
+    }
+
+    /// Every knob along with the name of its current choice.
+    pub fn knobs(&self) -> Vec<(&'static str, &'static str)> {
+        Self::KEYS
+            .iter()
+            .map(|&key| (key, self.get(key).expect("all keys are known")))
+            .collect()
+    }
+
+    /// The knobs on which `self` and `other` differ, with both choices.
+    pub fn diff(&self, other: &Self) -> Vec<(&'static str, &'static str, &'static str)> {
+        self.knobs()
+            .into_iter()
+            .zip(other.knobs())
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((key, a), (_, b))| (key, a, b))
+            .collect()
+    }
+}
+
+impl FromStr for Semantics {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut semantics = Self::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let mut parts = setting.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => semantics.set(key.trim(), value.trim())?,
+                _ => bail!("Expected 'key=value', got '{}'", setting),
+            }
+        }
+        Ok(semantics)
+    }
+}
+
+impl fmt::Display for Semantics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings: Vec<_> = self
+            .knobs()
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        write!(f, "{}", settings.join(","))
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Versioned;
+use crate::{Direction, Position};
+
+/// Links a line of generated code back to the playfield cell it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Mapping {
+    /// One-based line in the generated source.
+    pub line: usize,
+    /// Column of the originating cell.
+    pub x: usize,
+    /// Row of the originating cell.
+    pub y: usize,
+    /// Direction the PC was moving through the cell, if the generated code
+    /// is specific to one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<char>,
+}
+
+/// A source map for a program translated to another language.
+///
+/// Mappings are kept sorted by generated line; a line without a mapping of
+/// its own belongs to the closest mapped line above it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceMap {
+    /// Name of the original program file, if any.
+    pub file: Option<String>,
+    /// The language the program was translated to.
+    pub target: String,
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Create an empty source map for code generated in `target`.
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            target: target.into(),
+            mappings: vec![],
+        }
+    }
+
+    /// Map generated `line` to the cell at `pos`, entered moving `dir` if
+    /// the code only handles one direction.
+    pub fn add(&mut self, line: usize, pos: Position, dir: Option<Direction>) {
+        let mapping = Mapping {
+            line,
+            x: pos.x,
+            y: pos.y,
+            direction: dir.map(Direction::as_char),
+        };
+        let idx = self.mappings.partition_point(|m| m.line <= line);
+        self.mappings.insert(idx, mapping);
+    }
+
+    /// All mappings, ordered by generated line.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Find the playfield cell generated `line` belongs to.
+    pub fn lookup(&self, line: usize) -> Option<&Mapping> {
+        let idx = self.mappings.partition_point(|m| m.line <= line);
+        idx.checked_sub(1).map(|idx| &self.mappings[idx])
+    }
+
+    /// Serialize as JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Versioned::new(self)).expect("source maps are always serializable")
+    }
+
+    /// Parse a JSON source map.
+    pub fn from_json(s: &str) -> Result<Self> {
+        serde_json::from_str::<Versioned<Self>>(s)
+            .context("Parsing source map")?
+            .into_data()
+    }
+}
@@ -0,0 +1,422 @@
+//! The stack, holding values as wide as the `cells` semantics ask.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context, Result};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::semantics::{Cells, Choice};
+use crate::theme::Theme;
+use crate::StackTy;
+
+/// An operation on the two values on top of the stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    /// Division, which the caller checks isn't by zero.
+    Div,
+    Mod,
+    Gt,
+}
+
+/// What pushing onto a full stack does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Abort the run.
+    Error,
+    /// Drop the value at the bottom to make room.
+    DropOldest,
+    /// Drop the value pushed.
+    Ignore,
+}
+
+impl Choice for Overflow {
+    const CHOICES: &'static [(&'static str, Self)] = &[
+        ("error", Self::Error),
+        ("drop-oldest", Self::DropOldest),
+        ("ignore", Self::Ignore),
+    ];
+}
+
+/// How many values the stack may hold, and what pushing more does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackLimit {
+    pub depth: usize,
+    pub overflow: Overflow,
+}
+
+#[derive(Clone, PartialEq)]
+enum Values {
+    /// Values wrapped to 32 or 64 bits, as `cells` says.
+    Fixed(Cells, Vec<StackTy>),
+    Big(Vec<BigInt>),
+}
+
+/// The stack. Popping an empty stack gives zero.
+///
+/// Serialized as the width of its cells and its values, bottom first; the
+/// values of bigint cells are decimal strings, so they keep every digit.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "StackRepr", try_from = "StackRepr")]
+pub struct Stack {
+    values: Values,
+    limit: Option<StackLimit>,
+    /// Whether a value was pushed past the limit with `Overflow::Error`.
+    overflowed: bool,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new(Cells::I64)
+    }
+}
+
+impl Stack {
+    pub(crate) fn new(cells: Cells) -> Self {
+        let values = match cells {
+            Cells::Big => Values::Big(vec![]),
+            cells => Values::Fixed(cells, vec![]),
+        };
+        Self {
+            values,
+            limit: None,
+            overflowed: false,
+        }
+    }
+
+    /// Hold values as wide as `cells` from now on, converting those on the
+    /// stack.
+    pub(crate) fn set_cells(&mut self, cells: Cells) {
+        let mut stack = Self::new(cells);
+        stack.limit = self.limit;
+        for value in self.to_big() {
+            stack.push_big(value);
+        }
+        *self = stack;
+    }
+
+    /// Hold at most `limit.depth` values from now on, or any number with
+    /// `None`.
+    pub(crate) fn set_limit(&mut self, limit: Option<StackLimit>) {
+        self.limit = limit;
+        self.trim();
+        self.overflowed = false;
+    }
+
+    pub(crate) fn get_limit(&self) -> Option<StackLimit> {
+        self.limit
+    }
+
+    /// Whether a value was pushed past the limit with `Overflow::Error`
+    /// since the last call.
+    pub(crate) fn take_overflow(&mut self) -> bool {
+        std::mem::take(&mut self.overflowed)
+    }
+
+    /// Drop values past the limit, after pushing.
+    fn trim(&mut self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        let excess = self.len().saturating_sub(limit.depth);
+        if excess == 0 {
+            return;
+        }
+        match (limit.overflow, &mut self.values) {
+            (Overflow::DropOldest, Values::Fixed(_, values)) => {
+                values.drain(..excess);
+            }
+            (Overflow::DropOldest, Values::Big(values)) => {
+                values.drain(..excess);
+            }
+            (overflow, values) => {
+                self.overflowed |= overflow == Overflow::Error;
+                match values {
+                    Values::Fixed(_, values) => values.truncate(limit.depth),
+                    Values::Big(values) => values.truncate(limit.depth),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.overflowed = false;
+        match &mut self.values {
+            Values::Fixed(_, values) => values.clear(),
+            Values::Big(values) => values.clear(),
+        }
+    }
+
+    /// Pop the top value, clamped to 64 bits.
+    pub(crate) fn pop(&mut self) -> StackTy {
+        match &mut self.values {
+            Values::Fixed(_, values) => values.pop().unwrap_or(0),
+            Values::Big(values) => values.pop().as_ref().map_or(0, clamp),
+        }
+    }
+
+    pub(crate) fn push(&mut self, val: StackTy) {
+        match &mut self.values {
+            Values::Fixed(cells, values) => values.push(wrap(*cells, val)),
+            Values::Big(values) => values.push(val.into()),
+        }
+        self.trim();
+    }
+
+    fn push_big(&mut self, val: BigInt) {
+        match &mut self.values {
+            Values::Fixed(cells, values) => {
+                // The low 64 bits, as two's complement.
+                let low = (&val & BigInt::from(u64::MAX)).to_u64().unwrap_or(0);
+                values.push(wrap(*cells, low as StackTy));
+            }
+            Values::Big(values) => values.push(val),
+        }
+        self.trim();
+    }
+
+    /// The top value, clamped to 64 bits.
+    pub(crate) fn peek(&self) -> StackTy {
+        match &self.values {
+            Values::Fixed(_, values) => *values.last().unwrap_or(&0),
+            Values::Big(values) => values.last().map_or(0, clamp),
+        }
+    }
+
+    /// Push a copy of the top value.
+    pub(crate) fn dup(&mut self) {
+        match &mut self.values {
+            Values::Fixed(_, values) => values.push(*values.last().unwrap_or(&0)),
+            Values::Big(values) => values.push(values.last().cloned().unwrap_or_default()),
+        }
+        self.trim();
+    }
+
+    /// Swap the two values on top.
+    pub(crate) fn swap(&mut self) {
+        match &mut self.values {
+            Values::Fixed(_, values) => {
+                let x = values.pop().unwrap_or(0);
+                let y = values.pop().unwrap_or(0);
+                values.push(x);
+                values.push(y);
+            }
+            Values::Big(values) => {
+                let x = values.pop().unwrap_or_default();
+                let y = values.pop().unwrap_or_default();
+                values.push(x);
+                values.push(y);
+            }
+        }
+        self.trim();
+    }
+
+    /// Pop `y`, then `x`, and push `x op y`, wrapping to the width of the
+    /// cells.
+    pub(crate) fn binop(&mut self, op: BinOp) {
+        match &mut self.values {
+            Values::Fixed(cells, values) => {
+                let y = values.pop().unwrap_or(0);
+                let x = values.pop().unwrap_or(0);
+                let result = match op {
+                    BinOp::Add => x.wrapping_add(y),
+                    BinOp::Sub => x.wrapping_sub(y),
+                    BinOp::Mul => x.wrapping_mul(y),
+                    BinOp::Div => x.wrapping_div(y),
+                    BinOp::Mod => x.wrapping_rem(y),
+                    BinOp::Gt => StackTy::from(x > y),
+                };
+                values.push(wrap(*cells, result));
+            }
+            Values::Big(values) => {
+                let y = values.pop().unwrap_or_default();
+                let x = values.pop().unwrap_or_default();
+                values.push(match op {
+                    BinOp::Add => x + y,
+                    BinOp::Sub => x - y,
+                    BinOp::Mul => x * y,
+                    BinOp::Div => x / y,
+                    BinOp::Mod => x % y,
+                    BinOp::Gt => BigInt::from(u8::from(x > y)),
+                });
+            }
+        }
+        self.trim();
+    }
+
+    /// Pop the top value as text, however wide it is.
+    pub(crate) fn pop_text(&mut self) -> String {
+        match &mut self.values {
+            Values::Fixed(_, values) => values.pop().unwrap_or(0).to_string(),
+            Values::Big(values) => values.pop().unwrap_or_default().to_string(),
+        }
+    }
+
+    /// Push the number written in `text`, wrapped to the width of the
+    /// cells.
+    pub(crate) fn push_parsed(&mut self, text: &str) -> Result<()> {
+        let context = || anyhow!("Parsing '{}' into a number", text);
+        match &mut self.values {
+            Values::Fixed(cells, values) => {
+                values.push(wrap(*cells, text.parse().with_context(context)?))
+            }
+            Values::Big(values) => values.push(text.parse().with_context(context)?),
+        }
+        self.trim();
+        Ok(())
+    }
+
+    /// The values of a fixed-width stack, for native code to work on.
+    #[cfg(feature = "jit")]
+    pub(crate) fn fixed_mut(&mut self) -> Option<&mut Vec<StackTy>> {
+        match &mut self.values {
+            Values::Fixed(_, values) => Some(values),
+            Values::Big(_) => None,
+        }
+    }
+
+    /// How many values are on the stack.
+    pub fn len(&self) -> usize {
+        match &self.values {
+            Values::Fixed(_, values) => values.len(),
+            Values::Big(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The values, bottom first. Values past 64 bits are clamped.
+    pub fn values(&self) -> Vec<StackTy> {
+        match &self.values {
+            Values::Fixed(_, values) => values.clone(),
+            Values::Big(values) => values.iter().map(clamp).collect(),
+        }
+    }
+
+    /// Whether `self` and `other` hold the same values, however wide
+    /// their cells are.
+    pub(crate) fn same_values(&self, other: &Self) -> bool {
+        match (&self.values, &other.values) {
+            (Values::Fixed(_, a), Values::Fixed(_, b)) => a == b,
+            (Values::Big(a), Values::Big(b)) => a == b,
+            _ => self.len() == other.len() && self.texts() == other.texts(),
+        }
+    }
+
+    /// The values as text, bottom first, however wide they are.
+    pub fn texts(&self) -> Vec<String> {
+        match &self.values {
+            Values::Fixed(_, values) => values.iter().map(StackTy::to_string).collect(),
+            Values::Big(values) => values.iter().map(BigInt::to_string).collect(),
+        }
+    }
+
+    fn to_big(&self) -> Vec<BigInt> {
+        match &self.values {
+            Values::Fixed(_, values) => values.iter().map(|&v| v.into()).collect(),
+            Values::Big(values) => values.clone(),
+        }
+    }
+
+    /// The values, bottom first, drawn with `theme`.
+    pub fn render(&self, theme: &Theme) -> String {
+        self.texts()
+            .into_iter()
+            .map(|text| theme.stack.paint(text + " ").to_string())
+            .collect()
+    }
+}
+
+/// A value of a serialized stack.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Cell {
+    Number(StackTy),
+    Text(String),
+}
+
+/// How a stack is serialized.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename = "Stack")]
+struct StackRepr {
+    /// The `cells` semantics: i32, i64 or bigint.
+    cells: String,
+    /// Bottom first.
+    values: Vec<Cell>,
+}
+
+impl From<Stack> for StackRepr {
+    fn from(stack: Stack) -> Self {
+        let (cells, values) = match stack.values {
+            Values::Fixed(cells, values) => (cells, values.into_iter().map(Cell::Number).collect()),
+            Values::Big(values) => (
+                Cells::Big,
+                values.iter().map(|v| Cell::Text(v.to_string())).collect(),
+            ),
+        };
+        Self {
+            cells: cells.name().to_string(),
+            values,
+        }
+    }
+}
+
+impl TryFrom<StackRepr> for Stack {
+    type Error = anyhow::Error;
+
+    fn try_from(repr: StackRepr) -> Result<Self> {
+        let cells = Cells::parse(&repr.cells)?;
+        let mut stack = Self::new(cells);
+        for cell in repr.values {
+            match cell {
+                Cell::Number(value) if cells != Cells::I32 || wrap(cells, value) == value => {
+                    stack.push(value)
+                }
+                Cell::Number(value) => bail!("{} doesn't fit in i32 cells", value),
+                Cell::Text(text) => stack.push_parsed(&text)?,
+            }
+        }
+        Ok(stack)
+    }
+}
+
+impl JsonSchema for Stack {
+    fn schema_name() -> String {
+        "Stack".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        StackRepr::json_schema(gen)
+    }
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.render(&Theme::default()))
+    }
+}
+
+/// `value` wrapped to the width of `cells`.
+fn wrap(cells: Cells, value: StackTy) -> StackTy {
+    match cells {
+        Cells::I32 => value as i32 as StackTy,
+        _ => value,
+    }
+}
+
+/// `value` clamped to 64 bits, which keeps its sign and whether it is zero.
+fn clamp(value: &BigInt) -> StackTy {
+    value.to_i64().unwrap_or(if value < &BigInt::zero() {
+        StackTy::MIN
+    } else {
+        StackTy::MAX
+    })
+}
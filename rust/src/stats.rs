@@ -0,0 +1,73 @@
+use std::collections::{BTreeMap, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{self, Dialect};
+use crate::{Command, Interpreter};
+
+/// How many times each instruction executed in a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RunStats {
+    /// Steps taken.
+    pub steps: u64,
+    /// Executions of each character outside of string mode, spaces
+    /// included.
+    pub instructions: BTreeMap<char, u64>,
+    /// Steps by kind of instruction: arith, flow, stack, push, string, io,
+    /// mem, or other for characters that aren't instructions. Characters
+    /// pushed in string mode count as string.
+    pub kinds: BTreeMap<String, u64>,
+}
+
+/// Counts the steps of the current run by the character executed.
+#[derive(Clone, Default)]
+pub(crate) struct Counter {
+    executed: HashMap<char, u64>,
+    /// Characters pushed in string mode.
+    pushed: u64,
+}
+
+impl Counter {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Count a step executing `cmd`, or pushing it in string mode.
+    pub(crate) fn count(&mut self, cmd: Command, stringmode: bool) {
+        if stringmode && cmd != Command::Str {
+            self.pushed += 1;
+        } else {
+            *self.executed.entry(cmd.as_char()).or_default() += 1;
+        }
+    }
+}
+
+impl Interpreter {
+    /// Count the instructions executed in subsequent runs, or stop counting
+    /// with `false`. Runs are then stepped one cell at a time.
+    pub fn collect_stats(&mut self, collect: bool) {
+        self.stats = collect.then(Counter::default);
+    }
+
+    /// How many times each instruction executed in the current run, if
+    /// counting them with `collect_stats`.
+    pub fn get_stats(&self) -> Option<RunStats> {
+        let counter = self.stats.as_ref()?;
+        let mut stats = RunStats {
+            steps: counter.pushed,
+            ..RunStats::default()
+        };
+        if counter.pushed > 0 {
+            stats.kinds.insert("string".to_string(), counter.pushed);
+        }
+        for (&c, &n) in &counter.executed {
+            let kind =
+                instructions::lookup(Dialect::Befunge93, c).map_or("other", |doc| doc.kind.name());
+            stats.steps += n;
+            stats.instructions.insert(c, n);
+            *stats.kinds.entry(kind.to_string()).or_default() += n;
+        }
+        Some(stats)
+    }
+}
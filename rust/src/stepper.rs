@@ -0,0 +1,106 @@
+use anyhow::Result;
+
+use crate::input::InputQueue;
+use crate::{Command, Interpreter, NumberScan, StepResult};
+
+/// Drives an interpreter one step at a time, with input pushed by the
+/// caller. This is what bindings for other languages build on.
+pub struct Stepper {
+    interpreter: Interpreter,
+    input: InputQueue,
+    started: bool,
+    halted: bool,
+    steps: usize,
+}
+
+impl Default for Stepper {
+    fn default() -> Self {
+        Self::new(Interpreter::new())
+    }
+}
+
+impl Stepper {
+    /// Drive `interpreter`, replacing its input.
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        let input = InputQueue::new();
+        interpreter.set_input(input.clone());
+        Self {
+            interpreter,
+            input,
+            started: false,
+            halted: false,
+            steps: 0,
+        }
+    }
+
+    /// Inspect the interpreter.
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    /// Load a program in place of the last one, to be run from the start.
+    pub fn load(&mut self, program: &[u8]) -> Result<()> {
+        self.interpreter.reload(&mut &program[..])?;
+        self.started = false;
+        self.halted = false;
+        self.steps = 0;
+        Ok(())
+    }
+
+    /// Append to the input read by `&` and `~`.
+    pub fn push_input(&self, input: &[u8]) {
+        self.input.push(input);
+    }
+
+    /// Whether the next step would read more input than has been pushed.
+    pub fn needs_input(&self) -> bool {
+        if self.halted || self.interpreter.stringmode {
+            return false;
+        }
+        let unread = self.interpreter.unread;
+        match self.interpreter.get_current_command() {
+            Command::InC => unread.is_none() && self.input.is_empty(),
+            Command::InI => {
+                !NumberScan::default().is_complete(unread.into_iter().chain(self.input.bytes()))
+            }
+            _ => false,
+        }
+    }
+
+    /// Execute one step. Returns `false` once the program has ended.
+    pub fn step(&mut self) -> Result<bool> {
+        if self.halted {
+            return Ok(false);
+        }
+        if !self.started {
+            self.interpreter.reset();
+            self.started = true;
+        }
+
+        let result = self.interpreter.step_in_context()?;
+        self.steps += 1;
+        self.halted = result == StepResult::Stop;
+        Ok(!self.halted)
+    }
+
+    /// Execute up to `max_steps` steps, stopping early when input is
+    /// needed. Returns `false` once the program has ended.
+    pub fn run(&mut self, max_steps: usize) -> Result<bool> {
+        for _ in 0..max_steps {
+            if self.needs_input() || !self.step()? {
+                break;
+            }
+        }
+        Ok(!self.halted)
+    }
+
+    /// Whether the program has ended.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Steps executed since the program was loaded.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
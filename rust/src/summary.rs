@@ -0,0 +1,50 @@
+//! The summary of a run printed by `bef --json`, for scripts and graders.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::stats::RunStats;
+
+/// Why a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum HaltReason {
+    /// The program reached `@`.
+    End,
+    /// A step failed.
+    Error,
+    /// The run was stopped after the most steps allowed.
+    StepLimit,
+    /// The run was stopped after the most time allowed.
+    TimeLimit,
+}
+
+impl HaltReason {
+    /// The name of the reason, as in JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::End => "end",
+            Self::Error => "error",
+            Self::StepLimit => "step-limit",
+            Self::TimeLimit => "time-limit",
+        }
+    }
+}
+
+/// How a run went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RunSummary {
+    pub halt_reason: HaltReason,
+    /// What the program printed.
+    pub output: String,
+    /// The stack at the end, bottom first.
+    pub stack: Vec<i64>,
+    /// Steps taken, including the one ending the program.
+    pub steps: usize,
+    pub elapsed_ms: u64,
+    /// The error ending the run, if there was one.
+    pub error: Option<String>,
+    /// How many times each instruction executed, if counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<RunStats>,
+}
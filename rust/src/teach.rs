@@ -0,0 +1,327 @@
+//! Step-by-step walkthroughs of a run, one frame per step with the state of
+//! the machine and a plain-language explanation, as rendered by `bef teach`
+//! for teaching material.
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use anyhow::Result;
+
+use crate::semantics::Unknown;
+use crate::{Command, Direction, Interpreter, Position, StackTy, StepResult};
+
+/// The state of the machine around one step.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Number of the step, starting from one.
+    pub step: usize,
+    /// The cell executed.
+    pub pos: Position,
+    pub command: Command,
+    /// Whether string mode was active before the step.
+    pub stringmode: bool,
+    /// The rows of the playfield before the step, up to the last non-empty
+    /// one or the PC.
+    pub playfield: Vec<String>,
+    /// The stack before the step, bottom first.
+    pub stack_before: Vec<StackTy>,
+    /// The stack after the step, bottom first.
+    pub stack_after: Vec<StackTy>,
+    /// Everything printed up to and including the step.
+    pub output: String,
+    /// What the step did.
+    pub explanation: String,
+}
+
+impl Interpreter {
+    /// Run the loaded program from the start and describe the steps in
+    /// `steps`, counted from one. Stops early if the program ends. A step
+    /// in `steps` that fails gets a frame explaining the error, while one
+    /// before them fails the call.
+    pub fn teach(&mut self, steps: RangeInclusive<usize>) -> Result<Vec<Frame>> {
+        self.reset();
+        let mut frames = vec![];
+        for step in 1..=*steps.end() {
+            let pos = self.get_position();
+            let command = self.get_current_command();
+            let stringmode = self.stringmode;
+            let stack_before = self.stack.values();
+            let playfield = if steps.contains(&step) {
+                self.playfield_rows(pos)
+            } else {
+                vec![]
+            };
+
+            let result = self.step_in_context();
+            if !steps.contains(&step) {
+                match result? {
+                    StepResult::Cont => continue,
+                    StepResult::Stop => break,
+                }
+            }
+
+            let explanation = match &result {
+                Ok(_) => self.explain(command, stringmode, &stack_before),
+                Err(e) => format!("The step fails: {:#}.", e),
+            };
+            frames.push(Frame {
+                step,
+                pos,
+                command,
+                stringmode,
+                playfield,
+                stack_before,
+                stack_after: self.stack.values(),
+                output: self.get_output().to_string(),
+                explanation,
+            });
+            if !matches!(result, Ok(StepResult::Cont)) {
+                break;
+            }
+        }
+        Ok(frames)
+    }
+
+    fn playfield_rows(&self, pos: Position) -> Vec<String> {
+        let mut rows: Vec<_> = (0..self.get_size().rows).map(|y| self.get_row(y)).collect();
+        while rows.len() > pos.y + 1 && rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        rows
+    }
+
+    /// Explain the step just taken, which executed `cmd` with `stack`.
+    fn explain(&self, cmd: Command, stringmode: bool, stack: &[StackTy]) -> String {
+        // The nth value from the top, as popped.
+        let top = |n: usize| stack.len().checked_sub(n + 1).map_or(0, |idx| stack[idx]);
+        let (a, b) = (top(1), top(0));
+        let pushed = self.stack.peek();
+
+        if stringmode && cmd != Command::Str {
+            return format!(
+                "In string mode, push the code of {:?}, {}.",
+                cmd.as_char(),
+                cmd.as_char() as u32
+            );
+        }
+        match cmd {
+            Command::Num(n) => format!("Push the number {}.", n),
+            Command::Add => format!("Pop {} and {} and push their sum, {}.", b, a, pushed),
+            Command::Sub => format!("Pop {} and {} and push {} - {} = {}.", b, a, a, b, pushed),
+            Command::Mul => format!("Pop {} and {} and push their product, {}.", b, a, pushed),
+            // Only reached if division by zero is allowed.
+            Command::Div | Command::Mod if b == 0 => {
+                format!("Pop {} and {}; dividing by zero pushes 0.", b, a)
+            }
+            Command::Div => format!("Pop {} and {} and push {} / {} = {}.", b, a, a, b, pushed),
+            Command::Mod => format!(
+                "Pop {} and {} and push the remainder of {} / {}, {}.",
+                b, a, a, b, pushed
+            ),
+            Command::Not => format!(
+                "Pop {} and push {}: 1 if it was zero, 0 otherwise.",
+                b, pushed
+            ),
+            Command::Gt => format!(
+                "Pop {} and {} and push {}: 1 if {} > {}, 0 otherwise.",
+                b, a, pushed, a, b
+            ),
+            Command::Right | Command::Left | Command::Up | Command::Down => {
+                format!("Start moving {}.", direction_name(self.dir))
+            }
+            Command::Rand => format!(
+                "Start moving in a random direction: {}.",
+                direction_name(self.dir)
+            ),
+            Command::IfH | Command::IfV => format!(
+                "Pop {}; it is {}zero, so move {}.",
+                b,
+                if b == 0 { "" } else { "not " },
+                direction_name(self.dir)
+            ),
+            Command::Str if stringmode => "Turn string mode off.".to_string(),
+            Command::Str => {
+                "Turn string mode on: until the next '\"', cells push their character codes."
+                    .to_string()
+            }
+            Command::Dup => format!("Duplicate the top of the stack, {}.", b),
+            Command::Swap => format!("Swap the top two values, {} and {}.", b, a),
+            Command::Pop => format!("Discard the top of the stack, {}.", b),
+            Command::OutI => format!("Pop {} and print it as a number.", b),
+            Command::OutC => format!(
+                "Pop {} and print it as the character {:?}.",
+                b, b as u8 as char
+            ),
+            Command::Bri => "Jump over the next cell.".to_string(),
+            Command::Get => format!(
+                "Pop y = {} and x = {} and push the code of the character at ({}, {}), {}.",
+                b, a, a, b, pushed
+            ),
+            Command::Put => format!(
+                "Pop y = {}, x = {} and {}, and write {:?} to ({}, {}).",
+                b,
+                a,
+                top(2),
+                top(2) as u8 as char,
+                a,
+                b
+            ),
+            Command::InI => format!("Read a number from the input and push it, {}.", pushed),
+            Command::InC => format!(
+                "Read a character from the input and push its code, {}.",
+                pushed
+            ),
+            Command::End => "End the program.".to_string(),
+            Command::Space => "Nothing to do here; keep moving.".to_string(),
+            Command::Char(c) => match self.semantics.unknown {
+                Unknown::Error => format!("{:?} is not an instruction.", c),
+                Unknown::Nop => format!("{:?} is not an instruction, so it is skipped.", c),
+                Unknown::Reflect => {
+                    format!("{:?} is not an instruction, so the PC turns around.", c)
+                }
+            },
+        }
+    }
+}
+
+fn direction_name(dir: Direction) -> &'static str {
+    match dir {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+fn stack_text(stack: &[StackTy]) -> String {
+    if stack.is_empty() {
+        return "empty".to_string();
+    }
+    let values: Vec<_> = stack.iter().map(|v| v.to_string()).collect();
+    values.join(" ")
+}
+
+/// Render `frames` as plain text, one page per frame separated by form
+/// feeds.
+pub fn to_text(frames: &[Frame]) -> String {
+    let mut out = String::new();
+    for (idx, frame) in frames.iter().enumerate() {
+        if idx > 0 {
+            out.push('\x0c');
+        }
+        let _ = writeln!(out, "Step {} at {}", frame.step, frame.pos);
+        let _ = writeln!(out);
+        for (y, row) in frame.playfield.iter().enumerate() {
+            let _ = writeln!(out, "  {}", row);
+            if y == frame.pos.y {
+                let _ = writeln!(out, "  {}^", " ".repeat(frame.pos.x));
+            }
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", frame.explanation);
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Stack before: {}", stack_text(&frame.stack_before));
+        let _ = writeln!(out, "Stack after:  {}", stack_text(&frame.stack_after));
+        let _ = writeln!(out, "Output:       {:?}", frame.output);
+    }
+    out
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; max-width: 60em; margin: auto; }
+section { page-break-after: always; break-after: page; padding: 1em 0; border-bottom: 1px solid #ccc; }
+pre.field { background: #f6f6f6; padding: 0.5em; line-height: 1.2; }
+pre.field mark { background: #f5c542; }
+.stack span { display: inline-block; min-width: 2em; margin: 0 2px; padding: 2px 4px; border: 1px solid #888; text-align: center; font-family: monospace; }
+.explanation { font-size: 1.2em; }
+nav { color: #666; font-size: 0.9em; }
+";
+
+/// Render `frames` as a standalone HTML document titled `title`, one page
+/// per frame when printed.
+pub fn to_html(frames: &[Frame], title: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{}</title>", escape_html(title));
+    let _ = writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(out, "<h1>{}</h1>", escape_html(title));
+
+    for (idx, frame) in frames.iter().enumerate() {
+        let _ = writeln!(out, "<section id=\"step-{}\">", frame.step);
+        let _ = writeln!(
+            out,
+            "<h2>Step {} <small>at {}</small></h2>",
+            frame.step, frame.pos
+        );
+        let _ = writeln!(out, "<pre class=\"field\">");
+        for (y, row) in frame.playfield.iter().enumerate() {
+            if y != frame.pos.y {
+                let _ = writeln!(out, "{}", escape_html(row));
+                continue;
+            }
+            let mut chars: Vec<char> = row.chars().collect();
+            chars.resize(chars.len().max(frame.pos.x + 1), ' ');
+            let before: String = chars[..frame.pos.x].iter().collect();
+            let after: String = chars[frame.pos.x + 1..].iter().collect();
+            let _ = writeln!(
+                out,
+                "{}<mark>{}</mark>{}",
+                escape_html(&before),
+                escape_html(&chars[frame.pos.x].to_string()),
+                escape_html(&after)
+            );
+        }
+        let _ = writeln!(out, "</pre>");
+        let _ = writeln!(
+            out,
+            "<p class=\"explanation\">{}</p>",
+            escape_html(&frame.explanation)
+        );
+        for (label, stack) in [
+            ("before", &frame.stack_before),
+            ("after", &frame.stack_after),
+        ] {
+            let values: String = stack
+                .iter()
+                .map(|v| format!("<span>{}</span>", v))
+                .collect();
+            let values = if values.is_empty() {
+                "<em>empty</em>".to_string()
+            } else {
+                values
+            };
+            let _ = writeln!(
+                out,
+                "<p class=\"stack\">Stack {} (bottom first): {}</p>",
+                label, values
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<p>Output so far: <code>{}</code></p>",
+            escape_html(&frame.output)
+        );
+        let _ = write!(out, "<nav>Page {} of {}", idx + 1, frames.len());
+        if idx > 0 {
+            let _ = write!(
+                out,
+                " &middot; <a href=\"#step-{}\">previous</a>",
+                frames[idx - 1].step
+            );
+        }
+        if let Some(next) = frames.get(idx + 1) {
+            let _ = write!(out, " &middot; <a href=\"#step-{}\">next</a>", next.step);
+        }
+        let _ = writeln!(out, "</nav>\n</section>");
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
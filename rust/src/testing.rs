@@ -0,0 +1,222 @@
+//! Property testing the interpreter: random playfields, stacks and inputs,
+//! and checks of what should hold for any of them, such as runs with the
+//! same seed doing the same.
+//!
+//! Values are drawn from a `rand` generator, or, with the `arbitrary`
+//! feature, built from the bytes of fuzzers and property-testing crates
+//! through `arbitrary::Arbitrary`.
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, Context, Result};
+use rand::Rng;
+
+use crate::generator::{self, GeneratorOptions};
+use crate::lockstep::lockstep;
+use crate::repl::Repl;
+use crate::{Interpreter, StackTy};
+
+/// The source of a program fitting the playfield.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playfield(pub String);
+
+impl Playfield {
+    /// A program of up to 20x8 cells, mostly instructions, that has a way
+    /// to end.
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let opts = GeneratorOptions {
+            cols: rng.gen_range(1, 21),
+            rows: rng.gen_range(1, 9),
+            ..GeneratorOptions::default()
+        };
+        Self(generator::generate(&opts, rng).expect("the size fits the playfield"))
+    }
+}
+
+/// Values to start a stack with, bottom first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackValues(pub Vec<StackTy>);
+
+/// Values stacks are built from besides small numbers, as they are where
+/// arithmetic overflows.
+const EDGE_VALUES: &[StackTy] = &[StackTy::MIN, StackTy::MIN + 1, -1, StackTy::MAX];
+
+impl StackValues {
+    /// Up to 16 values, mostly small ones.
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let len = rng.gen_range(0, 17);
+        Self(
+            (0..len)
+                .map(|_| match rng.gen_range(0, 8) {
+                    0 => EDGE_VALUES[rng.gen_range(0, EDGE_VALUES.len())],
+                    _ => rng.gen_range(-256, 256),
+                })
+                .collect(),
+        )
+    }
+
+    /// A REPL on a fresh interpreter holding the values.
+    pub fn repl(&self) -> Repl {
+        let mut repl = Repl::new(Interpreter::new());
+        for &value in &self.0 {
+            repl.interpreter_mut().push(value);
+        }
+        repl
+    }
+}
+
+/// Input for `&` and `~`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input(pub Vec<u8>);
+
+impl Input {
+    /// Numbers and bytes of text, separated by whitespace.
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let mut bytes = vec![];
+        for _ in 0..rng.gen_range(0, 8) {
+            match rng.gen_range(0, 3) {
+                0 => bytes.extend(rng.gen_range(-1000, 1000).to_string().bytes()),
+                1 => bytes.push(rng.gen_range(b' ', b'~' + 1)),
+                _ => bytes.push(rng.gen()),
+            }
+            bytes.push(if rng.gen() { b' ' } else { b'\n' });
+        }
+        Self(bytes)
+    }
+}
+
+/// A program along with everything its runs depend on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub playfield: Playfield,
+    pub input: Input,
+    /// Seed of the directions taken by `?`.
+    pub seed: u64,
+}
+
+impl Case {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            playfield: Playfield::random(rng),
+            input: Input::random(rng),
+            seed: rng.gen(),
+        }
+    }
+
+    /// A fresh interpreter with the program loaded, ready to run it.
+    pub fn interpreter(&self) -> Result<Interpreter> {
+        let mut int = Interpreter::new();
+        int.set_seed(Some(self.seed));
+        int.set_input(Cursor::new(self.input.0.clone()));
+        int.load(&mut self.playfield.0.as_bytes())?;
+        Ok(int)
+    }
+}
+
+/// Fail unless two runs of `case` take the same steps, stack and output
+/// for up to `max_steps` steps.
+pub fn check_deterministic(case: &Case, max_steps: usize) -> Result<()> {
+    let (mut left, mut right) = (case.interpreter()?, case.interpreter()?);
+    match lockstep(&mut left, &mut right, max_steps) {
+        Some(mismatch) => bail!(
+            "Two runs of the same program differ in their {:?} after step {}",
+            mismatch.aspect,
+            mismatch.step
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Fail unless loading the source of a loaded `playfield` gives back the
+/// same source.
+pub fn check_source_round_trip(playfield: &Playfield) -> Result<()> {
+    let mut int = Interpreter::new();
+    int.load(&mut playfield.0.as_bytes())?;
+    let source = int.get_source();
+    let mut again = Interpreter::new();
+    again.load(&mut source.as_bytes())?;
+    if again.get_source() != source {
+        bail!(
+            "Loading {:?} gives {:?}, which loads as {:?}",
+            playfield.0,
+            source,
+            again.get_source()
+        );
+    }
+    Ok(())
+}
+
+/// Fail unless running `line`, such as `:$`, on a stack holding `stack`
+/// leaves it as it was.
+pub fn check_stack_preserved(stack: &StackValues, line: &str) -> Result<()> {
+    let mut repl = stack.repl();
+    repl.eval(line)
+        .with_context(|| anyhow!("Failed to run {:?} on {:?}", line, stack.0))?;
+    let after = repl.interpreter().get_stack().values();
+    if after != stack.0 {
+        bail!("{:?} turns the stack {:?} into {:?}", line, stack.0, after);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls {
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    use super::{Case, Input, Playfield, StackValues, EDGE_VALUES};
+    use crate::instructions::{instructions, Dialect};
+    use crate::{PLAYFIELD_COLS, PLAYFIELD_ROWS};
+
+    impl<'a> Arbitrary<'a> for Playfield {
+        /// Rows of Befunge-93 instructions and spaces, within the
+        /// playfield.
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let mut cells: Vec<char> = instructions(Dialect::Befunge93)
+                .iter()
+                .map(|doc| doc.c)
+                .collect();
+            cells.push(' ');
+            let rows = u.int_in_range(1..=PLAYFIELD_ROWS)?;
+            let mut source = String::new();
+            for _ in 0..rows {
+                let cols = u.int_in_range(0..=PLAYFIELD_COLS)?;
+                for _ in 0..cols {
+                    source.push(*u.choose(&cells)?);
+                }
+                source.push('\n');
+            }
+            Ok(Self(source))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for StackValues {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let len = u.int_in_range(0..=16)?;
+            let mut values = Vec::with_capacity(len);
+            for _ in 0..len {
+                values.push(if u.ratio(1, 8)? {
+                    *u.choose(EDGE_VALUES)?
+                } else {
+                    u.int_in_range(-256..=255)?
+                });
+            }
+            Ok(Self(values))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Input {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self(Vec::arbitrary(u)?))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Case {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                playfield: Playfield::arbitrary(u)?,
+                input: Input::arbitrary(u)?,
+                seed: u64::arbitrary(u)?,
+            })
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! The styles of what is drawn in terminals, which a theme file can
+//! change, and which `Theme::plain` turns off for `NO_COLOR`.
+//!
+//! A theme file holds `key = style` lines overriding the default theme,
+//! and comments on lines starting with `#`, e.g.
+//!
+//! ```text
+//! pc = bold black on yellow
+//! border = 8
+//! highlight.arrow = #5599ff
+//! ```
+//!
+//! A style is any of `bold`, `dimmed`, `italic`, `underline` and `reverse`,
+//! then a colour and `on` a background colour, or `none`. Colours are
+//! names such as `red`, numbers of the 256 terminal colours or `#rrggbb`.
+//! Highlight colours must be `#rrggbb`, as they are used in images too,
+//! and `highlight = none` turns highlighting off.
+
+use ansi_term::Colour::{self, Blue, Green, Red, White, Yellow};
+use ansi_term::Style;
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::render::{Palette, Rgb};
+
+/// Styles of what is drawn in terminals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// The cell the PC is on.
+    pub pc: Style,
+    /// The box around the playfield.
+    pub border: Style,
+    /// Values on the stack.
+    pub stack: Style,
+    /// Labels such as `Output:`.
+    pub label: Style,
+    /// Things that went well.
+    pub ok: Style,
+    pub error: Style,
+    pub warning: Style,
+    /// Gutters and arrows pointing at places in programs.
+    pub accent: Style,
+    /// Titles and names.
+    pub strong: Style,
+    /// Notes and hints.
+    pub faint: Style,
+    /// Colours of instructions when highlighting them, if at all.
+    pub highlight: Option<Palette>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            pc: Red.on(White).bold(),
+            border: Yellow.normal(),
+            stack: Green.on(White),
+            label: Green.normal(),
+            ok: Green.normal(),
+            error: Red.bold(),
+            warning: Yellow.normal(),
+            accent: Blue.bold(),
+            strong: Style::new().bold(),
+            faint: Style::new().dimmed(),
+            highlight: Some(Palette::default()),
+        }
+    }
+}
+
+impl Theme {
+    /// No styles at all, for output without escape sequences.
+    pub fn plain() -> Self {
+        Self {
+            pc: Style::new(),
+            border: Style::new(),
+            stack: Style::new(),
+            label: Style::new(),
+            ok: Style::new(),
+            error: Style::new(),
+            warning: Style::new(),
+            accent: Style::new(),
+            strong: Style::new(),
+            faint: Style::new(),
+            highlight: None,
+        }
+    }
+
+    /// The default theme with the overrides in the theme file `text`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut theme = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| anyhow!("Line {} of the theme: expected KEY = STYLE", i + 1))?;
+            theme
+                .set(key.trim(), value.trim())
+                .with_context(|| anyhow!("Line {} of the theme", i + 1))?;
+        }
+        Ok(theme)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        if key == "highlight" && value == "none" {
+            self.highlight = None;
+            return Ok(());
+        }
+        if let Some(category) = key.strip_prefix("highlight.") {
+            let palette = self.highlight.get_or_insert_with(Palette::default);
+            let colour = parse_rgb(value)?;
+            match category {
+                "arrow" => palette.arrow = colour,
+                "arith" => palette.arith = colour,
+                "io" => palette.io = colour,
+                "stack" => palette.stack = colour,
+                "digit" => palette.digit = colour,
+                "string" => palette.string = colour,
+                "other" => palette.other = colour,
+                _ => bail!("Unknown instruction category '{}'", category),
+            }
+            return Ok(());
+        }
+        let style = match key {
+            "pc" => &mut self.pc,
+            "border" => &mut self.border,
+            "stack" => &mut self.stack,
+            "label" => &mut self.label,
+            "ok" => &mut self.ok,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "accent" => &mut self.accent,
+            "strong" => &mut self.strong,
+            "faint" => &mut self.faint,
+            _ => bail!("Unknown key '{}'", key),
+        };
+        *style = parse_style(value)?;
+        Ok(())
+    }
+}
+
+/// Parse a style such as `bold red on white`.
+fn parse_style(s: &str) -> Result<Style> {
+    let mut style = Style::new();
+    let mut words = s.split_whitespace().peekable();
+    if words.peek() == Some(&"none") {
+        words.next();
+    }
+    while let Some(word) = words.next() {
+        style = match word {
+            "bold" => style.bold(),
+            "dimmed" => style.dimmed(),
+            "italic" => style.italic(),
+            "underline" => style.underline(),
+            "reverse" => style.reverse(),
+            "on" => {
+                let colour = words.next().context("Expected a colour after 'on'")?;
+                style.on(parse_colour(colour)?)
+            }
+            colour => style.fg(parse_colour(colour)?),
+        };
+    }
+    Ok(style)
+}
+
+fn parse_colour(s: &str) -> Result<Colour> {
+    Ok(match s {
+        "black" => Colour::Black,
+        "red" => Colour::Red,
+        "green" => Colour::Green,
+        "yellow" => Colour::Yellow,
+        "blue" => Colour::Blue,
+        "purple" | "magenta" => Colour::Purple,
+        "cyan" => Colour::Cyan,
+        "white" => Colour::White,
+        _ if s.starts_with('#') => {
+            let (r, g, b) = parse_rgb(s)?;
+            Colour::RGB(r, g, b)
+        }
+        _ => Colour::Fixed(
+            s.parse()
+                .map_err(|_| anyhow!("Expected a colour, got '{}'", s))?,
+        ),
+    })
+}
+
+fn parse_rgb(s: &str) -> Result<Rgb> {
+    let hex = s
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .with_context(|| anyhow!("Expected a colour as #rrggbb, got '{}'", s))?;
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| anyhow!("Expected a colour as #rrggbb, got '{}'", s))
+    };
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::modifications::Modification;
+use crate::schema::{check_version, TraceHeader, SCHEMA_VERSION};
+use crate::{Command, Direction, Interpreter, Position};
+
+/// What happened in one step of an interpreter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TraceEvent {
+    /// Number of the step, starting from one.
+    pub step: usize,
+    /// Position of the executed command.
+    pub pc: Position,
+    /// Direction of the PC when the command was executed.
+    pub dir: Direction,
+    /// The executed command.
+    pub command: Command,
+    /// The stack before the step, bottom first.
+    pub stack_before: Vec<i64>,
+    /// The stack after the step, bottom first.
+    pub stack_after: Vec<i64>,
+    /// Output produced by the step.
+    pub output_delta: String,
+    /// The cell written by the step with `p`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modification: Option<Modification>,
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |stack: &[i64]| {
+            let values: Vec<_> = stack.iter().map(|v| v.to_string()).collect();
+            values.join(" ")
+        };
+        write!(
+            f,
+            "#{} {} {} {:?} [{}] -> [{}]",
+            self.step,
+            self.pc,
+            self.dir,
+            self.command.as_char(),
+            join(&self.stack_before),
+            join(&self.stack_after)
+        )?;
+        if !self.output_delta.is_empty() {
+            write!(f, " {:?}", self.output_delta)?;
+        }
+        if let Some(m) = &self.modification {
+            write!(
+                f,
+                " {} {:?} -> {:?}",
+                m.pos,
+                m.old.as_char(),
+                m.new.as_char()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Emits a `TraceEvent` for every step while tracing is enabled.
+pub(crate) struct Tracer {
+    steps: usize,
+    sink: Box<dyn FnMut(TraceEvent) + Send>,
+}
+
+impl Tracer {
+    pub(crate) fn new(sink: impl FnMut(TraceEvent) + Send + 'static) -> Self {
+        Self {
+            steps: 0,
+            sink: Box::new(sink),
+        }
+    }
+
+    /// Start numbering steps from one again.
+    pub(crate) fn restart(&mut self) {
+        self.steps = 0;
+    }
+
+    /// Capture the state before a step.
+    pub(crate) fn before(&self, int: &Interpreter) -> TraceEvent {
+        TraceEvent {
+            step: self.steps + 1,
+            pc: int.get_position(),
+            dir: int.get_direction(),
+            command: int.get_current_command(),
+            stack_before: int.stack.values(),
+            stack_after: vec![],
+            output_delta: String::new(),
+            modification: None,
+        }
+    }
+
+    /// Complete `event` with the state after the step and emit it.
+    pub(crate) fn after(&mut self, int: &Interpreter, mut event: TraceEvent, output_seq: u64) {
+        event.stack_after = int.stack.values();
+        event.output_delta = int.output_since(output_seq).text;
+        event.modification = int.last_modification(event.step);
+        self.steps += 1;
+        (self.sink)(event);
+    }
+}
+
+/// Writes `TraceEvent`s as JSON lines, after a `TraceHeader` line.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    started: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            let header = TraceHeader {
+                schema_version: SCHEMA_VERSION,
+            };
+            serde_json::to_writer(&mut self.writer, &header)?;
+            writeln!(self.writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write one event.
+    pub fn write(&mut self, event: &TraceEvent) -> io::Result<()> {
+        self.start()?;
+        serde_json::to_writer(&mut self.writer, event)?;
+        writeln!(self.writer)
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Read a trace written by `TraceWriter`. Traces without a header, from
+/// before traces were versioned, are accepted too.
+pub fn read_trace(reader: impl BufRead) -> Result<Vec<TraceEvent>> {
+    let mut events = vec![];
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line.context("Reading trace")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if idx == 0 {
+            if let Ok(header) = serde_json::from_str::<TraceHeader>(&line) {
+                check_version(header.schema_version)?;
+                continue;
+            }
+        }
+        events.push(
+            serde_json::from_str(&line)
+                .with_context(|| anyhow!("Parsing trace line {}", idx + 1))?,
+        );
+    }
+    Ok(events)
+}
+
+/// Index of the first element at which `left` and `right` differ, counting
+/// the end of the shorter one as a difference. `None` if they are equal.
+pub fn first_divergence<T: PartialEq>(left: &[T], right: &[T]) -> Option<usize> {
+    let common = left.iter().zip(right).take_while(|(l, r)| l == r).count();
+    if common == left.len() && common == right.len() {
+        None
+    } else {
+        Some(common)
+    }
+}
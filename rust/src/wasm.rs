@@ -0,0 +1,108 @@
+//! Bindings for JavaScript, built with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm-bindgen --crate-type cdylib`
+//! and then `wasm-bindgen` on the `.wasm` file.
+
+use wasm_bindgen::prelude::*;
+
+use crate::stepper::Stepper;
+
+fn js_error(e: anyhow::Error) -> JsValue {
+    JsValue::from_str(&format!("{:#}", e))
+}
+
+/// An interpreter for JavaScript, taking program input pushed from JS.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Befunge(Stepper);
+
+#[wasm_bindgen]
+impl Befunge {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a program, to be run from the start.
+    pub fn load(&mut self, program: &str) -> Result<(), JsValue> {
+        self.0.load(program.as_bytes()).map_err(js_error)
+    }
+
+    /// Append to the input read by `&` and `~`.
+    #[wasm_bindgen(js_name = pushInput)]
+    pub fn push_input(&self, input: &str) {
+        self.0.push_input(input.as_bytes());
+    }
+
+    /// Whether the next step would read more input than has been pushed.
+    #[wasm_bindgen(js_name = needsInput)]
+    pub fn needs_input(&self) -> bool {
+        self.0.needs_input()
+    }
+
+    /// Execute one step. Returns `false` once the program has ended.
+    pub fn step(&mut self) -> Result<bool, JsValue> {
+        self.0.step().map_err(js_error)
+    }
+
+    /// Execute up to `max_steps` steps, stopping early when input is
+    /// needed. Returns `false` once the program has ended.
+    pub fn run(&mut self, max_steps: usize) -> Result<bool, JsValue> {
+        self.0.run(max_steps).map_err(js_error)
+    }
+
+    /// Whether the program has ended.
+    #[wasm_bindgen(getter)]
+    pub fn halted(&self) -> bool {
+        self.0.halted()
+    }
+
+    /// Steps executed since the program was loaded.
+    #[wasm_bindgen(getter)]
+    pub fn steps(&self) -> usize {
+        self.0.steps()
+    }
+
+    /// Column of the PC.
+    #[wasm_bindgen(getter)]
+    pub fn x(&self) -> usize {
+        self.0.interpreter().get_position().x
+    }
+
+    /// Row of the PC.
+    #[wasm_bindgen(getter)]
+    pub fn y(&self) -> usize {
+        self.0.interpreter().get_position().y
+    }
+
+    /// Columns of the part of the playfield in use.
+    #[wasm_bindgen(getter)]
+    pub fn cols(&self) -> usize {
+        self.0.interpreter().get_bounds().cols
+    }
+
+    /// Rows of the part of the playfield in use.
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> usize {
+        self.0.interpreter().get_bounds().rows
+    }
+
+    /// The output so far.
+    #[wasm_bindgen(js_name = getOutput)]
+    pub fn get_output(&self) -> String {
+        self.0.interpreter().get_output().to_string()
+    }
+
+    /// The stack, bottom first.
+    #[wasm_bindgen(js_name = getStack)]
+    pub fn get_stack(&self) -> Vec<i64> {
+        self.0.interpreter().get_stack().values()
+    }
+
+    /// The playfield as text, one line per row.
+    #[wasm_bindgen(js_name = getPlayfield)]
+    pub fn get_playfield(&self) -> String {
+        let int = self.0.interpreter();
+        let rows: Vec<_> = (0..int.get_size().rows).map(|y| int.get_row(y)).collect();
+        rows.join("\n")
+    }
+}
@@ -0,0 +1,113 @@
+//! Running a program from compiled blocks, and with the `jit` feature from
+//! native code, must do just what stepping through it does.
+
+use befunge_93::output::{OutputLimit, OutputOverflow};
+use befunge_93::semantics::{OutOfBounds, Semantics};
+use befunge_93::Interpreter;
+
+/// How a run ended: its error, if any, its output and its stack.
+type Outcome = (Option<String>, String, Vec<i64>);
+
+fn outcome(int: &Interpreter, result: anyhow::Result<()>) -> Outcome {
+    (
+        result.err().map(|e| format!("{:#}", e)),
+        int.get_output().to_string(),
+        int.get_stack().values(),
+    )
+}
+
+/// Run `program` in every way, each on an interpreter prepared by `setup`,
+/// failing unless they all end alike after as many steps. The outcome of
+/// stepping.
+fn assert_same(program: &str, setup: impl Fn(&mut Interpreter)) -> Outcome {
+    let load = || {
+        let mut int = Interpreter::new();
+        setup(&mut int);
+        int.load(&mut program.as_bytes()).unwrap();
+        int
+    };
+
+    let mut stepped = load();
+    let result = stepped.run(|_, _| true);
+    let expected = outcome(&stepped, result);
+
+    let mut compiled = load();
+    let result = compiled.run_compiled();
+    assert_eq!(outcome(&compiled, result), expected, "run_compiled");
+    assert_eq!(compiled.get_steps(), stepped.get_steps(), "run_compiled");
+
+    #[cfg(feature = "jit")]
+    {
+        let mut native = load();
+        let result = native.run_jit();
+        assert_eq!(outcome(&native, result), expected, "run_jit");
+        assert_eq!(native.get_steps(), stepped.get_steps(), "run_jit");
+    }
+
+    expected
+}
+
+#[test]
+fn wrapped_put_into_a_compiled_block() {
+    // The `0` is flipped between `0` and `1` through x = 91, which wraps to
+    // its column.
+    let program = "\"(\">:!#@_1-0.\"a\"92+0g-\"[\"0pv\n   ^                       <\n";
+    let (error, output, _) = assert_same(program, |int| {
+        int.set_semantics(Semantics {
+            out_of_bounds: OutOfBounds::Wrap,
+            ..Semantics::default()
+        })
+    });
+    assert_eq!(error, None);
+    assert_eq!(output, "0 1 ".repeat(20));
+}
+
+#[test]
+fn dup_on_an_empty_stack() {
+    // Loops often enough for the block starting with `:` to run natively,
+    // counting down in the cell below.
+    let program = ">:.02g1-:02p!#@_v\n^               <\n(\n";
+    let (error, output, stack) = assert_same(program, |_| {});
+    assert_eq!(error, None);
+    assert_eq!(output, "0 ".repeat(40));
+    assert!(stack.is_empty());
+}
+
+#[test]
+fn output_past_the_limit() {
+    let program = ">:.02g1-:02p!#@_v\n^               <\n(\n";
+    let (error, output, _) = assert_same(program, |int| {
+        int.set_output_limit(Some(OutputLimit {
+            bytes: 60,
+            overflow: OutputOverflow::Error,
+        }))
+    });
+    assert!(error.unwrap().contains("Output overflow"));
+    assert_eq!(output, "0 ".repeat(30));
+}
+
+#[test]
+fn hot_loop_invalidated_again_and_again() {
+    // The inner loop runs often enough to be compiled natively, and every
+    // time it has the `0` is flipped, invalidating it; after a few times it
+    // runs its operations instead.
+    let program = ">0.45*>1-:#v_$\"a\"10g-10p04g1-:04p!#@_v\n      ^    <\n\n^                                    <\n(\n";
+    let (error, output, _) = assert_same(program, |_| {});
+    assert_eq!(error, None);
+    assert_eq!(output, "0 1 ".repeat(20));
+}
+
+#[test]
+fn steps_of_a_run() {
+    let mut int = Interpreter::new();
+    int.load(&mut &b"1.@"[..]).unwrap();
+    int.run(|_, _| true).unwrap();
+    // The step ending the program counts.
+    assert_eq!(int.get_steps(), 3);
+
+    // Nor does the failing division, after the loop has run natively.
+    let program = ">:.02g1-:02p!#/_v\n^               <\n(\n";
+    let (error, output, _) = assert_same(program, |_| {});
+    assert!(error.unwrap().contains("Division by zero"));
+    assert_eq!(output, "0 ".repeat(40));
+}
@@ -0,0 +1,95 @@
+//! Programs written for the Befunge-96 and Befunge-97 drafts must run on
+//! their larger playfield, and Befunge-93 ones on its 80x25 one.
+
+use befunge_93::instructions::Dialect;
+use befunge_93::{Bounds, Interpreter, Position};
+
+fn load(dialect: Dialect, source: &str) -> Interpreter {
+    let mut int = Interpreter::new();
+    int.set_dialect(dialect).unwrap();
+    int.load(&mut source.as_bytes()).unwrap();
+    int
+}
+
+#[test]
+fn sizes_of_the_playfields() {
+    let sizes: Vec<_> = Dialect::ALL
+        .iter()
+        .map(|&dialect| load(dialect, "@").get_size())
+        .collect();
+    assert_eq!(
+        sizes,
+        [
+            Bounds { cols: 80, rows: 25 },
+            Bounds {
+                cols: 160,
+                rows: 50
+            },
+            Bounds {
+                cols: 160,
+                rows: 50
+            },
+        ]
+    );
+}
+
+#[test]
+fn lines_past_80_columns() {
+    let source = format!("9{}.@\n", " ".repeat(87));
+
+    let mut int = load(Dialect::Befunge96, &source);
+    int.run(|_, _| true).unwrap();
+    assert_eq!(int.get_output(), "9 ");
+
+    // Befunge-93 wraps the end of the line onto the next row.
+    let int = load(Dialect::Befunge93, &source);
+    assert_eq!(int.get_row(0), "9");
+    assert_eq!(int.get_row(1), "        .@");
+}
+
+#[test]
+fn put_and_get_past_80x25() {
+    // Writes an `A` at (81, 30) and reads it back.
+    let mut int = load(Dialect::Befunge97, "\"A\"99*56*p99*56*g,@\n");
+    int.run(|_, _| true).unwrap();
+    assert_eq!(int.get_output(), "A");
+    assert_eq!(int.get_row(30), format!("{}A", " ".repeat(81)));
+
+    let mut int = load(Dialect::Befunge93, "@");
+    let error = int.set_cell(Position { x: 81, y: 30 }, b'A').unwrap_err();
+    assert_eq!(error.to_string(), "(81, 30) is outside the playfield");
+}
+
+#[test]
+fn wraps_at_the_edges_of_the_larger_playfield() {
+    // Going left from the origin wraps to column 159.
+    let source = format!("<{}@.1\n", " ".repeat(156));
+    let mut int = load(Dialect::Befunge96, &source);
+    int.run(|_, _| true).unwrap();
+    assert_eq!(int.get_output(), "1 ");
+
+    // And going up to row 49.
+    let source = format!("^{}@\n.\n1\n", "\n".repeat(47));
+    let mut int = load(Dialect::Befunge96, &source);
+    int.run(|_, _| true).unwrap();
+    assert_eq!(int.get_output(), "1 ");
+}
+
+#[test]
+fn switching_dialects_resizes_the_playfield() {
+    let mut int = load(Dialect::Befunge96, &format!("{}@\n", " ".repeat(100)));
+    int.set_dialect(Dialect::Befunge93).unwrap();
+    assert_eq!(int.get_size(), Bounds { cols: 80, rows: 25 });
+    assert_eq!(int.get_row(0), "");
+    assert_eq!(int.get_bounds(), Bounds::default());
+
+    int.set_dialect(Dialect::Befunge97).unwrap();
+    assert_eq!(
+        int.get_size(),
+        Bounds {
+            cols: 160,
+            rows: 50
+        }
+    );
+    assert_eq!(int.get_row(0), "");
+}
@@ -0,0 +1,150 @@
+//! Golden traces: every program in `tests/golden` is run and each of its
+//! steps compared with the trace recorded next to it, so that changes to the
+//! interpreter can't silently change what programs do.
+//!
+//! After an intended change, record the traces again with
+//! `cargo test --test golden -- --bless` and review the diff. Setting the
+//! `BLESS` environment variable does the same.
+//!
+//! For each `NAME.bf`, `NAME.in` holds the input, if any, and `NAME.trace`
+//! the expected trace: one line per step followed by how the run ended.
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+
+use befunge_93::trace::first_divergence;
+use befunge_93::Interpreter;
+
+/// Runs taking more steps are cut off, so that a change making a fixture
+/// loop forever shows up as a diverging trace instead of a hang.
+const MAX_STEPS: usize = 10_000;
+
+/// Seed for `?`, so that traces of programs using it are reproducible.
+const SEED: u64 = 0;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// The trace of a run of the program at `path`, as lines.
+fn record(path: &Path) -> Result<Vec<String>> {
+    let source = fs::read(path).with_context(|| anyhow!("Failed to read {}", path.display()))?;
+    let input = fs::read(path.with_extension("in")).unwrap_or_default();
+
+    let lines = Arc::new(Mutex::new(vec![]));
+    let sink = Arc::clone(&lines);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_seed(Some(SEED));
+    interpreter.set_input(Cursor::new(input));
+    interpreter.on_trace(move |event| sink.lock().unwrap().push(event.to_string()));
+    interpreter.load(&mut &source[..])?;
+
+    let mut cut_off = false;
+    let result = interpreter.run(|_, steps| {
+        cut_off = steps >= MAX_STEPS;
+        !cut_off
+    });
+    let ending = match result {
+        Err(e) => format!("error: {:#}", e),
+        Ok(()) if cut_off => format!("cut off after {} steps", MAX_STEPS),
+        Ok(()) => "ended".to_string(),
+    };
+
+    let mut lines = std::mem::take(&mut *lines.lock().unwrap());
+    lines.push(ending);
+    lines.push(format!("output: {:?}", interpreter.get_output()));
+    Ok(lines)
+}
+
+/// Compare the run of the program at `path` with its trace, or record the
+/// trace if `bless`.
+fn check(path: &Path, bless: bool) -> Result<()> {
+    let actual = record(path)?;
+    let trace_path = path.with_extension("trace");
+    if bless {
+        let mut text = actual.join("\n");
+        text.push('\n');
+        return fs::write(&trace_path, text)
+            .with_context(|| anyhow!("Failed to write {}", trace_path.display()));
+    }
+
+    let expected = fs::read_to_string(&trace_path).with_context(|| {
+        anyhow!(
+            "Failed to read {}; record it with `cargo test --test golden -- --bless`",
+            trace_path.display()
+        )
+    })?;
+    let expected: Vec<_> = expected.lines().map(String::from).collect();
+    if let Some(idx) = first_divergence(&expected, &actual) {
+        let line = |lines: &[String]| lines.get(idx).cloned().unwrap_or_else(|| "<none>".into());
+        let context = idx.saturating_sub(3)..idx;
+        let mut message = format!(
+            "diverges at line {} of {}:\n",
+            idx + 1,
+            trace_path.display()
+        );
+        for same in &expected[context] {
+            message += &format!("      {}\n", same);
+        }
+        message += &format!("    - {}\n    + {}", line(&expected), line(&actual));
+        return Err(anyhow!(message));
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // Other flags are libtest's, passed on by `cargo test`; names filter
+    // fixtures as they do tests.
+    let args: Vec<String> = env::args().skip(1).collect();
+    let bless = args.iter().any(|arg| arg == "--bless") || env::var_os("BLESS").is_some();
+    let filters: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+
+    let mut fixtures: Vec<PathBuf> = match fs::read_dir(fixtures_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bf"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to list the golden fixtures: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    fixtures.sort();
+
+    let mut failed = vec![];
+    let mut run = 0;
+    for path in &fixtures {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+            continue;
+        }
+        run += 1;
+        match check(path, bless) {
+            Ok(()) if bless => println!("golden {} ... blessed", name),
+            Ok(()) => println!("golden {} ... ok", name),
+            Err(e) => {
+                println!("golden {} ... FAILED\n{:#}\n", name, e);
+                failed.push(name);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        println!("\ngolden traces: {} checked", run);
+        ExitCode::SUCCESS
+    } else {
+        println!(
+            "\ngolden traces: {} of {} diverged: {}\nIf the change is intended, run `cargo test --test golden -- --bless`.",
+            failed.len(),
+            run,
+            failed.join(", ")
+        );
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,2 @@
+9>:.1-:#v_@
+ ^      <
@@ -0,0 +1,2 @@
+&>:1-:v v *_$.@
+ ^    _$>\:^
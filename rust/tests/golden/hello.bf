@@ -0,0 +1 @@
+0"!dlroW olleH">:#,_@
@@ -0,0 +1,3 @@
+v  >1.@
+>  ?2.@
+   >3.@
@@ -0,0 +1 @@
+"@"90p7.  8.@
@@ -0,0 +1,73 @@
+//! Program files of other Befunge tools must load into the playfield they
+//! do there, and programs written for them must load back unchanged.
+
+use befunge_93::interop::{export, import, SourceFormat};
+use befunge_93::Interpreter;
+
+const PROGRAM: &str =
+    "#!/usr/bin/env bef\n\"olleh\">:#,_@\n\n  v <\n  >^\n__END__\nexpect: hello\n";
+
+fn load(source: &[u8]) -> Interpreter {
+    let mut int = Interpreter::new();
+    int.load(&mut &source[..]).unwrap();
+    int
+}
+
+fn playfield(int: &Interpreter) -> Vec<String> {
+    (0..int.get_size().rows).map(|y| int.get_row(y)).collect()
+}
+
+#[test]
+fn round_trips() {
+    let original = load(PROGRAM.as_bytes());
+    for format in [SourceFormat::Bef, SourceFormat::Funge98, SourceFormat::Dos] {
+        let exported = export(&original, format).unwrap();
+        let imported = load(&import(&exported, format));
+        assert_eq!(playfield(&imported), playfield(&original), "{}", format);
+        if format != SourceFormat::Funge98 {
+            assert_eq!(imported.get_source(), PROGRAM, "{}", format);
+        }
+    }
+}
+
+#[test]
+fn writes_the_line_breaks_of_each_format() {
+    let int = load(PROGRAM.as_bytes());
+    let dos = export(&int, SourceFormat::Dos).unwrap();
+    assert_eq!(dos, PROGRAM.replace('\n', "\r\n").into_bytes());
+
+    // Funge-98 readers would load the `#!` line and comments as code.
+    let funge98 = export(&int, SourceFormat::Funge98).unwrap();
+    assert_eq!(funge98, b"\"olleh\">:#,_@\n\n  v <\n  >^\n");
+}
+
+#[test]
+fn reads_funge98_line_breaks() {
+    let source = b"v\r>1.@\r\n\x0c\n>^\n";
+    let int = load(&import(source, SourceFormat::Funge98));
+    assert_eq!(&playfield(&int)[..4], ["v", ">1.@", "", ">^"]);
+
+    // A lone `\r` is a cell elsewhere.
+    let int = load(&import(b"1\r.@\r\n", SourceFormat::Dos));
+    assert_eq!(int.get_row(0), "1\r.@");
+    assert_eq!(int.get_row(1), "");
+}
+
+#[test]
+fn refuses_cells_funge98_files_cant_hold() {
+    let int = load(b"1\r.@\n");
+    let error = export(&int, SourceFormat::Funge98).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Cell (1, 0) holds '\\r', which Funge-98 files can't hold"
+    );
+}
+
+#[test]
+fn writes_cells_as_they_were_read() {
+    let source = b"\"\xe9\",@\n";
+    let int = load(source);
+    for format in [SourceFormat::Bef, SourceFormat::Funge98] {
+        assert_eq!(export(&int, format).unwrap(), source, "{}", format);
+    }
+}
@@ -0,0 +1,75 @@
+//! Properties every program should have, checked on random programs,
+//! stacks and inputs from `befunge_93::testing`.
+//!
+//! The cases are drawn from a fixed seed, so failures reproduce; set
+//! `PROPERTY_SEED` to try others.
+
+use std::env;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use befunge_93::testing::{self, Case, StackValues};
+
+/// Random cases checked for each property.
+const CASES: usize = 200;
+
+/// Steps runs are compared for.
+const MAX_STEPS: usize = 2_000;
+
+/// Lines of instructions that give back the stack they started with.
+const NEUTRAL_LINES: &[&str] = &[":$", "0$", "\"ab\"$$", "01-$", "#$ "];
+
+/// Check `property` on `CASES` random values drawn by `draw`, printing the
+/// first failure. Whether every case passed.
+fn check<T>(
+    name: &str,
+    rng: &mut SmallRng,
+    draw: impl Fn(&mut SmallRng) -> T,
+    property: impl Fn(&T) -> Result<()>,
+) -> bool {
+    for n in 0..CASES {
+        if let Err(e) = property(&draw(rng)) {
+            println!("property {} ... FAILED at case {}: {:#}", name, n, e);
+            return false;
+        }
+    }
+    println!("property {} ... ok", name);
+    true
+}
+
+fn main() -> ExitCode {
+    let seed = env::var("PROPERTY_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0);
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    let results = [
+        check("deterministic", &mut rng, Case::random, |case| {
+            testing::check_deterministic(case, MAX_STEPS)
+        }),
+        check(
+            "source round trip",
+            &mut rng,
+            |rng| Case::random(rng).playfield,
+            testing::check_source_round_trip,
+        ),
+        check("neutral lines", &mut rng, StackValues::random, |stack| {
+            NEUTRAL_LINES
+                .iter()
+                .try_for_each(|line| testing::check_stack_preserved(stack, line))
+        }),
+    ];
+
+    let failed = results.iter().filter(|&&ok| !ok).count();
+    println!();
+    println!("properties: {} checked, {} failed", results.len(), failed);
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,29 @@
+//! Running an interpreter on a worker thread.
+
+use befunge_93::runner::Runner;
+use befunge_93::Interpreter;
+
+fn interpreter(program: &str) -> Interpreter {
+    let mut int = Interpreter::new();
+    int.load(&mut program.as_bytes()).unwrap();
+    int
+}
+
+#[test]
+fn joining_a_paused_run() {
+    let runner = Runner::spawn(interpreter("1.2.@"), true);
+    runner.step();
+    runner.step();
+    assert_eq!(runner.snapshot().unwrap().steps, 2);
+    let (int, result) = runner.join();
+    assert!(result.is_ok());
+    assert_eq!(int.get_output(), "1 ");
+}
+
+#[test]
+fn joining_a_running_run() {
+    let runner = Runner::spawn(interpreter("1.2.@"), false);
+    let (int, result) = runner.join();
+    assert!(result.is_ok());
+    assert_eq!(int.get_output(), "1 2 ");
+}
@@ -0,0 +1,40 @@
+//! The stack through serde, as checkpoints and reports carry it.
+
+use befunge_93::semantics::{Cells, Semantics};
+use befunge_93::{Interpreter, Stack};
+
+/// The stack after squaring 2 six times, which is 2^64 in bigint cells
+/// and wraps to zero in the others, on top of a negative number.
+fn stack(cells: Cells) -> Stack {
+    let mut int = Interpreter::new();
+    int.set_semantics(Semantics {
+        cells,
+        ..Semantics::default()
+    });
+    int.load(&mut &b"05-2:*:*:*:*:*:*@"[..]).unwrap();
+    int.run(|_, _| true).unwrap();
+    int.get_stack()
+}
+
+#[test]
+fn round_trips_through_json() {
+    for (cells, json) in [
+        (Cells::I32, r#"{"cells":"i32","values":[-5,0]}"#),
+        (Cells::I64, r#"{"cells":"i64","values":[-5,0]}"#),
+        (
+            Cells::Big,
+            r#"{"cells":"bigint","values":["-5","18446744073709551616"]}"#,
+        ),
+    ] {
+        let stack = stack(cells);
+        assert_eq!(serde_json::to_string(&stack).unwrap(), json);
+        let back: Stack = serde_json::from_str(json).unwrap();
+        assert!(back == stack, "{:?} cells", cells);
+    }
+}
+
+#[test]
+fn rejects_values_wider_than_the_cells() {
+    assert!(serde_json::from_str::<Stack>(r#"{"cells":"i32","values":[4294967296]}"#).is_err());
+    assert!(serde_json::from_str::<Stack>(r#"{"cells":"i128","values":[]}"#).is_err());
+}
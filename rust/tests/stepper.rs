@@ -0,0 +1,16 @@
+//! Driving programs step by step, as the bindings for other languages do.
+
+use befunge_93::stepper::Stepper;
+
+#[test]
+fn loading_a_shorter_program() {
+    let mut stepper = Stepper::default();
+    stepper.load(b"\"hello\",,,,,@").unwrap();
+    stepper.run(100).unwrap();
+    stepper.load(b"1.@").unwrap();
+
+    assert_eq!(stepper.interpreter().get_source(), "1.@\n");
+    assert!(!stepper.run(100).unwrap());
+    assert_eq!(stepper.steps(), 3);
+    assert_eq!(stepper.interpreter().get_output(), "1 ");
+}